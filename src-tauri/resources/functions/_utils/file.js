@@ -93,9 +93,12 @@
                 return rst.content;
             });
 
-        await methods.invoke("path.delete", {
+        const rst = await methods.invoke("path.delete", {
             input_paths: existingPaths,
         });
+        if (rst.status === "error") {
+            throw new Error(rst.message);
+        }
     }
 
     function right_replace_once(str, search, replace) {
@@ -104,9 +104,12 @@
                 return rst.content;
             });
 
-        await methods.invoke("path.delete", {
+        const rst = await methods.invoke("path.delete", {
             input_paths: existingPaths,
         });
+        if (rst.status === "error") {
+            throw new Error(rst.message);
+        }
     }
 
     window.task_utils = {
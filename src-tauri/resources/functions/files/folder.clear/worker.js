@@ -121,7 +121,10 @@ async function process_path(the_path) {
         const deleted = await methods
             .invoke("path.delete", { paths: [thePathObj.path] })
             .then((rst) => {
-                return rst.content;
+                if (rst.status === "error") {
+                    throw new Error(rst.message);
+                }
+                return rst.content.count;
             });
         return deleted;
     }
@@ -212,10 +212,13 @@ async function svg_to_image(pathResult, srcPathObj) {
     pathResult.src_path = srcPathObj.path;
     pathResult.dest_paths = [output_file];
 
-    // 4rd, remove the temp png file
-    await methods.invoke("path.delete", {
+    // 4rd, remove the temp png file, a leftover is not an error of the conversion
+    const rst = await methods.invoke("path.delete", {
         paths: [png_output_file],
     });
+    if (rst.status === "error") {
+        console.error(rst.message);
+    }
 }
 
 async function image_to_image(pathResult, srcPathObj) {
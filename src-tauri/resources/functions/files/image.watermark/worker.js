@@ -106,10 +106,13 @@ async function main({ action, task }) {
         process_func
     );
 
-    // delete the temp watermark file
-    await task.methods.invoke("path.delete", {
+    // delete the temp watermark file, a leftover is not an error of the task
+    const rst = await task.methods.invoke("path.delete", {
         paths: [temp_watermark_path],
     });
+    if (rst.status === "error") {
+        console.error(rst.message);
+    }
 }
 
 async function convert(pathResult, srcPathObj, temp_watermark_path) {
@@ -40,12 +40,12 @@ pub async fn route(_app: &tauri::AppHandle, action: &str, args: &str) -> Result<
         }
         // load file functions
         "load.file.functions" => match functions::file::read::load_functions() {
-            Ok(_) => Ok(json!("ok")),
+            Ok(report) => Ok(json!(report)),
             Err(e) => Err(e.into()),
         },
         // load tool functions
         "load.tools" => match functions::tool::read::load_tools() {
-            Ok(_) => Ok(json!("ok")),
+            Ok(report) => Ok(json!(report)),
             Err(e) => Err(e.into()),
         },
 
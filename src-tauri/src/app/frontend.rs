@@ -21,6 +21,23 @@ pub fn notify_user(
     Ok(())
 }
 
+/// emit progress/status of a backend task (see `thelib::task`) to the front-end, event: `task_progress`
+pub fn emit_task_progress(app: &tauri::AppHandle, payload: &JsonValue) -> Result<(), BoxedError> {
+    app.emit_to(EventTarget::any(), "task_progress", Some(payload))?;
+
+    Ok(())
+}
+
+/// emit the report after the functions are reloaded on file changes, event: `functions_reloaded`
+pub fn emit_functions_reloaded(
+    app: &tauri::AppHandle,
+    payload: &JsonValue,
+) -> Result<(), BoxedError> {
+    app.emit_to(EventTarget::any(), "functions_reloaded", Some(payload))?;
+
+    Ok(())
+}
+
 // /// info_type: "info", "error", "success"
 // pub fn pack_notify_data_toast(info_type: &str, message: &str) -> JsonValue {
 //     json!({
@@ -36,3 +36,14 @@ pub fn setup_logger() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+/// logger for the headless CLI: stderr only, so stdout stays clean for the command result.
+/// level can be overridden by the `RUST_LOG` env var
+pub fn setup_cli_logger() -> Result<(), Box<dyn std::error::Error>> {
+    Logger::try_with_env_or_str("warn")
+        .unwrap_or_else(|_| Logger::with(LogSpecification::default()))
+        .log_to_stderr()
+        .start()?;
+
+    Ok(())
+}
@@ -49,6 +49,16 @@ pub fn get_app_download_dir() -> Result<PathBuf, BoxedError> {
     Ok(download_dir)
 }
 
+pub fn get_app_temp_dir() -> Result<PathBuf, BoxedError> {
+    let app_data_dir: PathBuf = get_app_data_dir()?;
+    Ok(app_data_dir.join(".temp"))
+}
+
+pub fn get_journal_dir() -> Result<PathBuf, BoxedError> {
+    let app_data_dir: PathBuf = get_app_data_dir()?;
+    Ok(app_data_dir.join("journal"))
+}
+
 pub fn get_i18n_dir_in_app_data() -> Result<PathBuf, BoxedError> {
     // default: app-data-dir/i18n/vX.Y.Z
     let app_data_dir: PathBuf = get_app_data_dir()?;
@@ -91,6 +101,12 @@ pub fn get_function_dir_using() -> Result<PathBuf, BoxedError> {
     Ok(function_dir)
 }
 
+/// functions installed from packages, kept across app and function updates
+pub fn get_user_function_dir() -> Result<PathBuf, BoxedError> {
+    let app_data_dir = get_app_data_dir()?;
+    Ok(app_data_dir.join("functions_user"))
+}
+
 pub fn get_function_category_dir_using(
     category_dirnames: Vec<&str>,
 ) -> Result<PathBuf, BoxedError> {
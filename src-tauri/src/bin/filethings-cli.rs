@@ -0,0 +1,260 @@
+//! Headless command line entry, drives `commands::command_router::route` without the GUI.
+//!
+//! Usage:
+//!   filethings-cli [OPTIONS] <COMMAND> [PARAMS_JSON]
+//!
+//! Params (default `{}`):
+//!   PARAMS_JSON              inline json, e.g. '{"input_file": "a.txt"}'
+//!   --params-file <FILE>     read json params from a file
+//!   -                        read json params from stdin (same as `--params-file -`)
+//!
+//! Options:
+//!   --app-version <VER>      override the app version used to locate function/i18n dirs
+//!   --pretty                 pretty print the result json
+//!   -h, --help               print help
+//!
+//! Prints `CommandResult::to_json()` to stdout. Exit code is 0 on success,
+//! 1 if the command failed or returned status `error`, 2 on invalid usage.
+
+use project_lib::app::logger::setup_cli_logger;
+use project_lib::commands::command_router;
+use project_lib::config::caller::load_user_config;
+use project_lib::config::init::APP_STATUS;
+use project_lib::functions;
+use serde_json::Value as JsonValue;
+use std::io::Read;
+use std::process::ExitCode;
+
+// the app version lives in tauri.conf.json, not in Cargo.toml
+const TAURI_CONF: &str = include_str!("../../tauri.conf.json");
+
+struct CliArgs {
+    command: String,
+    params: String,
+    app_version: Option<String>,
+    pretty: bool,
+}
+
+fn print_usage() {
+    eprintln!(
+        "Usage: filethings-cli [--app-version <VER>] [--pretty] <COMMAND> [PARAMS_JSON | --params-file <FILE> | -]"
+    );
+}
+
+fn parse_args(args: Vec<String>) -> Result<Option<CliArgs>, String> {
+    let mut command: Option<String> = None;
+    let mut params: Option<String> = None;
+    let mut app_version: Option<String> = None;
+    let mut pretty = false;
+
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--pretty" => pretty = true,
+            "--app-version" => {
+                app_version = Some(iter.next().ok_or("`--app-version` requires a value")?);
+            }
+            "--params-file" => {
+                let file = iter.next().ok_or("`--params-file` requires a value")?;
+                params = Some(read_params_file(&file)?);
+            }
+            "-" => params = Some(read_params_file("-")?),
+            _ => {
+                if command.is_none() {
+                    command = Some(arg);
+                } else if params.is_none() {
+                    params = Some(arg);
+                } else {
+                    return Err(format!("Unexpected argument: {}", arg));
+                }
+            }
+        }
+    }
+
+    let command = command.ok_or("Missing command name")?;
+
+    Ok(Some(CliArgs {
+        command,
+        params: params.unwrap_or("{}".to_string()),
+        app_version,
+        pretty,
+    }))
+}
+
+fn read_params_file(file: &str) -> Result<String, String> {
+    if file == "-" {
+        let mut buf = String::new();
+        std::io::stdin()
+            .read_to_string(&mut buf)
+            .map_err(|e| format!("Failed to read params from stdin: {}", e))?;
+        return Ok(buf);
+    }
+    std::fs::read_to_string(file).map_err(|e| format!("Failed to read params file {}: {}", file, e))
+}
+
+fn set_app_version(version: Option<String>) {
+    let version = match version {
+        Some(v) => v,
+        None => serde_json::from_str::<JsonValue>(TAURI_CONF)
+            .ok()
+            .and_then(|conf| conf["version"].as_str().map(|v| v.to_string()))
+            .unwrap_or("unknown".to_string()),
+    };
+
+    match APP_STATUS.lock() {
+        Ok(mut status) => {
+            status.version = version;
+            log::info!("App version: {}", status.version);
+        }
+        Err(_) => {
+            log::error!("Failed to lock APP_STATUS");
+        }
+    };
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    if let Err(e) = setup_cli_logger() {
+        eprintln!("Failed to setup logger: {}", e);
+    }
+
+    let cli_args = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(Some(a)) => a,
+        Ok(None) => {
+            print_usage();
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            print_usage();
+            return ExitCode::from(2);
+        }
+    };
+
+    let params: JsonValue = match serde_json::from_str(&cli_args.params) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Failed to parse params json: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    set_app_version(cli_args.app_version);
+
+    // user settings, e.g. the update source
+    load_user_config();
+
+    // load functions the same way the front-end does at startup (`load.tools`, `load.file.functions`)
+    if let Err(e) = functions::tool::read::load_tools() {
+        log::warn!("Failed to load tools: {}", e);
+    }
+    if let Err(e) = functions::file::read::load_functions() {
+        log::warn!("Failed to load file functions: {}", e);
+    }
+
+    let cmd_result = match command_router::route(&cli_args.command, &params).await {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("Failed to call command `{}`: {}", cli_args.command, e);
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let output = cmd_result.to_json();
+    if cli_args.pretty {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&output).unwrap_or(output.to_string())
+        );
+    } else {
+        println!("{}", output);
+    }
+
+    if cmd_result.status == "error" {
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn parse_err(list: &[&str]) -> String {
+        match parse_args(args(list)) {
+            Ok(_) => panic!("expected an error for {:?}", list),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let cli_args = parse_args(args(&["path.exists"])).unwrap().unwrap();
+        assert_eq!(cli_args.command, "path.exists");
+        assert_eq!(cli_args.params, "{}");
+        assert_eq!(cli_args.app_version, None);
+        assert!(!cli_args.pretty);
+
+        let cli_args = parse_args(args(&[
+            "--pretty",
+            "--app-version",
+            "0.9.1",
+            "path.exists",
+            r#"{"input_path": "a.txt"}"#,
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(cli_args.command, "path.exists");
+        assert_eq!(cli_args.params, r#"{"input_path": "a.txt"}"#);
+        assert_eq!(cli_args.app_version.as_deref(), Some("0.9.1"));
+        assert!(cli_args.pretty);
+
+        // help wins over anything else
+        assert!(parse_args(args(&["-h"])).unwrap().is_none());
+        assert!(parse_args(args(&["path.exists", "--help"]))
+            .unwrap()
+            .is_none());
+
+        assert_eq!(parse_err(&[]), "Missing command name");
+        assert_eq!(parse_err(&["--pretty"]), "Missing command name");
+        assert_eq!(
+            parse_err(&["path.exists", "--app-version"]),
+            "`--app-version` requires a value"
+        );
+        assert_eq!(
+            parse_err(&["path.exists", "--params-file"]),
+            "`--params-file` requires a value"
+        );
+        assert_eq!(
+            parse_err(&["path.exists", "{}", "extra"]),
+            "Unexpected argument: extra"
+        );
+    }
+
+    #[test]
+    fn test_read_params_file() {
+        let file = std::env::temp_dir().join(format!("filethings-cli-{}.json", std::process::id()));
+        std::fs::write(&file, r#"{"input_path": "a.txt"}"#).unwrap();
+        let file_str = file.to_string_lossy().to_string();
+
+        let cli_args = parse_args(args(&["path.exists", "--params-file", &file_str]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(cli_args.params, r#"{"input_path": "a.txt"}"#);
+        // params from a file take the place of the inline json
+        assert_eq!(
+            parse_err(&["path.exists", "--params-file", &file_str, "{}"]),
+            "Unexpected argument: {}"
+        );
+        std::fs::remove_file(&file).unwrap();
+
+        let err = read_params_file(&file_str).unwrap_err();
+        assert!(err.starts_with("Failed to read params file"), "{}", err);
+    }
+}
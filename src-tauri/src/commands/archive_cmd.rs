@@ -0,0 +1,236 @@
+use crate::commands::structures::CommandResult;
+use crate::commands::utils::get_string_val_from_params;
+use crate::errors::BoxedError;
+use crate::thelib::compress::{self, ArchiveFormat, EntryFilter, PasswordError};
+use crate::thelib::file_path::{get_unique_filepath, MAX_UNUSED_PATH_SUFFIX};
+use crate::thelib::glob::glob_to_regex;
+use serde_json::{json, Value as JsonValue};
+use std::path::{Path, PathBuf};
+
+/// pack `input_paths` (files and directories) into an archive.
+/// params:
+/// - `output_file` (optional): default is next to the first input path, named by it
+///   (or "Archive" for multiple input paths)
+/// - `format` (optional): zip, tar, tar.gz, tar.zst. default by `output_file` extension, or zip
+/// - `level` (optional): compression level, zip/tar.gz 0-9, tar.zst 1-22
+/// - `password` (optional): encrypt with AES-256, zip only
+pub fn create_archive(params: &JsonValue) -> Result<CommandResult, BoxedError> {
+    let input_paths = get_input_paths(params)?;
+
+    let output_file_param = get_string_val_from_params(vec!["output_file", "output_path"], params);
+
+    let format = match params.get("format") {
+        Some(v) => ArchiveFormat::from_name(v.as_str().ok_or("format must be a string")?)?,
+        None => match &output_file_param {
+            Ok(f) => ArchiveFormat::from_path(Path::new(f))?,
+            Err(_) => ArchiveFormat::Zip,
+        },
+    };
+
+    let level: Option<i64> = match params.get("level") {
+        Some(v) => Some(v.as_i64().ok_or("level must be an integer")?),
+        None => None,
+    };
+
+    let output_file: String = match output_file_param {
+        Ok(f) => f,
+        Err(_) => {
+            let first_path = &input_paths[0];
+            let stem = if input_paths.len() == 1 {
+                first_path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string()
+            } else {
+                "Archive".to_string()
+            };
+            let dir = first_path.parent().unwrap_or(Path::new(""));
+            get_unique_filepath(
+                &dir.join(&stem).to_string_lossy(),
+                Some(&stem),
+                Some(format.extension()),
+            )?
+        }
+    };
+
+    let password = get_string_val_from_params(vec!["password"], params).ok();
+
+    let count = compress::create_archive(
+        &input_paths,
+        Path::new(&output_file),
+        format,
+        level,
+        password.as_deref(),
+    )?;
+
+    let mut result = CommandResult::default();
+    result.content = json!(output_file);
+    result.message = format!("{} entries archived", count);
+    result.add_output_path(&output_file);
+
+    Ok(result)
+}
+
+/// list the entries of an archive without extracting it
+pub fn list_archive(params: &JsonValue) -> Result<CommandResult, BoxedError> {
+    let input_file = get_string_val_from_params(vec!["input_file", "input_path"], params)?;
+    let input_path = Path::new(&input_file);
+    if !input_path.is_file() {
+        return Err(format!("File not found: '{}'", &input_file).into());
+    }
+
+    let entries = compress::list_entries(input_path)?;
+    let total_size: u64 = entries.iter().map(|e| e.size).sum();
+
+    let mut result = CommandResult::default();
+    result.content = json!({
+        "entries": entries.iter().map(|e| e.to_json()).collect::<Vec<JsonValue>>(),
+        "total_size": total_size,
+    });
+    result.message = format!("{} entries", entries.len());
+
+    Ok(result)
+}
+
+/// extract an archive (zip, tar, tar.gz, tar.zst, 7z; detected by file name).
+/// if `output_dir` is not given, extract to a new directory next to the archive ("Extract here").
+/// only extract some entries by one of (optional):
+/// - `entries`: entry names, a directory name selects all entries under it
+/// - `glob`: glob pattern of entry names, e.g. `docs/**/*.md`
+/// - `regex`: regex pattern of entry names
+///
+/// `password` (optional) is for encrypted zip and 7z, see `zip.unzip_file` for password errors
+pub fn extract_archive(params: &JsonValue) -> Result<CommandResult, BoxedError> {
+    let input_file = get_string_val_from_params(vec!["input_file", "input_path"], params)?;
+    let input_path = Path::new(&input_file);
+    if !input_path.is_file() {
+        return Err(format!("File not found: '{}'", &input_file).into());
+    }
+
+    let format = ArchiveFormat::from_path(input_path)?;
+
+    let output_dir: PathBuf = match get_string_val_from_params(vec!["output_dir"], params) {
+        Ok(d) => PathBuf::from(d),
+        Err(_) => {
+            let file_name = input_path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            // strip the full archive extension, e.g. `a.tar.gz` -> `a`
+            let suffix_len = format.extension().len() + 1;
+            let dir_name = match file_name.len() > suffix_len {
+                true => file_name[..file_name.len() - suffix_len].to_string(),
+                false => file_name,
+            };
+            let parent = input_path.parent().unwrap_or(Path::new(""));
+            get_unused_dir_path(&parent.join(dir_name))?
+        }
+    };
+
+    let filter = get_entry_filter(params)?;
+
+    let password = get_string_val_from_params(vec!["password"], params).ok();
+
+    let extracted = match compress::extract_entries_to(
+        input_path,
+        &output_dir,
+        filter.as_ref(),
+        password.as_deref(),
+    ) {
+        Ok(v) => v,
+        Err(e) => return password_error_result(e),
+    };
+
+    let output_dir_str = output_dir.to_string_lossy().to_string();
+    let mut result = CommandResult::default();
+    result.content = json!(output_dir_str);
+    result.message = format!("{} entries extracted", extracted.len());
+    match filter {
+        // selective extraction, output the extracted paths
+        Some(_) => {
+            for p in &extracted {
+                result.add_output_path(&p.to_string_lossy());
+            }
+        }
+        None => result.add_output_path(&output_dir_str),
+    }
+
+    Ok(result)
+}
+
+/// turn a `PasswordError` into an error result the front-end can recognize by `content.error`,
+/// other errors are returned as is
+pub fn password_error_result(e: BoxedError) -> Result<CommandResult, BoxedError> {
+    match e.downcast_ref::<PasswordError>() {
+        Some(password_error) => {
+            let mut result = CommandResult::default();
+            result.status = "error".to_string();
+            result.message = password_error.to_string();
+            result.content = json!({ "error": password_error.code() });
+            Ok(result)
+        }
+        None => Err(e),
+    }
+}
+
+fn get_entry_filter(params: &JsonValue) -> Result<Option<EntryFilter>, BoxedError> {
+    if let Some(v) = params.get("entries") {
+        let names: Vec<String> = v
+            .as_array()
+            .ok_or("entries must be an array")?
+            .iter()
+            .map(|n| n.as_str().unwrap_or("").to_string())
+            .filter(|n| !n.is_empty())
+            .collect();
+        if names.is_empty() {
+            return Err("entries must not be empty".into());
+        }
+        return Ok(Some(EntryFilter::Names(names)));
+    }
+    if let Ok(pattern) = get_string_val_from_params(vec!["glob"], params) {
+        return Ok(Some(EntryFilter::Pattern(glob_to_regex(&pattern)?)));
+    }
+    if let Ok(pattern) = get_string_val_from_params(vec!["regex"], params) {
+        let re = regex::Regex::new(&pattern).map_err(|e| format!("pattern error: {}", e))?;
+        return Ok(Some(EntryFilter::Pattern(re)));
+    }
+    Ok(None)
+}
+
+fn get_input_paths(params: &JsonValue) -> Result<Vec<PathBuf>, BoxedError> {
+    let input_paths: Vec<PathBuf> = match params.get("input_paths") {
+        Some(v) => v
+            .as_array()
+            .ok_or("input_paths must be an array")?
+            .iter()
+            .map(|p| p.as_str().unwrap_or(""))
+            .filter(|p| !p.is_empty())
+            .map(PathBuf::from)
+            .collect(),
+        None => {
+            let input_path = get_string_val_from_params(vec!["input_path", "input_file"], params)?;
+            vec![PathBuf::from(input_path)]
+        }
+    };
+    if input_paths.is_empty() {
+        return Err("Missing parameter `input_paths`".into());
+    }
+    Ok(input_paths)
+}
+
+/// add a number suffix to the dir name if it already exists
+fn get_unused_dir_path(dir: &Path) -> Result<PathBuf, BoxedError> {
+    if !dir.exists() {
+        return Ok(dir.to_path_buf());
+    }
+    let name = dir.file_name().unwrap_or_default().to_string_lossy();
+    for suffix in 1..=MAX_UNUSED_PATH_SUFFIX {
+        let new_dir = dir.with_file_name(format!("{}-{}", name, suffix));
+        if !new_dir.exists() {
+            return Ok(new_dir);
+        }
+    }
+    Err(format!("No unused dir path for {}", dir.display()).into())
+}
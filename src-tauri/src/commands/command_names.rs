@@ -14,10 +14,12 @@ pub const PATH_DELETE: &'static str = "path.delete";
 pub const PATH_RENAME: &'static str = "path.rename"; // same as file.rename
                                                      // DIR
 pub const DIR_LIST: &'static str = "dir.list";
+pub const DIR_WALK: &'static str = "dir.walk";
 // file
 
 pub const FILE_GET_NAME: &'static str = "file.get_name";
 pub const FILE_GET_EXTENSION: &'static str = "file.get_extension";
+pub const FILE_DETECT_TYPE: &'static str = "file.detect_type";
 
 pub const FILE_INFO_BASIC: &'static str = "file.info.basic";
 pub const FILE_INFO_METADATA: &'static str = "file.info.metadata";
@@ -31,6 +33,10 @@ pub const FILE_WRITE: &'static str = "file.write";
 pub const FILE_COPY: &'static str = "file.copy";
 // FILES
 pub const FILES_CLEAR: &'static str = "files.clear";
+pub const FILES_DISK_USAGE: &'static str = "files.disk_usage";
+pub const FILES_FIND_DUPLICATES: &'static str = "files.find_duplicates";
+pub const FILES_RENAME_BATCH: &'static str = "files.rename_batch";
+pub const FILES_RENAME_UNDO: &'static str = "files.rename_undo";
 
 // file - convert
 pub const FILE_SVG_TO_PNG: &'static str = "file.svg_to_png";
@@ -43,6 +49,7 @@ pub const FILE_BINARY_SPLIT: &'static str = "file.binary.split";
 pub const FILE_BINARY_JOIN: &'static str = "file.binary.join";
 
 pub const FILE_HASH: &'static str = "file.hash";
+pub const FILE_HASH_VERIFY: &'static str = "file.hash.verify";
 
 // raw
 pub const IMAGE_SVG_TO_PNG: &'static str = "image.svg_to_png";
@@ -55,9 +62,33 @@ pub const I18N_LOAD_TRANSLATION: &'static str = "i18n.load.translation";
 // NOTE: prefix using tool_data. to avoid confusion with tool. (tool. for executing tool commands)
 pub const TOOL_DATA_GET_ALL_TOOLS: &'static str = "tool_data.get_all_tools";
 pub const TOOL_DATA_GET_BIN_PATH: &'static str = "tool_data.get_bin_path";
+// tool installation
+pub const TOOL_INSTALL: &'static str = "tool.install";
+pub const TOOL_UNINSTALL: &'static str = "tool.uninstall";
+pub const TOOL_VERIFY: &'static str = "tool.verify";
+
+// Functions
+pub const FUNCTIONS_VALIDATE: &'static str = "functions.validate";
+pub const FUNCTIONS_INSTALL: &'static str = "functions.install";
+pub const FUNCTIONS_UNINSTALL: &'static str = "functions.uninstall";
+pub const FUNCTIONS_EXPORT: &'static str = "functions.export";
+pub const FUNCTIONS_LIST_INSTALLED: &'static str = "functions.list_installed";
 //
 pub const HTTP_DOWNLOAD_FILE: &'static str = "http.download_file";
 pub const ZIP_UNZIP_FILE: &'static str = "zip.unzip_file";
+pub const ZIP_CREATE: &'static str = "zip.create";
+// archive: zip, tar, tar.gz, tar.zst, 7z (extract only)
+pub const ARCHIVE_CREATE: &'static str = "archive.create";
+pub const ARCHIVE_EXTRACT: &'static str = "archive.extract";
+pub const ARCHIVE_LIST: &'static str = "archive.list";
+
+// journal: undo of the mutating file commands
+pub const JOURNAL_LIST: &'static str = "journal.list";
+pub const JOURNAL_UNDO: &'static str = "journal.undo";
+
+// task: long-running commands
+pub const TASK_CANCEL: &'static str = "task.cancel";
+pub const TASK_LIST: &'static str = "task.list";
 
 //
 pub const URL_OPEN: &'static str = "url.open";
@@ -90,5 +121,7 @@ pub const TEMPLATE_GET_TEMPLATE_CONTENT: &'static str = "template.get_template_c
 //
 pub const UPDATER_UPDATE_WORKFLOWS: &'static str = "updater.update_functions";
 pub const UPDATER_UPDATE_I18N: &'static str = "updater.update_i18n";
+pub const UPDATER_ROLLBACK: &'static str = "updater.rollback";
+pub const UPDATER_CHECK: &'static str = "updater.check";
 pub const UPDATER_DOWNLOAD_APP_WINDOWS_INSTALLER: &'static str =
     "updater.download_app_windows_installer";
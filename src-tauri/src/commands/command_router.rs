@@ -3,12 +3,82 @@ use crate::commands::command_names as cmd_names;
 use crate::commands::structures::CommandResult;
 use crate::commands::tools_cmd::model_tool_mock_command_execution;
 use crate::errors::BoxedError;
-use crate::functions::tool::read::{check_tool_available, get_bin_path};
+use crate::functions::tool::read::{check_tool_available, get_bin_path, get_tool_status};
+use crate::functions::tool::structures::TOOL_STATUS_MISSING;
+use crate::thelib::task;
 use async_recursion::async_recursion;
 use serde_json::Value as JsonValue;
 
-#[async_recursion]
+// commands which may run for a long time, they are routed as cancellable tasks. the sync ones
+// also run on the blocking thread pool, see `route_command`
+const LONG_RUNNING_COMMANDS: &[&str] = &[
+    cmd_names::PATH_DELETE,
+    cmd_names::DIR_WALK,
+    cmd_names::FILE_EXIF_GET,
+    cmd_names::FILE_COUNT_FILES,
+    cmd_names::FILE_COPY,
+    cmd_names::FILES_CLEAR,
+    cmd_names::FILES_DISK_USAGE,
+    cmd_names::FILES_FIND_DUPLICATES,
+    cmd_names::FILES_RENAME_BATCH,
+    cmd_names::FILE_SVG_TO_PNG,
+    cmd_names::FILE_IMAGE_TO_SVG,
+    cmd_names::FILE_IMAGE_REMOVE_BACKGROUND,
+    cmd_names::FILE_IMAGE_PNG_OPTIMIZE,
+    cmd_names::FILE_BINARY_SPLIT,
+    cmd_names::FILE_BINARY_JOIN,
+    cmd_names::FILE_HASH,
+    cmd_names::FILE_HASH_VERIFY,
+    cmd_names::TOOL_INSTALL,
+    cmd_names::FUNCTIONS_INSTALL,
+    cmd_names::UPDATER_UPDATE_WORKFLOWS,
+    cmd_names::UPDATER_UPDATE_I18N,
+    cmd_names::UPDATER_CHECK,
+    cmd_names::UPDATER_DOWNLOAD_APP_WINDOWS_INSTALLER,
+    cmd_names::HTTP_DOWNLOAD_FILE,
+    cmd_names::ZIP_UNZIP_FILE,
+    cmd_names::ZIP_CREATE,
+    cmd_names::ARCHIVE_CREATE,
+    cmd_names::ARCHIVE_EXTRACT,
+    cmd_names::ARCHIVE_LIST,
+];
+
+fn is_long_running(command: &str) -> bool {
+    command.starts_with("shell.")
+        || command.starts_with("tool.exe.")
+        || LONG_RUNNING_COMMANDS.contains(&command)
+}
+
+/// route the command, a long-running command is run as a cancellable task. the task id is
+/// taken from `task_id` in params (so the caller can cancel it by `task.cancel`), or generated
+/// if not given. a generated id is sent in the `started` task progress event and set as
+/// `task_id` of the result
 pub async fn route(command: &str, params: &JsonValue) -> Result<CommandResult, BoxedError> {
+    if !is_long_running(command) {
+        return route_command(command, params).await;
+    }
+
+    let task_id = match params.get("task_id").and_then(|v| v.as_str()) {
+        Some(id) if !id.is_empty() => id.to_string(),
+        _ => uuid::Uuid::new_v4().to_string(),
+    };
+
+    let mut result = task::run_as_task(&task_id, command, route_command(command, params)).await?;
+    result.task_id = Some(task_id);
+    Ok(result)
+}
+
+/// run a sync command on the blocking thread pool, in the current task
+async fn run_blocking(
+    command: fn(&JsonValue) -> Result<CommandResult, BoxedError>,
+    params: &JsonValue,
+) -> Result<CommandResult, BoxedError> {
+    let params = params.clone();
+    task::run_blocking(move || command(&params)).await
+}
+
+#[async_recursion]
+async fn route_command(command: &str, params: &JsonValue) -> Result<CommandResult, BoxedError> {
     log::debug!("route - command: {}, params: {:?}", command, params);
 
     // shell.command
@@ -26,8 +96,19 @@ pub async fn route(command: &str, params: &JsonValue) -> Result<CommandResult, B
         };
 
         if !tool_exists {
-            log::error!("Tool `{}` not available.", name);
-            return Err(format!("Tool `{}` not available, please install it first.", name).into());
+            let status = match get_tool_status(&name) {
+                Ok((status, version)) if !version.is_empty() => {
+                    format!("{}, version {}", status, version)
+                }
+                Ok((status, _)) => status,
+                Err(_) => TOOL_STATUS_MISSING.to_string(),
+            };
+            log::error!("Tool `{}` not available: {}", name, status);
+            return Err(format!(
+                "Tool `{}` not available ({}), please install it first.",
+                name, status
+            )
+            .into());
         }
 
         let cmd = get_bin_path(&name)?;
@@ -57,38 +138,61 @@ pub async fn route(command: &str, params: &JsonValue) -> Result<CommandResult, B
         cmd_names::PATH_NEW_TEMP_FILE_PATH => commands::path_cmd::new_temp_file_path(&params),
         cmd_names::PATH_LOCATE => commands::path_cmd::locate_path(&params),
         cmd_names::PATH_LOCATE_APP_DATA_DIR => commands::path_cmd::locate_app_data_dir(&params),
-        cmd_names::PATH_DELETE => commands::path_cmd::delete_path(&params),
+        cmd_names::PATH_DELETE => run_blocking(commands::path_cmd::delete_path, params).await,
         cmd_names::PATH_RENAME => commands::file_cmd::rename_file(&params), // same as file.rename
 
         //
         cmd_names::DIR_LIST => commands::path_cmd::dir_list(&params),
+        cmd_names::DIR_WALK => run_blocking(commands::path_cmd::dir_walk, params).await,
 
         // FILE
         cmd_names::FILE_GET_NAME => commands::file_cmd::get_file_name(&params),
         cmd_names::FILE_GET_EXTENSION => commands::file_cmd::get_file_extension(&params),
+        cmd_names::FILE_DETECT_TYPE => commands::file_cmd::detect_file_type(&params),
         cmd_names::FILE_INFO_BASIC => commands::file_cmd::get_basic_info(&params),
         cmd_names::FILE_INFO_METADATA => commands::file_cmd::get_metadata_info(&params),
-        cmd_names::FILE_EXIF_GET => commands::exif_cmd::get_tags(&params),
-        cmd_names::FILE_COUNT_FILES => commands::file_cmd::count_files(&params),
+        cmd_names::FILE_EXIF_GET => run_blocking(commands::exif_cmd::get_tags, params).await,
+        cmd_names::FILE_COUNT_FILES => run_blocking(commands::file_cmd::count_files, params).await,
         //
         cmd_names::FILE_RENAME => commands::file_cmd::rename_file(&params),
         // FILE
         cmd_names::FILE_READ => commands::file_cmd::read_file(&params),
-        cmd_names::FILE_COPY => commands::file_cmd::copy_file(&params),
+        cmd_names::FILE_COPY => run_blocking(commands::file_cmd::copy_file, params).await,
         cmd_names::FILE_WRITE => commands::file_cmd::write_file(&params),
         // FILES
-        cmd_names::FILES_CLEAR => commands::file_cmd::clear_files(&params),
+        cmd_names::FILES_CLEAR => run_blocking(commands::file_cmd::clear_files, params).await,
+        cmd_names::FILES_DISK_USAGE => run_blocking(commands::file_cmd::disk_usage, params).await,
+        cmd_names::FILES_FIND_DUPLICATES => {
+            run_blocking(commands::file_cmd::find_duplicates, params).await
+        }
+        cmd_names::FILES_RENAME_BATCH => {
+            run_blocking(commands::file_cmd::rename_batch, params).await
+        }
+        cmd_names::FILES_RENAME_UNDO => commands::file_cmd::rename_undo(&params),
 
         //
-        cmd_names::FILE_SVG_TO_PNG => commands::image_cmd::file_svg_to_png(&params),
-        cmd_names::FILE_IMAGE_TO_SVG => commands::image_cmd::file_image_to_svg(&params),
-        cmd_names::FILE_IMAGE_REMOVE_BACKGROUND => commands::image_cmd::remove_background(&params),
-        cmd_names::FILE_IMAGE_PNG_OPTIMIZE => commands::image_cmd::png_optimize(&params),
+        cmd_names::FILE_SVG_TO_PNG => {
+            run_blocking(commands::image_cmd::file_svg_to_png, params).await
+        }
+        cmd_names::FILE_IMAGE_TO_SVG => {
+            run_blocking(commands::image_cmd::file_image_to_svg, params).await
+        }
+        cmd_names::FILE_IMAGE_REMOVE_BACKGROUND => {
+            run_blocking(commands::image_cmd::remove_background, params).await
+        }
+        cmd_names::FILE_IMAGE_PNG_OPTIMIZE => {
+            run_blocking(commands::image_cmd::png_optimize, params).await
+        }
         //
-        cmd_names::FILE_BINARY_SPLIT => commands::file_cmd::split_file_in_bytes(&params),
-        cmd_names::FILE_BINARY_JOIN => commands::file_cmd::join_files_in_bytes(params),
+        cmd_names::FILE_BINARY_SPLIT => {
+            run_blocking(commands::file_cmd::split_file_in_bytes, params).await
+        }
+        cmd_names::FILE_BINARY_JOIN => {
+            run_blocking(commands::file_cmd::join_files_in_bytes, params).await
+        }
         //
-        cmd_names::FILE_HASH => commands::file_cmd::hash_file(&params),
+        cmd_names::FILE_HASH => run_blocking(commands::file_cmd::hash_file, params).await,
+        cmd_names::FILE_HASH_VERIFY => run_blocking(commands::file_cmd::verify_hash, params).await,
 
         // text
         cmd_names::TEXT_REPLACE => commands::text_cmd::replace_text(&params),
@@ -119,6 +223,18 @@ pub async fn route(command: &str, params: &JsonValue) -> Result<CommandResult, B
         // tool data
         cmd_names::TOOL_DATA_GET_ALL_TOOLS => commands::tools_cmd::get_tools(),
         cmd_names::TOOL_DATA_GET_BIN_PATH => commands::tools_cmd::get_tool_bin_path(&params),
+        cmd_names::TOOL_INSTALL => commands::tools_cmd::install_tool(&params).await,
+        cmd_names::TOOL_UNINSTALL => commands::tools_cmd::uninstall_tool(&params),
+        cmd_names::TOOL_VERIFY => commands::tools_cmd::verify_tool_available(&params),
+
+        // functions
+        cmd_names::FUNCTIONS_VALIDATE => commands::functions_cmd::validate_function(&params),
+        cmd_names::FUNCTIONS_INSTALL => commands::functions_cmd::install_function(&params).await,
+        cmd_names::FUNCTIONS_UNINSTALL => {
+            commands::functions_cmd::uninstall_function_package(&params)
+        }
+        cmd_names::FUNCTIONS_EXPORT => commands::functions_cmd::export_function_package(&params),
+        cmd_names::FUNCTIONS_LIST_INSTALLED => commands::functions_cmd::list_installed_functions(),
 
         //
         cmd_names::IMAGE_SVG_TO_PNG => commands::image_cmd::raw_svg_to_png(&params),
@@ -133,13 +249,31 @@ pub async fn route(command: &str, params: &JsonValue) -> Result<CommandResult, B
         //
         cmd_names::UPDATER_UPDATE_WORKFLOWS => commands::updater_cmd::update_functions().await,
         cmd_names::UPDATER_UPDATE_I18N => commands::updater_cmd::update_i18n().await,
+        cmd_names::UPDATER_ROLLBACK => commands::updater_cmd::rollback(&params),
+        cmd_names::UPDATER_CHECK => commands::updater_cmd::check_updates().await,
         cmd_names::UPDATER_DOWNLOAD_APP_WINDOWS_INSTALLER => {
             commands::updater_cmd::download_app_windows_installer(&params).await
         }
 
         //
         cmd_names::HTTP_DOWNLOAD_FILE => commands::http_cmd::download_file(&params).await,
-        cmd_names::ZIP_UNZIP_FILE => commands::zip_cmd::unzip_file(&params),
+        cmd_names::ZIP_UNZIP_FILE => run_blocking(commands::zip_cmd::unzip_file, params).await,
+        cmd_names::ZIP_CREATE => run_blocking(commands::zip_cmd::create_zip, params).await,
+        cmd_names::ARCHIVE_CREATE => {
+            run_blocking(commands::archive_cmd::create_archive, params).await
+        }
+        cmd_names::ARCHIVE_EXTRACT => {
+            run_blocking(commands::archive_cmd::extract_archive, params).await
+        }
+        cmd_names::ARCHIVE_LIST => run_blocking(commands::archive_cmd::list_archive, params).await,
+
+        // journal
+        cmd_names::JOURNAL_LIST => commands::journal_cmd::list_entries(&params),
+        cmd_names::JOURNAL_UNDO => commands::journal_cmd::undo_entry(&params),
+
+        // task
+        cmd_names::TASK_CANCEL => commands::task_cmd::cancel_task(&params),
+        cmd_names::TASK_LIST => commands::task_cmd::list_tasks(),
 
         //
         _ => Err(format!("Unknown command: {}", command).into()),
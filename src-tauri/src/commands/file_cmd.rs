@@ -1,13 +1,23 @@
+use crate::app::resource::get_app_data_dir;
+use crate::commands::command_names as cmd_names;
+use crate::commands::journal_cmd::{open_journal, undo_entry};
+use crate::commands::path_cmd::{delete_path, DeleteReport, DELETE_ACTION_DELETE};
 use crate::commands::structures::CommandResult;
-use crate::commands::utils::get_string_val_from_params;
+use crate::commands::utils::{
+    get_conflict_policy, get_patterns_from_params, get_string_val_from_params,
+};
 use crate::errors::BoxedError;
 use crate::thelib;
 use crate::thelib::file_count;
-use crate::thelib::file_path::make_parent_dirs;
+use crate::thelib::file_rename::{self, RenameOptions};
+use crate::thelib::journal::{FileStamp, Journal, JournalEntry, JournalOp};
+use crate::thelib::path_matcher::PathMatcher;
+use crate::thelib::safe_write::{resolve_conflict, write_atomic};
+use crate::thelib::walk::{walk, SymlinkPolicy, WalkOptions};
 use base64::{engine::general_purpose::STANDARD as b64, Engine};
 use regex::Regex;
 use serde_json::{json, Value as JsonValue};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub fn rename_file(params: &JsonValue) -> Result<CommandResult, BoxedError> {
     let input_file = get_string_val_from_params(vec!["input_file", "input_path"], &params)?;
@@ -20,6 +30,20 @@ pub fn rename_file(params: &JsonValue) -> Result<CommandResult, BoxedError> {
         }
     }
 
+    // an existing target is overwritten, keep it for undo
+    let journal = open_journal()?;
+    let mut entry = journal.new_entry(cmd_names::FILE_RENAME);
+    let output_path = Path::new(&output_file);
+    let is_same_file = std::fs::canonicalize(&input_file).ok() == output_path.canonicalize().ok();
+    if output_path.is_file() && !is_same_file {
+        let backup = journal.backup(&entry, output_path, false)?;
+        entry.add(JournalOp::Write {
+            path: output_path.to_path_buf(),
+            backup: Some(backup),
+            written: None,
+        });
+    }
+
     match std::fs::rename(&input_file, &output_file) {
         Ok(_) => {} // do nothing
         Err(_) => {
@@ -29,6 +53,11 @@ pub fn rename_file(params: &JsonValue) -> Result<CommandResult, BoxedError> {
             std::fs::remove_file(&input_file)?;
         }
     };
+    entry.add(JournalOp::Rename {
+        from: PathBuf::from(&input_file),
+        to: output_path.to_path_buf(),
+    });
+    journal.save(&entry);
 
     let mut result = CommandResult::default();
     result.content = json!(true);
@@ -37,6 +66,100 @@ pub fn rename_file(params: &JsonValue) -> Result<CommandResult, BoxedError> {
     Ok(result)
 }
 
+/// rename paths by a template, e.g. `{stem}_{n:03}.{ext}`. params:
+/// - `input_paths`, `template`
+/// - `pattern` (optional): regex matched against the file name, capture groups can be used
+///   as `{1}` or `{name}`; paths not matched are skipped
+/// - `start` (optional): first value of `{n}`, default 1
+/// - `dry_run` (optional): only return the preview, default true
+///
+/// the renames are applied only if there is no conflict, and recorded in a journal
+/// which can be reverted by `files.rename_undo`
+pub fn rename_batch(params: &JsonValue) -> Result<CommandResult, BoxedError> {
+    let input_paths: Vec<PathBuf> = match params.get("input_paths") {
+        Some(v) => v
+            .as_array()
+            .ok_or("`input_paths` must be an array")?
+            .iter()
+            .filter_map(|p| p.as_str())
+            .filter(|p| !p.is_empty())
+            .map(PathBuf::from)
+            .collect(),
+        None => return Err("`input_paths` is required".into()),
+    };
+    let template = get_string_val_from_params(vec!["template"], params)?;
+
+    let mut options = RenameOptions::default();
+    if let Ok(pattern) = get_string_val_from_params(vec!["pattern", "regex"], params) {
+        options.pattern = match Regex::new(&pattern) {
+            Ok(r) => Some(r),
+            Err(e) => return Err(format!("Invalid pattern: '{}', error: {}", pattern, e).into()),
+        };
+    }
+    if let Some(v) = params["start"].as_u64() {
+        options.start = v;
+    }
+    let dry_run = params["dry_run"].as_bool().unwrap_or(true);
+
+    let items = file_rename::plan_renames(&input_paths, &template, &options, &mut |p: &Path| {
+        thelib::exiftool::get_file_metadata(&p.to_string_lossy())
+    })?;
+    let preview: Vec<JsonValue> = items.iter().map(|i| i.to_json()).collect();
+    let conflicts = items
+        .iter()
+        .filter(|i| i.status == file_rename::STATUS_CONFLICT)
+        .count();
+
+    let mut result = CommandResult::default();
+    if dry_run || conflicts > 0 {
+        result.content = json!({ "items": preview, "conflicts": conflicts, "applied": false });
+        if conflicts > 0 {
+            result.message = format!("{} conflicts, nothing renamed", conflicts);
+        }
+        return Ok(result);
+    }
+
+    let journal = open_journal()?;
+    let mut entry = journal.new_entry(cmd_names::FILES_RENAME_BATCH);
+    let done = file_rename::apply_renames(&items)?;
+    for (from, to) in &done {
+        result.add_output_path(&to.to_string_lossy());
+        entry.add(JournalOp::Rename {
+            from: from.clone(),
+            to: to.clone(),
+        });
+    }
+    journal.save(&entry);
+    let journal_id = if done.is_empty() {
+        None
+    } else {
+        Some(entry.id)
+    };
+    result.content = json!({
+        "items": preview,
+        "conflicts": 0,
+        "applied": true,
+        "journal_id": journal_id,
+    });
+    result.message = format!("{} paths renamed", done.len());
+
+    Ok(result)
+}
+
+/// revert a `files.rename_batch` run. `journal_id` (optional): default the latest run
+pub fn rename_undo(params: &JsonValue) -> Result<CommandResult, BoxedError> {
+    let journal = open_journal()?;
+    let journal_id = match get_string_val_from_params(vec!["journal_id", "id"], params) {
+        Ok(v) => v,
+        Err(_) => journal
+            .latest(cmd_names::FILES_RENAME_BATCH)
+            .map(|e| e.id)
+            .ok_or("No rename batch to undo")?,
+    };
+
+    undo_entry(&json!({ "id": journal_id }))
+}
+
 pub fn copy_file(params: &JsonValue) -> Result<CommandResult, BoxedError> {
     let input_file = get_string_val_from_params(vec!["input_file", "input_path"], &params)?;
     let output_file = get_string_val_from_params(vec!["output_file", "output_path"], &params)?;
@@ -57,7 +180,15 @@ pub fn copy_file(params: &JsonValue) -> Result<CommandResult, BoxedError> {
     Ok(result)
 }
 
-/// clear specific files by name or path patterns
+/// clear specific files by name or path patterns. params:
+/// - `input_paths`: dirs searched recursively, or files
+/// - `includes`: patterns of the files to delete, see `get_patterns_from_params`
+/// - `excludes` (optional): patterns of files or dirs to keep, a matched dir keeps all below it
+/// - `dry_run` (optional, default false): only report the matched files
+/// - `undoable` (optional, default false): move the files into the journal backups instead
+///   of deleting them, so `journal.undo` can restore them
+///
+/// content: see `DeleteReport`
 pub fn clear_files(params: &JsonValue) -> Result<CommandResult, BoxedError> {
     // get input paths
     let mut input_paths = match params.get("input_paths") {
@@ -100,130 +231,245 @@ pub fn clear_files(params: &JsonValue) -> Result<CommandResult, BoxedError> {
         .collect();
 
     // get patterns
-    let include_name_patterns = match params["includes"]["name_patterns"].as_array() {
-        Some(v) => v.clone(),
-        None => Vec::new(),
-    };
-    let include_path_patterns = match params["includes"]["path_patterns"].as_array() {
-        Some(v) => v.clone(),
-        None => Vec::new(),
+    let matcher = PathMatcher {
+        includes: get_patterns_from_params(&params["includes"])?,
+        excludes: get_patterns_from_params(&params["excludes"])?,
     };
 
-    let mut include_name_matchers: Vec<Regex> = Vec::new();
-    if !include_name_patterns.is_empty() {
-        for pattern in &include_name_patterns {
-            let pat = pattern.as_str().unwrap_or("");
-            if pat.is_empty() {
-                return Err(format!("Invalid include name patterns:{:?}", pattern).into());
-            }
-            let matcher = match Regex::new(pat) {
-                Ok(v) => v,
-                Err(e) => {
-                    return Err(format!(
-                        "Invalid include name patterns:{:?},error:{:?}",
-                        pattern, e
-                    )
-                    .into());
-                }
-            };
-            include_name_matchers.push(matcher);
+    let dry_run = params["dry_run"].as_bool().unwrap_or(false);
+    let undoable = params["undoable"].as_bool().unwrap_or(false);
+
+    // the journal is only opened when the files are kept for undo
+    let mut undo = match undoable && !dry_run {
+        true => {
+            let journal = open_journal()?;
+            let entry = journal.new_entry(cmd_names::FILES_CLEAR);
+            Some((journal, entry))
         }
-    }
-    let mut include_path_matchers: Vec<Regex> = Vec::new();
-    if !include_path_patterns.is_empty() {
-        for pattern in &include_path_patterns {
-            let pat = pattern.as_str().unwrap_or("");
-            if pat.is_empty() {
-                return Err(format!("Invalid include path patterns:{:?}", pattern).into());
-            }
-            let matcher = match Regex::new(pat) {
-                Ok(v) => v,
-                Err(e) => {
-                    return Err(format!(
-                        "Invalid include path patterns:{:?},error:{:?}",
-                        pattern, e
-                    )
-                    .into());
-                }
-            };
-            include_path_matchers.push(matcher);
+        false => None,
+    };
+    let mut report = DeleteReport::new(dry_run);
+    let mut rst: Result<(), BoxedError> = Ok(());
+    for path_str in &input_paths_arr {
+        rst = clear_files_in(
+            Path::new(path_str),
+            &matcher,
+            undo.as_mut().map(|(journal, entry)| (&*journal, entry)),
+            &mut report,
+        );
+        if rst.is_err() {
+            break;
         }
     }
+    if let Some((journal, entry)) = &undo {
+        journal.save(entry);
+    }
+    rst?;
 
-    let mut count_deleted = 0;
+    Ok(report.into_result())
+}
 
-    for path_str in &input_paths_arr {
-        let path = std::path::Path::new(&path_str);
-        if !path.exists() {
+/// delete the files matched in `path`, recursively.
+/// with a journal entry they are moved into its backups instead
+fn clear_files_in(
+    path: &Path,
+    matcher: &PathMatcher,
+    mut undo: Option<(&Journal, &mut JournalEntry)>,
+    report: &mut DeleteReport,
+) -> Result<(), BoxedError> {
+    // without include patterns nothing is deleted
+    if matcher.includes.is_empty() {
+        return Ok(());
+    }
+    let root = path.to_str().unwrap_or("");
+    let options = WalkOptions {
+        dirs: false,
+        ..Default::default()
+    };
+    for walk_entry in walk(path, &options) {
+        let walk_entry = walk_entry?;
+        // links and special files are never deleted
+        if !walk_entry.is_file() {
             continue;
         }
-
-        if path.is_file() {
-            let filename = match path.file_name() {
-                Some(v) => v.to_str().unwrap_or(""),
-                None => "",
-            };
-            if filename.is_empty() {
-                continue;
+        if walk_entry.rel_path.is_empty() {
+            // `path` is a file itself
+            let name = walk_entry.path.file_name().and_then(|n| n.to_str());
+            let parent = walk_entry.path.parent().and_then(|p| p.to_str());
+            match (parent, name) {
+                (Some(parent), Some(name)) if matcher.is_match(parent, name) => {}
+                _ => continue,
             }
-            let mut is_match = false;
-            for matcher in &include_name_matchers {
-                if matcher.is_match(filename) {
-                    is_match = true;
-                    break;
-                }
+        } else if !matcher.is_match(root, &walk_entry.rel_path) {
+            continue;
+        }
+
+        let outcome = match (report.is_dry_run(), undo.as_mut()) {
+            (true, _) => None,
+            (false, Some((journal, entry))) => {
+                Some(journal.backup(entry, &walk_entry.path, true).map(|backup| {
+                    entry.add(JournalOp::Delete {
+                        path: walk_entry.path.clone(),
+                        backup,
+                    });
+                }))
             }
-            if !is_match {
-                for matcher in &include_path_matchers {
-                    if matcher.is_match(&path_str) {
-                        is_match = true;
-                        break;
-                    }
-                }
+            (false, None) => Some(std::fs::remove_file(&walk_entry.path).map_err(BoxedError::from)),
+        };
+        report.add(
+            &walk_entry.path,
+            walk_entry.size,
+            DELETE_ACTION_DELETE,
+            outcome,
+        );
+    }
+
+    Ok(())
+}
+
+/// find duplicate files in `input_paths`, grouped by content.
+/// optional `action` for the copies (the `keep` file of each group is kept, "oldest" or "newest"):
+/// - "none" (default): only report
+/// - "trash": move the copies to trash in one `path.delete`, copies in the app data dir
+///   can't be trashed and are reported as failed
+/// - "hardlink": replace the copies with hardlinks to the kept file, the copies are kept
+///   in the journal for undo
+///
+/// `hidden`, `gitignore` and `symlinks` (optional) are the walk options, see `dir.walk`
+pub fn find_duplicates(params: &JsonValue) -> Result<CommandResult, BoxedError> {
+    let input_paths = match &params.get("input_paths") {
+        Some(v) => v.as_array().ok_or("`input_paths` must be an array")?,
+        None => return Err("`input_paths` is required".into()),
+    };
+    let input_paths_arr: Vec<&str> = input_paths
+        .iter()
+        .map(|p| p.as_str().unwrap_or(""))
+        .filter(|p| !p.is_empty())
+        .collect();
+
+    let min_size: u64 = match params.get("min_size") {
+        Some(v) => v.as_u64().ok_or("min_size must be an integer")?,
+        None => 1, // ignore empty files by default
+    };
+    let action = match params.get("action") {
+        Some(v) => v.as_str().ok_or("action must be a string")?,
+        None => "none",
+    };
+    if !["none", "trash", "hardlink"].contains(&action) {
+        return Err(format!(
+            "Invalid action: '{}'. Must be 'none', 'trash' or 'hardlink'",
+            action
+        )
+        .into());
+    }
+    let keep = match params.get("keep") {
+        Some(v) => v.as_str().ok_or("keep must be a string")?,
+        None => "oldest",
+    };
+
+    let mut options = WalkOptions {
+        min_size: Some(min_size),
+        hidden: params["hidden"].as_bool().unwrap_or(true),
+        gitignore: params["gitignore"].as_bool().unwrap_or(false),
+        ..Default::default()
+    };
+    if let Some(v) = params["symlinks"].as_str() {
+        options.symlinks = SymlinkPolicy::from_name(v)?;
+    }
+
+    let groups = thelib::file_duplicate::find_duplicates(input_paths_arr, &options)?;
+
+    let total_wasted_bytes: u64 = groups.iter().map(|g| g.wasted_bytes()).sum();
+    let duplicate_file_count: usize = groups.iter().map(|g| g.files.len() - 1).sum();
+
+    // (copy, kept) of all groups
+    let mut copies: Vec<(PathBuf, PathBuf)> = Vec::new();
+    if action != "none" {
+        for group in &groups {
+            let (kept, group_copies) = thelib::file_duplicate::split_keep_and_copies(group, keep)?;
+            for copy in group_copies {
+                copies.push((copy.path, kept.path.clone()));
             }
+        }
+    }
 
-            if is_match {
-                std::fs::remove_file(path)?;
-                count_deleted += 1;
+    let mut processed: Vec<JsonValue> = Vec::new();
+    let mut failed: Vec<JsonValue> = Vec::new();
+    let mut add_outcome = |copy: &Path, kept: &Path, outcome: Result<(), String>| {
+        let copy_str = copy.to_string_lossy().to_string();
+        match outcome {
+            Ok(_) => processed.push(json!({ "path": copy_str, "kept": kept.to_string_lossy() })),
+            Err(e) => {
+                log::error!("Failed to {} duplicate file {}: {}", action, copy_str, e);
+                failed.push(json!({ "path": copy_str, "error": e }));
+            }
+        }
+    };
+    match action {
+        "trash" if !copies.is_empty() => {
+            // `path.delete` deletes paths in the app data dir for good, they are left alone
+            let app_data_dir = get_app_data_dir()?;
+            let (in_app_data, to_trash): (Vec<_>, Vec<_>) = copies
+                .into_iter()
+                .partition(|(copy, _)| copy.starts_with(&app_data_dir));
+            for (copy, kept) in &in_app_data {
+                add_outcome(
+                    copy,
+                    kept,
+                    Err("In the app data dir, can't be moved to trash".into()),
+                );
             }
-        } else if path.is_dir() {
-            let entries = match std::fs::read_dir(path) {
-                Ok(v) => v,
-                Err(e) => {
-                    log::error!("Error reading directory:{:?},error:{:?}", path, e);
-                    continue;
-                }
-            };
 
-            for entry in entries {
-                let entry = match entry {
-                    Ok(v) => v,
-                    Err(e) => {
-                        log::error!("Error reading entry:{:?}", e);
-                        continue;
-                    }
-                };
-                let entry_path = entry.path();
-                let entry_path_str = entry_path.to_str().unwrap_or("");
-                if entry_path_str.is_empty() {
-                    continue;
-                }
-                let sub_params = json!({
-                    "input_paths": [entry_path_str],
-                    "include": {
-                        "name_patterns": include_name_patterns.clone(),
-                        "path_patterns": include_path_patterns.clone(),
+            // one call, so one journal entry for the whole run
+            let paths: Vec<String> = to_trash
+                .iter()
+                .map(|(copy, _)| copy.to_string_lossy().to_string())
+                .collect();
+            let report = delete_path(&json!({ "input_paths": paths }))?;
+            let empty = vec![];
+            let items = report.content["items"].as_array().unwrap_or(&empty);
+            for ((copy, kept), path) in to_trash.iter().zip(&paths) {
+                let item = items.iter().find(|i| i["path"].as_str() == Some(path));
+                let outcome = match item {
+                    Some(i) => match i["error"].as_str() {
+                        Some(e) => Err(e.to_string()),
+                        None => Ok(()),
                     },
-                });
-                let sub_rst = clear_files(&sub_params)?;
-                count_deleted += sub_rst.content.as_u64().unwrap_or(0);
+                    None => Err("Not found".to_string()),
+                };
+                add_outcome(copy, kept, outcome);
+            }
+        }
+        "hardlink" if !copies.is_empty() => {
+            let journal = open_journal()?;
+            let mut entry = journal.new_entry(cmd_names::FILES_FIND_DUPLICATES);
+            for (copy, kept) in &copies {
+                let outcome = thelib::file_duplicate::replace_with_hardlink(
+                    kept,
+                    copy,
+                    Some((&journal, &mut entry)),
+                );
+                add_outcome(copy, kept, outcome.map_err(|e| e.to_string()));
             }
+            journal.save(&entry);
         }
+        _ => {}
     }
 
     let mut result = CommandResult::default();
-    result.content = json!(count_deleted);
-    result.message = format!("{}", count_deleted);
+    result.content = json!({
+        "groups": groups.iter().map(|g| g.to_json()).collect::<Vec<JsonValue>>(),
+        "group_count": groups.len(),
+        "duplicate_file_count": duplicate_file_count,
+        "total_wasted_bytes": total_wasted_bytes,
+        "action": action,
+        "processed": processed,
+        "failed": failed,
+    });
+    result.message = format!(
+        "{} duplicate files, {} bytes wasted",
+        duplicate_file_count, total_wasted_bytes
+    );
 
     Ok(result)
 }
@@ -310,7 +556,42 @@ pub fn join_files_in_bytes(params: &JsonValue) -> Result<CommandResult, BoxedErr
         }
     }
 
-    let actual_output_file = thelib::file_binary::join_in_bytes(&input_files_arr, &output_file)?;
+    // the existing output file is replaced, keep a copy for undo
+    let journal = open_journal()?;
+    let mut entry = journal.new_entry(cmd_names::FILE_BINARY_JOIN);
+    let output_path = Path::new(&output_file);
+    let backup = if output_path.is_file() {
+        Some(journal.backup(&entry, output_path, false)?)
+    } else {
+        None
+    };
+
+    let actual_output_file =
+        match thelib::file_binary::join_in_bytes(&input_files_arr, &output_file) {
+            Ok(f) => f,
+            Err(e) => {
+                // put the previous output back over the partial one
+                if let Some(backup) = &backup {
+                    match std::fs::copy(backup, output_path) {
+                        // an entry without ops drops its backups
+                        Ok(_) => journal.save(&entry),
+                        Err(copy_err) => log::error!(
+                            "Failed to restore {} from {}: {}",
+                            output_file,
+                            backup.display(),
+                            copy_err
+                        ),
+                    }
+                }
+                return Err(e);
+            }
+        };
+    entry.add(JournalOp::Write {
+        path: PathBuf::from(&actual_output_file),
+        backup,
+        written: FileStamp::of(&actual_output_file).ok(),
+    });
+    journal.save(&entry);
 
     let mut result = CommandResult::default();
     result.add_output_path(&actual_output_file);
@@ -342,6 +623,24 @@ pub fn count_files(params: &JsonValue) -> Result<CommandResult, BoxedError> {
     Ok(result)
 }
 
+/// disk usage of a dir. params:
+/// - `input_dir`
+/// - `max_depth` (optional, default 3): depth of the dir tree, sizes include all levels
+/// - `top` (optional, default 20): number of the largest files and dirs
+///
+/// sizes are allocated bytes where available, `apparent_size` the sum of file lengths
+pub fn disk_usage(params: &JsonValue) -> Result<CommandResult, BoxedError> {
+    let input_dir = get_string_val_from_params(vec!["input_dir", "input_path"], params)?;
+    let max_depth = params["max_depth"].as_u64().unwrap_or(3) as usize;
+    let top = params["top"].as_u64().unwrap_or(20) as usize;
+
+    let usage = thelib::disk_usage::DiskUsage::scan(&input_dir, max_depth, top)?;
+
+    let mut result = CommandResult::default();
+    result.content = usage.to_json();
+    Ok(result)
+}
+
 pub fn get_file_name(params: &JsonValue) -> Result<CommandResult, BoxedError> {
     let input_file = get_string_val_from_params(vec!["input_file", "input_path"], &params)?;
 
@@ -366,6 +665,36 @@ pub fn get_file_extension(params: &JsonValue) -> Result<CommandResult, BoxedErro
     Ok(result)
 }
 
+/// detect the file type from the content. content is `{mime, ext, confidence, name_ext, mismatch}`,
+/// mime, ext and confidence are null if unknown; mismatch is true if the detected type
+/// overrides the name's extension, see `FileKind::of_file`
+pub fn detect_file_type(params: &JsonValue) -> Result<CommandResult, BoxedError> {
+    let input_file = get_string_val_from_params(vec!["input_file", "input_path"], &params)?;
+    if !Path::new(&input_file).is_file() {
+        return Err(format!("File not found: '{}'", &input_file).into());
+    }
+
+    let detected = thelib::file_type::detect_file_type(&input_file)?;
+    let (_, _, name_ext) = thelib::file_path::split_file_path(&input_file);
+    let name_ext = name_ext.to_lowercase();
+    let mismatch = match &detected {
+        Some(d) if d.confidence >= thelib::file_type::TRUSTED_CONFIDENCE => {
+            thelib::file_type::overrides_ext(&name_ext, d)
+        }
+        _ => false,
+    };
+
+    let mut result = CommandResult::default();
+    result.content = json!({
+        "mime": detected.as_ref().map(|d| d.mime),
+        "ext": detected.as_ref().map(|d| d.ext),
+        "confidence": detected.as_ref().map(|d| d.confidence),
+        "name_ext": name_ext,
+        "mismatch": mismatch,
+    });
+    Ok(result)
+}
+
 pub fn read_file(params: &JsonValue) -> Result<CommandResult, BoxedError> {
     let input_file = get_string_val_from_params(vec!["input_file", "input_path"], &params)?;
     if !Path::new(&input_file).is_file() {
@@ -406,6 +735,9 @@ pub fn read_file(params: &JsonValue) -> Result<CommandResult, BoxedError> {
     Ok(result)
 }
 
+/// write `content` (`format`: text, base64 or bytes) to `output_file` atomically.
+/// `conflict` (optional): overwrite (default), skip, rename or error if the file exists;
+/// the status is "ok", "overwritten", "skipped" or "renamed"
 pub fn write_file(params: &JsonValue) -> Result<CommandResult, BoxedError> {
     let output_file = get_string_val_from_params(vec!["output_file", "output_path"], &params)?;
 
@@ -419,26 +751,21 @@ pub fn write_file(params: &JsonValue) -> Result<CommandResult, BoxedError> {
         None => return Err("content is required".into()),
     };
 
-    // write to file
-    make_parent_dirs(&output_file)?;
-
-    match format {
+    let bytes: Vec<u8> = match format {
         "text" => {
             let content = content_json.as_str().ok_or("content must be a string")?;
-            std::fs::write(&output_file, content)?;
+            content.as_bytes().to_vec()
         }
         "base64" => {
             let content = content_json.as_str().ok_or("content must be a string")?;
-            let bytes = b64.decode(content.as_bytes())?;
-            std::fs::write(&output_file, bytes)?;
+            b64.decode(content.as_bytes())?
         }
         "bytes" => {
             let content = content_json.as_array().ok_or("content must be an array")?;
-            let bytes: Vec<u8> = content
+            content
                 .iter()
                 .map(|v| v.as_u64().unwrap_or(0) as u8)
-                .collect();
-            std::fs::write(&output_file, bytes)?;
+                .collect()
         }
         _ => {
             return Err(format!(
@@ -447,33 +774,269 @@ pub fn write_file(params: &JsonValue) -> Result<CommandResult, BoxedError> {
             )
             .into())
         }
-    }
+    };
 
+    let conflict = get_conflict_policy(params)?;
+    let outcome = resolve_conflict(Path::new(&output_file), conflict)?;
     let mut result = CommandResult::default();
+    result.status = outcome.status.to_string();
+    if outcome.is_skipped() {
+        result.content = json!(false);
+        result.message = format!("File exists, skipped: {}", output_file);
+        return Ok(result);
+    }
+
+    // keep the previous content for undo
+    let journal = open_journal()?;
+    let mut entry = journal.new_entry(cmd_names::FILE_WRITE);
+    let backup = if outcome.path.is_file() {
+        Some(journal.backup(&entry, &outcome.path, false)?)
+    } else {
+        None
+    };
+
+    write_atomic(&outcome.path, &bytes)?;
+    entry.add(JournalOp::Write {
+        path: outcome.path.clone(),
+        backup,
+        written: FileStamp::of(&outcome.path).ok(),
+    });
+    journal.save(&entry);
+
     result.content = json!(true);
-    result.add_output_path(&output_file);
+    result.add_output_path(&outcome.path.to_string_lossy());
 
     Ok(result)
 }
 
+/// hash a file with one (`hash_type`) or several (`hash_types`) algorithms in one pass.
+/// content is the digest string for `hash_type`, or an object of type -> digest for `hash_types`
 pub fn hash_file(params: &JsonValue) -> Result<CommandResult, BoxedError> {
     let input_file = get_string_val_from_params(vec!["input_file", "input_path"], &params)?;
 
-    let hash_type = match params.get("hash_type") {
-        Some(v) => v.as_str().ok_or("hash_type must be a string")?,
-        None => "md5",
+    let hash_types: Option<Vec<String>> = match params.get("hash_types") {
+        Some(v) => {
+            let arr = v.as_array().ok_or("hash_types must be an array")?;
+            let mut types = Vec::new();
+            for t in arr {
+                types.push(
+                    t.as_str()
+                        .ok_or("hash_types must be strings")?
+                        .to_lowercase(),
+                );
+            }
+            Some(types)
+        }
+        None => None,
     };
 
-    let hash = match hash_type {
-        "md5" => thelib::hash::calc_md5(&std::fs::read(&input_file)?),
-        "sha1" => thelib::hash::calc_sha1(&std::fs::read(&input_file)?),
-        "sha256" => thelib::hash::calc_sha256(&std::fs::read(&input_file)?),
-        "sha512" => thelib::hash::calc_sha512(&std::fs::read(&input_file)?),
-        _ => return Err(format!("Invalid hash type: '{}'", hash_type).into()),
+    let mut result = CommandResult::default();
+    match hash_types {
+        Some(types) => {
+            let types: Vec<&str> = types.iter().map(|t| t.as_str()).collect();
+            let digests = thelib::hash::hash_file(&input_file, &types)?;
+            result.content = json!(digests);
+        }
+        None => {
+            let hash_type = match params.get("hash_type") {
+                Some(v) => v.as_str().ok_or("hash_type must be a string")?,
+                None => "md5",
+            };
+            let hash_type = hash_type.to_lowercase();
+            let mut digests = thelib::hash::hash_file(&input_file, &[hash_type.as_str()])?;
+            result.content = json!(digests.remove(&hash_type).unwrap_or_default());
+        }
+    }
+
+    Ok(result)
+}
+
+/// verify files against checksum files, each file is reported as `match`, `mismatch` or `missing`
+/// (files without any checksum are reported as `no_checksum`, files whose hash type can't be
+/// guessed as `unknown_type`).
+/// params:
+/// - `checksum_file`: a SHA256SUMS-style manifest or a sidecar file (e.g. `a.zip.sha256`)
+/// - `input_paths` (or `input_file`): files to verify by their sidecar files, if no `checksum_file`
+/// - `hash_type` (optional): force the hash type instead of guessing from file name or digest length
+pub fn verify_hash(params: &JsonValue) -> Result<CommandResult, BoxedError> {
+    let forced_hash_type: Option<String> = match params.get("hash_type") {
+        Some(v) => Some(
+            v.as_str()
+                .ok_or("hash_type must be a string")?
+                .to_lowercase(),
+        ),
+        None => None,
     };
 
+    // (file path, hash type, expected digest), hash type is empty if unknown
+    let mut checks: Vec<(PathBuf, String, String)> = Vec::new();
+    let mut no_checksum_paths: Vec<String> = Vec::new();
+
+    match params.get("checksum_file") {
+        Some(v) => {
+            let checksum_file = v.as_str().ok_or("checksum_file must be a string")?;
+            checks = read_checksum_file(Path::new(checksum_file), &forced_hash_type)?;
+        }
+        None => {
+            let mut input_paths: Vec<String> = match params.get("input_paths") {
+                Some(v) => v
+                    .as_array()
+                    .ok_or("input_paths must be an array")?
+                    .iter()
+                    .map(|p| p.as_str().unwrap_or("").to_string())
+                    .filter(|p| !p.is_empty())
+                    .collect(),
+                None => vec![],
+            };
+            if input_paths.is_empty() {
+                input_paths.push(get_string_val_from_params(
+                    vec!["input_file", "input_path"],
+                    &params,
+                )?);
+            }
+
+            for input_path in &input_paths {
+                let sidecar = thelib::hash::SUPPORTED_HASH_TYPES
+                    .iter()
+                    .map(|t| PathBuf::from(format!("{}.{}", input_path, t)))
+                    .find(|p| p.is_file());
+                match sidecar {
+                    Some(sidecar) => {
+                        let mut sidecar_checks = read_checksum_file(&sidecar, &forced_hash_type)?;
+                        // a sidecar belongs to its data file, whatever name is written inside
+                        for check in sidecar_checks.iter_mut() {
+                            check.0 = PathBuf::from(input_path);
+                        }
+                        checks.extend(sidecar_checks);
+                    }
+                    None => no_checksum_paths.push(input_path.to_string()),
+                }
+            }
+        }
+    }
+
+    let mut items: Vec<JsonValue> = Vec::new();
+    let (mut count_match, mut count_mismatch, mut count_missing) = (0, 0, 0);
+    let mut count_unknown_type = 0;
+    for (path, hash_type, expected) in &checks {
+        let path_str = path.to_string_lossy().to_string();
+        if !path.is_file() {
+            count_missing += 1;
+            items.push(json!({
+                "path": path_str,
+                "hash_type": hash_type,
+                "expected": expected,
+                "actual": "",
+                "status": "missing",
+            }));
+            continue;
+        }
+        if hash_type.is_empty() {
+            // e.g. a 64 chars digest, sha256 or blake3
+            count_unknown_type += 1;
+            items.push(json!({
+                "path": path_str,
+                "hash_type": "",
+                "expected": expected,
+                "actual": "",
+                "status": "unknown_type",
+            }));
+            continue;
+        }
+
+        let mut digests = thelib::hash::hash_file(&path, &[hash_type.as_str()])?;
+        let actual = digests.remove(hash_type).unwrap_or_default();
+        let status = if &actual == expected {
+            count_match += 1;
+            "match"
+        } else {
+            count_mismatch += 1;
+            "mismatch"
+        };
+        items.push(json!({
+            "path": path_str,
+            "hash_type": hash_type,
+            "expected": expected,
+            "actual": actual,
+            "status": status,
+        }));
+    }
+    for path in &no_checksum_paths {
+        items.push(json!({
+            "path": path,
+            "hash_type": "",
+            "expected": "",
+            "actual": "",
+            "status": "no_checksum",
+        }));
+    }
+
     let mut result = CommandResult::default();
-    result.content = json!(hash);
+    result.content = json!({
+        "items": items,
+        "matched": count_match,
+        "mismatched": count_mismatch,
+        "missing": count_missing,
+        "no_checksum": no_checksum_paths.len(),
+        "unknown_type": count_unknown_type,
+    });
+    result.message = format!(
+        "{} matched, {} mismatched, {} missing",
+        count_match, count_mismatch, count_missing
+    );
+    if count_unknown_type > 0 {
+        result.message += &format!(
+            ", {} of unknown hash type, set `hash_type`",
+            count_unknown_type
+        );
+    }
+    if count_mismatch > 0 || count_missing > 0 || count_unknown_type > 0 {
+        result.status = "error".to_string();
+    }
 
     Ok(result)
 }
+
+/// read a checksum manifest or sidecar file, returns (file path, hash type, expected digest) list.
+/// relative file names are resolved against the checksum file's directory
+fn read_checksum_file(
+    checksum_file: &Path,
+    forced_hash_type: &Option<String>,
+) -> Result<Vec<(PathBuf, String, String)>, BoxedError> {
+    let content = match std::fs::read_to_string(checksum_file) {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(format!("Failed to read checksum file {:?}: {}", checksum_file, e).into())
+        }
+    };
+    let base_dir = checksum_file.parent().unwrap_or(Path::new(""));
+    let checksum_file_name = checksum_file
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let type_by_file_name = thelib::hash::guess_hash_type_by_file_name(&checksum_file_name);
+
+    let mut checks = Vec::new();
+    for entry in thelib::hash::parse_checksum_content(&content) {
+        let path = if entry.file_name.is_empty() {
+            // bare digest sidecar, e.g. `a.zip.sha256` -> `a.zip`
+            checksum_file.with_extension("")
+        } else {
+            base_dir.join(&entry.file_name)
+        };
+        let hash_type = forced_hash_type
+            .clone()
+            .or(entry.hash_type.clone())
+            .or(type_by_file_name.clone())
+            .or(thelib::hash::guess_hash_type_by_digest(&entry.digest))
+            .unwrap_or_default();
+        checks.push((path, hash_type, entry.digest));
+    }
+
+    if checks.is_empty() {
+        return Err(format!("No checksum found in file: {:?}", checksum_file).into());
+    }
+
+    Ok(checks)
+}
@@ -0,0 +1,157 @@
+use crate::app::resource::get_app_download_dir;
+use crate::commands::structures::CommandResult;
+use crate::commands::updater_cmd::fetch_file;
+use crate::commands::utils::get_string_val_from_params;
+use crate::errors::BoxedError;
+use crate::functions::common::config::{function_type_of_category, validate_function_dir};
+use crate::functions::common::package::{
+    export_function, find_function_dir, install_package, load_registry, uninstall_function,
+    PACKAGE_EXT,
+};
+use crate::functions::watcher::reload_functions;
+use crate::thelib::file_path::get_unique_filepath;
+use crate::thelib::json::load_json_file;
+use serde_json::{json, Value as JsonValue};
+use std::path::{Path, PathBuf};
+
+/// Check the config.json and worker file in a function dir, like one being developed.
+/// The function type is read from the parent dir name, e.g. "executables", when it is one
+pub fn validate_function(params: &JsonValue) -> Result<CommandResult, BoxedError> {
+    let input_dir = get_string_val_from_params(vec!["input_dir", "dir"], params)?;
+    let dir = Path::new(&input_dir);
+    if !dir.is_dir() {
+        return Err(format!("Not a directory: {}", input_dir).into());
+    }
+
+    let expected_type = dir
+        .parent()
+        .and_then(|p| p.file_name())
+        .and_then(|n| function_type_of_category(&n.to_string_lossy()));
+    let errors = validate_function_dir(dir, expected_type);
+
+    let mut result = CommandResult::default();
+    if !errors.is_empty() {
+        result.message = format!("Found {} errors in the function config", errors.len());
+    }
+    result.content = json!({
+        "valid": errors.is_empty(),
+        "expected_type": expected_type,
+        "errors": errors,
+    });
+
+    Ok(result)
+}
+
+/// Install a function package (.ftfunc) into the user function dir.
+/// params:
+/// - `input_file`: a local package, or `url`: http(s) or `file://`, with its `sha256`
+/// - `force`: replace an installed function of the same or a newer version,
+///   or override an official function
+pub async fn install_function(params: &JsonValue) -> Result<CommandResult, BoxedError> {
+    let force = params["force"].as_bool().unwrap_or(false);
+
+    // `.ok()` so no error is held across the await below
+    let input_file = get_string_val_from_params(vec!["input_file", "file"], params).ok();
+    let (package_file, source, is_downloaded) = match input_file {
+        Some(f) => (PathBuf::from(&f), f, false),
+        None => {
+            let url = get_string_val_from_params(vec!["url"], params)?;
+            // a package runs as a function, so it must be the one expected
+            let sha256 = get_string_val_from_params(vec!["sha256"], params)
+                .map_err(|_| "`sha256` of the package is required to install from a url")?;
+            let downloaded_file =
+                get_app_download_dir()?.join(format!("{}.{}", uuid::Uuid::new_v4(), PACKAGE_EXT));
+            fetch_file(&url, &downloaded_file, Some(sha256.as_str()), None).await?;
+            (downloaded_file, url, true)
+        }
+    };
+
+    let installed = install_package(&package_file, &source, force);
+    if is_downloaded && package_file.exists() {
+        let _ = std::fs::remove_file(&package_file);
+    }
+    let installed = installed?;
+
+    // load it now, the watcher would only pick it up on its next poll
+    let report = reload_functions()?;
+
+    let mut result = CommandResult::default();
+    result.message = format!(
+        "Installed {} version {}",
+        installed.full_name(),
+        installed.version
+    );
+    result.add_output_path(&installed.dir);
+    result.content = json!({
+        "installed": installed,
+        "load_report": report,
+    });
+
+    Ok(result)
+}
+
+/// Remove a function installed from a package.
+/// params: `name`: like "file.image.blur"
+pub fn uninstall_function_package(params: &JsonValue) -> Result<CommandResult, BoxedError> {
+    let name = get_string_val_from_params(vec!["name", "function_name"], params)?;
+    let removed = uninstall_function(&name)?;
+    let report = reload_functions()?;
+
+    let mut result = CommandResult::default();
+    result.message = format!("Uninstalled {}", removed.full_name());
+    result.content = json!({
+        "uninstalled": removed,
+        "load_report": report,
+    });
+
+    Ok(result)
+}
+
+/// Pack a function into a .ftfunc file, from the dir it is loaded from.
+/// params:
+/// - `name`: like "file.image.blur"
+/// - `output_file`, or `output_dir` for `<name>-<version>.ftfunc` in it
+pub fn export_function_package(params: &JsonValue) -> Result<CommandResult, BoxedError> {
+    let name = get_string_val_from_params(vec!["name", "function_name"], params)?;
+    let (func_type, func_dir) = find_function_dir(&name)?;
+
+    let output_file = match get_string_val_from_params(vec!["output_file"], params) {
+        Ok(f) => f,
+        Err(_) => {
+            let output_dir = get_string_val_from_params(vec!["output_dir"], params)?;
+            let config = load_json_file(func_dir.join("config.json"))?;
+            let version = config["profile"]["version"].as_str().unwrap_or("");
+            let dir_name = func_dir
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            let stem = match version.is_empty() {
+                true => dir_name,
+                false => format!("{}-{}", dir_name, version),
+            };
+            get_unique_filepath(
+                &Path::new(&output_dir).join(&stem).to_string_lossy(),
+                Some(&stem),
+                Some(PACKAGE_EXT),
+            )?
+        }
+    };
+
+    let manifest = export_function(&func_dir, func_type, Path::new(&output_file))?;
+
+    let mut result = CommandResult::default();
+    result.add_output_path(&output_file);
+    result.content = json!(manifest);
+
+    Ok(result)
+}
+
+/// The functions installed from packages, with versions and sources
+pub fn list_installed_functions() -> Result<CommandResult, BoxedError> {
+    let registry = load_registry()?;
+    let mut result = CommandResult::default();
+    result.content = json!(registry.values().collect::<Vec<_>>());
+
+    Ok(result)
+}
@@ -2,11 +2,21 @@ use crate::commands::structures::CommandResult;
 use crate::commands::utils::get_string_val_from_params;
 use crate::errors::BoxedError;
 use crate::thelib;
+use crate::thelib::download::DownloadOptions;
 use serde_json::json;
 use serde_json::Value as JsonValue;
-use std::collections::HashMap;
 use std::path::Path;
 
+/// download a file. params:
+/// - `url`, `output_file`
+/// - `resume` (optional): continue a previous download of the same file
+/// - `headers` (optional): request headers
+/// - `mirrors` (optional): urls to try in order if `url` fails
+/// - `sha256`, `size` (optional): expectations verified after download
+/// - `retries` (optional): retries on transient errors for each url, default 3
+/// - `segments` (optional): download in N parallel byte-range segments, default 1
+///
+/// the file is written to `<output_file>.part` and renamed after it is verified
 pub async fn download_file(params: &JsonValue) -> Result<CommandResult, BoxedError> {
     let url = match params["url"].as_str() {
         Some(v) => v,
@@ -15,24 +25,44 @@ pub async fn download_file(params: &JsonValue) -> Result<CommandResult, BoxedErr
     let output_file: String =
         get_string_val_from_params(vec!["output_file", "output_path"], params)?;
 
-    let resume = match params["resume"].as_bool() {
+    let mut options = DownloadOptions::default();
+
+    options.resume = match params["resume"].as_bool() {
         Some(v) => v,
         None => false,
     };
 
     // Parse headers from params
-    let headers: Option<HashMap<String, String>> = params["headers"].as_object().map(|obj| {
+    options.headers = params["headers"].as_object().map(|obj| {
         obj.iter()
             .map(|(k, v)| (k.clone(), v.as_str().unwrap_or("").to_string()))
             .collect()
     });
 
+    if let Some(v) = params.get("mirrors") {
+        options.mirrors = v
+            .as_array()
+            .ok_or("`mirrors` must be an array")?
+            .iter()
+            .filter_map(|m| m.as_str())
+            .filter(|m| !m.is_empty())
+            .map(|m| m.to_string())
+            .collect();
+    }
+    options.sha256 = get_string_val_from_params(vec!["sha256"], params).ok();
+    options.size = params["size"].as_u64();
+    if let Some(v) = params["retries"].as_u64() {
+        options.max_retries = v as u32;
+    }
+    if let Some(v) = params["segments"].as_u64() {
+        options.segments = v.clamp(1, 16) as u32;
+    }
+
     let file_path = Path::new(output_file.as_str());
-    match thelib::download::download_file(
+    match thelib::download::download_file_with_retry(
         url,
-        &file_path.to_path_buf(),
-        &headers,
-        resume,
+        file_path,
+        &options,
         &reqwest::Client::new(),
     )
     .await
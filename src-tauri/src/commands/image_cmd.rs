@@ -1,9 +1,21 @@
 use crate::commands::structures::CommandResult;
 use crate::commands::utils::get_string_val_from_params;
-use crate::commands::utils::{parse_file_conversion_params, FileConversionParams};
+use crate::commands::utils::{
+    get_conflict_policy, parse_file_conversion_params, FileConversionParams,
+};
 use crate::errors::BoxedError;
 use crate::thelib;
+use crate::thelib::safe_write::{safe_write, WriteOutcome};
 use serde_json::{json, Value as JsonValue};
+use std::path::Path;
+
+/// report the applied conflict policy in the status; a skipped file is not an output
+fn set_write_outcome(result: &mut CommandResult, outcome: &WriteOutcome) {
+    result.status = outcome.status.to_string();
+    if !outcome.is_skipped() {
+        result.add_output_path(&outcome.path.to_string_lossy());
+    }
+}
 
 pub fn raw_svg_to_png(params: &JsonValue) -> Result<CommandResult, BoxedError> {
     let svg_text = match params["svg"].as_str() {
@@ -49,12 +61,14 @@ pub fn file_svg_to_png(params: &JsonValue) -> Result<CommandResult, BoxedError>
         None => 0, // default, use the SVG viewBox height
     };
 
+    let conflict = get_conflict_policy(params)?;
+
     let svg_text = std::fs::read_to_string(&input_file)?;
     let (_, _, png_data) = thelib::svg::svg_to_png(&svg_text, width, height)?;
-    std::fs::write(&output_file, &png_data)?;
+    let outcome = safe_write(Path::new(&output_file), &png_data, conflict)?;
 
     let mut result = CommandResult::default();
-    result.add_output_path(&output_file);
+    set_write_outcome(&mut result, &outcome);
     Ok(result)
 }
 
@@ -68,11 +82,16 @@ pub fn file_image_to_svg(params: &JsonValue) -> Result<CommandResult, BoxedError
         return Ok(result);
     }
 
-    fcp.actual_output_file =
-        thelib::image::file_image_to_svg(&fcp.input_file, &fcp.actual_output_file, &params)?;
+    let conflict = get_conflict_policy(params)?;
+    let outcome = thelib::image::file_image_to_svg(
+        &fcp.input_file,
+        &fcp.actual_output_file,
+        &params,
+        conflict,
+    )?;
+    fcp.actual_output_file = outcome.path.to_string_lossy().to_string();
 
-    // result.status = "ok".to_string();
-    result.add_output_path(&fcp.actual_output_file);
+    set_write_outcome(&mut result, &outcome);
     result.content = json!(fcp.actual_output_file.clone());
 
     Ok(result)
@@ -0,0 +1,48 @@
+use crate::app::resource::get_journal_dir;
+use crate::commands::structures::CommandResult;
+use crate::commands::utils::get_string_val_from_params;
+use crate::errors::BoxedError;
+use crate::thelib::journal::Journal;
+use serde_json::{json, Value as JsonValue};
+
+/// the journal of the mutating file commands, under the app data dir
+pub fn open_journal() -> Result<Journal, BoxedError> {
+    Ok(Journal::new(get_journal_dir()?))
+}
+
+/// journal entries, newest first. params:
+/// - `command` (optional): only entries of this command
+/// - `limit` (optional): default 50
+pub fn list_entries(params: &JsonValue) -> Result<CommandResult, BoxedError> {
+    let command = get_string_val_from_params(vec!["command"], params).ok();
+    let limit = params["limit"].as_u64().unwrap_or(50) as usize;
+
+    let entries: Vec<JsonValue> = open_journal()?
+        .list()
+        .into_iter()
+        .filter(|e| command.as_ref().is_none_or(|c| &e.command == c))
+        .take(limit)
+        .map(|e| json!(e))
+        .collect();
+
+    let mut result = CommandResult::default();
+    result.content = json!(entries);
+
+    Ok(result)
+}
+
+/// revert a journal entry by `id`
+pub fn undo_entry(params: &JsonValue) -> Result<CommandResult, BoxedError> {
+    let id = get_string_val_from_params(vec!["id", "journal_id"], params)?;
+
+    let restored = open_journal()?.undo(&id)?;
+
+    let mut result = CommandResult::default();
+    for path in &restored {
+        result.add_output_path(&path.to_string_lossy());
+    }
+    result.content = json!(id);
+    result.message = format!("{} paths restored", restored.len());
+
+    Ok(result)
+}
@@ -1,3 +1,4 @@
+pub mod archive_cmd;
 pub mod array_cmd;
 pub mod command_names;
 pub mod command_router;
@@ -7,13 +8,16 @@ pub mod env_cmd;
 pub mod exif_cmd;
 pub mod file_cmd;
 pub mod font_cmd;
+pub mod functions_cmd;
 pub mod http_cmd;
 pub mod i18n_cmd;
 pub mod image_cmd;
+pub mod journal_cmd;
 pub mod path_cmd;
 pub mod semver_cmd;
 pub mod shell_cmd;
 pub mod structures;
+pub mod task_cmd;
 pub mod template_cmd;
 pub mod text_cmd;
 pub mod tools_cmd;
@@ -3,15 +3,25 @@ use crate::errors::BoxedError;
 use crate::thelib;
 use trash;
 
-use crate::app::resource::get_app_data_dir;
-use crate::commands::utils::get_string_val_from_params;
+use crate::app::resource::{get_app_data_dir, get_app_temp_dir, get_journal_dir};
+use crate::commands::command_names as cmd_names;
+use crate::commands::journal_cmd::open_journal;
+use crate::commands::utils::{
+    get_patterns_from_params, get_string_array_from_params, get_string_val_from_params,
+    get_time_from_params,
+};
 use crate::functions::file::methods::get_supported_file_functions;
 use crate::thelib::file_find::reveal_path;
 use crate::thelib::file_path;
 use crate::thelib::file_path::{
-    count_files_and_dirs_to_check_bulk, get_absolute_path_with_home_dir, get_all_extensions,
-    get_relative_path_with_home_dir,
+    count_files_and_dirs_to_check_bulk, get_absolute_path_with_home_dir, get_file_selection,
+    get_relative_path_with_home_dir, FileSelection,
 };
+use crate::thelib::journal::{Journal, JournalEntry, JournalOp};
+use crate::thelib::path_matcher::PathMatcher;
+use crate::thelib::task::report_progress;
+use crate::thelib::walk::{walk, SymlinkPolicy, WalkOptions};
+use regex::Regex;
 use serde_json::json;
 use serde_json::Value as JsonValue;
 use std::collections::HashMap;
@@ -114,9 +124,15 @@ pub fn parse_for_task(params: &JsonValue) -> Result<CommandResult, BoxedError> {
     });
 
     // 2. supported things
-    let file_exts_string: HashSet<String> = get_all_extensions(&paths_set, 500);
-    let file_exts: HashSet<&str> = file_exts_string.iter().map(|ext| ext.as_str()).collect();
-    let supported_file_functions: JsonValue = get_supported_file_functions(file_exts.clone())?;
+    let selection: FileSelection = get_file_selection(&paths_set, 500);
+    let file_exts: HashSet<&str> = selection.kinds.iter().map(|k| k.ext.as_str()).collect();
+    let file_mimes: HashSet<&str> = selection
+        .kinds
+        .iter()
+        .map(|k| k.mime.as_str())
+        .filter(|m| !m.is_empty())
+        .collect();
+    let supported_file_functions: JsonValue = get_supported_file_functions(&selection)?;
 
     // 3. is multiple files or dirs
     let to_check_paths: Vec<&Path> = paths_set.iter().map(|p| Path::new(p)).collect();
@@ -128,6 +144,7 @@ pub fn parse_for_task(params: &JsonValue) -> Result<CommandResult, BoxedError> {
     result.content = json!({
         "paths": path_items,
         "file_exts": file_exts,
+        "file_mimes": file_mimes,
         "file_functions": supported_file_functions,
         "is_multiple_files": is_multiple_files,
         "is_multiple_dirs": is_multiple_dirs,
@@ -274,10 +291,15 @@ pub fn join_path(params: &JsonValue) -> Result<CommandResult, BoxedError> {
 pub fn dir_list(params: &JsonValue) -> Result<CommandResult, BoxedError> {
     let input_dir = get_string_val_from_params(vec!["input_dir", "dir"], &params)?;
 
-    let regex_pattern = match params.get("pattern") {
-        Some(s) => s.as_str().ok_or("regex `pattern` must be a string")?,
-        None => ".*",
+    // regex `pattern` of the name, and the patterns of `get_patterns_from_params`
+    let mut matcher = PathMatcher {
+        includes: get_patterns_from_params(params)?,
+        excludes: get_patterns_from_params(&params["excludes"])?,
     };
+    if let Some(s) = params.get("pattern") {
+        let pattern = s.as_str().ok_or("regex `pattern` must be a string")?;
+        matcher.includes.add_name_regex(pattern)?;
+    }
 
     let is_full_path: bool = match params.get("is_full_path") {
         Some(s) => s.as_bool().ok_or("is_full_path must be a boolean")?,
@@ -293,13 +315,15 @@ pub fn dir_list(params: &JsonValue) -> Result<CommandResult, BoxedError> {
         None => false,
     };
 
-    let file_names = thelib::file_find::dir_list(
-        &input_dir,
-        &regex_pattern,
-        is_full_path,
-        ignore_file,
-        ignore_dir,
-    )?;
+    let file_names: Vec<String> =
+        thelib::file_find::dir_list(&input_dir, ".*", false, ignore_file, ignore_dir)?
+            .into_iter()
+            .filter(|name| matcher.is_match(&input_dir, name))
+            .map(|name| match is_full_path {
+                true => Path::new(&input_dir).join(name).display().to_string(),
+                false => name,
+            })
+            .collect();
 
     let mut result = CommandResult::default();
     result.content = json!(file_names);
@@ -307,6 +331,87 @@ pub fn dir_list(params: &JsonValue) -> Result<CommandResult, BoxedError> {
     Ok(result)
 }
 
+/// walk a directory recursively. params:
+/// - `input_dir`
+/// - `min_depth` (optional, default 1), `max_depth` (optional)
+/// - `include_globs`, `exclude_globs`, `include_regexes`, `exclude_regexes` (optional):
+///   matched against the path relative to `input_dir`, with `/` separators
+/// - `hidden` (optional, default true), `gitignore` (optional, default false)
+/// - `symlinks` (optional): skip, list (default) or follow
+/// - `files`, `dirs` (optional, default true): entry types to return
+/// - `min_size`, `max_size`, `modified_after`, `modified_before` (optional): file filters
+/// - `after` (optional): `rel_path` of the last entry of the previous page, the walk resumes
+///   after it without walking the dirs before it again
+/// - `limit` (optional, default 1000): max entries of the page
+///
+/// content is `{entries, next_after}`, pass next_after as `after` for the next page.
+/// it is null on the last page
+pub fn dir_walk(params: &JsonValue) -> Result<CommandResult, BoxedError> {
+    let input_dir = get_string_val_from_params(vec!["input_dir", "dir"], params)?;
+    if !Path::new(&input_dir).is_dir() {
+        return Err(format!("Not a directory: {}", input_dir).into());
+    }
+
+    let mut options = WalkOptions {
+        min_depth: params["min_depth"].as_u64().unwrap_or(1) as usize,
+        max_depth: params["max_depth"].as_u64().map(|d| d as usize),
+        hidden: params["hidden"].as_bool().unwrap_or(true),
+        gitignore: params["gitignore"].as_bool().unwrap_or(false),
+        files: params["files"].as_bool().unwrap_or(true),
+        dirs: params["dirs"].as_bool().unwrap_or(true),
+        min_size: params["min_size"].as_u64(),
+        max_size: params["max_size"].as_u64(),
+        modified_after: get_time_from_params("modified_after", params)?,
+        modified_before: get_time_from_params("modified_before", params)?,
+        after: get_string_val_from_params(vec!["after"], params).ok(),
+        ..Default::default()
+    };
+    if let Some(v) = params["symlinks"].as_str() {
+        options.symlinks = SymlinkPolicy::from_name(v)?;
+    }
+    for pattern in get_string_array_from_params("include_globs", params)? {
+        options.add_include_glob(&pattern)?;
+    }
+    for pattern in get_string_array_from_params("exclude_globs", params)? {
+        options.add_exclude_glob(&pattern)?;
+    }
+    for (key, regexes) in [
+        ("include_regexes", &mut options.includes),
+        ("exclude_regexes", &mut options.excludes),
+    ] {
+        for pattern in get_string_array_from_params(key, params)? {
+            match Regex::new(&pattern) {
+                Ok(r) => regexes.push(r),
+                Err(e) => return Err(format!("Invalid regex: '{}', error: {}", pattern, e).into()),
+            }
+        }
+    }
+
+    let limit = params["limit"].as_u64().unwrap_or(1000) as usize;
+
+    let mut entries: Vec<JsonValue> = Vec::new();
+    let mut last_rel_path: Option<String> = None;
+    let mut next_after: Option<String> = None;
+    for entry in walk(&input_dir, &options) {
+        let entry = entry?;
+        if entries.len() >= limit {
+            next_after = last_rel_path;
+            break;
+        }
+        entries.push(entry.to_json());
+        last_rel_path = Some(entry.rel_path);
+        report_progress(entries.len() as u64, 0, "items");
+    }
+
+    let mut result = CommandResult::default();
+    result.content = json!({
+        "entries": entries,
+        "next_after": next_after,
+    });
+
+    Ok(result)
+}
+
 pub fn new_temp_file_path(params: &JsonValue) -> Result<CommandResult, BoxedError> {
     // get ext from `ext` or `extension` in params
     let ext = match params.get("ext") {
@@ -330,11 +435,10 @@ pub fn new_temp_file_path(params: &JsonValue) -> Result<CommandResult, BoxedErro
     let temp_dir: PathBuf = if !parent_dir_str.is_empty() {
         PathBuf::from(parent_dir_str)
     } else {
-        let app_data_dir = match get_app_data_dir() {
+        match get_app_temp_dir() {
             Ok(d) => d,
             Err(e) => return Err(format!("Can't get dir for temp file: {}", e).into()),
-        };
-        app_data_dir.join(".temp")
+        }
     };
 
     let temp_file = thelib::file_path::new_temp_file_path(&temp_dir, &ext)?;
@@ -344,7 +448,104 @@ pub fn new_temp_file_path(params: &JsonValue) -> Result<CommandResult, BoxedErro
     Ok(result)
 }
 
-/// delete files or directories
+// actions and statuses of the `DeleteReport` items
+pub const DELETE_ACTION_DELETE: &str = "delete";
+pub const DELETE_ACTION_TRASH: &str = "trash";
+pub const DELETE_STATUS_PENDING: &str = "pending";
+pub const DELETE_STATUS_OK: &str = "ok";
+pub const DELETE_STATUS_FAILED: &str = "failed";
+
+/// Report of `path.delete` and `files.clear`, the same for dry runs and real runs.
+/// In a dry run the items are "pending", otherwise "ok" or "failed" with the error
+pub struct DeleteReport {
+    dry_run: bool,
+    items: Vec<JsonValue>,
+    count: u64,
+    failed_count: u64,
+    total_bytes: u64,
+}
+
+impl DeleteReport {
+    pub fn new(dry_run: bool) -> Self {
+        DeleteReport {
+            dry_run,
+            items: Vec::new(),
+            count: 0,
+            failed_count: 0,
+            total_bytes: 0,
+        }
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub fn failed_count(&self) -> u64 {
+        self.failed_count
+    }
+
+    /// `outcome` is none in a dry run
+    pub fn add(
+        &mut self,
+        path: &Path,
+        size: u64,
+        action: &str,
+        outcome: Option<Result<(), BoxedError>>,
+    ) {
+        let (status, error) = match outcome {
+            None => (DELETE_STATUS_PENDING, None),
+            Some(Ok(())) => (DELETE_STATUS_OK, None),
+            Some(Err(e)) => {
+                log::error!("Failed to {} {}: {}", action, path.display(), e);
+                (DELETE_STATUS_FAILED, Some(e.to_string()))
+            }
+        };
+        if error.is_some() {
+            self.failed_count += 1;
+        } else {
+            self.count += 1;
+            self.total_bytes += size;
+        }
+        self.items.push(json!({
+            "path": path.to_string_lossy(),
+            "size": size,
+            "action": action,
+            "status": status,
+            "error": error,
+        }));
+    }
+
+    /// content is `{dry_run, items, count, failed_count, total_bytes}`;
+    /// count and total_bytes (the reclaimable bytes) leave out the failed items.
+    /// the status is "error" if any item failed in a real run
+    pub fn into_result(self) -> CommandResult {
+        let mut result = CommandResult::default();
+        result.message = match self.dry_run {
+            true => format!("{} to delete, {} bytes", self.count, self.total_bytes),
+            false => format!("{} deleted, {} bytes", self.count, self.total_bytes),
+        };
+        if self.failed_count > 0 {
+            result.message += &format!(", {} failed", self.failed_count);
+            result.status = "error".to_string();
+        }
+        result.content = json!({
+            "dry_run": self.dry_run,
+            "items": self.items,
+            "count": self.count,
+            "failed_count": self.failed_count,
+            "total_bytes": self.total_bytes,
+        });
+        result
+    }
+}
+
+/// delete files or directories. params:
+/// - `input_paths`
+/// - `dry_run` (optional, default false): only report what would be deleted
+/// - `undoable` (optional, default false): move paths in the app data dir into the journal
+///   backups instead of deleting them, so `journal.undo` can restore them
+///
+/// paths outside the app data dir are moved to trash. content: see `DeleteReport`
 pub fn delete_path(params: &JsonValue) -> Result<CommandResult, BoxedError> {
     // get paths from `input_paths` or `paths` in params
     let input_paths = match params.get("input_paths") {
@@ -369,35 +570,141 @@ pub fn delete_path(params: &JsonValue) -> Result<CommandResult, BoxedError> {
         .map(|path| path.to_string())
         .collect();
 
-    let app_data_dir_str: String = get_app_data_dir()?.to_string_lossy().to_string();
+    let dry_run = params["dry_run"].as_bool().unwrap_or(false);
+    let undoable = params["undoable"].as_bool().unwrap_or(false);
 
-    let mut count_deleted: usize = 0;
+    let app_data_dir_str: String = get_app_data_dir()?.to_string_lossy().to_string();
+    let temp_dir_str: String = get_app_temp_dir()?.to_string_lossy().to_string();
+    let journal_dir_str: String = get_journal_dir()?.to_string_lossy().to_string();
+
+    // the journal is only opened when something is deleted
+    let mut undo = match dry_run {
+        true => None,
+        false => {
+            let journal = open_journal()?;
+            let entry = journal.new_entry(cmd_names::PATH_DELETE);
+            Some((journal, entry))
+        }
+    };
+    let mut report = DeleteReport::new(dry_run);
 
-    let mut wait_to_trash: Vec<String> = Vec::new();
+    let unrecorded_dirs = [temp_dir_str, journal_dir_str];
     for input_path in &input_paths_arr {
-        if input_path.starts_with(&app_data_dir_str) {
-            // if specified path is a sub path of app data dir or temp dir, delete it directly
-            // include temp files created by workflows (located in app_data_dir/.temp)
-            let path = std::path::Path::new(&input_path);
-            if path.is_dir() {
-                std::fs::remove_dir_all(input_path)?;
-            } else if path.is_file() {
-                std::fs::remove_file(input_path)?;
-            }
-            count_deleted += 1;
-        } else {
-            // otherwise, for safety, move it to trash
-            wait_to_trash.push(input_path.to_string());
+        let path = Path::new(input_path);
+        // missing paths are skipped
+        if std::fs::symlink_metadata(path).is_err() {
+            continue;
         }
+        let size = thelib::file_count::path_size(path)?;
+        let action = match input_path.starts_with(&app_data_dir_str) {
+            true => DELETE_ACTION_DELETE,
+            false => DELETE_ACTION_TRASH,
+        };
+        let outcome = undo.as_mut().map(|(journal, entry)| {
+            delete_one_path(
+                input_path,
+                &app_data_dir_str,
+                &unrecorded_dirs,
+                undoable,
+                journal,
+                entry,
+            )
+        });
+        report.add(path, size, action, outcome);
     }
-    if !wait_to_trash.is_empty() {
-        for path in &wait_to_trash {
-            trash::delete(path)?;
+    if let Some((journal, entry)) = &undo {
+        journal.save(entry);
+    }
+
+    Ok(report.into_result())
+}
+
+fn delete_one_path(
+    input_path: &str,
+    app_data_dir_str: &str,
+    unrecorded_dirs: &[String],
+    undoable: bool,
+    journal: &Journal,
+    entry: &mut JournalEntry,
+) -> Result<(), BoxedError> {
+    let path = Path::new(input_path);
+    if !input_path.starts_with(app_data_dir_str) {
+        // for safety, move it to trash. the trash keeps the absolute path
+        let abs_path = path.canonicalize().unwrap_or(path.to_path_buf());
+        trash::delete(input_path)?;
+        entry.add(JournalOp::Trash { path: abs_path });
+        return Ok(());
+    }
+
+    // if specified path is a sub path of app data dir, delete it directly.
+    // temp files created by workflows (located in app_data_dir/.temp) and the journal
+    // itself are never recorded
+    let recorded = undoable
+        && !unrecorded_dirs
+            .iter()
+            .any(|d| input_path.starts_with(d.as_str()));
+    if !recorded {
+        if path.is_dir() {
+            std::fs::remove_dir_all(input_path)?;
+        } else if path.is_file() {
+            std::fs::remove_file(input_path)?;
         }
-        count_deleted += wait_to_trash.len();
+    } else if path.exists() {
+        // moved into the journal backups, so it can be restored
+        let backup = journal.backup(entry, path, true)?;
+        entry.add(JournalOp::Delete {
+            path: path.to_path_buf(),
+            backup,
+        });
     }
+    Ok(())
+}
 
-    let mut result = CommandResult::default();
-    result.content = json!(count_deleted);
-    Ok(result)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delete_report() {
+        let mut report = DeleteReport::new(true);
+        assert!(report.is_dry_run());
+        report.add(Path::new("/a"), 10, DELETE_ACTION_DELETE, None);
+        report.add(Path::new("/b"), 5, DELETE_ACTION_TRASH, None);
+        let result = report.into_result();
+        assert_eq!(result.status, "ok");
+        assert_eq!(result.message, "2 to delete, 15 bytes");
+        assert_eq!(result.content["dry_run"], json!(true));
+        assert_eq!(result.content["count"], json!(2));
+        assert_eq!(result.content["total_bytes"], json!(15));
+        assert_eq!(result.content["items"][1]["action"], json!("trash"));
+        assert_eq!(result.content["items"][1]["status"], json!("pending"));
+
+        // failed items are left out of the count and the bytes
+        let mut report = DeleteReport::new(false);
+        report.add(Path::new("/a"), 10, DELETE_ACTION_DELETE, Some(Ok(())));
+        report.add(
+            Path::new("/b"),
+            5,
+            DELETE_ACTION_DELETE,
+            Some(Err("Permission denied".into())),
+        );
+        assert_eq!(report.failed_count(), 1);
+        let result = report.into_result();
+        assert_eq!(result.status, "error");
+        assert_eq!(result.message, "1 deleted, 10 bytes, 1 failed");
+        assert_eq!(result.content["count"], json!(1));
+        assert_eq!(result.content["failed_count"], json!(1));
+        assert_eq!(result.content["total_bytes"], json!(10));
+        assert_eq!(result.content["items"][0]["status"], json!("ok"));
+        assert_eq!(result.content["items"][0]["error"], JsonValue::Null);
+        assert_eq!(result.content["items"][1]["status"], json!("failed"));
+        assert_eq!(
+            result.content["items"][1]["error"],
+            json!("Permission denied")
+        );
+
+        let result = DeleteReport::new(false).into_result();
+        assert_eq!(result.status, "ok");
+        assert_eq!(result.message, "0 deleted, 0 bytes");
+    }
 }
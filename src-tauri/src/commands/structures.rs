@@ -6,6 +6,8 @@ pub struct CommandResult {
     pub status: String,
     pub message: String,
     pub output_paths: Vec<String>,
+    /// the task the command ran as, see `command_router::route`
+    pub task_id: Option<String>,
 }
 
 impl CommandResult {
@@ -15,6 +17,7 @@ impl CommandResult {
             status: "ok".to_string(),
             message: "".to_string(),
             output_paths: vec![],
+            task_id: None,
         }
     }
 
@@ -23,11 +26,15 @@ impl CommandResult {
     }
 
     pub fn to_json(&self) -> JsonValue {
-        serde_json::json!({
+        let mut data = serde_json::json!({
             "content": self.content,
             "status": self.status,
             "message": self.message,
             "output_paths": self.output_paths,
-        })
+        });
+        if let Some(task_id) = &self.task_id {
+            data["task_id"] = serde_json::json!(task_id);
+        }
+        data
     }
 }
@@ -0,0 +1,30 @@
+use crate::commands::structures::CommandResult;
+use crate::commands::utils::get_string_val_from_params;
+use crate::errors::BoxedError;
+use crate::thelib::task;
+use serde_json::{json, Value as JsonValue};
+
+/// request a running task to stop, content is false if the task is not running
+pub fn cancel_task(params: &JsonValue) -> Result<CommandResult, BoxedError> {
+    let task_id = get_string_val_from_params(vec!["task_id", "id"], params)?;
+
+    let found = task::cancel_task(&task_id)?;
+
+    let mut result = CommandResult::default();
+    result.content = json!(found);
+    if !found {
+        result.status = "ignored".to_string();
+        result.message = format!("Task `{}` is not running", task_id);
+    }
+
+    Ok(result)
+}
+
+pub fn list_tasks() -> Result<CommandResult, BoxedError> {
+    let task_ids = task::list_tasks()?;
+
+    let mut result = CommandResult::default();
+    result.content = json!(task_ids);
+
+    Ok(result)
+}
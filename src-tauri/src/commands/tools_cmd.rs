@@ -1,34 +1,37 @@
+use crate::app::resource::{get_app_download_dir, get_app_temp_dir};
 use crate::commands::structures::CommandResult;
+use crate::commands::updater_cmd::fetch_file;
 use crate::commands::utils::get_string_val_from_params;
 use crate::errors::BoxedError;
-use crate::functions::tool::read::TOOL_FUNCTIONS;
-use crate::functions::tool::structures::ToolFunction;
+use crate::functions::tool::install::{
+    install_package_file, uninstall, InstallTarget, ToolPackage, FORMAT_FILE,
+};
+use crate::functions::tool::read::{
+    check_tool_available, check_tools_in_background, get_tool_status, lock_tool_functions,
+    reset_tool_available,
+};
+use crate::functions::tool::structures::{ToolFunction, TOOL_STATUS_UNCHECKED};
+use crate::thelib::sys::get_platform;
 use serde_json::{json, Value as JsonValue};
 use std::collections::HashMap;
-use std::time::{Duration, Instant};
+use std::path::Path;
 
+/// All tools, with `status` and `version` of each tool, see `check_tool_available`.
+/// Tools not checked yet are checked in the background, they are "unchecked" until then
 pub fn get_tools() -> Result<CommandResult, BoxedError> {
-    // use independent scope to release lock; use loop to try to wait for lock automatically;
-    let start = Instant::now();
-    let tools: HashMap<String, ToolFunction>;
-    loop {
-        match TOOL_FUNCTIONS.lock() {
-            Ok(c) => {
-                tools = c.clone();
-                break;
-            }
-            Err(_) => {
-                if start.elapsed() > Duration::from_millis(200) {
-                    return Err("Failed to acquire lock TOOL_FUNCTIONS".into());
-                }
-                std::thread::sleep(Duration::from_millis(10)); // wait 10ms and retry
-            }
-        }
-    }
+    let unchecked_names: Vec<String> = lock_tool_functions()
+        .values()
+        .filter(|t| t.status == TOOL_STATUS_UNCHECKED)
+        .map(|t| t.name.clone())
+        .collect();
+    check_tools_in_background(unchecked_names);
+
+    let tools: HashMap<String, ToolFunction> = lock_tool_functions().clone();
 
     let mut model_names: Vec<String> = Vec::new();
     let mut exe_names: Vec<String> = Vec::new();
     let mut tools_map: HashMap<String, JsonValue> = HashMap::new();
+    let mut statuses: HashMap<String, String> = HashMap::new();
     for (name, tool) in tools.iter() {
         if tool.func_type == "tool.model" {
             model_names.push(name.clone());
@@ -36,6 +39,7 @@ pub fn get_tools() -> Result<CommandResult, BoxedError> {
             exe_names.push(name.clone());
         }
         tools_map.insert(name.clone(), tool.to_json());
+        statuses.insert(name.clone(), tool.status.clone());
     }
     model_names.sort();
     exe_names.sort();
@@ -45,6 +49,7 @@ pub fn get_tools() -> Result<CommandResult, BoxedError> {
             "items": tools_map,
             "exe_names": exe_names,
             "model_names": model_names,
+            "statuses": statuses,
     });
 
     Ok(result)
@@ -53,23 +58,7 @@ pub fn get_tools() -> Result<CommandResult, BoxedError> {
 pub fn get_tool_bin_path(params: &JsonValue) -> Result<CommandResult, BoxedError> {
     let tool_name = get_string_val_from_params(vec!["tool_name", "name"], params)?;
 
-    // use independent scope to release lock; use loop to try to wait for lock automatically;
-    let start = Instant::now();
-    let tools: HashMap<String, ToolFunction>;
-    loop {
-        match TOOL_FUNCTIONS.lock() {
-            Ok(c) => {
-                tools = c.clone();
-                break;
-            }
-            Err(_) => {
-                if start.elapsed() > Duration::from_millis(200) {
-                    return Err("Failed to acquire lock TOOL_FUNCTIONS".into());
-                }
-                std::thread::sleep(Duration::from_millis(10)); // wait 10ms and retry
-            }
-        }
-    }
+    let tools: HashMap<String, ToolFunction> = lock_tool_functions().clone();
 
     let tool = tools.get(&tool_name);
     let mut result = CommandResult::default();
@@ -86,6 +75,121 @@ pub fn get_tool_bin_path(params: &JsonValue) -> Result<CommandResult, BoxedError
     Ok(result)
 }
 
+fn get_tool_function(tool_name: &str) -> Result<ToolFunction, BoxedError> {
+    match lock_tool_functions().get(tool_name) {
+        Some(t) => Ok(t.clone()),
+        None => Err(format!("Tool `{}` not found.", tool_name).into()),
+    }
+}
+
+/// run the availability check again, not the cached result
+fn verify_tool(tool: &ToolFunction) -> Result<JsonValue, BoxedError> {
+    reset_tool_available(&tool.name)?;
+    let available = check_tool_available(&tool.name)?;
+    let (status, version) = get_tool_status(&tool.name)?;
+    Ok(json!({
+        "name": tool.name,
+        "bin_path": tool.bin_path,
+        "bin_exists": Path::new(&tool.bin_path).exists(),
+        "available": available,
+        "status": status,
+        "version": version,
+        "required_version": {
+            "min": tool.required_bin_version_min,
+            "max": tool.required_bin_version_max
+        },
+    }))
+}
+
+/// Download and install a tool by the `bin.installation` package of the current platform,
+/// see `functions::tool::install`.
+/// params: `tool_name`, like "tool.exe.ffmpeg"; `force`: reinstall an available tool
+pub async fn install_tool(params: &JsonValue) -> Result<CommandResult, BoxedError> {
+    let tool_name = get_string_val_from_params(vec!["tool_name", "name"], params)?;
+    let force = params["force"].as_bool().unwrap_or(false);
+    let tool = get_tool_function(&tool_name)?;
+    let package = ToolPackage::for_platform(&tool.installation, &get_platform())?;
+    let target = InstallTarget::new(&tool, &package)?;
+
+    let mut result = CommandResult::default();
+    if !force && check_tool_available(&tool.name)? {
+        result.status = "ignored".to_string();
+        result.message = format!("{} is already available", tool.name);
+        result.content = verify_tool(&tool)?;
+        return Ok(result);
+    }
+
+    // keep the archive extension, the unpacking detects the format by it
+    let ext = match package.format.as_str() {
+        FORMAT_FILE => "download".to_string(),
+        format => format.to_string(),
+    };
+    let package_file =
+        get_app_download_dir()?.join(format!("{}-{}.{}", tool.name, uuid::Uuid::new_v4(), ext));
+    log::info!("Installing {} from {}", tool.name, package.url);
+    fetch_file(
+        &package.url,
+        &package_file,
+        Some(package.sha256.as_str()),
+        package.size,
+    )
+    .await?;
+
+    let staging_dir = get_app_temp_dir()?.join(format!("tool-{}", uuid::Uuid::new_v4()));
+    let installed = install_package_file(&package, &target, &package_file, &staging_dir);
+    for path in [&package_file, &staging_dir] {
+        let _ = match path.is_dir() {
+            true => std::fs::remove_dir_all(path),
+            false => std::fs::remove_file(path),
+        };
+    }
+    installed?;
+
+    result.content = verify_tool(&tool)?;
+    result.add_output_path(&target.path.to_string_lossy());
+    if result.content["available"] != json!(true) {
+        result.status = "error".to_string();
+        result.message = format!(
+            "{} is installed but not available: {}",
+            tool.name, result.content["status"]
+        );
+    } else {
+        result.message = format!("{} installed", tool.name);
+    }
+
+    Ok(result)
+}
+
+/// Remove a tool installed by `install_tool`.
+/// params: `tool_name`
+pub fn uninstall_tool(params: &JsonValue) -> Result<CommandResult, BoxedError> {
+    let tool_name = get_string_val_from_params(vec!["tool_name", "name"], params)?;
+    let tool = get_tool_function(&tool_name)?;
+    let package = ToolPackage::for_platform(&tool.installation, &get_platform())?;
+    let target = InstallTarget::new(&tool, &package)?;
+
+    let mut result = CommandResult::default();
+    if !uninstall(&target)? {
+        result.status = "ignored".to_string();
+        result.message = format!("{} is not installed", tool.name);
+    }
+    result.content = verify_tool(&tool)?;
+
+    Ok(result)
+}
+
+/// Check a tool again, e.g. after it is installed manually.
+/// params: `tool_name`
+pub fn verify_tool_available(params: &JsonValue) -> Result<CommandResult, BoxedError> {
+    let tool_name = get_string_val_from_params(vec!["tool_name", "name"], params)?;
+    let tool = get_tool_function(&tool_name)?;
+
+    let mut result = CommandResult::default();
+    result.content = verify_tool(&tool)?;
+
+    Ok(result)
+}
+
 /// Mock command execution for tool.model
 pub fn model_tool_mock_command_execution(
     _command: &str,
@@ -3,243 +3,402 @@ use crate::app::resource::{
 };
 use crate::commands::http_cmd::download_file;
 use crate::commands::structures::CommandResult;
+use crate::commands::utils::get_string_val_from_params;
 use crate::commands::zip_cmd::unzip_file;
+use crate::config::init::{lock_config, USER_UPDATER_CONFIG};
 use crate::errors::BoxedError;
+use crate::functions::common::config::{join_config_errors, FunctionConfig};
+use crate::functions::common::structures::FunctionIgnored;
+use crate::functions::file::structures::FileFunction;
+use crate::functions::tool::structures::ToolFunction;
+use crate::thelib::compress::read_zip_entry_to_string;
+use crate::thelib::file_path::{copy_dir_all, get_sub_dirnames};
+use crate::thelib::hash::hash_file;
+use crate::thelib::json::load_json_file;
 use crate::thelib::sys::get_app_ver_dirname;
+use crate::thelib::version::is_newer_version;
 use serde_json::json;
 use serde_json::Value as JsonValue;
+use std::path::{Path, PathBuf};
+
+// the downloadable resource bundles, also the dir name inside the bundle zip
+const BUNDLE_FUNCTIONS: &str = "functions";
+const BUNDLE_I18N: &str = "i18n";
+
+const DEFAULT_UPDATE_SOURCE: &str = "https://releases.filethings.net";
 
 pub async fn update_functions() -> Result<CommandResult, BoxedError> {
-    let target_dir = get_function_dir_in_app_data()?;
-    let app_ver_dirname = get_app_ver_dirname();
-    let downloads_dir = get_app_download_dir()?;
+    let mut cmd_rst = update_bundle(BUNDLE_FUNCTIONS).await?;
+    if cmd_rst.status == "ok" {
+        cmd_rst.message = "Functions updated".to_string();
+    }
+    Ok(cmd_rst)
+}
 
-    // download the latest functions.json
-    let endpoint = format!(
-        "https://releases.filethings.net/res/functions/for-app-{}.json",
-        app_ver_dirname
-    );
-    log::info!("check functions from: {}", endpoint);
-    let latest_data_file = downloads_dir.join("latest-functions.json");
-    let latest_data_file_str = match latest_data_file.to_str() {
-        Some(s) => s,
-        None => return Err("Failed to get the latest data file path".into()),
-    };
-    let params = json!({
-        "url":endpoint,
-        "output_file":latest_data_file_str,
-        "resume":false
-    });
-    let result = download_file(&params).await?;
-    if result.status != "ok" {
-        return Err(result.message.into());
+pub async fn update_i18n() -> Result<CommandResult, BoxedError> {
+    let mut cmd_rst = update_bundle(BUNDLE_I18N).await?;
+    if cmd_rst.status == "ok" {
+        cmd_rst.message = "I18N translations updated".to_string();
+    }
+    Ok(cmd_rst)
+}
+
+/// restore the previous version of a bundle kept by the last update.
+/// params: `bundle`: "functions" or "i18n"
+pub fn rollback(params: &JsonValue) -> Result<CommandResult, BoxedError> {
+    let bundle = get_string_val_from_params(vec!["bundle"], params)?;
+    let target_dir = get_bundle_target_dir(&bundle)?;
+    if !get_backup_dir(&target_dir).is_dir() {
+        return Err(format!("No previous version of {} to roll back to", bundle).into());
+    }
+    if let Err(e) = swap_with_backup_dir(&target_dir) {
+        return Err(format!("Failed to roll back {}: {}", bundle, e).into());
     }
 
-    // load the functions.json
-    let data_str = match std::fs::read_to_string(&latest_data_file) {
-        Ok(s) => s,
-        Err(e) => {
-            return Err(format!(
-                "Failed to read the latest data file: {}, Error:{}",
-                &latest_data_file_str, e
-            )
-            .into())
+    let version = read_bundle_version(&target_dir);
+    log::info!("Rolled back {} to version: {}", bundle, version);
+
+    let mut cmd_rst = CommandResult::default();
+    cmd_rst.content = json!(version);
+    cmd_rst.message = format!("Rolled back {} to version {}", bundle, version);
+
+    Ok(cmd_rst)
+}
+
+/// swap the current and the previous version, so the rollback can be undone by another one
+fn swap_with_backup_dir(target_dir: &Path) -> Result<(), BoxedError> {
+    let backup_dir = get_backup_dir(target_dir);
+    let tmp_dir = target_dir.with_file_name(format!("{}.rollback", get_dir_name(target_dir)));
+    if tmp_dir.exists() {
+        std::fs::remove_dir_all(&tmp_dir)?;
+    }
+    if target_dir.exists() {
+        std::fs::rename(target_dir, &tmp_dir)?;
+    }
+    if let Err(e) = std::fs::rename(&backup_dir, target_dir) {
+        log::error!("Failed to restore {:?}: {}", backup_dir, e);
+        if tmp_dir.exists() {
+            std::fs::rename(&tmp_dir, target_dir)?;
         }
-    };
-    let data_json: Result<serde_json::Value, serde_json::Error> =
-        serde_json::from_str(data_str.as_str());
-    let data_json = match data_json {
-        Ok(j) => j,
-        Err(e) => return Err(format!("Failed to parse the data json: {}", e).into()),
-    };
-    let new_version: String = data_json["version"].as_str().unwrap_or("0").to_string();
-    log::info!("found remote version: {}", new_version);
+        return Err(e.into());
+    }
+    if tmp_dir.exists() {
+        std::fs::rename(&tmp_dir, &backup_dir)?;
+    }
+    Ok(())
+}
 
-    // read the current version from : functions/version.txt
-    let version_file = target_dir.join("version.txt");
-    let current_version = if version_file.exists() {
-        std::fs::read_to_string(&version_file)?.trim().to_string()
-    } else {
-        "0".to_string()
-    };
+fn get_bundle_target_dir(bundle: &str) -> Result<PathBuf, BoxedError> {
+    match bundle {
+        BUNDLE_FUNCTIONS => get_function_dir_in_app_data(),
+        BUNDLE_I18N => get_i18n_dir_in_app_data(),
+        _ => Err(format!("Unknown bundle: '{}'", bundle).into()),
+    }
+}
 
-    // compare the version
-    if new_version <= current_version {
-        log::info!("It's already the latest version: {}", current_version);
-        // no need to update
-        let mut cmd_rst = CommandResult::default();
-        cmd_rst.status = "ignored".to_string();
-        cmd_rst.content = json!(current_version);
-        cmd_rst.message = "It's already the latest version".to_string();
-        return Ok(cmd_rst);
+fn get_dir_name(dir: &Path) -> String {
+    dir.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+// the previous version is kept next to the current one, e.g. `functions/v1.2.3.prev`
+fn get_backup_dir(target_dir: &Path) -> PathBuf {
+    target_dir.with_file_name(format!("{}.prev", get_dir_name(target_dir)))
+}
+
+fn read_bundle_version(bundle_dir: &Path) -> String {
+    let version_file = bundle_dir.join("version.txt");
+    match std::fs::read_to_string(&version_file) {
+        Ok(s) => s.trim().to_string(),
+        Err(_) => {
+            log::error!("Failed to read the version file: {:?}", version_file);
+            "0".to_string()
+        }
     }
+}
 
-    log::info!("Update the functions to version: {}", new_version);
+/// where updates come from, read from the user `updater` config:
+/// - `source`: base url of the release server (default `https://releases.filethings.net`),
+///   or a `file://` directory with the same layout, e.g. an internal mirror
+/// - `channel`: "stable" (default) or "beta", beta manifests are under `res/<bundle>/beta/`
+/// - `functions_zip`, `i18n_zip` (optional): a local bundle zip, installed without any manifest
+struct UpdateSource {
+    base: String,
+    channel: String,
+    local_zips: JsonValue,
+}
 
-    // update the functions
-    let url = match data_json["url"].as_str() {
-        Some(s) => s,
-        None => return Err("Invalid functions.json, missing url".into()),
-    };
-    let downloaded_file = downloads_dir.join("functions.zip");
-    let downloaded_file_str = match downloaded_file.to_str() {
-        Some(s) => s,
-        None => return Err("Failed to get the destination file path".into()),
-    };
+impl UpdateSource {
+    fn from_config() -> Result<UpdateSource, BoxedError> {
+        let config: JsonValue = lock_config(&USER_UPDATER_CONFIG).get_all()?;
+
+        let base = match config["source"].as_str() {
+            Some(s) if !s.trim().is_empty() => s.trim().trim_end_matches('/').to_string(),
+            _ => DEFAULT_UPDATE_SOURCE.to_string(),
+        };
+        let channel = config["channel"].as_str().unwrap_or("stable").to_string();
+        if !["stable", "beta"].contains(&channel.as_str()) {
+            return Err(format!("Invalid update channel: '{}'", channel).into());
+        }
 
-    let params = json!({
-        "url":url,
-        "output_file":downloaded_file_str,
-        "resume":false
-    });
-    let result = download_file(&params).await?;
-    if result.status != "ok" {
-        return Err(result.message.into());
+        Ok(UpdateSource {
+            base,
+            channel,
+            local_zips: json!({
+                BUNDLE_FUNCTIONS: config["functions_zip"],
+                BUNDLE_I18N: config["i18n_zip"],
+            }),
+        })
     }
 
-    // unzip the functions.zip
-    let unzipped_dir = downloads_dir.join("functions_new");
-    //  - clear the existing `unzipped_dir` if it exists
-    if unzipped_dir.exists() {
-        std::fs::remove_dir_all(&unzipped_dir)?;
+    fn get_manifest_url(&self, bundle: &str, app_ver_dirname: &str) -> String {
+        let channel_dir = match self.channel.as_str() {
+            "stable" => "".to_string(),
+            c => format!("{}/", c),
+        };
+        format!(
+            "{}/res/{}/{}for-app-{}.json",
+            self.base, bundle, channel_dir, app_ver_dirname
+        )
     }
-    //  - create the `unzipped_dir`
-    std::fs::create_dir_all(&unzipped_dir)?;
-    let unzipped_dir_str = match unzipped_dir.to_str() {
-        Some(s) => s,
-        None => return Err("Failed to get the unzipped directory path".into()),
-    };
-    let params = json!({
-        "input_file":downloaded_file_str,
-        "output_dir":unzipped_dir_str
-    });
-    let result = match unzip_file(&params) {
-        Ok(r) => r,
+
+    fn get_local_zip(&self, bundle: &str) -> Option<PathBuf> {
+        match self.local_zips[bundle].as_str() {
+            Some(s) if !s.is_empty() => Some(PathBuf::from(s)),
+            _ => None,
+        }
+    }
+}
+
+/// the latest release of a bundle
+struct BundleRelease {
+    version: String,
+    /// url (http or `file://`) of the bundle zip, or the local zip path
+    package: String,
+    is_local_zip: bool,
+    sha256: Option<String>,
+    size: Option<u64>,
+}
+
+async fn fetch_bundle_release(
+    source: &UpdateSource,
+    bundle: &str,
+) -> Result<BundleRelease, BoxedError> {
+    if let Some(zip_path) = source.get_local_zip(bundle) {
+        log::info!("check {} from local zip: {:?}", bundle, zip_path);
+        let version_entry = format!("{}/version.txt", bundle);
+        let version = read_zip_entry_to_string(&zip_path, &version_entry)?;
+        return Ok(BundleRelease {
+            version: version.trim().to_string(),
+            package: zip_path.to_string_lossy().to_string(),
+            is_local_zip: true,
+            sha256: None,
+            size: None,
+        });
+    }
+
+    // download the latest manifest
+    let endpoint = source.get_manifest_url(bundle, &get_app_ver_dirname());
+    log::info!("check {} from: {}", bundle, endpoint);
+    let downloads_dir = get_app_download_dir()?;
+    let latest_data_file = downloads_dir.join(format!("latest-{}.json", bundle));
+    fetch_file(&endpoint, &latest_data_file, None, None).await?;
+
+    // load the manifest
+    let data_json = match load_json_file(&latest_data_file) {
+        Ok(j) => j,
         Err(e) => {
-            log::error!("Failed to unzip the functions.zip: {}", e);
-            return Err(e);
+            return Err(format!(
+                "Failed to parse the latest data file: {:?}, Error:{}",
+                &latest_data_file, e
+            )
+            .into())
         }
     };
+    let version: String = data_json["version"].as_str().unwrap_or("0").to_string();
+    let url = match data_json["url"].as_str() {
+        Some(s) => s,
+        None => return Err(format!("Invalid {}.json, missing url", bundle).into()),
+    };
 
-    if result.status != "ok" {
-        log::error!("Failed to unzip the functions.zip: {}", result.message);
-        return Err(result.message.into());
+    Ok(BundleRelease {
+        version,
+        package: resolve_package_url(&endpoint, url),
+        is_local_zip: false,
+        sha256: data_json["sha256"].as_str().map(|s| s.to_string()),
+        size: data_json["size"].as_u64(),
+    })
+}
+
+/// a relative url is relative to the manifest, handy for mirrors
+fn resolve_package_url(manifest_url: &str, url: &str) -> String {
+    if url.contains("://") {
+        return url.to_string();
     }
+    let manifest_dir = manifest_url.rsplit_once('/').map(|(d, _)| d).unwrap_or("");
+    format!("{}/{}", manifest_dir, url.trim_start_matches("./"))
+}
 
-    // clean the cur ver's `functions` directory at first (if exists)
-    if target_dir.exists() {
-        match std::fs::remove_dir_all(&target_dir) {
-            Ok(_) => {}
-            Err(e) => {
-                log::error!("Failed to remove the existing functions directory: {}", e);
+/// the local path of a `file://` url, percent-encoded bytes like `%20` are decoded
+fn file_url_to_path(url: &str) -> Option<PathBuf> {
+    let encoded = url.strip_prefix("file://")?.as_bytes();
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        let hex = encoded
+            .get(i + 1..i + 3)
+            .filter(|h| h.iter().all(u8::is_ascii_hexdigit));
+        match hex {
+            Some(h) if encoded[i] == b'%' => {
+                // two ascii hex digits
+                let h = std::str::from_utf8(h).ok()?;
+                decoded.push(u8::from_str_radix(h, 16).ok()?);
+                i += 3;
+            }
+            _ => {
+                decoded.push(encoded[i]);
+                i += 1;
             }
         }
     }
-    // create target_dir's parent dir
-    let target_parent = match target_dir.parent() {
-        Some(p) => p,
-        None => return Err("Failed to get the parent dir of the target dir".into()),
+    let path = String::from_utf8_lossy(&decoded).to_string();
+
+    // `file:///C:/dir` on Windows
+    #[cfg(target_os = "windows")]
+    let path = match path.as_bytes().get(2) {
+        Some(b':') if path.starts_with('/') => path[1..].to_string(),
+        _ => path,
     };
-    match std::fs::create_dir_all(&target_parent) {
-        Ok(_) => {}
-        Err(e) => {
-            log::error!("Failed to create the functions directory: {}", e);
+
+    Some(PathBuf::from(path))
+}
+
+/// get a file by http(s) or from a `file://` path, verified by `sha256` and `size` if given
+pub async fn fetch_file(
+    url: &str,
+    output_file: &Path,
+    sha256: Option<&str>,
+    size: Option<u64>,
+) -> Result<(), BoxedError> {
+    let local_path = match file_url_to_path(url) {
+        Some(p) => p,
+        None => {
+            let output_file_str = output_file
+                .to_str()
+                .ok_or("Failed to get the destination file path")?;
+            let mut params = json!({
+                "url":url,
+                "output_file":output_file_str,
+                "resume":false
+            });
+            if let Some(sha256) = sha256 {
+                params["sha256"] = json!(sha256);
+            }
+            if let Some(size) = size {
+                params["size"] = json!(size);
+            }
+            let result = download_file(&params).await?;
+            if result.status != "ok" {
+                return Err(result.message.into());
+            }
+            return Ok(());
         }
+    };
+
+    log::info!("copy {:?} to {:?}", local_path, output_file);
+    if let Some(parent) = output_file.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if let Err(e) = std::fs::copy(&local_path, output_file) {
+        return Err(format!("Failed to copy {:?}: {}", local_path, e).into());
     }
 
-    // rename `unzipped_dir/functions` to `target_dir`
-    let from_dir = unzipped_dir.join("functions");
-    match std::fs::rename(&from_dir, &target_dir) {
-        Ok(_) => {}
-        Err(e) => {
-            log::error!(
-                "Failed to rename dir: from {:?} to {:?}. Error: {:?}",
-                from_dir,
-                target_dir,
-                e
-            );
+    if let Some(expected_size) = size {
+        let actual_size = std::fs::metadata(output_file)?.len();
+        if actual_size != expected_size {
+            return Err(format!(
+                "Size mismatch: expected {} bytes, got {} bytes",
+                expected_size, actual_size
+            )
+            .into());
+        }
+    }
+    if let Some(expected_sha256) = sha256 {
+        let digests = hash_file(output_file, &["sha256"])?;
+        let actual_sha256 = digests.get("sha256").cloned().unwrap_or_default();
+        if !actual_sha256.eq_ignore_ascii_case(expected_sha256.trim()) {
+            return Err(format!(
+                "Checksum mismatch: expected sha256 {}, got {}",
+                expected_sha256, actual_sha256
+            )
+            .into());
         }
     }
 
-    // remove the downloaded files
-    std::fs::remove_file(&downloaded_file)?;
-    std::fs::remove_dir_all(&unzipped_dir)?;
+    Ok(())
+}
 
-    // re-read the version file
-    let new_version = match std::fs::read_to_string(&version_file) {
-        Ok(s) => s.trim().to_string(),
-        Err(_) => {
-            log::error!(
-                "Failed to read the version file: {}",
-                version_file.to_str().unwrap()
-            );
-            "0".to_string()
-        }
-    };
+fn read_current_version(bundle: &str) -> Result<String, BoxedError> {
+    let target_dir = get_bundle_target_dir(bundle)?;
+    if target_dir.join("version.txt").exists() {
+        Ok(read_bundle_version(&target_dir))
+    } else {
+        Ok("0".to_string())
+    }
+}
+
+/// report the available versions of the bundles without installing
+pub async fn check_updates() -> Result<CommandResult, BoxedError> {
+    let source = UpdateSource::from_config()?;
+
+    let mut bundles = json!({});
+    for bundle in [BUNDLE_FUNCTIONS, BUNDLE_I18N] {
+        let current_version = read_current_version(bundle)?;
+        bundles[bundle] = match fetch_bundle_release(&source, bundle).await {
+            Ok(release) => json!({
+                "current_version": current_version,
+                "latest_version": release.version,
+                "update_available": is_newer_version(&release.version, &current_version),
+                "package": release.package,
+            }),
+            Err(e) => {
+                log::error!("Failed to check {} updates: {}", bundle, e);
+                json!({
+                    "current_version": current_version,
+                    "error": e.to_string(),
+                })
+            }
+        };
+    }
 
-    // command result
     let mut cmd_rst = CommandResult::default();
-    cmd_rst.content = json!(new_version);
-    cmd_rst.message = "Functions updated".to_string();
+    cmd_rst.content = json!({
+        "source": source.base,
+        "channel": source.channel,
+        "bundles": bundles,
+    });
 
     Ok(cmd_rst)
 }
 
-pub async fn update_i18n() -> Result<CommandResult, BoxedError> {
-    let app_ver_dirname = get_app_ver_dirname();
+/// download, verify and install the latest bundle:
+/// 1. get the manifest `for-app-<ver>.json` (version, url, sha256, size) from the update source
+/// 2. download the zip, verified by the manifest's sha256 (required) and size
+/// 3. unpack into a staging dir and validate every file in it
+/// 4. swap the staging dir in, keeping the current one for `updater.rollback`
+async fn update_bundle(bundle: &str) -> Result<CommandResult, BoxedError> {
+    let target_dir = get_bundle_target_dir(bundle)?;
     let downloads_dir = get_app_download_dir()?;
-    let target_dir = get_i18n_dir_in_app_data()?;
+    let source = UpdateSource::from_config()?;
 
-    // download the latest i18n.json
-    let endpoint = format!(
-        "https://releases.filethings.net/res/i18n/for-app-{}.json",
-        app_ver_dirname
-    );
-    log::info!("check i18n from: {}", endpoint);
-    let latest_data_file = downloads_dir.join("latest-i18n.json");
-    let latest_data_file_str = match latest_data_file.to_str() {
-        Some(s) => s,
-        None => return Err("Failed to get the latest data file path".into()),
-    };
-    let params = json!({
-        "url":endpoint,
-        "output_file":&latest_data_file_str,
-        "resume":false
-    });
-    let result = download_file(&params).await?;
-    if result.status != "ok" {
-        return Err(result.message.into());
-    }
-
-    // load the data json
-    let data_str = match std::fs::read_to_string(&latest_data_file) {
-        Ok(s) => s,
-        Err(e) => {
-            return Err(format!(
-                "Failed to read the latest data file: {}, Error:{}",
-                &latest_data_file_str, e
-            )
-            .into())
-        }
-    };
-    let data_json: Result<serde_json::Value, serde_json::Error> =
-        serde_json::from_str(data_str.as_str());
-    let data_json = match data_json {
-        Ok(j) => j,
-        Err(e) => return Err(format!("Failed to parse the data json: {}", e).into()),
-    };
-    let new_version: String = data_json["version"].as_str().unwrap_or("0").to_string();
-
-    // read the current version
-    let version_file = &target_dir.join("version.txt");
-    let current_version = if version_file.exists() {
-        std::fs::read_to_string(&version_file)?.trim().to_string()
-    } else {
-        "0".to_string()
-    };
+    let release = fetch_bundle_release(&source, bundle).await?;
+    log::info!("found remote version: {}", release.version);
 
     // compare the version
-    if new_version <= current_version {
+    let current_version = read_current_version(bundle)?;
+    if !is_newer_version(&release.version, &current_version) {
         log::info!("It's already the latest version: {}", current_version);
         // no need to update
         let mut cmd_rst = CommandResult::default();
@@ -249,122 +408,229 @@ pub async fn update_i18n() -> Result<CommandResult, BoxedError> {
         return Ok(cmd_rst);
     }
 
-    log::info!("Update the i18n to version: {}", new_version);
-
-    let url = match data_json["url"].as_str() {
-        Some(s) => s,
-        None => return Err("Invalid i18n.json, missing url".into()),
+    log::info!("Update the {} to version: {}", bundle, release.version);
+
+    let downloaded_file = match release.is_local_zip {
+        true => PathBuf::from(&release.package),
+        false => {
+            let downloaded_file = downloads_dir.join(format!("{}.zip", bundle));
+            // a package is only installed verified, a local zip is chosen by the user
+            if release.sha256.is_none() {
+                return Err(format!(
+                    "No sha256 in the {} manifest, can't verify the package",
+                    bundle
+                )
+                .into());
+            }
+            fetch_file(
+                &release.package,
+                &downloaded_file,
+                release.sha256.as_deref(),
+                release.size,
+            )
+            .await?;
+            downloaded_file
+        }
     };
-    let downloaded_file = downloads_dir.join("i18n.zip");
     let downloaded_file_str = match downloaded_file.to_str() {
         Some(s) => s,
         None => return Err("Failed to get the destination file path".into()),
     };
 
-    let params = json!({
-        "url":url,
-        "output_file":downloaded_file_str,
-        "resume":false
-    });
-    let result = download_file(&params).await?;
-    if result.status != "ok" {
-        return Err(result.message.into());
+    // unpack into the staging dir
+    let staging_dir = downloads_dir.join(format!("{}_new", bundle));
+    if staging_dir.exists() {
+        std::fs::remove_dir_all(&staging_dir)?;
     }
-
-    // unzip the .zip
-    let unzipped_dir = downloads_dir.join("i18n_new");
-    //  - clear the existing `unzipped_dir` if it exists
-    if unzipped_dir.exists() {
-        match std::fs::remove_dir_all(&unzipped_dir) {
-            Ok(_) => {}
-            Err(e) => {
-                log::error!("Failed to remove the existing unzipped directory: {}", e);
-            }
-        }
-    }
-    //  - create the `unzipped_dir`
-    match std::fs::create_dir_all(&unzipped_dir) {
-        Ok(_) => {}
-        Err(e) => {
-            log::error!("Failed to create the unzipped directory: {}", e);
-        }
-    }
-    let unzipped_dir_str = match unzipped_dir.to_str() {
+    std::fs::create_dir_all(&staging_dir)?;
+    let staging_dir_str = match staging_dir.to_str() {
         Some(s) => s,
         None => return Err("Failed to get the unzipped directory path".into()),
     };
     let params = json!({
         "input_file":downloaded_file_str,
-        "output_dir":unzipped_dir_str
+        "output_dir":staging_dir_str
     });
     let result = match unzip_file(&params) {
         Ok(r) => r,
         Err(e) => {
-            log::error!("Failed to unzip the i18n.zip: {}", e);
+            log::error!("Failed to unzip the {}.zip: {}", bundle, e);
             return Err(e);
         }
     };
     if result.status != "ok" {
-        log::error!("Failed to unzip the i18n.zip: {}", result.message);
+        log::error!("Failed to unzip the {}.zip: {}", bundle, result.message);
         return Err(result.message.into());
     }
 
-    // remove the dest `i18n` directory at first
-    if target_dir.exists() {
-        match std::fs::remove_dir_all(&target_dir) {
-            Ok(_) => {}
-            Err(e) => {
-                log::error!("Failed to remove the existing i18n directory: {}", e);
-            }
-        }
+    // validate before touching the current version
+    let new_dir = staging_dir.join(bundle);
+    let validated = match bundle {
+        BUNDLE_FUNCTIONS => validate_functions_bundle(&new_dir),
+        _ => validate_i18n_bundle(&new_dir),
+    };
+    if let Err(e) = validated {
+        log::error!("Invalid {} bundle: {}", bundle, e);
+        let _ = std::fs::remove_dir_all(&staging_dir);
+        return Err(format!("Invalid {} bundle: {}", bundle, e).into());
+    }
+
+    swap_in_dir(&new_dir, &target_dir)?;
+
+    // clean the downloaded files
+    if !release.is_local_zip {
+        std::fs::remove_file(&downloaded_file)?;
     }
-    // create the target_dir's parent dir
+    std::fs::remove_dir_all(&staging_dir)?;
+
+    // re-read the version file
+    let new_version = read_bundle_version(&target_dir);
+
+    // command result
+    let mut cmd_rst = CommandResult::default();
+    cmd_rst.content = json!(new_version);
+
+    Ok(cmd_rst)
+}
+
+/// move `new_dir` to `target_dir`, the current `target_dir` is kept as the backup dir.
+/// both renames are in the same parent dir, if the second one fails the current one is restored
+fn swap_in_dir(new_dir: &Path, target_dir: &Path) -> Result<(), BoxedError> {
     let target_parent = match target_dir.parent() {
         Some(p) => p,
         None => return Err("Failed to get the parent dir of the target dir".into()),
     };
-    match std::fs::create_dir_all(&target_parent) {
-        Ok(_) => {}
-        Err(e) => {
-            log::error!("Failed to create the i18n directory: {}", e);
-        }
+    std::fs::create_dir_all(target_parent)?;
+
+    let backup_dir = get_backup_dir(target_dir);
+    if backup_dir.exists() {
+        std::fs::remove_dir_all(&backup_dir)?;
     }
-    // rename the unzipped `i18n` to dest `i18n`
-    let from_dir = unzipped_dir.join("i18n");
-    match std::fs::rename(&from_dir, &target_dir) {
-        Ok(_) => {}
+    if target_dir.exists() {
+        std::fs::rename(target_dir, &backup_dir)?;
+    }
+
+    // the staging dir may be on another volume, fall back to copying into place
+    let moved = match std::fs::rename(new_dir, target_dir) {
+        Ok(_) => Ok(()),
         Err(e) => {
-            log::error!(
-                "Failed to rename dir: from {:?} to {:?}. Error: {:?}",
-                from_dir,
+            log::warn!(
+                "Failed to rename dir: from {:?} to {:?}, try to copy. Error: {:?}",
+                new_dir,
                 target_dir,
                 e
             );
+            copy_dir_all(new_dir, target_dir)
         }
+    };
+    if let Err(e) = moved {
+        log::error!("Failed to install {:?}: {}", target_dir, e);
+        let _ = std::fs::remove_dir_all(target_dir);
+        if backup_dir.exists() {
+            std::fs::rename(&backup_dir, target_dir)?;
+        }
+        return Err(format!("Failed to install the new version: {}", e).into());
     }
 
-    // clean the downloaded files
-    std::fs::remove_file(&downloaded_file)?;
-    std::fs::remove_dir_all(&unzipped_dir)?;
+    Ok(())
+}
 
-    // re-read the version file
-    let new_version = match std::fs::read_to_string(&version_file) {
-        Ok(s) => s.trim().to_string(),
-        Err(_) => {
-            log::error!(
-                "Failed to read the version file: {}",
-                version_file.to_str().unwrap()
-            );
-            "0".to_string()
+/// every function in the bundle must have a valid config.json and a worker.js.
+/// functions ignored for the current platform or app version are not errors
+fn validate_functions_bundle(bundle_dir: &Path) -> Result<(), BoxedError> {
+    if !bundle_dir.join("version.txt").is_file() {
+        return Err("version.txt not found".into());
+    }
+
+    let mut count = 0;
+    for (cat_dir_name, func_type) in [
+        ("files", "file"),
+        ("executables", "tool.exe"),
+        ("models", "tool.model"),
+    ] {
+        let category_dir = bundle_dir.join(cat_dir_name);
+        for sub_dirname in get_sub_dirnames(&category_dir) {
+            let func_dir = category_dir.join(&sub_dirname);
+            let function_name = format!("{}.{}", func_type, sub_dirname);
+            let config = match load_json_file(func_dir.join("config.json")) {
+                Ok(v) => v,
+                Err(e) => {
+                    return Err(format!("{}: invalid config.json, {}", function_name, e).into())
+                }
+            };
+            let config = match FunctionConfig::parse(&config, Some(func_type)) {
+                Ok(c) => c,
+                Err(errors) => {
+                    return Err(format!(
+                        "{}: invalid config.json, {}",
+                        function_name,
+                        join_config_errors(&errors)
+                    )
+                    .into())
+                }
+            };
+            let worker_file = func_dir.join("worker.js");
+            if !worker_file.is_file() {
+                return Err(format!("{}: worker.js not found", function_name).into());
+            }
+
+            let worker_file = worker_file.to_string_lossy().to_string();
+            let utils_file = bundle_dir
+                .join("_utils")
+                .join(if func_type == "file" {
+                    "file.js"
+                } else {
+                    "tool.js"
+                })
+                .to_string_lossy()
+                .to_string();
+            let loaded = match func_type {
+                "file" => FileFunction::new(&function_name, &config, &worker_file, &utils_file)
+                    .map(|_| ()),
+                _ => ToolFunction::new(&function_name, &config, &worker_file, &utils_file)
+                    .map(|_| ()),
+            };
+            match loaded {
+                Ok(_) => count += 1,
+                // filtered out by the matches, valid but not for this app
+                Err(e) if e.downcast_ref::<FunctionIgnored>().is_some() => {
+                    log::debug!("{}: {}", function_name, e);
+                }
+                Err(e) => return Err(format!("{}: {}", function_name, e).into()),
+            }
         }
-    };
+    }
 
-    // command result
-    let mut cmd_rst = CommandResult::default();
-    cmd_rst.content = json!(new_version);
-    cmd_rst.message = "I18N translations updated".to_string();
+    if count == 0 {
+        return Err("No function available in the bundle".into());
+    }
+    log::info!("Validated {} functions in {:?}", count, bundle_dir);
 
-    Ok(cmd_rst)
+    Ok(())
+}
+
+/// all the json files of the i18n bundle must be valid
+fn validate_i18n_bundle(bundle_dir: &Path) -> Result<(), BoxedError> {
+    if !bundle_dir.join("version.txt").is_file() {
+        return Err("version.txt not found".into());
+    }
+    if let Err(e) = load_json_file(bundle_dir.join("languages.json")) {
+        return Err(format!("invalid languages.json, {}", e).into());
+    }
+
+    for lang in get_sub_dirnames(&bundle_dir.to_path_buf()) {
+        for entry in std::fs::read_dir(bundle_dir.join(&lang))? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            if let Err(e) = load_json_file(&path) {
+                return Err(format!("invalid {}/{:?}, {}", lang, path.file_name(), e).into());
+            }
+        }
+    }
+
+    Ok(())
 }
 
 pub async fn download_app_windows_installer(
@@ -375,9 +641,10 @@ pub async fn download_app_windows_installer(
         None => return Err("`version` is required".into()),
     };
 
+    let source = UpdateSource::from_config()?;
     let endpoint = format!(
-        "https://releases.filethings.net/app/v{}/FileThings_{}_x64-setup.exe",
-        &version, &version
+        "{}/app/v{}/FileThings_{}_x64-setup.exe",
+        source.base, &version, &version
     );
 
     let downloads_dir = get_app_download_dir()?;
@@ -388,15 +655,7 @@ pub async fn download_app_windows_installer(
         Some(s) => s,
         None => return Err("Failed to get the installer file path".into()),
     };
-    let params = json!({
-        "url":endpoint,
-        "output_file":installer_file_str,
-        "resume":false
-    });
-    let result = download_file(&params).await?;
-    if result.status != "ok" {
-        return Err(result.message.into());
-    }
+    fetch_file(&endpoint, &installer_file, None, None).await?;
 
     // command result
     let mut cmd_rst = CommandResult::default();
@@ -405,3 +664,138 @@ pub async fn download_app_windows_installer(
 
     Ok(cmd_rst)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thelib::test_utils::TempDir;
+
+    fn write_bundle(dir: &Path, version: &str) {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join("version.txt"), version).unwrap();
+    }
+
+    #[test]
+    fn test_get_manifest_url() {
+        let mut source = UpdateSource {
+            base: DEFAULT_UPDATE_SOURCE.to_string(),
+            channel: "stable".to_string(),
+            local_zips: json!({}),
+        };
+        assert_eq!(
+            source.get_manifest_url(BUNDLE_FUNCTIONS, "v0.9"),
+            format!("{}/res/functions/for-app-v0.9.json", DEFAULT_UPDATE_SOURCE)
+        );
+        source.base = "file:///mnt/mirror".to_string();
+        source.channel = "beta".to_string();
+        assert_eq!(
+            source.get_manifest_url(BUNDLE_I18N, "v0.9"),
+            "file:///mnt/mirror/res/i18n/beta/for-app-v0.9.json"
+        );
+    }
+
+    #[test]
+    fn test_resolve_package_url() {
+        let manifest_url = "https://example.com/res/functions/for-app-v0.9.json";
+        assert_eq!(
+            resolve_package_url(manifest_url, "functions-20240722.zip"),
+            "https://example.com/res/functions/functions-20240722.zip"
+        );
+        assert_eq!(
+            resolve_package_url(manifest_url, "./zips/functions.zip"),
+            "https://example.com/res/functions/zips/functions.zip"
+        );
+        assert_eq!(
+            resolve_package_url(manifest_url, "https://cdn.example.com/functions.zip"),
+            "https://cdn.example.com/functions.zip"
+        );
+        assert_eq!(
+            resolve_package_url("file:///mnt/my%20mirror/for-app-v0.9.json", "f.zip"),
+            "file:///mnt/my%20mirror/f.zip"
+        );
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn test_file_url_to_path() {
+        assert_eq!(file_url_to_path("https://example.com/f.zip"), None);
+        assert_eq!(
+            file_url_to_path("file:///mnt/my%20mirror/f.zip"),
+            Some(PathBuf::from("/mnt/my mirror/f.zip"))
+        );
+        assert_eq!(
+            file_url_to_path("file:///tmp/%E6%96%87%E4%BB%B6.zip"),
+            Some(PathBuf::from("/tmp/文件.zip"))
+        );
+        // not an escape, kept as is
+        assert_eq!(
+            file_url_to_path("file:///tmp/100%.zip"),
+            Some(PathBuf::from("/tmp/100%.zip"))
+        );
+    }
+
+    #[test]
+    fn test_swap_in_dir_and_rollback() {
+        let tmp = TempDir::new("updater");
+        let target_dir = tmp.path().join("functions").join("v1");
+        write_bundle(&target_dir, "1");
+        let new_dir = tmp.path().join("staging").join("functions");
+        write_bundle(&new_dir, "2");
+
+        swap_in_dir(&new_dir, &target_dir).unwrap();
+        assert!(!new_dir.exists());
+        assert_eq!(read_bundle_version(&target_dir), "2");
+        assert_eq!(read_bundle_version(&get_backup_dir(&target_dir)), "1");
+
+        swap_with_backup_dir(&target_dir).unwrap();
+        assert_eq!(read_bundle_version(&target_dir), "1");
+        assert_eq!(read_bundle_version(&get_backup_dir(&target_dir)), "2");
+        // a rollback can be undone by another one
+        swap_with_backup_dir(&target_dir).unwrap();
+        assert_eq!(read_bundle_version(&target_dir), "2");
+        assert!(!tmp.path().join("functions").join("v1.rollback").exists());
+
+        // nothing to restore, the current version stays
+        std::fs::remove_dir_all(get_backup_dir(&target_dir)).unwrap();
+        assert!(swap_with_backup_dir(&target_dir).is_err());
+        assert_eq!(read_bundle_version(&target_dir), "2");
+    }
+
+    #[test]
+    fn test_validate_functions_bundle() {
+        let resources = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources");
+        validate_functions_bundle(&resources.join("functions")).unwrap();
+
+        let tmp = TempDir::new("updater-bundle");
+        let bundle_dir = tmp.path().join("functions");
+        let err = validate_functions_bundle(&bundle_dir).unwrap_err();
+        assert_eq!(err.to_string(), "version.txt not found");
+
+        write_bundle(&bundle_dir, "1");
+        let func_dir = bundle_dir.join("files").join("demo");
+        std::fs::create_dir_all(&func_dir).unwrap();
+        let mut config = json!({
+            "type": "file",
+            "profile": { "title": { "en": "Demo" }, "version": "1.0.0" },
+            "matches": { "platforms": ["*"], "extensions": ["txt"], "app": { "min": "999.0.0" } },
+        });
+        std::fs::write(func_dir.join("config.json"), config.to_string()).unwrap();
+        let err = validate_functions_bundle(&bundle_dir).unwrap_err();
+        assert_eq!(err.to_string(), "file.demo: worker.js not found");
+
+        // valid but for a later app version only
+        std::fs::write(func_dir.join("worker.js"), "").unwrap();
+        let err = validate_functions_bundle(&bundle_dir).unwrap_err();
+        assert_eq!(err.to_string(), "No function available in the bundle");
+
+        config["matches"]["app"] = JsonValue::Null;
+        std::fs::write(func_dir.join("config.json"), config.to_string()).unwrap();
+        validate_functions_bundle(&bundle_dir).unwrap();
+
+        std::fs::write(func_dir.join("config.json"), "{").unwrap();
+        let err = validate_functions_bundle(&bundle_dir).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("file.demo: invalid config.json"));
+    }
+}
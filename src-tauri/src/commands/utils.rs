@@ -1,6 +1,10 @@
 use crate::errors::BoxedError;
 use crate::thelib::file_path::split_file_path;
+use crate::thelib::path_matcher::Patterns;
+use crate::thelib::safe_write::ConflictPolicy;
+use chrono::{DateTime, Local, NaiveDate};
 use serde_json::Value as JsonValue;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
@@ -51,6 +55,82 @@ pub fn parse_file_conversion_params(
     Ok(fcp)
 }
 
+/// an optional array of non-empty strings
+pub fn get_string_array_from_params(
+    key: &str,
+    params: &JsonValue,
+) -> Result<Vec<String>, BoxedError> {
+    match params.get(key) {
+        None | Some(JsonValue::Null) => Ok(Vec::new()),
+        Some(v) => Ok(v
+            .as_array()
+            .ok_or(format!("`{}` must be an array", key))?
+            .iter()
+            .filter_map(|p| p.as_str())
+            .filter(|p| !p.is_empty())
+            .map(|p| p.to_string())
+            .collect()),
+    }
+}
+
+/// the patterns in `params`, all optional arrays:
+/// - `name_patterns`, `path_patterns`: regexes of the file name or the full path
+/// - `name_globs`: globs of the file name
+/// - `path_globs`: globs of the path relative to the searched dir, or of the full path
+/// - `globs`: path globs if they contain a `/`, otherwise name globs
+pub fn get_patterns_from_params(params: &JsonValue) -> Result<Patterns, BoxedError> {
+    let mut patterns = Patterns::default();
+    for p in get_string_array_from_params("name_patterns", params)? {
+        patterns.add_name_regex(&p)?;
+    }
+    for p in get_string_array_from_params("path_patterns", params)? {
+        patterns.add_path_regex(&p)?;
+    }
+    for p in get_string_array_from_params("name_globs", params)? {
+        patterns.add_name_glob(&p)?;
+    }
+    for p in get_string_array_from_params("path_globs", params)? {
+        patterns.add_path_glob(&p)?;
+    }
+    for p in get_string_array_from_params("globs", params)? {
+        patterns.add_glob(&p)?;
+    }
+    Ok(patterns)
+}
+
+/// an optional time, as unix seconds or a string like `2024-05-01` or RFC 3339
+pub fn get_time_from_params(
+    key: &str,
+    params: &JsonValue,
+) -> Result<Option<SystemTime>, BoxedError> {
+    let v = &params[key];
+    if v.is_null() {
+        return Ok(None);
+    }
+    if let Some(secs) = v.as_u64() {
+        return Ok(Some(UNIX_EPOCH + Duration::from_secs(secs)));
+    }
+    let s = v.as_str().ok_or(format!("Invalid parameter: {}", key))?;
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Ok(Some(dt.into()));
+    }
+    let date = NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map_err(|_| format!("Invalid time for `{}`: '{}'", key, s))?;
+    let dt = date
+        .and_hms_opt(0, 0, 0)
+        .and_then(|d| d.and_local_timezone(Local).earliest())
+        .ok_or(format!("Invalid time for `{}`: '{}'", key, s))?;
+    Ok(Some(dt.into()))
+}
+
+/// `conflict` param: what to do if the output file exists, default overwrite
+pub fn get_conflict_policy(params: &JsonValue) -> Result<ConflictPolicy, BoxedError> {
+    match params["conflict"].as_str() {
+        Some(v) if !v.is_empty() => ConflictPolicy::from_name(v),
+        _ => Ok(ConflictPolicy::Overwrite),
+    }
+}
+
 pub fn get_string_val_from_params(
     accept_keys: Vec<&str>,
     params: &JsonValue,
@@ -1,3 +1,4 @@
+use crate::commands::archive_cmd;
 use crate::commands::structures::CommandResult;
 use crate::commands::utils::get_string_val_from_params;
 use crate::errors::BoxedError;
@@ -6,15 +7,27 @@ use serde_json::json;
 use serde_json::Value as JsonValue;
 use std::path::Path;
 
+/// unzip a file, `password` (optional) is for AES and ZipCrypto encrypted entries.
+/// a missing or wrong password returns status "error" with content `{"error": "wrong_password"}`
+/// (or "password_required")
 pub fn unzip_file(params: &JsonValue) -> Result<CommandResult, BoxedError> {
     let input_file = get_string_val_from_params(vec!["input_file", "input_path"], params)?;
 
     let output_dir = get_string_val_from_params(vec!["output_dir"], params)?;
 
+    let password = get_string_val_from_params(vec!["password"], params).ok();
+
     let input_file_path = Path::new(&input_file);
     let output_dir_path = Path::new(&output_dir);
 
-    thelib::compress::unzip_to(input_file_path, output_dir_path)?;
+    if let Err(e) = thelib::compress::unzip_entries_to(
+        input_file_path,
+        output_dir_path,
+        None,
+        password.as_deref(),
+    ) {
+        return archive_cmd::password_error_result(e);
+    }
 
     let mut result = CommandResult::default();
     result.content = json!(output_dir);
@@ -24,3 +37,10 @@ pub fn unzip_file(params: &JsonValue) -> Result<CommandResult, BoxedError> {
 
     Ok(result)
 }
+
+/// same as `archive.create` with zip format
+pub fn create_zip(params: &JsonValue) -> Result<CommandResult, BoxedError> {
+    let mut params = params.clone();
+    params["format"] = json!("zip");
+    archive_cmd::create_archive(&params)
+}
@@ -1,7 +1,6 @@
-use crate::config::init::{USER_THING_CONFIG, USER_UI_CONFIG};
+use crate::config::init::{lock_config, USER_THING_CONFIG, USER_UI_CONFIG, USER_UPDATER_CONFIG};
 use crate::errors::{pack_called_error, CallError};
 use serde_json::Value as JsonValue;
-use std::time::{Duration, Instant};
 
 pub fn route(
     _app: &tauri::AppHandle,
@@ -16,7 +15,7 @@ pub fn route(
         action_params
     );
 
-    if !["ui", "file_function"].contains(&scope) {
+    if !["ui", "file_function", "updater"].contains(&scope) {
         return pack_called_error(&format!("Invalid scope: {}", scope));
     }
     if !["get", "set", "get_all", "set_all"].contains(&action) {
@@ -33,31 +32,12 @@ pub fn route(
         }
     };
 
-    // use independent scope to release lock; use loop to try to wait for lock automatically;
-    let start = Instant::now();
-    let mut config;
-    loop {
-        let locked = match scope {
-            "ui" => USER_UI_CONFIG.lock(),
-            "file_function" => USER_THING_CONFIG.lock(),
-            _ => return pack_called_error(&format!("Config scope not found: {}", scope)),
-        };
-
-        match locked {
-            Ok(c) => {
-                config = c;
-                break;
-            }
-            Err(_) => {
-                if start.elapsed() > Duration::from_millis(200) {
-                    return Err(CallError::new(
-                        format!("Failed to acquire lock {}", scope).as_str(),
-                    ));
-                }
-                std::thread::sleep(Duration::from_millis(10)); // wait 10ms and retry
-            }
-        }
-    }
+    let mut config = match scope {
+        "ui" => lock_config(&USER_UI_CONFIG),
+        "file_function" => lock_config(&USER_THING_CONFIG),
+        "updater" => lock_config(&USER_UPDATER_CONFIG),
+        _ => return pack_called_error(&format!("Config scope not found: {}", scope)),
+    };
 
     let result = match action {
         "get_all" => config.get_all(),
@@ -105,4 +85,19 @@ pub fn load_user_config() {
             log::error!("Failed to lock USER_THING_CONFIG: {}", e)
         }
     }
+
+    // user updater config
+    match USER_UPDATER_CONFIG.lock() {
+        Ok(mut config) => match config.load_from_file() {
+            Ok(_) => {
+                log::info!("USER_UPDATER_CONFIG loaded");
+            }
+            Err(e) => {
+                log::error!("Failed to load USER_UPDATER_CONFIG: {}", e)
+            }
+        },
+        Err(e) => {
+            log::error!("Failed to lock USER_UPDATER_CONFIG: {}", e)
+        }
+    }
 }
@@ -3,12 +3,24 @@ pub const APP_DATA_DIR_NAME: &str = "FileThings";
 
 use crate::config::structures::{AppConfig, AppStatus};
 use lazy_static::lazy_static;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, MutexGuard};
 
 lazy_static! {
     pub static ref USER_UI_CONFIG: Arc<Mutex<AppConfig>> =
         Arc::new(Mutex::new(AppConfig::new("user", "ui",)));
     pub static ref USER_THING_CONFIG: Arc<Mutex<AppConfig>> =
         Arc::new(Mutex::new(AppConfig::new("user", "file_function",)));
+    // update source and channel, see `commands::updater_cmd`
+    pub static ref USER_UPDATER_CONFIG: Arc<Mutex<AppConfig>> =
+        Arc::new(Mutex::new(AppConfig::new("user", "updater",)));
     pub static ref APP_STATUS: Arc<Mutex<AppStatus>> = Arc::new(Mutex::new(AppStatus::new()));
 }
+
+/// Lock a user config, like `lock_config(&USER_THING_CONFIG)`.
+/// A lock poisoned by a panicked holder is taken over, the config is still usable
+pub fn lock_config(config: &Mutex<AppConfig>) -> MutexGuard<'_, AppConfig> {
+    config.lock().unwrap_or_else(|e| {
+        log::warn!("Recovered a poisoned config lock");
+        e.into_inner()
+    })
+}
@@ -0,0 +1,826 @@
+//! Validation of a function's `config.json`. The expected format:
+//!
+//! - `type`: "file", "tool.exe" or "tool.model"
+//! - `profile`: `title` (text or `{lang: text}`), optional `summary`, `version`, `website`,
+//!   `authors` (`[{name, page}]`)
+//! - `matches`: `platforms` (macos, windows, linux or *), optional `app` (`{min, max}` versions),
+//!   `extensions`, `mime_types`, `size` (`{min, max}` bytes or "50 MB"), `count` (`{min, max}`),
+//!   `requires_tools`
+//! - `variables` (file functions, optional): `[{name, type, label, ...}]`, `options` for select
+//! - `bin` (tool functions): `path` (`{platform: path}`), `version_arguments` (tool.exe),
+//!   optional `version_regex` (finds the version in the output of `version_arguments`),
+//!   `required_version` (`{min, max}`), `installation`
+//!
+//! The format is defined by `FunctionConfig`, the functions are built from it
+
+use crate::functions::common::structures::parse_size;
+use crate::thelib::version::compare_semver;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::path::Path;
+
+pub const FUNCTION_TYPES: [&str; 3] = ["file", "tool.exe", "tool.model"];
+const PLATFORMS: [&str; 4] = ["macos", "windows", "linux", "*"];
+const VARIABLE_TYPES: [&str; 11] = [
+    "int", "integer", "float", "number", "text", "string", "color", "file", "select", "switch",
+    "@save_to",
+];
+
+/// type of the functions in a category dir, like "executables"
+pub fn function_type_of_category(dirname: &str) -> Option<&'static str> {
+    match dirname {
+        "files" => Some("file"),
+        "executables" => Some("tool.exe"),
+        "models" => Some("tool.model"),
+        _ => None,
+    }
+}
+
+/// One problem in a config, `path` is a JSON path like `$.matches.platforms[1]`
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigError {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// A function not loaded, with the reasons
+#[derive(Debug, Clone, Serialize)]
+pub struct RejectedFunction {
+    pub name: String,
+    pub dir: String,
+    pub reasons: Vec<String>,
+}
+
+impl RejectedFunction {
+    pub fn new(name: &str, dir: &Path, reasons: Vec<String>) -> Self {
+        log::warn!("Ignore function {}, {}", name, reasons.join("; "));
+        RejectedFunction {
+            name: name.to_string(),
+            dir: dir.to_string_lossy().to_string(),
+            reasons,
+        }
+    }
+}
+
+/// A string which must not be empty
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Text(String);
+
+impl Text {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for Text {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.is_empty() {
+            true => Err("must not be empty".to_string()),
+            false => Ok(Text(s)),
+        }
+    }
+}
+
+impl From<Text> for String {
+    fn from(text: Text) -> Self {
+        text.0
+    }
+}
+
+/// A version like 1.2.3
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Version(String);
+
+impl Version {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for Version {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match compare_semver(&s, "=", &s) {
+            Ok(_) => Ok(Version(s)),
+            Err(_) => Err(format!("invalid version '{}', expected like 1.2.3", s)),
+        }
+    }
+}
+
+/// One of `PLATFORMS`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Platform(String);
+
+impl Platform {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for Platform {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let s = Text::try_from(s)?.0;
+        match PLATFORMS.contains(&s.as_str()) {
+            true => Ok(Platform(s)),
+            false => Err(format!(
+                "unknown platform '{}', expected one of {:?}",
+                s, PLATFORMS
+            )),
+        }
+    }
+}
+
+/// A mime type like image/png or image/*, `!` to exclude it
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct MimeType(String);
+
+impl MimeType {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for MimeType {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let s = Text::try_from(s)?.0;
+        match s.trim_start_matches('!').contains('/') {
+            true => Ok(MimeType(s)),
+            false => Err(format!(
+                "invalid mime type '{}', expected like image/png or image/*",
+                s
+            )),
+        }
+    }
+}
+
+/// Bytes, as a number or a string with a unit, see `parse_size`
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(try_from = "JsonValue")]
+pub struct Size(pub u64);
+
+impl TryFrom<JsonValue> for Size {
+    type Error = String;
+
+    fn try_from(value: JsonValue) -> Result<Self, Self::Error> {
+        match parse_size(&value, "size") {
+            Ok(Some(n)) => Ok(Size(n)),
+            Ok(None) => Err("must not be null".to_string()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
+
+/// A text, or texts by language
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "JsonValue", into = "JsonValue")]
+pub struct Localized(JsonValue);
+
+impl TryFrom<JsonValue> for Localized {
+    type Error = String;
+
+    fn try_from(value: JsonValue) -> Result<Self, Self::Error> {
+        let valid = match &value {
+            JsonValue::String(s) => !s.is_empty(),
+            JsonValue::Object(map) => {
+                !map.is_empty()
+                    && map
+                        .values()
+                        .all(|v| v.as_str().is_some_and(|s| !s.is_empty()))
+            }
+            _ => false,
+        };
+        match valid {
+            true => Ok(Localized(value)),
+            false => Err("must be a string or an object of strings by language".to_string()),
+        }
+    }
+}
+
+impl From<Localized> for JsonValue {
+    fn from(localized: Localized) -> Self {
+        localized.0
+    }
+}
+
+/// `{min, max}`, both optional
+#[derive(Debug, Clone, Deserialize)]
+pub struct Range<T> {
+    pub min: Option<T>,
+    pub max: Option<T>,
+}
+
+/// A function's `config.json`, see the format above
+#[derive(Debug, Clone, Deserialize)]
+pub struct FunctionConfig {
+    #[serde(rename = "type")]
+    pub func_type: Text,
+    pub profile: ProfileConfig,
+    pub matches: MatchesConfig,
+    pub variables: Option<Vec<VariableConfig>>,
+    pub bin: Option<BinConfig>,
+    /// the config as written, for the front-end which also reads keys not checked here,
+    /// e.g. `filters` of a variable or `manual` of an installation
+    #[serde(skip)]
+    pub raw: JsonValue,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProfileConfig {
+    pub title: Localized,
+    pub summary: Option<Localized>,
+    pub version: Option<Version>,
+    pub website: Option<Text>,
+    pub authors: Option<Vec<AuthorConfig>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthorConfig {
+    pub name: Text,
+    pub page: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MatchesConfig {
+    pub platforms: Vec<Platform>,
+    pub app: Option<Range<Version>>,
+    pub extensions: Option<Vec<Text>>,
+    pub mime_types: Option<Vec<MimeType>>,
+    pub size: Option<Range<Size>>,
+    pub count: Option<Range<u64>>,
+    pub requires_tools: Option<Vec<Text>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct VariableConfig {
+    pub name: Text,
+    #[serde(rename = "type")]
+    pub var_type: Text,
+    pub label: Localized,
+    pub required: Option<bool>,
+    pub description: Option<Localized>,
+    pub placeholder: Option<Localized>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub step: Option<f64>,
+    /// of a select
+    pub options: Option<Vec<SelectOption>>,
+    pub default: Option<JsonValue>,
+    /// conditions on other variables, `@` keys are provided by the app
+    pub when_matches: Option<BTreeMap<String, Vec<JsonValue>>>,
+    pub when_not_matches: Option<BTreeMap<String, Vec<JsonValue>>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SelectOption {
+    pub value: JsonValue,
+    pub label: Localized,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BinConfig {
+    /// by platform, `*` for all
+    pub path: BTreeMap<String, Text>,
+    pub version_arguments: Option<Vec<Text>>,
+    pub version_regex: Option<Text>,
+    pub required_version: Option<Range<Version>>,
+    pub installation: Option<InstallationConfig>,
+}
+
+/// see `functions::tool::install` for the packages
+#[derive(Debug, Clone, Deserialize)]
+pub struct InstallationConfig {
+    pub auto: Option<bool>,
+    pub packages: Option<BTreeMap<String, PackageConfig>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PackageConfig {
+    pub url: Text,
+    pub sha256: Option<Text>,
+    pub format: Option<Text>,
+    pub install_dir: Option<Text>,
+    pub size: Option<u64>,
+    pub strip_components: Option<u64>,
+    pub executables: Option<Vec<Text>>,
+}
+
+impl ConfigError {
+    fn new(path: &str, message: impl Into<String>) -> Self {
+        ConfigError {
+            path: path.to_string(),
+            message: message.into(),
+        }
+    }
+
+    /// the error at the path where the config failed to deserialize
+    fn from_serde(e: &serde_path_to_error::Error<serde_json::Error>) -> Self {
+        let path = match e.path().to_string().as_str() {
+            "." => "$".to_string(),
+            p => format!("$.{}", p),
+        };
+        let message = e.inner().to_string();
+        // reported at the parent, point at the field instead
+        match message
+            .strip_prefix("missing field `")
+            .and_then(|s| s.strip_suffix('`'))
+        {
+            Some(field) => ConfigError::new(&format!("{}.{}", path, field), "is required"),
+            None => ConfigError::new(&path, message),
+        }
+    }
+}
+
+impl FunctionConfig {
+    /// Parse and check a config against the format above. `expected_type` is the type
+    /// of the functions in the category dir, if known.
+    /// A config that doesn't deserialize has only that error, otherwise all the errors
+    /// between the fields (e.g. duplicate variable names) are returned
+    pub fn parse(data: &JsonValue, expected_type: Option<&str>) -> Result<Self, Vec<ConfigError>> {
+        let mut config: FunctionConfig = serde_path_to_error::deserialize(data)
+            .map_err(|e| vec![ConfigError::from_serde(&e)])?;
+        config.raw = data.clone();
+
+        let errors = config.check(expected_type);
+        match errors.is_empty() {
+            true => Ok(config),
+            false => Err(errors),
+        }
+    }
+
+    fn check(&self, expected_type: Option<&str>) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+        let func_type = self.func_type.as_str();
+        if !FUNCTION_TYPES.contains(&func_type) {
+            errors.push(ConfigError::new(
+                "$.type",
+                format!(
+                    "unknown type '{}', expected one of {:?}",
+                    func_type, FUNCTION_TYPES
+                ),
+            ));
+        } else if let Some(expected) = expected_type.filter(|e| *e != func_type) {
+            errors.push(ConfigError::new(
+                "$.type",
+                format!("type '{}' in a dir of '{}' functions", func_type, expected),
+            ));
+        }
+
+        if self.matches.platforms.is_empty() {
+            errors.push(ConfigError::new("$.matches.platforms", "must not be empty"));
+        }
+        if let Some(variables) = &self.variables {
+            check_variables(&mut errors, variables);
+        }
+        match &self.bin {
+            Some(bin) => check_bin(&mut errors, bin, func_type == "tool.exe"),
+            None if func_type.starts_with("tool.") => {
+                errors.push(ConfigError::new("$.bin", "is required"))
+            }
+            None => {}
+        }
+        errors
+    }
+}
+
+fn check_variables(errors: &mut Vec<ConfigError>, variables: &[VariableConfig]) {
+    let names: HashSet<&str> = variables.iter().map(|v| v.name.as_str()).collect();
+    let mut seen: HashSet<&str> = HashSet::new();
+    for (i, var) in variables.iter().enumerate() {
+        let var_path = format!("$.variables[{}]", i);
+        let name = var.name.as_str();
+        if !seen.insert(name) {
+            errors.push(ConfigError::new(
+                &format!("{}.name", var_path),
+                format!("duplicate variable name '{}'", name),
+            ));
+        }
+        let var_type = var.var_type.as_str();
+        if !VARIABLE_TYPES.contains(&var_type) {
+            errors.push(ConfigError::new(
+                &format!("{}.type", var_path),
+                format!(
+                    "unknown variable type '{}', expected one of {:?}",
+                    var_type, VARIABLE_TYPES
+                ),
+            ));
+        }
+        if let (Some(min), Some(max)) = (var.min, var.max) {
+            if min > max {
+                errors.push(ConfigError::new(
+                    &format!("{}.min", var_path),
+                    format!("{} is greater than max {}", min, max),
+                ));
+            }
+        }
+
+        if var_type == "select" {
+            match &var.options {
+                Some(options) if !options.is_empty() => {
+                    let values: Vec<&JsonValue> = options.iter().map(|o| &o.value).collect();
+                    if let Some(default) = var.default.as_ref().filter(|d| !values.contains(d)) {
+                        errors.push(ConfigError::new(
+                            &format!("{}.default", var_path),
+                            format!("{} is not one of the option values", default),
+                        ));
+                    }
+                }
+                Some(_) => errors.push(ConfigError::new(
+                    &format!("{}.options", var_path),
+                    "must be a non-empty array",
+                )),
+                None => errors.push(ConfigError::new(
+                    &format!("{}.options", var_path),
+                    "is required",
+                )),
+            }
+        }
+
+        for (key, conditions) in [
+            ("when_matches", &var.when_matches),
+            ("when_not_matches", &var.when_not_matches),
+        ] {
+            for name in conditions.iter().flat_map(|c| c.keys()) {
+                if !name.starts_with('@') && !names.contains(name.as_str()) {
+                    errors.push(ConfigError::new(
+                        &format!("{}.{}.{}", var_path, key, name),
+                        format!("unknown variable '{}'", name),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+fn check_bin(errors: &mut Vec<ConfigError>, bin: &BinConfig, is_exe: bool) {
+    if bin.path.is_empty() {
+        errors.push(ConfigError::new(
+            "$.bin.path",
+            "must be a non-empty object of paths by platform",
+        ));
+    }
+    for platform in bin.path.keys() {
+        if !PLATFORMS.contains(&platform.as_str()) {
+            errors.push(ConfigError::new(
+                &format!("$.bin.path.{}", platform),
+                format!("unknown platform '{}'", platform),
+            ));
+        }
+    }
+    if is_exe && bin.version_arguments.is_none() {
+        errors.push(ConfigError::new("$.bin.version_arguments", "is required"));
+    }
+    if let Some(pattern) = &bin.version_regex {
+        if let Err(e) = Regex::new(pattern.as_str()) {
+            errors.push(ConfigError::new(
+                "$.bin.version_regex",
+                format!("invalid regex: {}", e),
+            ));
+        }
+    }
+
+    let Some(installation) = &bin.installation else {
+        return;
+    };
+    // the app only installs a download it can verify
+    let auto = installation.auto == Some(true);
+    for (platform, package) in installation.packages.iter().flatten() {
+        let package_path = format!("$.bin.installation.packages.{}", platform);
+        if !PLATFORMS.contains(&platform.as_str()) {
+            errors.push(ConfigError::new(
+                &package_path,
+                format!("unknown platform '{}'", platform),
+            ));
+        }
+        if auto && package.sha256.is_none() {
+            errors.push(ConfigError::new(
+                &format!("{}.sha256", package_path),
+                "is required",
+            ));
+        }
+    }
+}
+
+/// Check a config against the format above; all errors are returned, none if valid.
+/// `expected_type` as in `FunctionConfig::parse`
+pub fn validate_function_config(data: &JsonValue, expected_type: Option<&str>) -> Vec<ConfigError> {
+    match FunctionConfig::parse(data, expected_type) {
+        Ok(_) => Vec::new(),
+        Err(errors) => errors,
+    }
+}
+
+/// Load and check a function dir: `config.json` and the worker file.
+/// `expected_type` as in `FunctionConfig::parse`
+pub fn load_function_dir(
+    dir: &Path,
+    expected_type: Option<&str>,
+) -> Result<FunctionConfig, Vec<ConfigError>> {
+    let config_path = dir.join("config.json");
+    let content = match std::fs::read_to_string(&config_path) {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(vec![ConfigError::new(
+                "config.json",
+                format!("failed to read: {}", e),
+            )])
+        }
+    };
+    let data: JsonValue = match serde_json::from_str(&content) {
+        Ok(d) => d,
+        Err(e) => {
+            return Err(vec![ConfigError::new(
+                "config.json",
+                format!(
+                    "invalid json at line {} column {}: {}",
+                    e.line(),
+                    e.column(),
+                    e
+                ),
+            )])
+        }
+    };
+
+    let parsed = FunctionConfig::parse(&data, expected_type);
+    let mut errors = match &parsed {
+        Ok(_) => Vec::new(),
+        Err(errors) => errors.clone(),
+    };
+    if !dir.join("worker.js").is_file() {
+        errors.push(ConfigError::new("worker.js", "worker file not found"));
+    }
+    match (parsed, errors.is_empty()) {
+        (Ok(config), true) => Ok(config),
+        _ => Err(errors),
+    }
+}
+
+/// Check a function dir, see `load_function_dir`; all errors are returned, none if valid
+pub fn validate_function_dir(dir: &Path, expected_type: Option<&str>) -> Vec<ConfigError> {
+    match load_function_dir(dir, expected_type) {
+        Ok(_) => Vec::new(),
+        Err(errors) => errors,
+    }
+}
+
+/// the errors as one message
+pub fn join_config_errors(errors: &[ConfigError]) -> String {
+    errors
+        .iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thelib::test_utils::TempDir;
+    use serde_json::json;
+
+    /// `path: message` of each error
+    fn errors_of(data: &JsonValue, expected_type: Option<&str>) -> Vec<String> {
+        validate_function_config(data, expected_type)
+            .iter()
+            .map(|e| e.to_string())
+            .collect()
+    }
+
+    fn file_config() -> JsonValue {
+        json!({
+            "type": "file",
+            "profile": { "title": { "en": "Resize", "zh": "缩放" }, "version": "1.0.0" },
+            "matches": { "platforms": ["*"], "extensions": ["jpg"], "size": { "max": "50 MB" } },
+            "variables": [
+                {
+                    "name": "mode",
+                    "type": "select",
+                    "label": "Mode",
+                    "options": [
+                        { "value": "fit", "label": "Fit" },
+                        { "value": "fill", "label": { "en": "Fill" } }
+                    ],
+                    "default": "fit"
+                },
+                {
+                    "name": "width",
+                    "type": "int",
+                    "label": "Width",
+                    "min": 1,
+                    "max": 10000,
+                    "when_matches": { "mode": ["fit"], "@input_count": [1] }
+                }
+            ]
+        })
+    }
+
+    #[test]
+    fn test_bundled_configs() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("resources")
+            .join("functions");
+        let mut count = 0;
+        for category in ["files", "executables", "models"] {
+            let func_type = function_type_of_category(category);
+            for entry in std::fs::read_dir(root.join(category)).unwrap() {
+                let dir = entry.unwrap().path();
+                if !dir.is_dir() {
+                    continue;
+                }
+                let errors = validate_function_dir(&dir, func_type);
+                assert!(errors.is_empty(), "{}: {:?}", dir.display(), errors);
+                count += 1;
+            }
+        }
+        assert!(count > 0);
+    }
+
+    #[test]
+    fn test_validate_function_config() {
+        assert!(errors_of(&file_config(), Some("file")).is_empty());
+        assert_eq!(
+            errors_of(&json!("file"), None),
+            vec!["$: invalid type: string \"file\", expected struct FunctionConfig"]
+        );
+        assert_eq!(
+            errors_of(&json!({ "type": "file" }), None),
+            vec!["$.profile: is required"]
+        );
+
+        // the first value which doesn't fit the format is reported
+        let cases = [
+            (["profile", "title"], json!(1), "$.profile.title: must be a string or an object of strings by language"),
+            (["profile", "version"], json!("v1"), "$.profile.version: invalid version 'v1', expected like 1.2.3"),
+            (["matches", "platforms"], json!(["linux", ""]), "$.matches.platforms[1]: must not be empty"),
+            (["matches", "platforms"], json!(["beos"]), "$.matches.platforms[0]: unknown platform 'beos', expected one of [\"macos\", \"windows\", \"linux\", \"*\"]"),
+            (["matches", "mime_types"], json!(["image/*", "png"]), "$.matches.mime_types[1]: invalid mime type 'png', expected like image/png or image/*"),
+            (["matches", "size"], json!({ "min": "5 XB" }), "$.matches.size.min: Invalid size of `size`: \"5 XB\""),
+            (["matches", "count"], json!({ "max": -1 }), "$.matches.count.max: invalid value: integer `-1`, expected u64"),
+        ];
+        for ([parent, key], value, expected) in cases {
+            let mut data = file_config();
+            data[parent][key] = value;
+            assert_eq!(errors_of(&data, None), vec![expected]);
+        }
+
+        // then all the errors between the fields
+        let mut data = file_config();
+        data["type"] = json!("tool.exe");
+        data["matches"]["platforms"] = json!([]);
+        assert_eq!(
+            errors_of(&data, Some("file")),
+            vec![
+                "$.type: type 'tool.exe' in a dir of 'file' functions",
+                "$.matches.platforms: must not be empty",
+                "$.bin: is required",
+            ]
+        );
+        data["type"] = json!("plugin");
+        let errors = errors_of(&data, None);
+        assert!(errors[0].starts_with("$.type: unknown type 'plugin'"));
+    }
+
+    #[test]
+    fn test_validate_variables() {
+        let mut data = file_config();
+        // a select default must be one of the option values
+        data["variables"][0]["default"] = json!("stretch");
+        // conditions refer to other variables, or to `@` keys of the app
+        data["variables"][1]["when_not_matches"] = json!({ "size": ["small"], "@os": ["macos"] });
+        data["variables"][1]["min"] = json!(100);
+        data["variables"][1]["max"] = json!(10);
+        data["variables"].as_array_mut().unwrap().push(json!({
+            "name": "mode",
+            "type": "slider",
+            "label": "Again"
+        }));
+        assert_eq!(
+            errors_of(&data, Some("file")),
+            vec![
+                "$.variables[0].default: \"stretch\" is not one of the option values",
+                "$.variables[1].min: 100 is greater than max 10",
+                "$.variables[1].when_not_matches.size: unknown variable 'size'",
+                "$.variables[2].name: duplicate variable name 'mode'",
+                "$.variables[2].type: unknown variable type 'slider', expected one of [\"int\", \"integer\", \"float\", \"number\", \"text\", \"string\", \"color\", \"file\", \"select\", \"switch\", \"@save_to\"]",
+            ]
+        );
+
+        let mut data = file_config();
+        data["variables"][0]["options"][1] = json!({ "value": "fill" });
+        assert_eq!(
+            errors_of(&data, None),
+            vec!["$.variables[0].options[1].label: is required"]
+        );
+        let mut data = file_config();
+        data["variables"][1]["when_matches"]["mode"] = json!("fit");
+        assert_eq!(
+            errors_of(&data, None),
+            vec!["$.variables[1].when_matches.mode: invalid type: string \"fit\", expected a sequence"]
+        );
+        let mut data = file_config();
+        data["variables"][0]["options"] = json!([]);
+        assert_eq!(
+            errors_of(&data, None),
+            vec!["$.variables[0].options: must be a non-empty array"]
+        );
+    }
+
+    #[test]
+    fn test_validate_tool_config() {
+        let mut data = json!({
+            "type": "tool.exe",
+            "profile": { "title": "FFmpeg" },
+            "matches": { "platforms": ["macos", "linux"] },
+            "bin": {
+                "path": { "macos": "ffmpeg", "linux": "ffmpeg" },
+                "version_arguments": ["-version"],
+                "version_regex": "ffmpeg version (\\S+)",
+                "required_version": { "min": "6.0" },
+                "installation": {
+                    "auto": true,
+                    "packages": {
+                        "linux": { "url": "https://example.com/ffmpeg.tar.xz", "sha256": "abc" }
+                    }
+                }
+            }
+        });
+        assert!(errors_of(&data, Some("tool.exe")).is_empty());
+
+        // a package installed by the app needs its checksum
+        let mut no_sha256 = data.clone();
+        no_sha256["bin"]["installation"]["packages"]["linux"]["sha256"] = JsonValue::Null;
+        assert_eq!(
+            errors_of(&no_sha256, Some("tool.exe")),
+            vec!["$.bin.installation.packages.linux.sha256: is required"]
+        );
+        no_sha256["bin"]["installation"]["auto"] = json!(false);
+        assert!(errors_of(&no_sha256, Some("tool.exe")).is_empty());
+
+        let mut bad_auto = data.clone();
+        bad_auto["bin"]["installation"]["auto"] = json!("yes");
+        assert_eq!(
+            errors_of(&bad_auto, None),
+            vec!["$.bin.installation.auto: invalid type: string \"yes\", expected a boolean"]
+        );
+        let mut bad_size = data.clone();
+        bad_size["bin"]["installation"]["packages"]["linux"]["size"] = json!("1 MB");
+        assert_eq!(
+            errors_of(&bad_size, None),
+            vec!["$.bin.installation.packages.linux.size: invalid type: string \"1 MB\", expected u64"]
+        );
+
+        data["bin"]["path"] = json!({ "beos": "ffmpeg" });
+        data["bin"]["version_arguments"] = JsonValue::Null;
+        data["bin"]["version_regex"] = json!("(");
+        let errors = errors_of(&data, Some("tool.exe"));
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert_eq!(errors[0], "$.bin.path.beos: unknown platform 'beos'");
+        assert_eq!(errors[1], "$.bin.version_arguments: is required");
+        assert!(errors[2].starts_with("$.bin.version_regex: invalid regex"));
+
+        // a model has no version to run
+        data["type"] = json!("tool.model");
+        data["bin"] = json!({ "path": { "*": "models/u2net.onnx" } });
+        assert!(errors_of(&data, Some("tool.model")).is_empty());
+    }
+
+    #[test]
+    fn test_validate_function_dir() {
+        let tmp = TempDir::new("function-config");
+        let dir = tmp.path();
+        std::fs::write(dir.join("config.json"), "{\n  \"type\": \"file\",\n}").unwrap();
+        let errors = validate_function_dir(dir, Some("file"));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "config.json");
+        assert!(errors[0]
+            .message
+            .starts_with("invalid json at line 3 column 1"));
+
+        std::fs::write(dir.join("config.json"), file_config().to_string()).unwrap();
+        let errors = validate_function_dir(dir, Some("file"));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "worker.js: worker file not found");
+
+        std::fs::write(dir.join("worker.js"), "").unwrap();
+        assert!(validate_function_dir(dir, Some("file")).is_empty());
+    }
+}
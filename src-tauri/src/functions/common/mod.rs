@@ -1 +1,4 @@
+pub mod config;
+pub mod package;
+pub mod roots;
 pub mod structures;
@@ -0,0 +1,526 @@
+//! Function packages (`.ftfunc`): a zip with a `ftfunc.json` manifest and one function dir,
+//! e.g. `ftfunc.json` + `image.blur/config.json` + `image.blur/worker.js`.
+//!
+//! Packages are installed into the user function root (see `get_user_function_dir`),
+//! by category like the official dir, and recorded in its `installed.json` registry.
+
+use crate::app::resource::{get_app_temp_dir, get_function_dir_using, get_user_function_dir};
+use crate::errors::BoxedError;
+use crate::functions::common::config::{
+    function_type_of_category, join_config_errors, load_function_dir, FunctionConfig,
+};
+use crate::functions::common::roots::{collect_function_sources, get_function_roots};
+use crate::functions::file::structures::FileFunction;
+use crate::functions::tool::structures::ToolFunction;
+use crate::thelib::compress::{create_archive, unzip_to, ArchiveFormat};
+use crate::thelib::file_path::move_path;
+use crate::thelib::hash::hash_file;
+use crate::thelib::json::load_json_file;
+use crate::thelib::safe_write::write_atomic;
+use crate::thelib::sys::get_app_version;
+use crate::thelib::version::{compare_semver, is_newer_version};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+pub const PACKAGE_EXT: &str = "ftfunc";
+pub const MANIFEST_FILE: &str = "ftfunc.json";
+const REGISTRY_FILE: &str = "installed.json";
+const MANIFEST_FORMAT: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageManifest {
+    pub format: u32,
+    /// the function dir name, like "image.blur"
+    pub name: String,
+    #[serde(rename = "type")]
+    pub func_type: String,
+    /// `profile.version` of the function
+    #[serde(default)]
+    pub version: String,
+    /// the app version which exported the package
+    #[serde(default)]
+    pub app_version: String,
+}
+
+/// An entry of the installed-functions registry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstalledFunction {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub func_type: String,
+    pub version: String,
+    /// the package file path or url
+    pub source: String,
+    pub sha256: String,
+    pub installed_at: String,
+    pub dir: String,
+}
+
+impl InstalledFunction {
+    /// like "file.image.blur", the key in `FILE_FUNCTIONS` and `TOOL_FUNCTIONS`
+    pub fn full_name(&self) -> String {
+        format!("{}.{}", self.func_type, self.name)
+    }
+}
+
+pub fn category_of_function_type(func_type: &str) -> Option<&'static str> {
+    ["files", "executables", "models"]
+        .into_iter()
+        .find(|c| function_type_of_category(c) == Some(func_type))
+}
+
+/// "file.image.blur" -> ("file", "image.blur")
+pub fn split_function_name(full_name: &str) -> Result<(&'static str, &str), BoxedError> {
+    for func_type in ["tool.exe", "tool.model", "file"] {
+        if let Some(name) = full_name
+            .strip_prefix(func_type)
+            .and_then(|s| s.strip_prefix('.'))
+        {
+            if !name.is_empty() {
+                return Ok((func_type, name));
+            }
+        }
+    }
+    Err(format!(
+        "Invalid function name: '{}', expected like file.<name> or tool.exe.<name>",
+        full_name
+    )
+    .into())
+}
+
+fn check_dir_name(name: &str) -> Result<(), BoxedError> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || name.contains(['/', '\\', ':'])
+        || name.trim() != name
+    {
+        return Err(format!("Invalid function name in the package: '{}'", name).into());
+    }
+    Ok(())
+}
+
+pub fn load_registry() -> Result<BTreeMap<String, InstalledFunction>, BoxedError> {
+    load_registry_in(&get_user_function_dir()?)
+}
+
+fn load_registry_in(user_dir: &Path) -> Result<BTreeMap<String, InstalledFunction>, BoxedError> {
+    let registry_file = user_dir.join(REGISTRY_FILE);
+    if !registry_file.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let data = load_json_file(&registry_file)?;
+    Ok(serde_json::from_value(data)?)
+}
+
+fn save_registry(
+    user_dir: &Path,
+    registry: &BTreeMap<String, InstalledFunction>,
+) -> Result<(), BoxedError> {
+    let registry_file = user_dir.join(REGISTRY_FILE);
+    write_atomic(
+        &registry_file,
+        serde_json::to_string_pretty(registry)?.as_bytes(),
+    )
+}
+
+/// the app version must be within `matches.app` of the config
+fn check_app_version(config: &FunctionConfig) -> Result<(), BoxedError> {
+    let app_version = get_app_version();
+    let Some(app) = &config.matches.app else {
+        return Ok(());
+    };
+    for (bound, operator) in [(&app.min, ">="), (&app.max, "<=")] {
+        let Some(required) = bound.as_ref().map(|v| v.as_str()) else {
+            continue;
+        };
+        if !compare_semver(&app_version, operator, required)? {
+            return Err(format!(
+                "The function requires app version {} {}, current version: {}",
+                operator, required, app_version
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// load the function the same way `load_functions`/`load_tools` do
+fn check_function_loads(
+    official_dir: &Path,
+    func_dir: &Path,
+    func_type: &str,
+    name: &str,
+    config: &FunctionConfig,
+) -> Result<(), BoxedError> {
+    let function_name = format!("{}.{}", func_type, name);
+    let worker_file = func_dir.join("worker.js").to_string_lossy().to_string();
+    let utils_file = official_dir
+        .join("_utils")
+        .join(match func_type {
+            "file" => "file.js",
+            _ => "tool.js",
+        })
+        .to_string_lossy()
+        .to_string();
+    let loaded = match func_type {
+        "file" => FileFunction::new(&function_name, config, &worker_file, &utils_file).map(|_| ()),
+        _ => ToolFunction::new(&function_name, config, &worker_file, &utils_file).map(|_| ()),
+    };
+    loaded.map_err(|e| format!("Failed to load {}: {}", function_name, e).into())
+}
+
+/// Install a package file, `source` is recorded in the registry (the url it came from).
+/// An installed function is only replaced by a newer version, or by any version with `force`.
+/// An official function is only overridden with `force`
+pub fn install_package(
+    package_file: &Path,
+    source: &str,
+    force: bool,
+) -> Result<InstalledFunction, BoxedError> {
+    install_package_in(
+        package_file,
+        source,
+        force,
+        &get_user_function_dir()?,
+        &get_function_dir_using()?,
+        &get_app_temp_dir()?,
+    )
+}
+
+/// `install_package` with the function roots given, unpacking in a new dir in `temp_dir`
+fn install_package_in(
+    package_file: &Path,
+    source: &str,
+    force: bool,
+    user_dir: &Path,
+    official_dir: &Path,
+    temp_dir: &Path,
+) -> Result<InstalledFunction, BoxedError> {
+    let staging_dir = temp_dir.join(format!("ftfunc-{}", uuid::Uuid::new_v4()));
+    let result = install_package_from(
+        package_file,
+        source,
+        force,
+        user_dir,
+        official_dir,
+        &staging_dir,
+    );
+    if staging_dir.exists() {
+        let _ = std::fs::remove_dir_all(&staging_dir);
+    }
+    result
+}
+
+fn install_package_from(
+    package_file: &Path,
+    source: &str,
+    force: bool,
+    user_dir: &Path,
+    official_dir: &Path,
+    staging_dir: &Path,
+) -> Result<InstalledFunction, BoxedError> {
+    std::fs::create_dir_all(staging_dir)?;
+    if let Err(e) = unzip_to(package_file, staging_dir) {
+        return Err(format!("Failed to unpack the package: {}", e).into());
+    }
+
+    // manifest
+    let manifest_file = staging_dir.join(MANIFEST_FILE);
+    if !manifest_file.is_file() {
+        return Err(format!("Not a function package, {} not found", MANIFEST_FILE).into());
+    }
+    let manifest: PackageManifest = serde_json::from_value(load_json_file(&manifest_file)?)
+        .map_err(|e| format!("Invalid {}: {}", MANIFEST_FILE, e))?;
+    if manifest.format > MANIFEST_FORMAT {
+        return Err(format!(
+            "Unsupported package format {}, please update the app",
+            manifest.format
+        )
+        .into());
+    }
+    check_dir_name(&manifest.name)?;
+    let category = category_of_function_type(&manifest.func_type)
+        .ok_or(format!("Unknown function type: '{}'", manifest.func_type))?;
+
+    // the function itself
+    let func_dir = staging_dir.join(&manifest.name);
+    if !func_dir.is_dir() {
+        return Err(format!("Function dir '{}' not found in the package", manifest.name).into());
+    }
+    let config = load_function_dir(&func_dir, Some(&manifest.func_type))
+        .map_err(|errors| format!("Invalid function: {}", join_config_errors(&errors)))?;
+    check_app_version(&config)?;
+    check_function_loads(
+        official_dir,
+        &func_dir,
+        &manifest.func_type,
+        &manifest.name,
+        &config,
+    )?;
+    let version = config
+        .profile
+        .version
+        .as_ref()
+        .map_or("", |v| v.as_str())
+        .to_string();
+
+    let mut registry = load_registry_in(user_dir)?;
+    let key = format!("{}.{}", manifest.func_type, manifest.name);
+    if !force && official_dir.join(category).join(&manifest.name).is_dir() {
+        return Err(format!(
+            "{} is an official function, use `force` to override it",
+            key
+        )
+        .into());
+    }
+    let target_dir = user_dir.join(category).join(&manifest.name);
+    if target_dir.exists() {
+        let installed_version = registry.get(&key).map(|f| f.version.as_str());
+        let is_upgrade = installed_version.is_some_and(|v| is_newer_version(&version, v));
+        if !force && !is_upgrade {
+            return Err(format!(
+                "{} is already installed (version {}), use `force` to replace it",
+                key,
+                installed_version.unwrap_or("unknown")
+            )
+            .into());
+        }
+    }
+    // keep the installed version in the staging dir until the new one is in place
+    let previous_dir = staging_dir.join(".previous");
+    if target_dir.exists() {
+        move_path(&target_dir, &previous_dir)?;
+    }
+    if let Some(parent) = target_dir.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if let Err(e) = move_path(&func_dir, &target_dir) {
+        let _ = std::fs::remove_dir_all(&target_dir);
+        if previous_dir.exists() {
+            move_path(&previous_dir, &target_dir)?;
+        }
+        return Err(format!("Failed to install the function: {}", e).into());
+    }
+
+    let sha256 = hash_file(package_file, &["sha256"])?
+        .remove("sha256")
+        .unwrap_or_default();
+    let installed = InstalledFunction {
+        name: manifest.name.clone(),
+        func_type: manifest.func_type.clone(),
+        version,
+        source: source.to_string(),
+        sha256,
+        installed_at: chrono::Local::now().to_rfc3339(),
+        dir: target_dir.to_string_lossy().to_string(),
+    };
+    registry.insert(key, installed.clone());
+    save_registry(user_dir, &registry)?;
+    log::info!(
+        "Installed function {} into {:?}",
+        installed.full_name(),
+        target_dir
+    );
+
+    Ok(installed)
+}
+
+/// Remove an installed function, `full_name` like "file.image.blur".
+/// Only functions in the registry can be removed, not the official or local dir ones
+pub fn uninstall_function(full_name: &str) -> Result<InstalledFunction, BoxedError> {
+    uninstall_function_in(&get_user_function_dir()?, full_name)
+}
+
+fn uninstall_function_in(
+    user_dir: &Path,
+    full_name: &str,
+) -> Result<InstalledFunction, BoxedError> {
+    let (func_type, name) = split_function_name(full_name)?;
+    let mut registry = load_registry_in(user_dir)?;
+    let installed = registry
+        .remove(&format!("{}.{}", func_type, name))
+        .ok_or(format!("{} is not an installed function", full_name))?;
+
+    let dir = PathBuf::from(&installed.dir);
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir)?;
+    }
+    save_registry(user_dir, &registry)?;
+    log::info!("Uninstalled function {} from {:?}", full_name, dir);
+
+    Ok(installed)
+}
+
+/// The dir a function is loaded from, `full_name` like "file.image.blur"
+pub fn find_function_dir(full_name: &str) -> Result<(&'static str, PathBuf), BoxedError> {
+    let (func_type, name) = split_function_name(full_name)?;
+    let category = category_of_function_type(func_type).unwrap_or("files");
+    let (sources, _) = collect_function_sources(&get_function_roots()?, category);
+    match sources.into_iter().find(|s| s.name == name) {
+        Some(s) => Ok((func_type, s.dir)),
+        None => Err(format!("Function not found: {}", full_name).into()),
+    }
+}
+
+/// Pack the function in `func_dir` into `output_file`
+pub fn export_function(
+    func_dir: &Path,
+    func_type: &str,
+    output_file: &Path,
+) -> Result<PackageManifest, BoxedError> {
+    let name = func_dir
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or(format!("Invalid function dir: {:?}", func_dir))?;
+    let config = load_json_file(func_dir.join("config.json"))?;
+    let manifest = PackageManifest {
+        format: MANIFEST_FORMAT,
+        name,
+        func_type: func_type.to_string(),
+        version: config["profile"]["version"]
+            .as_str()
+            .unwrap_or("")
+            .to_string(),
+        app_version: get_app_version(),
+    };
+
+    // the manifest is added by its file name, so write it into its own temp dir
+    let staging_dir = get_app_temp_dir()?.join(format!("ftfunc-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&staging_dir)?;
+    let manifest_file = staging_dir.join(MANIFEST_FILE);
+    std::fs::write(&manifest_file, serde_json::to_string_pretty(&manifest)?)?;
+    let result = create_archive(
+        &[manifest_file, func_dir.to_path_buf()],
+        output_file,
+        ArchiveFormat::Zip,
+        None,
+        None,
+    );
+    let _ = std::fs::remove_dir_all(&staging_dir);
+    result?;
+
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thelib::test_utils::TempDir;
+    use serde_json::json;
+
+    /// a `.ftfunc` of a file function `name` at `version`, written into `dir`
+    fn make_package(dir: &Path, name: &str, version: &str) -> PathBuf {
+        let src_dir = dir.join(format!("src-{}-{}", name, version));
+        let func_dir = src_dir.join(name);
+        std::fs::create_dir_all(&func_dir).unwrap();
+        let config = json!({
+            "type": "file",
+            "profile": { "title": name, "version": version },
+            "matches": { "platforms": ["*"] },
+        });
+        std::fs::write(func_dir.join("config.json"), config.to_string()).unwrap();
+        std::fs::write(func_dir.join("worker.js"), "").unwrap();
+        let manifest = json!({ "format": 1, "name": name, "type": "file", "version": version });
+        let manifest_file = src_dir.join(MANIFEST_FILE);
+        std::fs::write(&manifest_file, manifest.to_string()).unwrap();
+
+        let package_file = dir.join(format!("{}-{}.{}", name, version, PACKAGE_EXT));
+        create_archive(
+            &[manifest_file, func_dir],
+            &package_file,
+            ArchiveFormat::Zip,
+            None,
+            None,
+        )
+        .unwrap();
+        package_file
+    }
+
+    #[test]
+    fn test_split_function_name() {
+        assert_eq!(
+            split_function_name("file.image.blur").unwrap(),
+            ("file", "image.blur")
+        );
+        assert_eq!(
+            split_function_name("tool.exe.ffmpeg").unwrap(),
+            ("tool.exe", "ffmpeg")
+        );
+        assert_eq!(
+            split_function_name("tool.model.u2net").unwrap(),
+            ("tool.model", "u2net")
+        );
+        for name in ["file", "file.", "tool.exe", "tool.ffmpeg", "image.blur", ""] {
+            assert!(split_function_name(name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_check_dir_name() {
+        for name in ["image.blur", "ffmpeg", "text-upper_2"] {
+            assert!(check_dir_name(name).is_ok(), "{}", name);
+        }
+        for name in ["", ".hidden", "..", "a/b", "a\\b", "c:", " blur", "blur "] {
+            assert!(check_dir_name(name).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn test_install_upgrade_uninstall() {
+        let tmp = TempDir::new("ftfunc");
+        let dir = tmp.path();
+        let user_dir = dir.join("user");
+        let official_dir = dir.join("official");
+        let temp_dir = dir.join("temp");
+        std::fs::create_dir_all(official_dir.join("files").join("image.blur")).unwrap();
+        let install = |package_file: &Path, force: bool| {
+            install_package_in(
+                package_file,
+                "test",
+                force,
+                &user_dir,
+                &official_dir,
+                &temp_dir,
+            )
+        };
+
+        let v1 = make_package(dir, "text.upper", "1.0.0");
+        let installed = install(&v1, false).unwrap();
+        assert_eq!(installed.full_name(), "file.text.upper");
+        assert_eq!(installed.version, "1.0.0");
+        assert_eq!(installed.sha256.len(), 64);
+        let func_dir = user_dir.join("files").join("text.upper");
+        assert_eq!(PathBuf::from(&installed.dir), func_dir);
+        assert!(func_dir.join("worker.js").is_file());
+
+        // the same version again, or an older one, needs `force`
+        let err = install(&v1, false).unwrap_err().to_string();
+        assert!(err.contains("already installed (version 1.0.0)"), "{}", err);
+
+        let v2 = make_package(dir, "text.upper", "1.1.0");
+        assert_eq!(install(&v2, false).unwrap().version, "1.1.0");
+        assert!(install(&v1, false).is_err());
+        assert_eq!(install(&v1, true).unwrap().version, "1.0.0");
+        let registry = load_registry_in(&user_dir).unwrap();
+        assert_eq!(registry.len(), 1);
+        assert_eq!(registry["file.text.upper"].version, "1.0.0");
+
+        // an official function is only overridden with `force`
+        let blur = make_package(dir, "image.blur", "9.0.0");
+        let err = install(&blur, false).unwrap_err().to_string();
+        assert!(err.contains("is an official function"), "{}", err);
+        assert!(!user_dir.join("files").join("image.blur").exists());
+        assert!(install(&blur, true).is_ok());
+
+        // nothing is left behind in the staging dir
+        assert_eq!(std::fs::read_dir(&temp_dir).unwrap().count(), 0);
+
+        let removed = uninstall_function_in(&user_dir, "file.text.upper").unwrap();
+        assert_eq!(removed.version, "1.0.0");
+        assert!(!func_dir.exists());
+        let registry = load_registry_in(&user_dir).unwrap();
+        assert_eq!(registry.keys().collect::<Vec<_>>(), vec!["file.image.blur"]);
+        assert!(uninstall_function_in(&user_dir, "file.text.upper").is_err());
+    }
+}
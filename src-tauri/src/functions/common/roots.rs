@@ -0,0 +1,129 @@
+//! Function roots: the official function dir (see `get_function_dir_using`), the dir of
+//! installed packages (see `functions::common::package`) and the local function dirs added
+//! by the user in the `file_function` config, key `local_function_dirs`. Each root has the
+//! category dirs, e.g. `files/<name>/config.json`.
+//!
+//! Override order: local dirs in the configured order, the installed packages, then the
+//! official dir. A function
+//! found in several roots is loaded from the first one, the others are reported as conflicts.
+//! Local dirs are not touched by `updater.update_functions`.
+
+use crate::app::resource::{get_function_dir_using, get_user_function_dir};
+use crate::config::init::{lock_config, USER_THING_CONFIG};
+use crate::errors::BoxedError;
+use crate::functions::common::config::RejectedFunction;
+use crate::thelib::file_path::get_sub_dirnames;
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+pub const CONFIG_KEY_LOCAL_FUNCTION_DIRS: &str = "local_function_dirs";
+
+/// A function dir found in a root
+#[derive(Debug, Clone)]
+pub struct FunctionSource {
+    /// the dir name, like "image.resize"
+    pub name: String,
+    pub dir: PathBuf,
+}
+
+/// A function name in several roots
+#[derive(Debug, Clone, Serialize)]
+pub struct FunctionConflict {
+    pub name: String,
+    pub used: String,
+    pub ignored: Vec<String>,
+}
+
+/// The result of (re)loading functions
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LoadReport {
+    pub rejected: Vec<RejectedFunction>,
+    pub conflicts: Vec<FunctionConflict>,
+}
+
+impl LoadReport {
+    pub fn extend(&mut self, other: LoadReport) {
+        self.rejected.extend(other.rejected);
+        self.conflicts.extend(other.conflicts);
+    }
+}
+
+pub fn get_local_function_dirs() -> Result<Vec<PathBuf>, BoxedError> {
+    let config: JsonValue = lock_config(&USER_THING_CONFIG).get_all()?;
+
+    let dirs = match config[CONFIG_KEY_LOCAL_FUNCTION_DIRS].as_array() {
+        Some(a) => a
+            .iter()
+            .filter_map(|v| v.as_str())
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| PathBuf::from(s.trim()))
+            .collect(),
+        None => Vec::new(),
+    };
+    Ok(dirs)
+}
+
+/// All root dirs, in override order
+pub fn get_function_roots() -> Result<Vec<PathBuf>, BoxedError> {
+    let official_dir = get_function_dir_using()?;
+    let user_dir = get_user_function_dir()?;
+    let mut roots: Vec<PathBuf> = Vec::new();
+    for dir in get_local_function_dirs()? {
+        if !dir.is_dir() {
+            log::warn!("Local function dir not found: {}", dir.to_string_lossy());
+            continue;
+        }
+        if dir == official_dir || dir == user_dir || roots.contains(&dir) {
+            continue;
+        }
+        roots.push(dir);
+    }
+    if user_dir.is_dir() {
+        roots.push(user_dir);
+    }
+    roots.push(official_dir);
+    Ok(roots)
+}
+
+/// The function dirs of a category, e.g. "files", the first root wins on the same name
+pub fn collect_function_sources(
+    roots: &[PathBuf],
+    category: &str,
+) -> (Vec<FunctionSource>, Vec<FunctionConflict>) {
+    let mut sources: Vec<FunctionSource> = Vec::new();
+    let mut ignored: HashMap<String, Vec<String>> = HashMap::new();
+    for root in roots {
+        let category_dir = root.join(category);
+        for name in get_sub_dirnames(&category_dir) {
+            let dir = category_dir.join(&name);
+            if sources.iter().any(|s| s.name == name) {
+                ignored
+                    .entry(name)
+                    .or_default()
+                    .push(dir.to_string_lossy().to_string());
+                continue;
+            }
+            sources.push(FunctionSource { name, dir });
+        }
+    }
+
+    let mut conflicts: Vec<FunctionConflict> = Vec::new();
+    for source in &sources {
+        if let Some(dirs) = ignored.remove(&source.name) {
+            log::warn!(
+                "Function {} in {} overrides {}",
+                source.name,
+                source.dir.to_string_lossy(),
+                dirs.join(", ")
+            );
+            conflicts.push(FunctionConflict {
+                name: format!("{}/{}", category, source.name),
+                used: source.dir.to_string_lossy().to_string(),
+                ignored: dirs,
+            });
+        }
+    }
+    (sources, conflicts)
+}
@@ -1,6 +1,10 @@
 use crate::errors::BoxedError;
+use crate::functions::common::config::{MatchesConfig, ProfileConfig, Version};
+use crate::thelib::file_path::FileSelection;
+use crate::thelib::file_type::{mime_matches, FileKind};
 use serde_json::{json, Value as JsonValue};
 use std::collections::HashSet;
+use std::fmt;
 
 #[derive(Clone, Debug)]
 pub struct FunctionProfile {
@@ -11,13 +15,21 @@ pub struct FunctionProfile {
     pub authors: JsonValue,
 }
 impl FunctionProfile {
-    pub fn from_json(data: &JsonValue) -> FunctionProfile {
+    pub fn from_config(config: &ProfileConfig) -> FunctionProfile {
         FunctionProfile {
-            title: data["title"].clone(),
-            summary: data["summary"].clone(),
-            version: data["version"].as_str().unwrap_or("").to_string(),
-            website: data["website"].as_str().unwrap_or("").to_string(),
-            authors: data["authors"].clone(),
+            title: json!(config.title),
+            summary: json!(config.summary),
+            version: config
+                .version
+                .as_ref()
+                .map_or("", |v| v.as_str())
+                .to_string(),
+            website: config
+                .website
+                .as_ref()
+                .map_or("", |w| w.as_str())
+                .to_string(),
+            authors: json!(config.authors),
         }
     }
 
@@ -32,48 +44,299 @@ impl FunctionProfile {
     }
 }
 
+// codes of `MatchMismatch`
+pub const MISMATCH_FILE_TYPE: &str = "file_type";
+pub const MISMATCH_EXCLUDED_TYPE: &str = "excluded_type";
+pub const MISMATCH_SELECTION_TYPE: &str = "selection_type";
+pub const MISMATCH_COUNT: &str = "count";
+pub const MISMATCH_SIZE: &str = "size";
+pub const MISMATCH_TOOL: &str = "tool";
+
+/// special file kinds of a selection, see `get_file_selection`; later ones override earlier
+const SELECTION_TAGS: [&str; 5] = ["/file", "/dir", "/files", "/dirs", "/paths"];
+
+/// why a function does not match the selection, shown in the UI
+#[derive(Clone, Debug)]
+pub struct MatchMismatch {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl MatchMismatch {
+    fn new(code: &'static str, message: String) -> Self {
+        MatchMismatch { code, message }
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        json!({
+            "code": self.code,
+            "message": self.message,
+        })
+    }
+}
+
+/// A valid function which is not for the current platform or app version
+#[derive(Debug)]
+pub struct FunctionIgnored {
+    pub reason: String,
+}
+
+impl FunctionIgnored {
+    pub fn new(reason: impl Into<String>) -> Self {
+        FunctionIgnored {
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for FunctionIgnored {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Ignore the function, {}", self.reason)
+    }
+}
+
+impl std::error::Error for FunctionIgnored {}
+
 #[derive(Clone, Debug)]
 pub struct FunctionMatches {
     pub platforms: HashSet<String>,
+    /// with the special kinds of the selection, `!/dir` etc. to deny them
     pub extensions: HashSet<String>,
+    /// from `!ext` in extensions
+    pub excluded_extensions: HashSet<String>,
+    /// mime types detected from the file content, `type/*` allowed
+    pub mime_types: HashSet<String>,
+    /// from `!type` in mime_types
+    pub excluded_mime_types: HashSet<String>,
+    /// size limits of each file, in bytes
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    /// limits of the number of selected paths
+    pub min_count: Option<u64>,
+    pub max_count: Option<u64>,
+    /// tool functions which must be available
+    pub requires_tools: Vec<String>,
     pub app_version_min: String,
     pub app_version_max: String,
 }
+
+fn get_lowercase_strings<'a>(items: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    items.into_iter().map(|v| v.to_lowercase()).collect()
+}
+
+/// bytes as a number, or a string with a unit like "50 MB" (1 MB = 1024 KB)
+pub fn parse_size(data: &JsonValue, key: &str) -> Result<Option<u64>, BoxedError> {
+    if data.is_null() {
+        return Ok(None);
+    }
+    if let Some(n) = data.as_u64() {
+        return Ok(Some(n));
+    }
+    let invalid = || format!("Invalid size of `{}`: {}", key, data);
+    let s = data.as_str().ok_or_else(invalid)?.trim().to_uppercase();
+    let unit_start = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let number: f64 = s[..unit_start].trim().parse().map_err(|_| invalid())?;
+    let multiplier: u64 = match s[unit_start..].trim() {
+        "" | "B" => 1,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        "T" | "TB" => 1 << 40,
+        _ => return Err(invalid().into()),
+    };
+    Ok(Some((number * multiplier as f64) as u64))
+}
+
 impl FunctionMatches {
-    pub fn from_json(data: &JsonValue) -> Result<FunctionMatches, BoxedError> {
-        let extensions = data["extensions"]
-            .as_array()
-            .unwrap_or(&vec![])
+    pub fn from_config(config: &MatchesConfig) -> FunctionMatches {
+        // `!ext` excludes an extension, `!/tag` denies a special kind and stays in extensions
+        let (excluded_extensions, extensions): (HashSet<String>, HashSet<String>) =
+            get_lowercase_strings(config.extensions.iter().flatten().map(|e| e.as_str()))
+                .into_iter()
+                .partition(|ext| ext.starts_with('!') && !ext.starts_with("!/"));
+        let excluded_extensions = excluded_extensions
             .iter()
-            .map(|ext| ext.as_str().unwrap_or("").to_lowercase())
+            .map(|ext| ext.trim_start_matches('!').to_string())
             .collect();
 
-        let platforms = data["platforms"]
-            .as_array()
-            .unwrap_or(&vec![])
+        let (excluded_mime_types, mime_types): (HashSet<String>, HashSet<String>) =
+            get_lowercase_strings(config.mime_types.iter().flatten().map(|m| m.as_str()))
+                .into_iter()
+                .partition(|mime| mime.starts_with('!'));
+        let excluded_mime_types = excluded_mime_types
             .iter()
-            .map(|ext| ext.as_str().unwrap_or("").to_lowercase())
+            .map(|mime| mime.trim_start_matches('!').to_string())
             .collect();
 
-        let app_version_min = data["app"]["min"].as_str().unwrap_or("").to_string();
-        let app_version_max = data["app"]["max"].as_str().unwrap_or("").to_string();
+        let platforms = get_lowercase_strings(config.platforms.iter().map(|p| p.as_str()))
+            .into_iter()
+            .collect();
 
-        Ok(FunctionMatches {
+        let requires_tools = config
+            .requires_tools
+            .iter()
+            .flatten()
+            .map(|t| t.as_str().to_string())
+            .collect();
+
+        let app_version =
+            |version: Option<&Version>| version.map_or("", |v| v.as_str()).to_string();
+        let app = config.app.as_ref();
+
+        FunctionMatches {
             extensions,
+            excluded_extensions,
+            mime_types,
+            excluded_mime_types,
+            min_size: config.size.as_ref().and_then(|s| s.min).map(|s| s.0),
+            max_size: config.size.as_ref().and_then(|s| s.max).map(|s| s.0),
+            min_count: config.count.as_ref().and_then(|c| c.min),
+            max_count: config.count.as_ref().and_then(|c| c.max),
+            requires_tools,
             platforms,
-            app_version_min,
-            app_version_max,
-        })
+            app_version_min: app_version(app.and_then(|a| a.min.as_ref())),
+            app_version_max: app_version(app.and_then(|a| a.max.as_ref())),
+        }
+    }
+
+    /// whether a file of the extension or mime type is supported
+    pub fn matches_file(&self, ext: &str, mime: &str) -> bool {
+        self.extensions.contains("*")
+            || self.extensions.contains("/all")
+            || self.extensions.contains(ext)
+            || self.mime_types.iter().any(|m| mime_matches(m, mime))
+    }
+
+    pub fn is_excluded_file(&self, ext: &str, mime: &str) -> bool {
+        self.excluded_extensions.contains(ext)
+            || self
+                .excluded_mime_types
+                .iter()
+                .any(|m| mime_matches(m, mime))
+    }
+
+    /// Check the selection against the matches: file types, special kinds, count,
+    /// file sizes and required tools, in this order
+    pub fn check_selection(
+        &self,
+        selection: &FileSelection,
+        is_tool_available: &mut dyn FnMut(&str) -> bool,
+    ) -> Result<(), MatchMismatch> {
+        let kinds: Vec<&FileKind> = selection
+            .kinds
+            .iter()
+            .filter(|k| !k.ext.starts_with('/'))
+            .collect();
+        let kind_name = |k: &FileKind| match k.ext.is_empty() {
+            true => k.mime.clone(),
+            false => format!(".{}", k.ext),
+        };
+
+        // every file type must match, unless a special kind of the selection is allowed
+        let mut type_rst: Result<(), MatchMismatch> = Ok(());
+        if let Some(k) = kinds.iter().find(|k| !self.matches_file(&k.ext, &k.mime)) {
+            type_rst = Err(MatchMismatch::new(
+                MISMATCH_FILE_TYPE,
+                format!("Not supported file type: {}", kind_name(k)),
+            ));
+        }
+        for tag in SELECTION_TAGS {
+            if !selection.kinds.iter().any(|k| k.ext == tag) {
+                continue;
+            }
+            let allowed = self.extensions.contains(tag);
+            let denied = self.extensions.contains(&format!("!{}", tag));
+            if allowed && !denied {
+                type_rst = Ok(());
+            } else if denied {
+                type_rst = Err(MatchMismatch::new(
+                    MISMATCH_SELECTION_TYPE,
+                    format!("Not supported selection: {}", tag),
+                ));
+            }
+        }
+        type_rst?;
+
+        if let Some(k) = kinds
+            .iter()
+            .find(|k| self.is_excluded_file(&k.ext, &k.mime))
+        {
+            return Err(MatchMismatch::new(
+                MISMATCH_EXCLUDED_TYPE,
+                format!("Excluded file type: {}", kind_name(k)),
+            ));
+        }
+
+        let count = selection.count as u64;
+        if self.min_count.is_some_and(|min| count < min) {
+            return Err(MatchMismatch::new(
+                MISMATCH_COUNT,
+                format!("At least {} items required", self.min_count.unwrap_or(0)),
+            ));
+        }
+        if self.max_count.is_some_and(|max| count > max) {
+            return Err(MatchMismatch::new(
+                MISMATCH_COUNT,
+                format!("At most {} items allowed", self.max_count.unwrap_or(0)),
+            ));
+        }
+
+        if let (Some(min), Some(size)) = (self.min_size, selection.min_file_size) {
+            if size < min {
+                return Err(MatchMismatch::new(
+                    MISMATCH_SIZE,
+                    format!("A file is smaller than {} bytes", min),
+                ));
+            }
+        }
+        if let (Some(max), Some(size)) = (self.max_size, selection.max_file_size) {
+            if size > max {
+                return Err(MatchMismatch::new(
+                    MISMATCH_SIZE,
+                    format!("A file is larger than {} bytes", max),
+                ));
+            }
+        }
+
+        if let Some(tool) = self.requires_tools.iter().find(|t| !is_tool_available(t)) {
+            return Err(MatchMismatch::new(
+                MISMATCH_TOOL,
+                format!("Required tool is not available: {}", tool),
+            ));
+        }
+
+        Ok(())
     }
 
     pub fn to_json(&self) -> JsonValue {
+        let strings = |set: &HashSet<String>| {
+            let mut v: Vec<&String> = set.iter().collect();
+            v.sort();
+            json!(v)
+        };
+        // excluded ones with their `!` prefix, the same as in the config
+        let mut extensions: Vec<String> = self.extensions.iter().cloned().collect();
+        extensions.extend(self.excluded_extensions.iter().map(|e| format!("!{}", e)));
+        extensions.sort();
+        let mut mime_types: Vec<String> = self.mime_types.iter().cloned().collect();
+        mime_types.extend(self.excluded_mime_types.iter().map(|m| format!("!{}", m)));
+        mime_types.sort();
+
         json!({
-            "extensions": self.extensions.iter().map(|ext| {
-                JsonValue::String(ext.to_string())
-            }).collect::<Vec<JsonValue>>(),
-            "platforms": self.platforms.iter().map(|ext| {
-                JsonValue::String(ext.to_string())
-            }).collect::<Vec<JsonValue>>(),
+            "extensions": extensions,
+            "mime_types": mime_types,
+            "platforms": strings(&self.platforms),
+            "size": {
+                "min": self.min_size,
+                "max": self.max_size,
+            },
+            "count": {
+                "min": self.min_count,
+                "max": self.max_count,
+            },
+            "requires_tools": self.requires_tools,
             "app": {
                 "min": self.app_version_min.to_string(),
                 "max": self.app_version_max.to_string(),
@@ -81,3 +344,134 @@ impl FunctionMatches {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selection(kinds: &[(&str, &str)], count: usize, sizes: (u64, u64)) -> FileSelection {
+        FileSelection {
+            kinds: kinds
+                .iter()
+                .map(|(ext, mime)| FileKind {
+                    ext: ext.to_string(),
+                    mime: mime.to_string(),
+                })
+                .collect(),
+            count,
+            min_file_size: Some(sizes.0),
+            max_file_size: Some(sizes.1),
+        }
+    }
+
+    fn matches_of(data: JsonValue) -> FunctionMatches {
+        FunctionMatches::from_config(&serde_json::from_value(data).unwrap())
+    }
+
+    fn mismatch(matches: &FunctionMatches, selection: &FileSelection) -> Option<&'static str> {
+        let mut all_tools = |_: &str| true;
+        match matches.check_selection(selection, &mut all_tools) {
+            Ok(()) => None,
+            Err(m) => Some(m.code),
+        }
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size(&JsonValue::Null, "size").unwrap(), None);
+        assert_eq!(parse_size(&json!(1024), "size").unwrap(), Some(1024));
+        assert_eq!(parse_size(&json!("100"), "size").unwrap(), Some(100));
+        assert_eq!(parse_size(&json!("100 B"), "size").unwrap(), Some(100));
+        assert_eq!(parse_size(&json!("2k"), "size").unwrap(), Some(2048));
+        assert_eq!(
+            parse_size(&json!(" 50 MB "), "size").unwrap(),
+            Some(50 << 20)
+        );
+        assert_eq!(parse_size(&json!("1.5GB"), "size").unwrap(), Some(3 << 29));
+        assert_eq!(parse_size(&json!("1 TB"), "size").unwrap(), Some(1 << 40));
+        assert!(parse_size(&json!("50 XB"), "size").is_err());
+        assert!(parse_size(&json!("MB"), "size").is_err());
+        assert!(parse_size(&json!(-1), "size").is_err());
+        let err = parse_size(&json!(true), "size.max").unwrap_err();
+        assert_eq!(err.to_string(), "Invalid size of `size.max`: true");
+    }
+
+    #[test]
+    fn test_check_selection() {
+        let matches = matches_of(json!({
+            "platforms": ["*"],
+            "extensions": ["jpg", "!svg", "!/dirs"],
+            "mime_types": ["image/*", "!image/x-icon"],
+            "size": { "min": 2, "max": "1 KB" },
+            "count": { "min": 1, "max": 2 },
+            "requires_tools": ["exiftool"],
+        }));
+        let png = selection(&[("png", "image/png"), ("/file", "")], 1, (10, 10));
+        assert_eq!(mismatch(&matches, &png), None);
+        let mut tools = |name: &str| name == "exiftool";
+        assert!(matches.check_selection(&png, &mut tools).is_ok());
+        let mut no_tools = |_: &str| false;
+        let err = matches.check_selection(&png, &mut no_tools).unwrap_err();
+        assert_eq!(err.code, MISMATCH_TOOL);
+
+        // file types and the negative matches
+        let txt = selection(&[("txt", "text/plain"), ("/file", "")], 1, (10, 10));
+        assert_eq!(mismatch(&matches, &txt), Some(MISMATCH_FILE_TYPE));
+        let svg = selection(&[("svg", "image/svg+xml"), ("/file", "")], 1, (10, 10));
+        assert_eq!(mismatch(&matches, &svg), Some(MISMATCH_EXCLUDED_TYPE));
+        let ico = selection(&[("ico", "image/x-icon"), ("/file", "")], 1, (10, 10));
+        assert_eq!(mismatch(&matches, &ico), Some(MISMATCH_EXCLUDED_TYPE));
+        let dirs = selection(&[("png", "image/png"), ("/dirs", "")], 2, (10, 10));
+        assert_eq!(mismatch(&matches, &dirs), Some(MISMATCH_SELECTION_TYPE));
+
+        // count and size limits
+        let files =
+            |count, sizes| selection(&[("jpg", "image/jpeg"), ("/files", "")], count, sizes);
+        assert_eq!(mismatch(&matches, &files(2, (2, 1024))), None);
+        assert_eq!(
+            mismatch(&matches, &files(3, (10, 10))),
+            Some(MISMATCH_COUNT)
+        );
+        assert_eq!(
+            mismatch(&matches, &files(0, (10, 10))),
+            Some(MISMATCH_COUNT)
+        );
+        assert_eq!(mismatch(&matches, &files(2, (1, 10))), Some(MISMATCH_SIZE));
+        assert_eq!(
+            mismatch(&matches, &files(2, (10, 1025))),
+            Some(MISMATCH_SIZE)
+        );
+    }
+
+    #[test]
+    fn test_check_selection_tags() {
+        // an allowed selection tag accepts any file type in it, a denied one rejects it
+        let matches = matches_of(json!({
+            "platforms": ["*"],
+            "extensions": ["txt", "/dir", "!/paths"],
+        }));
+        let dir = selection(&[("png", "image/png"), ("/dir", "")], 1, (10, 10));
+        assert_eq!(mismatch(&matches, &dir), None);
+        let file = selection(&[("png", "image/png"), ("/file", "")], 1, (10, 10));
+        assert_eq!(mismatch(&matches, &file), Some(MISMATCH_FILE_TYPE));
+        let paths = selection(&[("txt", "text/plain"), ("/paths", "")], 2, (10, 10));
+        assert_eq!(mismatch(&matches, &paths), Some(MISMATCH_SELECTION_TYPE));
+
+        // tags are checked in the order of SELECTION_TAGS, the last one decides
+        let both = selection(
+            &[("png", "image/png"), ("/dirs", ""), ("/paths", "")],
+            2,
+            (0, 0),
+        );
+        let allow_later = matches_of(json!({
+            "platforms": ["*"],
+            "extensions": ["!/dirs", "/paths"],
+        }));
+        assert_eq!(mismatch(&allow_later, &both), None);
+        let deny_later = matches_of(json!({
+            "platforms": ["*"],
+            "extensions": ["/dirs", "!/paths"],
+        }));
+        assert_eq!(mismatch(&deny_later, &both), Some(MISMATCH_SELECTION_TYPE));
+    }
+}
@@ -1,11 +1,15 @@
 use crate::errors::BoxedError;
 use crate::functions;
+use crate::functions::tool::read::{check_tools_in_background, get_tool_status};
+use crate::functions::tool::structures::{TOOL_STATUS_AVAILABLE, TOOL_STATUS_UNCHECKED};
+use crate::thelib::file_path::FileSelection;
 use serde_json::{json, Value as JsonValue};
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
-// get supported things (operations) according to the file types
-pub fn get_supported_file_functions(file_exts: HashSet<&str>) -> Result<JsonValue, BoxedError> {
-    log::debug!("get_supported_functions for exts: {:?}", file_exts);
+// get supported things (operations) according to the selection. excluded functions are
+// returned with the reason, see `FunctionMatches::check_selection`
+pub fn get_supported_file_functions(selection: &FileSelection) -> Result<JsonValue, BoxedError> {
+    log::debug!("get_supported_functions for selection: {:?}", selection);
 
     let file_functions = match functions::file::read::FILE_FUNCTIONS.lock() {
         Ok(w) => w,
@@ -15,54 +19,41 @@ pub fn get_supported_file_functions(file_exts: HashSet<&str>) -> Result<JsonValu
         }
     };
 
-    // default support all operations, then take intersection with file types
-    let mut supported_function_names: HashSet<&str> =
-        file_functions.keys().map(|s| s.as_str()).collect();
+    // a tool is available by its last check, the selection is not held up by running it.
+    // tools not checked yet are checked in the background, for the next selection
+    let mut tool_statuses: HashMap<String, String> = HashMap::new();
+    let mut is_tool_available = |name: &str| -> bool {
+        let status = tool_statuses.entry(name.to_string()).or_insert_with(|| {
+            get_tool_status(name)
+                .map(|(status, _)| status)
+                .unwrap_or_default()
+        });
+        status.as_str() == TOOL_STATUS_AVAILABLE
+    };
 
-    // specific file type matching, take intersection
-    for ext in &file_exts {
-        if ext.starts_with("/") {
-            // skip special file types for internal use (will be processed later)
-            continue;
-        }
-        let mut ext_supported_thing_names: HashSet<&str> = HashSet::new();
-        for item in file_functions.iter() {
-            let (fn_name, function) = item;
-            if function.matches.extensions.contains("*")
-                || function.matches.extensions.contains(&"/all".to_string())
-                || function.matches.extensions.contains(&ext.to_string())
-            {
-                ext_supported_thing_names.insert(fn_name.as_str());
+    let mut supported_things: HashMap<String, JsonValue> = HashMap::new();
+    let mut excluded_things: HashMap<String, JsonValue> = HashMap::new();
+    for (fn_name, function) in file_functions.iter() {
+        match function
+            .matches
+            .check_selection(selection, &mut is_tool_available)
+        {
+            Ok(()) => {
+                supported_things.insert(fn_name.to_string(), function.to_json());
             }
-        }
-        // take intersection
-        supported_function_names = supported_function_names
-            .intersection(&ext_supported_thing_names)
-            .copied()
-            .collect();
-    }
-
-    // special file types for internal use
-    let inner_file_exts = vec!["/file", "/dir", "/files", "/dirs", "/paths"];
-    for inner_ext in inner_file_exts {
-        if file_exts.contains(inner_ext) {
-            for item in file_functions.iter() {
-                let (fn_name, function) = item;
-                let allow_matched: bool = function.matches.extensions.contains(inner_ext);
-                let not_allow_matched: bool = function
-                    .matches
-                    .extensions
-                    .contains(&format!("!{}", inner_ext));
-
-                if allow_matched && !not_allow_matched {
-                    supported_function_names.insert(fn_name.as_str());
-                } else if not_allow_matched {
-                    supported_function_names.remove(fn_name.as_str());
-                }
+            Err(mismatch) => {
+                excluded_things.insert(fn_name.to_string(), mismatch.to_json());
             }
         }
     }
 
+    let unchecked_tools: Vec<String> = tool_statuses
+        .into_iter()
+        .filter(|(_, status)| status == TOOL_STATUS_UNCHECKED)
+        .map(|(name, _)| name)
+        .collect();
+    check_tools_in_background(unchecked_tools);
+
     // all file processing functions (File Functions) are through configuration files as interfaces
     // no longer provide built-in file processing functions directly as interfaces to the front end
     // if file_exts.contains("/dir")
@@ -73,23 +64,15 @@ pub fn get_supported_file_functions(file_exts: HashSet<&str>) -> Result<JsonValu
     //     supported_function_names.insert(command_names::FILE_COUNT_FILES);
     // }
 
-    log::debug!("supported_function_names: {:?}", supported_function_names);
-
-    let mut supported_things: HashMap<String, JsonValue> = HashMap::new();
-    for thing_name in supported_function_names {
-        if let Some(thing_function) = file_functions.get(thing_name) {
-            supported_things.insert(thing_name.to_string(), thing_function.to_json());
-        } else {
-            supported_things.insert(thing_name.to_string(), json!({ "name": thing_name }));
-        }
-    }
+    log::debug!("supported_function_names: {:?}", supported_things.keys());
 
     let mut sorted_thing_names = supported_things.keys().collect::<Vec<&String>>();
     sorted_thing_names.sort();
 
     let result = json!({
         "names": sorted_thing_names,
-        "items": supported_things
+        "items": supported_things,
+        "excluded": excluded_things,
     });
 
     Ok(result)
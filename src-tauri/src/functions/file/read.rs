@@ -1,8 +1,8 @@
-use crate::app::resource::{get_function_category_dir_using, get_function_dir_using};
+use crate::app::resource::get_function_dir_using;
 use crate::errors::BoxedError;
+use crate::functions::common::config::{load_function_dir, RejectedFunction};
+use crate::functions::common::roots::{collect_function_sources, get_function_roots, LoadReport};
 use crate::functions::file::structures::FileFunction;
-use crate::thelib::file_path::get_sub_dirnames;
-use crate::thelib::json::load_json_file;
 use lazy_static::lazy_static;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
@@ -13,37 +13,39 @@ lazy_static! {
         Arc::new(Mutex::new(HashMap::new()));
 }
 
-// load file functions (operations)
-pub fn load_functions() -> Result<(), BoxedError> {
-    let function_category_dir = get_function_category_dir_using(vec!["files"])?;
+// load file functions (operations) from all function roots,
+// returns the functions not loaded and the name conflicts between roots
+pub fn load_functions() -> Result<LoadReport, BoxedError> {
     let function_dir = get_function_dir_using()?;
 
-    // get all folder names in the function directory, as they are the js function names
-    let sub_dirnames: Vec<String> = get_sub_dirnames(&function_category_dir);
+    // folder names in the category dirs are the js function names
+    let (sources, conflicts) = collect_function_sources(&get_function_roots()?, "files");
 
     // js function data files
     let mut functions = Vec::new();
-    for name in sub_dirnames {
+    let mut rejected = Vec::new();
+    for source in sources {
+        // local functions use the worker utils of the official dir
         let worker_utils_file = function_dir.join("_utils").join("file.js");
-        let file_func_dir = function_category_dir.join(&name);
-        let config_path = file_func_dir.join("config.json");
+        let name = source.name;
+        let file_func_dir = source.dir;
         let worker_file = file_func_dir.join("worker.js");
         let function_name = format!("file.{}", name);
 
-        let config = match load_json_file(&config_path) {
-            Ok(v) => v,
-            Err(e) => {
-                log::warn!("Failed to read config.json: {}", e);
+        // parse the config and check the worker file
+        let config = match load_function_dir(&file_func_dir, Some("file")) {
+            Ok(c) => c,
+            Err(errors) => {
+                let reasons = errors.iter().map(|e| e.to_string()).collect();
+                rejected.push(RejectedFunction::new(
+                    &function_name,
+                    &file_func_dir,
+                    reasons,
+                ));
                 continue;
             }
         };
 
-        // check worker file exists
-        if !worker_file.exists() {
-            log::warn!("Worker file not found: {}", &worker_file.to_string_lossy());
-            continue;
-        }
-
         let function = match FileFunction::new(
             &function_name,
             &config,
@@ -52,7 +54,11 @@ pub fn load_functions() -> Result<(), BoxedError> {
         ) {
             Ok(w) => w,
             Err(e) => {
-                log::warn!("Ignore function {}, {}", &function_name, e);
+                rejected.push(RejectedFunction::new(
+                    &function_name,
+                    &file_func_dir,
+                    vec![e.to_string()],
+                ));
                 continue;
             }
         };
@@ -72,7 +78,14 @@ pub fn load_functions() -> Result<(), BoxedError> {
         }
     }
 
-    log::info!("Loaded {} file functions", &functions.len());
+    log::info!(
+        "Loaded {} file functions, rejected {}",
+        &functions.len(),
+        &rejected.len()
+    );
 
-    Ok(())
+    Ok(LoadReport {
+        rejected,
+        conflicts,
+    })
 }
@@ -1,5 +1,6 @@
 use crate::errors::BoxedError;
-use crate::functions::common::structures::{FunctionMatches, FunctionProfile};
+use crate::functions::common::config::FunctionConfig;
+use crate::functions::common::structures::{FunctionIgnored, FunctionMatches, FunctionProfile};
 use crate::thelib::sys::{get_app_version, get_platform};
 use crate::thelib::version::compare_semver;
 use serde_json::json;
@@ -18,37 +19,28 @@ pub struct FileFunction {
 impl FileFunction {
     pub fn new(
         name: &str,
-        config_data: &JsonValue,
+        config: &FunctionConfig,
         worker_file: &String,
         worker_utils_file: &String,
     ) -> Result<FileFunction, BoxedError> {
-        let func_type = config_data["type"].as_str().unwrap_or("").to_string();
+        let func_type = config.func_type.as_str().to_string();
         if func_type.as_str() != "file" {
-            return Err(format!("Ignore the function, type is not file: {}", func_type).into());
+            return Err(format!("Not a file function, type: {}", func_type).into());
         }
 
-        let profile_data = config_data["profile"].clone();
-        if profile_data.is_null() {
-            return Err("Not found `profile` key in config data".into());
-        }
-        let profile = FunctionProfile::from_json(&profile_data);
-
-        let matches_data = config_data["matches"].clone();
-        if matches_data.is_null() {
-            return Err("Not found `matches` key in config data".into());
-        }
-        let matches = FunctionMatches::from_json(&matches_data)?;
+        let profile = FunctionProfile::from_config(&config.profile);
+        let matches = FunctionMatches::from_config(&config.matches);
 
         // skip not match platform
         let cur_platform = get_platform();
         if matches.platforms.is_empty() {
-            return Err("Ignore the function, not found platforms in matches".into());
+            return Err(FunctionIgnored::new("not found platforms in matches").into());
         }
         if !matches.platforms.contains(&cur_platform) && !matches.platforms.contains("*") {
-            return Err(format!(
-                "Ignore the function, not match current platform: {}",
+            return Err(FunctionIgnored::new(format!(
+                "not match current platform: {}",
                 cur_platform
-            )
+            ))
             .into());
         }
 
@@ -57,26 +49,26 @@ impl FileFunction {
         if !matches.app_version_min.is_empty() {
             let matched = compare_semver(&cur_app_version, ">=", &matches.app_version_min)?;
             if !matched {
-                return Err(format!(
-                    "Ignore the function, current app version is lower than min version: {} < {}",
+                return Err(FunctionIgnored::new(format!(
+                    "current app version is lower than min version: {} < {}",
                     cur_app_version, matches.app_version_min
-                )
+                ))
                 .into());
             }
         }
         if !matches.app_version_max.is_empty() {
             let matched = compare_semver(&cur_app_version, "<=", &matches.app_version_max)?;
             if !matched {
-                return Err(format!(
-                    "Ignore the function, current app version is higher than max version: {} > {}",
+                return Err(FunctionIgnored::new(format!(
+                    "current app version is higher than max version: {} > {}",
                     cur_app_version, matches.app_version_max
-                )
+                ))
                 .into());
             }
         }
 
-        // variables, optional
-        let variables = config_data["variables"].clone();
+        // variables, optional, as written for the front-end
+        let variables = config.raw["variables"].clone();
 
         Ok(FileFunction {
             name: name.to_string(),
@@ -1,3 +1,4 @@
 pub mod common;
 pub mod file;
 pub mod tool;
+pub mod watcher;
@@ -0,0 +1,519 @@
+//! Installation of tools by the app, from the `bin.installation` block of a tool config:
+//!
+//! ```json
+//! "installation": {
+//!     "auto": true,
+//!     "packages": {
+//!         "windows": {
+//!             "url": "https://example.com/ffmpeg-6.1-win64.zip",
+//!             "sha256": "...",
+//!             "size": 12345,
+//!             "strip_components": 1,
+//!             "executables": ["bin/ffprobe.exe"]
+//!         }
+//!     },
+//!     "manual": { ... }
+//! }
+//! ```
+//!
+//! Packages are by platform or `*`, each with the `sha256` of its download, required to install
+//! it by the app. `format` is one of the archive formats or "file"
+//! (the download is the bin itself), detected from the url if not set. An archive is
+//! unpacked into `install_dir`, relative to the app data dir and at least `tools/<kind>/<name>`;
+//! by default the `tools/<kind>/<name>` dir of a relative `bin.path` like
+//! `tools/exe/ffmpeg/bin/ffmpeg.exe`.
+
+use crate::app::resource::get_app_data_dir;
+use crate::errors::BoxedError;
+use crate::functions::tool::structures::ToolFunction;
+use crate::thelib::compress::{extract_to, ArchiveFormat};
+use crate::thelib::file_path::move_path;
+use serde_json::Value as JsonValue;
+use std::path::{Component, Path, PathBuf};
+
+const TOOLS_DIR: &str = "tools";
+pub const FORMAT_FILE: &str = "file";
+
+/// The package of a tool for one platform
+#[derive(Debug, Clone)]
+pub struct ToolPackage {
+    pub url: String,
+    pub sha256: String,
+    pub size: Option<u64>,
+    /// an archive format name, or "file"
+    pub format: String,
+    /// leading dirs of the archive to drop, each level must be a single dir
+    pub strip_components: usize,
+    pub install_dir: Option<String>,
+    /// relative to the install dir, made executable besides the bin
+    pub executables: Vec<String>,
+}
+
+impl ToolPackage {
+    /// the package for `platform` in the `installation` block, or `*`
+    pub fn for_platform(installation: &JsonValue, platform: &str) -> Result<Self, BoxedError> {
+        if installation["auto"].as_bool() == Some(false) {
+            return Err("The tool can only be installed manually".into());
+        }
+        let packages = &installation["packages"];
+        let data = match packages.get(platform).or_else(|| packages.get("*")) {
+            Some(v) if v.is_object() => v,
+            _ => return Err(format!("No installation package for platform: {}", platform).into()),
+        };
+
+        let url = match data["url"].as_str() {
+            Some(s) if !s.trim().is_empty() => s.trim().to_string(),
+            _ => return Err("Missing `url` of the installation package".into()),
+        };
+        // the download is run as a tool, so it must be the one expected
+        let sha256 = match data["sha256"].as_str() {
+            Some(s) if !s.trim().is_empty() => s.trim().to_string(),
+            _ => return Err("Missing `sha256` of the installation package".into()),
+        };
+        let format = match data["format"].as_str() {
+            Some(f) if f == FORMAT_FILE => f.to_string(),
+            Some(f) => ArchiveFormat::from_name(f)?.extension().to_string(),
+            None => {
+                // the url path without query, e.g. `.../ffmpeg.zip?download=1`
+                let url_path = url.split(['?', '#']).next().unwrap_or("");
+                match ArchiveFormat::from_path(Path::new(url_path)) {
+                    Ok(f) => f.extension().to_string(),
+                    Err(_) => FORMAT_FILE.to_string(),
+                }
+            }
+        };
+
+        Ok(ToolPackage {
+            url,
+            sha256,
+            size: data["size"].as_u64(),
+            format,
+            strip_components: data["strip_components"].as_u64().unwrap_or(0) as usize,
+            install_dir: data["install_dir"].as_str().map(|s| s.to_string()),
+            executables: data["executables"]
+                .as_array()
+                .map(|a| {
+                    a.iter()
+                        .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+    }
+
+    pub fn is_archive(&self) -> bool {
+        self.format != FORMAT_FILE
+    }
+}
+
+/// Where a tool is installed, all paths are inside the app data `tools/` dir
+#[derive(Debug, Clone)]
+pub struct InstallTarget {
+    pub bin_path: PathBuf,
+    /// executables get the exec bit, not models
+    pub is_exe: bool,
+    /// the install dir of an archive, or the bin itself for a single file
+    pub path: PathBuf,
+}
+
+/// a relative path without `..` or a root
+fn join_relative(base: &Path, rel: &str) -> Result<PathBuf, BoxedError> {
+    let rel_path = Path::new(rel.trim_start_matches(['/', '\\']));
+    if rel_path
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return Err(format!("Invalid relative path: '{}'", rel).into());
+    }
+    Ok(base.join(rel_path))
+}
+
+impl InstallTarget {
+    pub fn new(tool: &ToolFunction, package: &ToolPackage) -> Result<Self, BoxedError> {
+        Self::in_app_data_dir(tool, package, &get_app_data_dir()?)
+    }
+
+    fn in_app_data_dir(
+        tool: &ToolFunction,
+        package: &ToolPackage,
+        app_data_dir: &Path,
+    ) -> Result<Self, BoxedError> {
+        let tools_dir = app_data_dir.join(TOOLS_DIR);
+        let bin_path = PathBuf::from(&tool.bin_path);
+        let rel_bin = match bin_path.strip_prefix(&tools_dir) {
+            Ok(p) => p.to_path_buf(),
+            Err(_) => {
+                return Err(format!(
+                    "Can't install {}, its bin path is not in the app tools dir: {}",
+                    tool.name, tool.bin_path
+                )
+                .into())
+            }
+        };
+
+        let path = match (&package.install_dir, package.is_archive()) {
+            (_, false) => bin_path.clone(),
+            (Some(dir), true) => join_relative(app_data_dir, dir)?,
+            // tools/<kind>/<name>/...
+            (None, true) => {
+                let parts: Vec<Component> = rel_bin.components().collect();
+                if parts.len() < 3 {
+                    return Err(format!(
+                        "Set `install_dir` in the package, can't tell the install dir of {} from its bin path",
+                        tool.name
+                    )
+                    .into());
+                }
+                tools_dir.join(parts[0]).join(parts[1])
+            }
+        };
+        // not the dir of a kind or of all tools, it is removed on reinstall and uninstall
+        let depth = path
+            .strip_prefix(&tools_dir)
+            .map(|p| p.components().count())
+            .unwrap_or(0);
+        if depth < 2 {
+            return Err(format!(
+                "Install dir must be at least {:?}: {:?}",
+                tools_dir.join("<kind>").join("<name>"),
+                path
+            )
+            .into());
+        }
+        if package.is_archive() && !bin_path.starts_with(&path) {
+            return Err(format!(
+                "The bin {:?} is not in the install dir {:?}",
+                bin_path, path
+            )
+            .into());
+        }
+
+        Ok(InstallTarget {
+            bin_path,
+            is_exe: tool.func_type == "tool.exe",
+            path,
+        })
+    }
+}
+
+#[cfg(unix)]
+fn set_executable(path: &Path) -> Result<(), BoxedError> {
+    use std::os::unix::fs::PermissionsExt;
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    // read bits become exec bits, e.g. 644 -> 755
+    let mode = permissions.mode();
+    permissions.set_mode(mode | ((mode & 0o444) >> 2));
+    std::fs::set_permissions(path, permissions)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_executable(_path: &Path) -> Result<(), BoxedError> {
+    Ok(())
+}
+
+/// the dir after dropping `count` levels, each with a single dir
+fn strip_dirs(dir: &Path, count: usize) -> Result<PathBuf, BoxedError> {
+    let mut dir = dir.to_path_buf();
+    for _ in 0..count {
+        let entries: Vec<PathBuf> = std::fs::read_dir(&dir)?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .collect();
+        match entries.as_slice() {
+            [single] if single.is_dir() => dir = single.clone(),
+            _ => {
+                return Err(format!(
+                    "Can't strip the dir {:?} of the package, it has more than one entry",
+                    dir
+                )
+                .into())
+            }
+        }
+    }
+    Ok(dir)
+}
+
+/// put the installation kept in `previous` back, or remove a failed one if there was none
+fn restore_previous(previous: &Path, path: &Path) -> Result<(), BoxedError> {
+    let _ = match path.is_dir() {
+        true => std::fs::remove_dir_all(path),
+        false => std::fs::remove_file(path),
+    };
+    if previous.exists() {
+        move_path(previous, path)?;
+    }
+    Ok(())
+}
+
+/// Install the downloaded package, replacing the current installation.
+/// `staging_dir` is removed by the caller
+pub fn install_package_file(
+    package: &ToolPackage,
+    target: &InstallTarget,
+    package_file: &Path,
+    staging_dir: &Path,
+) -> Result<(), BoxedError> {
+    std::fs::create_dir_all(staging_dir)?;
+    let new_path = match package.is_archive() {
+        true => {
+            let unpacked_dir = staging_dir.join("unpacked");
+            std::fs::create_dir_all(&unpacked_dir)?;
+            extract_to(package_file, &unpacked_dir)?;
+            strip_dirs(&unpacked_dir, package.strip_components)?
+        }
+        false => package_file.to_path_buf(),
+    };
+
+    // keep the current installation until the new one is in place
+    let previous = staging_dir.join("previous");
+    if target.path.exists() {
+        move_path(&target.path, &previous)?;
+    }
+    if let Some(parent) = target.path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if let Err(e) = move_path(&new_path, &target.path) {
+        restore_previous(&previous, &target.path)?;
+        return Err(format!("Failed to install into {:?}: {}", target.path, e).into());
+    }
+
+    if !target.bin_path.exists() {
+        restore_previous(&previous, &target.path)?;
+        return Err(format!("The bin is not found after install: {:?}", target.bin_path).into());
+    }
+    if !target.is_exe {
+        return Ok(());
+    }
+    set_executable(&target.bin_path)?;
+    if package.is_archive() {
+        for rel in &package.executables {
+            let path = join_relative(&target.path, rel)?;
+            match path.is_file() {
+                true => set_executable(&path)?,
+                false => log::warn!("Executable not found in the package: {:?}", path),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove an installation, returns false if it is not installed
+pub fn uninstall(target: &InstallTarget) -> Result<bool, BoxedError> {
+    if target.path.is_dir() {
+        std::fs::remove_dir_all(&target.path)?;
+    } else if target.path.exists() {
+        std::fs::remove_file(&target.path)?;
+    } else {
+        return Ok(false);
+    }
+    log::info!("Removed tool installation: {:?}", target.path);
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::functions::common::config::FunctionConfig;
+    use crate::thelib::compress::create_archive;
+    use crate::thelib::test_utils::TempDir;
+    use serde_json::json;
+
+    /// an exe tool with its bin at `bin_path`
+    fn make_tool(bin_path: &Path) -> ToolFunction {
+        let config = json!({
+            "type": "tool.exe",
+            "profile": { "title": "FFmpeg" },
+            "matches": { "platforms": ["*"] },
+            "bin": { "path": { "*": "ffmpeg" }, "version_arguments": ["-version"] }
+        });
+        let config = FunctionConfig::parse(&config, None).unwrap();
+        let name = "tool.exe.ffmpeg".to_string();
+        let mut tool = ToolFunction::new(&name, &config, &String::new(), &String::new()).unwrap();
+        tool.bin_path = bin_path.to_string_lossy().to_string();
+        tool
+    }
+
+    fn make_package(format: &str, install_dir: Option<&str>) -> ToolPackage {
+        ToolPackage {
+            url: "https://example.com/ffmpeg".to_string(),
+            sha256: "abc".to_string(),
+            size: None,
+            format: format.to_string(),
+            strip_components: 0,
+            install_dir: install_dir.map(|s| s.to_string()),
+            executables: vec![],
+        }
+    }
+
+    #[test]
+    fn test_for_platform() {
+        let installation = json!({
+            "auto": true,
+            "packages": {
+                "linux": {
+                    "url": " https://example.com/ffmpeg.tar.gz?download=1 ",
+                    "sha256": "abc",
+                    "size": 100,
+                    "strip_components": 1,
+                    "executables": ["bin/ffprobe", 1]
+                },
+                "macos": { "url": "https://example.com/ffmpeg", "sha256": "def" },
+                "*": { "url": "https://example.com/ffmpeg.bin", "sha256": "ghi", "format": "zip" }
+            }
+        });
+        let package = ToolPackage::for_platform(&installation, "linux").unwrap();
+        assert_eq!(package.url, "https://example.com/ffmpeg.tar.gz?download=1");
+        assert_eq!(package.sha256, "abc");
+        assert_eq!(package.size, Some(100));
+        assert_eq!(package.format, "tar.gz");
+        assert_eq!(package.strip_components, 1);
+        assert_eq!(package.executables, vec!["bin/ffprobe"]);
+        assert!(package.is_archive());
+
+        let package = ToolPackage::for_platform(&installation, "macos").unwrap();
+        assert_eq!(package.format, FORMAT_FILE);
+        assert!(!package.is_archive());
+        // `*` for the other platforms, `format` over the url
+        let package = ToolPackage::for_platform(&installation, "windows").unwrap();
+        assert_eq!(package.sha256, "ghi");
+        assert_eq!(package.format, "zip");
+
+        let mut manual = installation.clone();
+        manual["auto"] = json!(false);
+        assert!(ToolPackage::for_platform(&manual, "linux").is_err());
+        let mut no_package = installation.clone();
+        no_package["packages"]["*"] = JsonValue::Null;
+        assert!(ToolPackage::for_platform(&no_package, "windows").is_err());
+        for key in ["url", "sha256"] {
+            let mut missing = installation.clone();
+            missing["packages"]["linux"][key] = json!(" ");
+            let err = ToolPackage::for_platform(&missing, "linux").unwrap_err();
+            assert!(err.to_string().contains(key), "{}", err);
+        }
+        let mut unknown_format = installation.clone();
+        unknown_format["packages"]["linux"]["format"] = json!("rar");
+        assert!(ToolPackage::for_platform(&unknown_format, "linux").is_err());
+    }
+
+    #[test]
+    fn test_join_relative() {
+        let base = Path::new("/data");
+        assert_eq!(
+            join_relative(base, "tools/exe").unwrap(),
+            base.join("tools").join("exe")
+        );
+        assert_eq!(join_relative(base, "/tools").unwrap(), base.join("tools"));
+        for rel in ["../tools", "tools/../../etc", "./tools"] {
+            assert!(join_relative(base, rel).is_err(), "{}", rel);
+        }
+    }
+
+    #[test]
+    fn test_install_target() {
+        let app_data_dir = Path::new("/data");
+        let tools_dir = app_data_dir.join(TOOLS_DIR);
+        let bin_path = tools_dir
+            .join("exe")
+            .join("ffmpeg")
+            .join("bin")
+            .join("ffmpeg");
+        let tool = make_tool(&bin_path);
+        let target_of = |tool: &ToolFunction, package: &ToolPackage| {
+            InstallTarget::in_app_data_dir(tool, package, app_data_dir)
+        };
+
+        // an archive goes into tools/<kind>/<name> by default
+        let target = target_of(&tool, &make_package("zip", None)).unwrap();
+        assert_eq!(target.path, tools_dir.join("exe").join("ffmpeg"));
+        assert_eq!(target.bin_path, bin_path);
+        assert!(target.is_exe);
+        let target = target_of(&tool, &make_package("zip", Some("tools/exe/ffmpeg/bin"))).unwrap();
+        assert_eq!(target.path, bin_path.parent().unwrap());
+        // a single file is the bin
+        let target = target_of(&tool, &make_package(FORMAT_FILE, None)).unwrap();
+        assert_eq!(target.path, bin_path);
+
+        for install_dir in ["tools", "tools/exe", "fonts/ffmpeg", "../tools/exe/ffmpeg"] {
+            let package = make_package("zip", Some(install_dir));
+            assert!(target_of(&tool, &package).is_err(), "{}", install_dir);
+        }
+        // the bin must be in the install dir
+        let package = make_package("zip", Some("tools/exe/ffprobe"));
+        assert!(target_of(&tool, &package).is_err());
+
+        // the bin path is in the tools dir, deep enough for a default install dir
+        let shallow = make_tool(&tools_dir.join("exe").join("ffmpeg"));
+        assert!(target_of(&shallow, &make_package("zip", None)).is_err());
+        assert!(target_of(&shallow, &make_package(FORMAT_FILE, None)).is_ok());
+        let outside = make_tool(Path::new("/usr/bin/ffmpeg"));
+        assert!(target_of(&outside, &make_package(FORMAT_FILE, None)).is_err());
+    }
+
+    #[test]
+    fn test_strip_dirs() {
+        let tmp = TempDir::new("strip-dirs");
+        let dir = tmp.path();
+        let top = dir.join("ffmpeg-6.1");
+        std::fs::create_dir_all(top.join("bin")).unwrap();
+        std::fs::write(top.join("bin").join("ffmpeg"), "").unwrap();
+        std::fs::write(top.join("README"), "").unwrap();
+
+        assert_eq!(strip_dirs(dir, 0).unwrap(), dir);
+        assert_eq!(strip_dirs(dir, 1).unwrap(), top);
+        // two entries in ffmpeg-6.1
+        assert!(strip_dirs(dir, 2).is_err());
+        // a single file is not a dir to go into
+        assert!(strip_dirs(&top.join("bin"), 1).is_err());
+    }
+
+    #[test]
+    fn test_install_package_file() {
+        let tmp = TempDir::new("tool-install");
+        let dir = tmp.path();
+        let bin_path = dir.join("tools").join("exe").join("ffmpeg").join("ffmpeg");
+        let target = InstallTarget {
+            bin_path: bin_path.clone(),
+            is_exe: true,
+            path: bin_path.parent().unwrap().to_path_buf(),
+        };
+        let mut package = make_package("zip", None);
+        package.strip_components = 1;
+
+        // a package with the bin under its top dir
+        let src_dir = dir.join("src").join("ffmpeg-6.1");
+        std::fs::create_dir_all(&src_dir).unwrap();
+        std::fs::write(src_dir.join("ffmpeg"), "new").unwrap();
+        let package_file = dir.join("ffmpeg.zip");
+        create_archive(&[src_dir], &package_file, ArchiveFormat::Zip, None, None).unwrap();
+        install_package_file(&package, &target, &package_file, &dir.join("staging-1")).unwrap();
+        assert_eq!(std::fs::read_to_string(&bin_path).unwrap(), "new");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&bin_path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o100, 0o100);
+        }
+
+        // without the bin, the previous installation is put back
+        let wrong_dir = dir.join("wrong").join("ffmpeg-7.0");
+        std::fs::create_dir_all(&wrong_dir).unwrap();
+        std::fs::write(wrong_dir.join("ffprobe"), "").unwrap();
+        let wrong_file = dir.join("wrong.zip");
+        create_archive(&[wrong_dir], &wrong_file, ArchiveFormat::Zip, None, None).unwrap();
+        let err = install_package_file(&package, &target, &wrong_file, &dir.join("staging-2"))
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("not found after install"),
+            "{}",
+            err
+        );
+        assert_eq!(std::fs::read_to_string(&bin_path).unwrap(), "new");
+        assert!(!target.path.join("ffprobe").exists());
+
+        assert!(uninstall(&target).unwrap());
+        assert!(!target.path.exists());
+        assert!(!uninstall(&target).unwrap());
+    }
+}
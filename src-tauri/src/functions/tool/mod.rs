@@ -1,2 +1,3 @@
+pub mod install;
 pub mod read;
 pub mod structures;
@@ -1,67 +1,76 @@
-use crate::app::resource::{get_function_category_dir_using, get_function_dir_using};
+use crate::app::resource::get_function_dir_using;
 use crate::errors::BoxedError;
-use crate::functions::tool::structures::ToolFunction;
-use crate::thelib::file_path::get_sub_dirnames;
-use crate::thelib::json::load_json_file;
+use crate::functions::common::config::{load_function_dir, RejectedFunction};
+use crate::functions::common::roots::{collect_function_sources, get_function_roots, LoadReport};
+use crate::functions::tool::structures::{
+    ToolFunction, TOOL_STATUS_AVAILABLE, TOOL_STATUS_MISSING, TOOL_STATUS_OUTDATED,
+    TOOL_STATUS_TOO_NEW, TOOL_STATUS_UNCHECKED,
+};
 use crate::thelib::shell::exec_command;
 use lazy_static::lazy_static;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
-use std::sync::{Arc, Mutex};
-use std::time::{Duration, Instant};
+use std::sync::{Arc, Mutex, MutexGuard};
 
 lazy_static! {
     // key is flow name, like "tool.exe.ffmpeg"
     pub static ref TOOL_FUNCTIONS: Arc<Mutex<HashMap<String, ToolFunction>>> =
         Arc::new(Mutex::new(HashMap::new()));
+    // names of the tools being checked by `check_tools_in_background`
+    static ref CHECKING_TOOLS: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
 }
 
-pub fn load_tools() -> Result<(), BoxedError> {
+/// Lock `TOOL_FUNCTIONS`. A lock poisoned by a panicked holder is taken over,
+/// each change to the tools is a single insert or field update
+pub fn lock_tool_functions() -> MutexGuard<'static, HashMap<String, ToolFunction>> {
+    TOOL_FUNCTIONS.lock().unwrap_or_else(|e| {
+        log::warn!("Recovered a poisoned lock TOOL_FUNCTIONS");
+        e.into_inner()
+    })
+}
+
+/// load the tools from all function roots,
+/// returns the tools not loaded and the name conflicts between roots
+pub fn load_tools() -> Result<LoadReport, BoxedError> {
     // clear the tool functions
-    match TOOL_FUNCTIONS.lock() {
-        Ok(mut w) => {
-            w.clear();
-        }
-        Err(e) => {
-            return Err(Box::new(e));
-        }
-    }
-    load_tool_functions("executables", "tool.exe")?;
-    load_tool_functions("models", "tool.model")?;
-    Ok(())
+    lock_tool_functions().clear();
+    let mut report = load_tool_functions("executables", "tool.exe")?;
+    report.extend(load_tool_functions("models", "tool.model")?);
+    Ok(report)
 }
 
-pub fn load_tool_functions(cat_dir_name: &str, func_type: &str) -> Result<(), BoxedError> {
+pub fn load_tool_functions(cat_dir_name: &str, func_type: &str) -> Result<LoadReport, BoxedError> {
     log::debug!("Loading {} tool functions", cat_dir_name);
-    let function_category_dir = get_function_category_dir_using(vec![cat_dir_name])?;
     let function_dir = get_function_dir_using()?;
 
-    // get all folder names in the function directory, as they are the js function names
-    let sub_dirnames: Vec<String> = get_sub_dirnames(&function_category_dir);
+    // folder names in the category dirs are the js function names
+    let (sources, conflicts) = collect_function_sources(&get_function_roots()?, cat_dir_name);
 
     // js function data files
     let mut functions = Vec::new();
-    for sub_dirname in sub_dirnames {
+    let mut rejected = Vec::new();
+    for source in sources {
+        // local tools use the worker utils of the official dir
         let worker_utils_file = function_dir.join("_utils").join("tool.js");
-        let tool_func_dir = function_category_dir.join(&sub_dirname);
-        let config_path = tool_func_dir.join("config.json");
+        let sub_dirname = source.name;
+        let tool_func_dir = source.dir;
         let worker_file = tool_func_dir.join("worker.js");
         let function_name = format!("{}.{}", func_type, sub_dirname);
 
-        let config = match load_json_file(&config_path) {
-            Ok(v) => v,
-            Err(e) => {
-                log::warn!("Failed to read config.json: {}", e);
+        // parse the config and check the worker file
+        let config = match load_function_dir(&tool_func_dir, Some(func_type)) {
+            Ok(c) => c,
+            Err(errors) => {
+                let reasons = errors.iter().map(|e| e.to_string()).collect();
+                rejected.push(RejectedFunction::new(
+                    &function_name,
+                    &tool_func_dir,
+                    reasons,
+                ));
                 continue;
             }
         };
 
-        // check worker file exists
-        if !worker_file.exists() {
-            log::warn!("Worker file not found: {}", &worker_file.to_string_lossy());
-            continue;
-        }
-
         let function = match ToolFunction::new(
             &function_name,
             &config,
@@ -70,7 +79,11 @@ pub fn load_tool_functions(cat_dir_name: &str, func_type: &str) -> Result<(), Bo
         ) {
             Ok(w) => w,
             Err(e) => {
-                log::warn!("Ignore function {}, {}", &function_name, e);
+                rejected.push(RejectedFunction::new(
+                    &function_name,
+                    &tool_func_dir,
+                    vec![e.to_string()],
+                ));
                 continue;
             }
         };
@@ -78,46 +91,28 @@ pub fn load_tool_functions(cat_dir_name: &str, func_type: &str) -> Result<(), Bo
         functions.push((function_name, function));
     }
 
-    match TOOL_FUNCTIONS.lock() {
-        Ok(mut w) => {
-            for (name, function) in &functions {
-                w.insert(name.clone(), function.clone());
-            }
-        }
-        Err(e) => {
-            return Err(Box::new(e));
+    {
+        let mut tool_functions = lock_tool_functions();
+        for (name, function) in &functions {
+            tool_functions.insert(name.clone(), function.clone());
         }
     }
 
     log::info!(
-        "Loaded {} {} tool functions",
+        "Loaded {} {} tool functions, rejected {}",
         &functions.len(),
-        cat_dir_name
+        cat_dir_name,
+        &rejected.len()
     );
 
-    Ok(())
+    Ok(LoadReport {
+        rejected,
+        conflicts,
+    })
 }
 
 pub fn get_bin_path(name: &str) -> Result<String, BoxedError> {
-    // use independent scope to release lock; use loop to try to wait for lock automatically;
-    let start = Instant::now();
-    let tool_functions: HashMap<String, ToolFunction>;
-    loop {
-        match TOOL_FUNCTIONS.lock() {
-            Ok(c) => {
-                tool_functions = c.clone();
-                break;
-            }
-            Err(_) => {
-                if start.elapsed() > Duration::from_millis(200) {
-                    return Err("Failed to acquire lock TOOL_FUNCTIONS".into());
-                }
-                std::thread::sleep(Duration::from_millis(10)); // wait 10ms and retry
-            }
-        }
-    }
-
-    match tool_functions.get(name) {
+    match lock_tool_functions().get(name) {
         Some(p) => Ok(p.bin_path.clone()),
         None => {
             log::error!("Not found tool bin path for: {}", name);
@@ -129,34 +124,12 @@ pub fn get_bin_path(name: &str) -> Result<String, BoxedError> {
 pub fn check_tool_available(name: &str) -> Result<bool, BoxedError> {
     log::debug!("Checking tool available: {}", name);
 
-    let tool = {
-        // use independent scope to release lock; use loop to try to wait for lock automatically;
-        let start = Instant::now();
-        let tool_functions: HashMap<String, ToolFunction>;
-        loop {
-            match TOOL_FUNCTIONS.lock() {
-                Ok(c) => {
-                    tool_functions = c.clone();
-                    break;
-                }
-                Err(_) => {
-                    if start.elapsed() > Duration::from_millis(200) {
-                        return Err("Failed to acquire lock TOOL_FUNCTIONS".into());
-                    }
-                    std::thread::sleep(Duration::from_millis(10)); // wait 10ms and retry
-                }
-            }
-        }
-
-        if !tool_functions.contains_key(name) {
+    let tool = match lock_tool_functions().get(name) {
+        Some(c) => c.clone(),
+        None => {
             log::debug!("Not found tool: {} in TOOL_FUNCTIONS", name);
             return Ok(false);
         }
-
-        match tool_functions.get(name) {
-            Some(c) => c.clone(),
-            None => return Ok(false),
-        }
     };
 
     if tool.available {
@@ -173,13 +146,12 @@ pub fn check_tool_available(name: &str) -> Result<bool, BoxedError> {
         }
     };
 
-    let is_available = match tool.func_type.as_str() {
+    let (status, version) = match tool.func_type.as_str() {
         "tool.model" => {
             // if bin path exists, the tool is available
-            if Path::new(&bin_path).exists() {
-                true
-            } else {
-                false
+            match Path::new(&bin_path).exists() {
+                true => (TOOL_STATUS_AVAILABLE, String::new()),
+                false => (TOOL_STATUS_MISSING, String::new()),
             }
         }
         "tool.exe" => {
@@ -190,38 +162,85 @@ pub fn check_tool_available(name: &str) -> Result<bool, BoxedError> {
                 .collect::<Vec<&str>>();
 
             match exec_command(&bin_path, cmd_args) {
-                Ok(_) => true,
-                Err(_) => false,
+                Ok(output) => {
+                    let version = tool.find_version(&output);
+                    (tool.version_status(&version), version)
+                }
+                Err(_) => (TOOL_STATUS_MISSING, String::new()),
             }
         }
 
         _ => return Err(format!("Unknown tool type: {}", tool.func_type).into()),
     };
+    let is_available = status == TOOL_STATUS_AVAILABLE;
+    if status == TOOL_STATUS_OUTDATED || status == TOOL_STATUS_TOO_NEW {
+        log::warn!(
+            "Tool {} version {} is {}, required: min {:?}, max {:?}",
+            name,
+            version,
+            status,
+            tool.required_bin_version_min,
+            tool.required_bin_version_max
+        );
+    }
 
-    // if the tool is available, set the tool available to true
-    if is_available {
-        // use independent scope to release lock; use loop to try to wait for lock automatically;
-        let start = Instant::now();
-        let mut tool_functions;
-        loop {
-            match TOOL_FUNCTIONS.lock() {
-                Ok(c) => {
-                    tool_functions = c;
-                    break;
-                }
-                Err(_) => {
-                    if start.elapsed() > Duration::from_millis(200) {
-                        return Err("Failed to acquire lock TOOL_FUNCTIONS".into());
-                    }
-                    std::thread::sleep(Duration::from_millis(10)); // wait 10ms and retry
-                }
+    // keep the status and version, only an available tool is not checked again
+    if let Some(c) = lock_tool_functions().get_mut(name) {
+        c.available = is_available;
+        c.version = version;
+        c.status = status.to_string();
+    }
+
+    Ok(is_available)
+}
+
+/// Run `check_tool_available` of the tools in a background thread, so the caller is not
+/// held up by running them. A tool already being checked is left out
+pub fn check_tools_in_background(names: Vec<String>) {
+    let names: Vec<String> = match CHECKING_TOOLS.lock() {
+        Ok(mut checking) => names
+            .into_iter()
+            .filter(|name| checking.insert(name.clone()))
+            .collect(),
+        Err(e) => {
+            log::error!("Failed to lock CHECKING_TOOLS: {}", e);
+            return;
+        }
+    };
+    if names.is_empty() {
+        return;
+    }
+
+    std::thread::spawn(move || {
+        for name in names {
+            if let Err(e) = check_tool_available(&name) {
+                log::error!("Failed to check tool {}: {}", name, e);
+            }
+            if let Ok(mut checking) = CHECKING_TOOLS.lock() {
+                checking.remove(&name);
             }
         }
+    });
+}
 
-        if let Some(c) = tool_functions.get_mut(name) {
-            c.available = true;
+/// Forget the cached availability of a tool, so `check_tool_available` runs it again,
+/// e.g. after it is installed or removed
+pub fn reset_tool_available(name: &str) -> Result<(), BoxedError> {
+    match lock_tool_functions().get_mut(name) {
+        Some(c) => {
+            c.available = false;
+            c.version = String::new();
+            c.status = TOOL_STATUS_UNCHECKED.to_string();
+            Ok(())
         }
+        None => Err(format!("Tool not found: {}", name).into()),
     }
+}
 
-    Ok(is_available)
+/// The status of a tool and its version by the last `check_tool_available`, see `TOOL_STATUS_*`
+pub fn get_tool_status(name: &str) -> Result<(String, String), BoxedError> {
+    match lock_tool_functions().get(name) {
+        Some(t) => Ok((t.status.clone(), t.version.clone())),
+        None => Err(format!("Tool not found: {}", name).into()),
+    }
 }
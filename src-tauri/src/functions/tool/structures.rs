@@ -1,11 +1,22 @@
 use crate::app::resource::get_app_data_dir;
 use crate::errors::BoxedError;
-use crate::functions::common::structures::{FunctionMatches, FunctionProfile};
+use crate::functions::common::config::{FunctionConfig, Version};
+use crate::functions::common::structures::{FunctionIgnored, FunctionMatches, FunctionProfile};
 use crate::thelib::sys::{get_app_version, get_platform};
-use crate::thelib::version::compare_semver;
+use crate::thelib::version::{compare_semver, find_version, DEFAULT_VERSION_PATTERN};
 use serde_json::{json, Value as JsonValue};
 use std::path::{Path, PathBuf};
 
+/// not run yet, see `check_tool_available`
+pub const TOOL_STATUS_UNCHECKED: &str = "unchecked";
+pub const TOOL_STATUS_AVAILABLE: &str = "available";
+/// lower than `required_version.min`
+pub const TOOL_STATUS_OUTDATED: &str = "outdated";
+/// higher than `required_version.max`
+pub const TOOL_STATUS_TOO_NEW: &str = "too_new";
+/// the bin is not found or fails to run
+pub const TOOL_STATUS_MISSING: &str = "missing";
+
 #[derive(Clone, Debug)]
 pub struct ToolFunction {
     pub name: String,
@@ -16,48 +27,42 @@ pub struct ToolFunction {
     pub worker_utils_file: String,
     pub bin_path: String,
     pub bin_version_args: Vec<String>,
+    /// finds the version in the output of `bin_version_args`, empty for the default pattern
+    pub bin_version_regex: String,
     pub required_bin_version_min: String,
     pub required_bin_version_max: String,
     pub installation: JsonValue,
     // status properties
     pub available: bool,
     pub version: String,
+    pub status: String,
 }
 
 impl ToolFunction {
     pub fn new(
         name: &String,
-        config_data: &JsonValue,
+        config: &FunctionConfig,
         worker_file: &String,
         worker_utils_file: &String,
     ) -> Result<ToolFunction, BoxedError> {
-        let func_type = config_data["type"].as_str().unwrap_or("").to_string();
+        let func_type = config.func_type.as_str().to_string();
         if !["tool.exe", "tool.model"].contains(&func_type.as_str()) {
-            return Err(format!("Ignore the function, unknown type: {}", func_type).into());
+            return Err(format!("Not a tool function, unknown type: {}", func_type).into());
         }
 
-        let profile_data = config_data["profile"].clone();
-        if profile_data.is_null() {
-            return Err("Not found `profile` key in config data".into());
-        }
-        let profile = FunctionProfile::from_json(&profile_data);
-
-        let matches_data = config_data["matches"].clone();
-        if matches_data.is_null() {
-            return Err("Not found `matches` key in config data".into());
-        }
-        let matches = FunctionMatches::from_json(&matches_data)?;
+        let profile = FunctionProfile::from_config(&config.profile);
+        let matches = FunctionMatches::from_config(&config.matches);
 
         // skip not match platform
         let cur_platform = get_platform();
         if matches.platforms.is_empty() {
-            return Err("Ignore the function, not found platforms in matches".into());
+            return Err(FunctionIgnored::new("not found platforms in matches").into());
         }
         if !matches.platforms.contains(&cur_platform) && !matches.platforms.contains("*") {
-            return Err(format!(
-                "Ignore the function, not match current platform: {}",
+            return Err(FunctionIgnored::new(format!(
+                "not match current platform: {}",
                 cur_platform
-            )
+            ))
             .into());
         }
 
@@ -66,37 +71,35 @@ impl ToolFunction {
         if !matches.app_version_min.is_empty() {
             let matched = compare_semver(&cur_app_version, ">=", &matches.app_version_min)?;
             if !matched {
-                return Err(format!(
-                    "Ignore the function, current app version is lower than min version: {} < {}",
+                return Err(FunctionIgnored::new(format!(
+                    "current app version is lower than min version: {} < {}",
                     cur_app_version, matches.app_version_min
-                )
+                ))
                 .into());
             }
         }
         if !matches.app_version_max.is_empty() {
             let matched = compare_semver(&cur_app_version, "<=", &matches.app_version_max)?;
             if !matched {
-                return Err(format!(
-                    "Ignore the function, current app version is higher than max version: {} > {}",
+                return Err(FunctionIgnored::new(format!(
+                    "current app version is higher than max version: {} > {}",
                     cur_app_version, matches.app_version_max
-                )
+                ))
                 .into());
             }
         }
 
-        // get bin path
-        let all_bin_paths = &config_data["bin"]["path"];
-        if all_bin_paths.is_null() {
-            return Err("Not found `bin.path` key in config data".into());
-        }
+        let bin = match &config.bin {
+            Some(b) => b,
+            None => return Err("Not found `bin` key in config data".into()),
+        };
         // get bin path by platform: 1st. try current platform string, 2nd. try all os (*) string
-        let path_json = all_bin_paths
-            .get(get_platform().as_str())
-            .unwrap_or(all_bin_paths.get("*").unwrap_or(&JsonValue::Null));
-        if path_json.is_null() {
-            return Err("Not found bin path for current platform".into());
-        }
-        let path_str = path_json.as_str().unwrap_or("");
+        let path_str = match bin.path.get(&get_platform()).or(bin.path.get("*")) {
+            Some(p) => p.as_str(),
+            None => {
+                return Err(FunctionIgnored::new("not found bin path for current platform").into())
+            }
+        };
         // if contains `/`, split it, then use path_join to join it
         let mut bin_path: PathBuf;
         if path_str.contains("/") {
@@ -110,28 +113,33 @@ impl ToolFunction {
         }
 
         // get version arguments of exe tool
-        let version_arguments: Vec<&str> = match config_data["bin"]["version_arguments"].as_array()
-        {
-            Some(arr) => arr.iter().map(|v| v.as_str().unwrap_or("")).collect(),
-            None => vec![],
-        };
+        let version_arguments: Vec<&str> = bin
+            .version_arguments
+            .iter()
+            .flatten()
+            .map(|v| v.as_str())
+            .collect();
         // tool.exe: required version arguments of exe tool
         if func_type.as_str() == "tool.exe" && version_arguments.is_empty() {
             return Err("Missing `version_arguments` in config data for tool.exe".into());
         }
 
-        // get required version (optional)
-        let required_version_min = config_data["bin"]["required_version"]["min"]
-            .as_str()
-            .unwrap_or("")
-            .to_string();
-        let required_version_max = config_data["bin"]["required_version"]["max"]
-            .as_str()
-            .unwrap_or("")
+        // get the pattern of the version in the output (optional), checked by the config
+        let version_regex = bin
+            .version_regex
+            .as_ref()
+            .map_or("", |r| r.as_str())
             .to_string();
 
-        // get bin installation data
-        let installation = config_data["bin"]["installation"].clone();
+        // get required version (optional)
+        let required_version =
+            |version: Option<&Version>| version.map_or("", |v| v.as_str()).to_string();
+        let required = bin.required_version.as_ref();
+        let required_version_min = required_version(required.and_then(|r| r.min.as_ref()));
+        let required_version_max = required_version(required.and_then(|r| r.max.as_ref()));
+
+        // get bin installation data, as written for the front-end and `ToolPackage`
+        let installation = config.raw["bin"]["installation"].clone();
 
         Ok(ToolFunction {
             name: name.to_string(),
@@ -142,12 +150,14 @@ impl ToolFunction {
             worker_utils_file: worker_utils_file.to_string(),
             bin_path: bin_path.to_string_lossy().to_string(),
             bin_version_args: version_arguments.iter().map(|v| v.to_string()).collect(),
+            bin_version_regex: version_regex,
             required_bin_version_min: required_version_min,
             required_bin_version_max: required_version_max,
             installation,
             //
             available: false, // default false
             version: "".to_string(),
+            status: TOOL_STATUS_UNCHECKED.to_string(),
         })
     }
 
@@ -161,6 +171,7 @@ impl ToolFunction {
             "worker_utils_file": self.worker_utils_file,
             "bin_path": self.bin_path,
             "bin_version_args": self.bin_version_args,
+            "bin_version_regex": self.bin_version_regex,
             "required_bin_version": {
                 "min": self.required_bin_version_min,
                 "max": self.required_bin_version_max
@@ -168,7 +179,108 @@ impl ToolFunction {
             "installation": self.installation,
             //
             "available": self.available,
-            "version": self.version
+            "version": self.version,
+            "status": self.status
         })
     }
+
+    /// The version in the output of running the bin with `bin_version_args`, empty if not found
+    pub fn find_version(&self, output: &str) -> String {
+        let pattern = match self.bin_version_regex.is_empty() {
+            true => DEFAULT_VERSION_PATTERN,
+            false => self.bin_version_regex.as_str(),
+        };
+        match find_version(output, pattern) {
+            Ok(Some(v)) => v,
+            Ok(None) => {
+                log::warn!("Not found the version of {} in: {}", self.name, output);
+                String::new()
+            }
+            Err(e) => {
+                log::warn!("Failed to find the version of {}: {}", self.name, e);
+                String::new()
+            }
+        }
+    }
+
+    /// The status of an installed tool by its version and `required_version`.
+    /// A version that can't be compared is not checked
+    pub fn version_status(&self, version: &str) -> &'static str {
+        if version.is_empty() {
+            return TOOL_STATUS_AVAILABLE;
+        }
+        let checks = [
+            (&self.required_bin_version_min, "<", TOOL_STATUS_OUTDATED),
+            (&self.required_bin_version_max, ">", TOOL_STATUS_TOO_NEW),
+        ];
+        for (required, operator, status) in checks {
+            if required.is_empty() {
+                continue;
+            }
+            match compare_semver(version, operator, required) {
+                Ok(true) => return status,
+                Ok(false) => {}
+                Err(e) => {
+                    log::warn!(
+                        "Failed to compare version {} of {}: {}",
+                        version,
+                        self.name,
+                        e
+                    );
+                    return TOOL_STATUS_AVAILABLE;
+                }
+            }
+        }
+        TOOL_STATUS_AVAILABLE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_tool(min: &str, max: &str) -> ToolFunction {
+        // an empty bound is not set
+        let bound = |v: &str| match v.is_empty() {
+            true => JsonValue::Null,
+            false => json!(v),
+        };
+        let config = json!({
+            "type": "tool.exe",
+            "profile": { "title": "FFmpeg" },
+            "matches": { "platforms": ["*"] },
+            "bin": {
+                "path": { "*": "ffmpeg" },
+                "version_arguments": ["-version"],
+                "required_version": { "min": bound(min), "max": bound(max) }
+            }
+        });
+        let config = FunctionConfig::parse(&config, None).unwrap();
+        let name = "tool.exe.ffmpeg".to_string();
+        ToolFunction::new(&name, &config, &String::new(), &String::new()).unwrap()
+    }
+
+    #[test]
+    fn test_version_status() {
+        let tool = make_tool("6.0", "7.1");
+        assert_eq!(tool.version_status("5.1.4"), TOOL_STATUS_OUTDATED);
+        assert_eq!(tool.version_status("6.0"), TOOL_STATUS_AVAILABLE);
+        assert_eq!(tool.version_status("6.1.1"), TOOL_STATUS_AVAILABLE);
+        assert_eq!(tool.version_status("7.1.0"), TOOL_STATUS_AVAILABLE);
+        assert_eq!(tool.version_status("7.1.1"), TOOL_STATUS_TOO_NEW);
+        assert_eq!(tool.version_status("8.0"), TOOL_STATUS_TOO_NEW);
+        // a version not found or not comparable is not checked
+        assert_eq!(tool.version_status(""), TOOL_STATUS_AVAILABLE);
+        assert_eq!(tool.version_status("N-113"), TOOL_STATUS_AVAILABLE);
+
+        // only the bounds set are checked
+        let min_only = make_tool("6.0", "");
+        assert_eq!(min_only.version_status("5.9"), TOOL_STATUS_OUTDATED);
+        assert_eq!(min_only.version_status("99.0"), TOOL_STATUS_AVAILABLE);
+        let max_only = make_tool("", "7.1");
+        assert_eq!(max_only.version_status("0.1"), TOOL_STATUS_AVAILABLE);
+        assert_eq!(max_only.version_status("7.2"), TOOL_STATUS_TOO_NEW);
+        let any = make_tool("", "");
+        assert_eq!(any.version_status("0.1"), TOOL_STATUS_AVAILABLE);
+    }
 }
@@ -0,0 +1,74 @@
+use crate::errors::BoxedError;
+use crate::functions::common::roots::{get_function_roots, LoadReport};
+use crate::functions::file::read::load_functions;
+use crate::functions::tool::read::load_tools;
+use crate::thelib::file_watcher::{spawn_poll_watcher, PollWatcher, WatcherHandle};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+/// the files which define a function, at `<root>/<category>/<name>/`
+const WATCHED_FILES: [&str; 2] = ["config.json", "worker.js"];
+const WATCHED_DEPTH: usize = 3;
+
+// reloads in progress, by the watcher or by commands (e.g. installing a function).
+// the watcher does not poll meanwhile, the files may be half written
+static RELOADING: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts a reload in progress until dropped, also if the reload fails
+struct ReloadingGuard;
+
+impl ReloadingGuard {
+    fn new() -> Self {
+        RELOADING.fetch_add(1, Ordering::SeqCst);
+        ReloadingGuard
+    }
+}
+
+impl Drop for ReloadingGuard {
+    fn drop(&mut self) {
+        RELOADING.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Reload tools and file functions, in the order the front-end loads them at startup
+pub fn reload_functions() -> Result<LoadReport, BoxedError> {
+    let _reloading = ReloadingGuard::new();
+    let mut report = load_tools()?;
+    report.extend(load_functions()?);
+    Ok(report)
+}
+
+/// Watch all function roots, including the local dirs added later, and reload the
+/// functions when a config.json or worker.js is added, changed or removed.
+/// `on_reload` gets the load report, e.g. to notify the front-end.
+/// The watcher runs until the returned handle is stopped
+pub fn start_function_watcher<F>(on_reload: F) -> WatcherHandle
+where
+    F: Fn(&LoadReport) + Send + 'static,
+{
+    let get_dirs = || -> Vec<PathBuf> {
+        match get_function_roots() {
+            Ok(roots) => roots,
+            Err(e) => {
+                log::error!("Failed to get function roots: {}", e);
+                Vec::new()
+            }
+        }
+    };
+
+    spawn_poll_watcher(
+        PollWatcher::new(&WATCHED_FILES, WATCHED_DEPTH),
+        WATCH_INTERVAL,
+        get_dirs,
+        || RELOADING.load(Ordering::SeqCst) > 0,
+        move |changed| {
+            log::info!("Function files changed, reloading: {:?}", changed);
+            match reload_functions() {
+                Ok(report) => on_reload(&report),
+                Err(e) => log::error!("Failed to reload functions: {}", e),
+            }
+        },
+    )
+}
@@ -0,0 +1,7 @@
+// Shared by the GUI app (main.rs) and the headless CLI (bin/filethings-cli.rs)
+pub mod app;
+pub mod commands;
+pub mod config;
+pub mod errors;
+pub mod functions;
+pub mod thelib;
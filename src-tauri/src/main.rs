@@ -1,18 +1,18 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use project_lib::app::logger::setup_logger;
+use project_lib::config::init::APP_STATUS;
+use project_lib::errors::{pack_called_error, CallError};
+use project_lib::thelib::file_watcher::WatcherHandle;
+use project_lib::{app, config, functions, thelib};
+use std::sync::Mutex;
 use sysinfo;
-mod app;
-mod commands;
-mod config;
-mod errors;
-mod functions;
-mod thelib;
-use crate::app::logger::setup_logger;
-use crate::config::init::APP_STATUS;
-use errors::{pack_called_error, CallError};
 use tauri;
 
+// the function watcher thread, stopped when the app exits
+static FUNCTION_WATCHER: Mutex<Option<WatcherHandle>> = Mutex::new(None);
+
 #[tauri::command]
 fn config_call(
     app: tauri::AppHandle,
@@ -111,6 +111,15 @@ fn main() {
                         log::info!("Killed child process: {}, {}", pid, process.name());
                     }
                 }
+
+                match FUNCTION_WATCHER.lock() {
+                    Ok(mut watcher) => {
+                        if let Some(watcher) = watcher.take() {
+                            watcher.stop();
+                        }
+                    }
+                    Err(_) => log::error!("Failed to lock FUNCTION_WATCHER"),
+                }
             }
             _ => {}
         })
@@ -126,6 +135,28 @@ fn main() {
                 }
             };
 
+            // forward progress of backend tasks (long-running commands) to the front-end
+            let app_handle = app.handle().clone();
+            thelib::task::set_progress_emitter(move |payload| {
+                if let Err(e) = app::frontend::emit_task_progress(&app_handle, payload) {
+                    log::error!("Failed to emit task progress: {}", e);
+                }
+            });
+
+            // reload functions when a config.json or worker.js changes, in the official
+            // or the local function dirs, then let the front-end refresh its lists
+            let app_handle = app.handle().clone();
+            let watcher = functions::watcher::start_function_watcher(move |report| {
+                let payload = serde_json::json!(report);
+                if let Err(e) = app::frontend::emit_functions_reloaded(&app_handle, &payload) {
+                    log::error!("Failed to emit functions reloaded: {}", e);
+                }
+            });
+            match FUNCTION_WATCHER.lock() {
+                Ok(mut w) => *w = Some(watcher),
+                Err(_) => log::error!("Failed to lock FUNCTION_WATCHER"),
+            }
+
             // updater plugin (only for desktop platform)
             #[cfg(desktop)]
             app.handle()
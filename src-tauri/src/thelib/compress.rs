@@ -1,20 +1,291 @@
 use crate::errors::BoxedError;
+use crate::thelib::task;
+use regex::Regex;
+use serde_json::{json, Value as JsonValue};
+use std::fmt;
 use std::fs::File;
-use std::path::Path;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
 // use std::path::MAIN_SEPARATOR;
 use zip::read::ZipArchive;
+use zip::result::ZipError;
+use zip::write::{SimpleFileOptions, ZipWriter};
+use zip::AesMode;
 
-pub fn unzip_to(archive_path: &Path, to_dir: &Path) -> Result<(), BoxedError> {
-    log::debug!("Unzipping {:?} to {:?}", archive_path, to_dir);
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+    TarZst,
+    SevenZ, // read only
+}
 
-    match std::fs::create_dir_all(&to_dir) {
-        Ok(_) => {}
-        Err(e) => {
-            log::error!("Failed to create directory {:?}: {:?}", to_dir, e);
-            return Err(format!("Failed to create directory {:?}: {:?}", to_dir, e).into());
+impl ArchiveFormat {
+    /// format name: zip, tar, tar.gz (tgz), tar.zst (tzst), 7z
+    pub fn from_name(name: &str) -> Result<ArchiveFormat, BoxedError> {
+        match name.trim_start_matches('.').to_lowercase().as_str() {
+            "zip" => Ok(ArchiveFormat::Zip),
+            "tar" => Ok(ArchiveFormat::Tar),
+            "tar.gz" | "tgz" => Ok(ArchiveFormat::TarGz),
+            "tar.zst" | "tzst" => Ok(ArchiveFormat::TarZst),
+            "7z" => Ok(ArchiveFormat::SevenZ),
+            _ => Err(format!("Unsupported archive format: '{}'", name).into()),
+        }
+    }
+
+    /// detect format by the file name suffix
+    pub fn from_path(path: &Path) -> Result<ArchiveFormat, BoxedError> {
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_lowercase();
+        for suffix in [
+            ".tar.gz", ".tgz", ".tar.zst", ".tzst", ".tar", ".zip", ".7z",
+        ] {
+            if name.ends_with(suffix) {
+                return ArchiveFormat::from_name(suffix);
+            }
+        }
+        Err(format!("Unsupported archive file: {:?}", path).into())
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::TarZst => "tar.zst",
+            ArchiveFormat::SevenZ => "7z",
+        }
+    }
+}
+
+/// Error of an encrypted archive when the password is missing or wrong.
+/// Callers can tell it from other errors by `err.downcast_ref::<PasswordError>()`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PasswordError {
+    Required,
+    Wrong,
+}
+
+impl PasswordError {
+    /// stable code for the front-end
+    pub fn code(&self) -> &'static str {
+        match self {
+            PasswordError::Required => "password_required",
+            PasswordError::Wrong => "wrong_password",
         }
     }
+}
 
+impl fmt::Display for PasswordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PasswordError::Required => write!(f, "Password required to decrypt the archive"),
+            PasswordError::Wrong => write!(f, "Wrong password"),
+        }
+    }
+}
+
+impl std::error::Error for PasswordError {}
+
+/// Sanitize an entry name of an archive into a relative path, the same way as zip's `mangled_name`:
+/// root, prefix and `..` components are dropped, so the path can't escape the output directory
+pub fn mangle_entry_path(name: &str) -> PathBuf {
+    let name = name.replace('\\', "/");
+    let mut path = PathBuf::new();
+    for component in Path::new(&name).components() {
+        if let Component::Normal(part) = component {
+            path.push(part);
+        }
+    }
+    path
+}
+
+/// An entry in an archive, for listing
+#[derive(Debug, Clone)]
+pub struct ArchiveEntry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
+    /// None if unknown (e.g. tar.gz and solid 7z are compressed as a whole)
+    pub compressed_size: Option<u64>,
+    /// local time, `%Y-%m-%d %H:%M:%S`
+    pub modified: Option<String>,
+    pub encrypted: bool,
+}
+
+impl ArchiveEntry {
+    pub fn to_json(&self) -> JsonValue {
+        json!({
+            "name": self.name,
+            "is_dir": self.is_dir,
+            "size": self.size,
+            "compressed_size": self.compressed_size,
+            "modified": self.modified,
+            "encrypted": self.encrypted,
+        })
+    }
+}
+
+/// Select the entries to extract from an archive
+pub enum EntryFilter {
+    /// entry names, a directory name selects all the entries under it
+    Names(Vec<String>),
+    /// glob or regex, matched against the whole entry name (`/` separated, no trailing `/`)
+    Pattern(Regex),
+}
+
+impl EntryFilter {
+    pub fn is_match(&self, entry_name: &str) -> bool {
+        let name = normalize_entry_name(entry_name);
+        match self {
+            EntryFilter::Names(names) => names.iter().any(|n| {
+                let n = normalize_entry_name(n);
+                !n.is_empty() && (name == n || name.starts_with(&format!("{}/", n)))
+            }),
+            EntryFilter::Pattern(re) => re.is_match(&name),
+        }
+    }
+}
+
+fn is_selected(filter: Option<&EntryFilter>, entry_name: &str) -> bool {
+    match filter {
+        Some(f) => f.is_match(entry_name),
+        None => true,
+    }
+}
+
+// `./a\b/` -> `a/b`
+fn normalize_entry_name(name: &str) -> String {
+    mangle_entry_path(name)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+fn format_unix_time(secs: i64) -> Option<String> {
+    let datetime = chrono::DateTime::from_timestamp(secs, 0)?;
+    Some(
+        datetime
+            .with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string(),
+    )
+}
+
+/// List the entries of an archive (format detected by file name), without extracting
+pub fn list_entries(archive_path: &Path) -> Result<Vec<ArchiveEntry>, BoxedError> {
+    match ArchiveFormat::from_path(archive_path)? {
+        ArchiveFormat::Zip => list_zip_entries(archive_path),
+        ArchiveFormat::SevenZ => list_7z_entries(archive_path),
+        format => list_tar_entries(archive_path, format),
+    }
+}
+
+fn list_zip_entries(archive_path: &Path) -> Result<Vec<ArchiveEntry>, BoxedError> {
+    let mut archive = open_zip_archive(archive_path)?;
+    let mut entries = Vec::new();
+    for i in 0..archive.len() {
+        // raw access, so encrypted entries can be listed without the password
+        let file = archive.by_index_raw(i)?;
+        let modified = file.last_modified().map(|t| {
+            format!(
+                "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+                t.year(),
+                t.month(),
+                t.day(),
+                t.hour(),
+                t.minute(),
+                t.second()
+            )
+        });
+        entries.push(ArchiveEntry {
+            name: file.name().to_string(),
+            is_dir: file.is_dir(),
+            size: file.size(),
+            compressed_size: Some(file.compressed_size()),
+            modified,
+            encrypted: file.encrypted(),
+        });
+    }
+    Ok(entries)
+}
+
+fn list_tar_entries(
+    archive_path: &Path,
+    format: ArchiveFormat,
+) -> Result<Vec<ArchiveEntry>, BoxedError> {
+    let mut archive = tar::Archive::new(open_tar_reader(archive_path, format)?);
+    let mut entries = Vec::new();
+    for entry in archive.entries()? {
+        task::check_cancelled()?;
+
+        let entry = entry?;
+        let header = entry.header();
+        let size = header.size()?;
+        entries.push(ArchiveEntry {
+            name: entry.path()?.to_string_lossy().to_string(),
+            is_dir: header.entry_type().is_dir(),
+            size,
+            compressed_size: match format {
+                ArchiveFormat::Tar => Some(size),
+                _ => None,
+            },
+            modified: header.mtime().ok().and_then(|t| format_unix_time(t as i64)),
+            encrypted: false,
+        });
+    }
+    Ok(entries)
+}
+
+fn list_7z_entries(archive_path: &Path) -> Result<Vec<ArchiveEntry>, BoxedError> {
+    let reader = sevenz_rust::SevenZReader::open(archive_path, sevenz_rust::Password::empty())?;
+    let entries = reader
+        .archive()
+        .files
+        .iter()
+        .map(|f| ArchiveEntry {
+            name: f.name().to_string(),
+            is_dir: f.is_directory(),
+            size: f.size(),
+            compressed_size: None,
+            modified: match f.has_last_modified_date {
+                true => format_unix_time(f.last_modified_date().to_unix_time()),
+                false => None,
+            },
+            encrypted: false,
+        })
+        .collect();
+    Ok(entries)
+}
+
+/// Extract an archive (format detected by file name) to the directory
+pub fn extract_to(archive_path: &Path, to_dir: &Path) -> Result<(), BoxedError> {
+    extract_entries_to(archive_path, to_dir, None, None)?;
+    Ok(())
+}
+
+/// Extract the entries selected by `filter` (all if None) to the directory.
+/// `password` is for encrypted zip (AES or ZipCrypto) and 7z archives.
+/// Returns the paths of the extracted files and directories
+pub fn extract_entries_to(
+    archive_path: &Path,
+    to_dir: &Path,
+    filter: Option<&EntryFilter>,
+    password: Option<&str>,
+) -> Result<Vec<PathBuf>, BoxedError> {
+    match ArchiveFormat::from_path(archive_path)? {
+        ArchiveFormat::Zip => unzip_entries_to(archive_path, to_dir, filter, password),
+        ArchiveFormat::SevenZ => extract_7z_to(archive_path, to_dir, filter, password),
+        format => extract_tar_to(archive_path, format, to_dir, filter),
+    }
+}
+
+fn open_zip_archive(archive_path: &Path) -> Result<ZipArchive<File>, BoxedError> {
     let reader = match File::open(archive_path) {
         Ok(f) => f,
         Err(e) => {
@@ -23,16 +294,103 @@ pub fn unzip_to(archive_path: &Path, to_dir: &Path) -> Result<(), BoxedError> {
         }
     };
 
-    let mut archive = match ZipArchive::new(reader) {
-        Ok(a) => a,
+    match ZipArchive::new(reader) {
+        Ok(a) => Ok(a),
         Err(e) => {
             log::error!("Failed to read zip archive {:?}: {:?}", archive_path, e);
-            return Err(format!("Failed to read zip archive {:?}: {:?}", archive_path, e).into());
+            Err(format!("Failed to read zip archive {:?}: {:?}", archive_path, e).into())
+        }
+    }
+}
+
+/// Read a (small, not encrypted) text entry of a zip without extracting it
+pub fn read_zip_entry_to_string(
+    archive_path: &Path,
+    entry_name: &str,
+) -> Result<String, BoxedError> {
+    let mut archive = open_zip_archive(archive_path)?;
+    let mut file = match archive.by_name(entry_name) {
+        Ok(f) => f,
+        Err(e) => {
+            return Err(
+                format!("Failed to read {} in {:?}: {}", entry_name, archive_path, e).into(),
+            )
         }
     };
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+pub fn unzip_to(archive_path: &Path, to_dir: &Path) -> Result<(), BoxedError> {
+    unzip_entries_to(archive_path, to_dir, None, None)?;
+    Ok(())
+}
+
+/// Unzip the entries selected by `filter` (all if None), encrypted entries need the `password`.
+/// Returns `PasswordError` if the password is missing or wrong
+pub fn unzip_entries_to(
+    archive_path: &Path,
+    to_dir: &Path,
+    filter: Option<&EntryFilter>,
+    password: Option<&str>,
+) -> Result<Vec<PathBuf>, BoxedError> {
+    log::debug!("Unzipping {:?} to {:?}", archive_path, to_dir);
+
+    match std::fs::create_dir_all(&to_dir) {
+        Ok(_) => {}
+        Err(e) => {
+            log::error!("Failed to create directory {:?}: {:?}", to_dir, e);
+            return Err(format!("Failed to create directory {:?}: {:?}", to_dir, e).into());
+        }
+    }
+
+    let mut archive = open_zip_archive(archive_path)?;
+
+    let mut extracted: Vec<PathBuf> = Vec::new();
+    if let Err(e) = unzip_selected(&mut archive, to_dir, filter, password, &mut extracted) {
+        // don't leave a partial extraction, e.g. after a wrong password of a later entry
+        for path in extracted.iter().rev() {
+            let _ = match path.is_dir() {
+                true => std::fs::remove_dir(path),
+                false => std::fs::remove_file(path),
+            };
+        }
+        return Err(e);
+    }
+
+    Ok(extracted)
+}
 
+/// the extracted paths are added to `extracted` before they are written
+fn unzip_selected(
+    archive: &mut ZipArchive<File>,
+    to_dir: &Path,
+    filter: Option<&EntryFilter>,
+    password: Option<&str>,
+    extracted: &mut Vec<PathBuf>,
+) -> Result<(), BoxedError> {
+    let total_entries = archive.len() as u64;
     for i in 0..archive.len() {
-        let mut file = archive.by_index(i)?;
+        task::check_cancelled()?;
+        task::report_progress(i as u64, total_entries, "items");
+
+        let (selected, encrypted) = {
+            let raw = archive.by_index_raw(i)?;
+            (is_selected(filter, raw.name()), raw.encrypted())
+        };
+        if !selected {
+            continue;
+        }
+        let mut file = match (encrypted, password) {
+            (true, None) => return Err(PasswordError::Required.into()),
+            (true, Some(p)) => match archive.by_index_decrypt(i, p.as_bytes()) {
+                Ok(f) => f,
+                Err(ZipError::InvalidPassword) => return Err(PasswordError::Wrong.into()),
+                Err(e) => return Err(e.into()),
+            },
+            (false, _) => archive.by_index(i)?,
+        };
         if file.is_dir() {
             let dir_path = to_dir.join(file.mangled_name());
             match std::fs::create_dir_all(&dir_path) {
@@ -44,6 +402,7 @@ pub fn unzip_to(archive_path: &Path, to_dir: &Path) -> Result<(), BoxedError> {
                     );
                 }
             };
+            extracted.push(dir_path);
         } else {
             let out_path = to_dir.join(file.mangled_name().as_os_str());
             if let Some(p) = out_path.parent() {
@@ -62,14 +421,490 @@ pub fn unzip_to(archive_path: &Path, to_dir: &Path) -> Result<(), BoxedError> {
                     return Err(format!("Failed to create file {:?}: {:?}", out_path, e).into());
                 }
             };
-            match std::io::copy(&mut file, &mut outfile) {
-                Ok(_) => {}
-                Err(e) => {
-                    log::error!("Failed to copy file {:?}: {:?}", out_path, e);
-                    return Err(format!("Failed to copy file {:?}: {:?}", out_path, e).into());
+            extracted.push(out_path.clone());
+            copy_zip_entry(&mut file, &mut outfile, encrypted, &out_path)?;
+        }
+    }
+    task::report_progress(total_entries, total_entries, "items");
+
+    Ok(())
+}
+
+/// Copy an unzipped entry. Write errors (e.g. disk full) are I/O errors; invalid data read
+/// from an encrypted entry means a wrong password: the ZipCrypto password check is weak,
+/// a wrong password may only fail the crc, or the authentication code of AES
+fn copy_zip_entry(
+    file: &mut impl Read,
+    outfile: &mut impl Write,
+    encrypted: bool,
+    out_path: &Path,
+) -> Result<(), BoxedError> {
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = match file.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) if encrypted && e.kind() == std::io::ErrorKind::InvalidData => {
+                log::error!("Failed to decrypt file {:?}: {:?}", out_path, e);
+                return Err(PasswordError::Wrong.into());
+            }
+            Err(e) => {
+                log::error!("Failed to read zip entry {:?}: {:?}", out_path, e);
+                return Err(format!("Failed to read zip entry {:?}: {:?}", out_path, e).into());
+            }
+        };
+        if let Err(e) = outfile.write_all(&buf[..n]) {
+            log::error!("Failed to write file {:?}: {:?}", out_path, e);
+            return Err(format!("Failed to write file {:?}: {:?}", out_path, e).into());
+        }
+    }
+}
+
+fn open_tar_reader(
+    archive_path: &Path,
+    format: ArchiveFormat,
+) -> Result<Box<dyn Read>, BoxedError> {
+    let file = match File::open(archive_path) {
+        Ok(f) => f,
+        Err(e) => {
+            log::error!("Failed to open file {:?}: {:?}", archive_path, e);
+            return Err(format!("Failed to open file {:?}: {:?}", archive_path, e).into());
+        }
+    };
+    let reader: Box<dyn Read> = match format {
+        ArchiveFormat::Tar => Box::new(file),
+        ArchiveFormat::TarGz => Box::new(flate2::read::GzDecoder::new(file)),
+        ArchiveFormat::TarZst => Box::new(zstd::stream::read::Decoder::new(file)?),
+        _ => return Err(format!("Not a tar archive: {:?}", archive_path).into()),
+    };
+    Ok(reader)
+}
+
+pub fn extract_tar_to(
+    archive_path: &Path,
+    format: ArchiveFormat,
+    to_dir: &Path,
+    filter: Option<&EntryFilter>,
+) -> Result<Vec<PathBuf>, BoxedError> {
+    log::debug!("Extracting {:?} to {:?}", archive_path, to_dir);
+
+    std::fs::create_dir_all(to_dir)?;
+
+    let mut archive = tar::Archive::new(open_tar_reader(archive_path, format)?);
+    let mut extracted: Vec<PathBuf> = Vec::new();
+    let mut count: u64 = 0;
+    for entry in archive.entries()? {
+        task::check_cancelled()?;
+        count += 1;
+        task::report_progress(count, 0, "items");
+
+        let mut entry = entry?;
+        let entry_path = entry.path()?.to_string_lossy().to_string();
+        if !is_selected(filter, &entry_path) {
+            continue;
+        }
+        let out_path = to_dir.join(mangle_entry_path(&entry_path));
+
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_dir() {
+            std::fs::create_dir_all(&out_path)?;
+        } else if entry_type.is_file() {
+            if let Some(p) = out_path.parent() {
+                std::fs::create_dir_all(p)?;
+            }
+            if let Err(e) = entry.unpack(&out_path) {
+                log::error!("Failed to extract file {:?}: {:?}", out_path, e);
+                return Err(format!("Failed to extract file {:?}: {:?}", out_path, e).into());
+            }
+        } else {
+            // links could point outside of the output dir, skip them (and other special entries)
+            log::warn!("Skip tar entry: {}, type: {:?}", entry_path, entry_type);
+            continue;
+        }
+        extracted.push(out_path);
+    }
+
+    Ok(extracted)
+}
+
+pub fn extract_7z_to(
+    archive_path: &Path,
+    to_dir: &Path,
+    filter: Option<&EntryFilter>,
+    password: Option<&str>,
+) -> Result<Vec<PathBuf>, BoxedError> {
+    log::debug!("Extracting {:?} to {:?}", archive_path, to_dir);
+
+    std::fs::create_dir_all(to_dir)?;
+
+    let password = match password {
+        Some(p) => sevenz_rust::Password::from(p),
+        None => sevenz_rust::Password::empty(),
+    };
+    let mut reader =
+        sevenz_rust::SevenZReader::open(archive_path, password).map_err(map_7z_error)?;
+    let total_entries = reader.archive().files.len() as u64;
+    let mut extracted: Vec<PathBuf> = Vec::new();
+    let mut count: u64 = 0;
+    reader
+        .for_each_entries(|entry, entry_reader| {
+            if task::check_cancelled().is_err() {
+                return Err(sevenz_rust::Error::other("Task cancelled"));
+            }
+            count += 1;
+            task::report_progress(count, total_entries, "items");
+
+            if !is_selected(filter, entry.name()) {
+                // entries of a solid block are read in sequence, skip the data of this one
+                std::io::copy(entry_reader, &mut std::io::sink())
+                    .map_err(sevenz_rust::Error::io)?;
+                return Ok(true);
+            }
+
+            let out_path = to_dir.join(mangle_entry_path(entry.name()));
+            if entry.is_directory() {
+                std::fs::create_dir_all(&out_path).map_err(sevenz_rust::Error::io)?;
+            } else if !entry.is_anti_item() {
+                if let Some(p) = out_path.parent() {
+                    std::fs::create_dir_all(p).map_err(sevenz_rust::Error::io)?;
                 }
+                let mut outfile = File::create(&out_path).map_err(sevenz_rust::Error::io)?;
+                if entry.has_stream() {
+                    std::io::copy(entry_reader, &mut outfile).map_err(sevenz_rust::Error::io)?;
+                }
+            } else {
+                return Ok(true);
             }
+            extracted.push(out_path);
+            Ok(true)
+        })
+        .map_err(map_7z_error)?;
+
+    Ok(extracted)
+}
+
+fn map_7z_error(e: sevenz_rust::Error) -> BoxedError {
+    match e {
+        sevenz_rust::Error::PasswordRequired => PasswordError::Required.into(),
+        sevenz_rust::Error::MaybeBadPassword(_) => PasswordError::Wrong.into(),
+        e => e.into(),
+    }
+}
+
+/// a file or directory to add into an archive
+struct ArchiveSource {
+    path: PathBuf,
+    name: String, // entry name in the archive, `/` separated
+    is_dir: bool,
+}
+
+fn collect_archive_sources(
+    path: &Path,
+    name: &str,
+    output_file: &Path,
+    sources: &mut Vec<ArchiveSource>,
+) -> Result<(), BoxedError> {
+    // do not pack the archive being written into itself
+    if path == output_file {
+        return Ok(());
+    }
+
+    if path.is_dir() {
+        sources.push(ArchiveSource {
+            path: path.to_path_buf(),
+            name: name.to_string(),
+            is_dir: true,
+        });
+        // symlinked directories are not followed, to avoid loops
+        if std::fs::symlink_metadata(path)?.file_type().is_symlink() {
+            log::warn!("Skip contents of symlinked directory: {:?}", path);
+            return Ok(());
+        }
+        let mut entries: Vec<PathBuf> = std::fs::read_dir(path)?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .collect();
+        entries.sort();
+        for sub_path in entries {
+            let sub_name = sub_path.file_name().unwrap_or_default().to_string_lossy();
+            let sub_name = format!("{}/{}", name, sub_name);
+            collect_archive_sources(&sub_path, &sub_name, output_file, sources)?;
+        }
+    } else if path.is_file() {
+        sources.push(ArchiveSource {
+            path: path.to_path_buf(),
+            name: name.to_string(),
+            is_dir: false,
+        });
+    } else {
+        log::warn!("Skip path, not a file or directory: {:?}", path);
+    }
+
+    Ok(())
+}
+
+/// Pack files and directories into an archive. Each input path is added by its own name
+/// (a directory with all its contents). `level` is the compression level, None for the default:
+/// zip (deflate) 0-9, tar.gz 0-9, tar.zst 1-22; ignored for tar.
+/// `password` encrypts the files with AES-256, zip only.
+/// Returns the number of entries written
+pub fn create_archive(
+    input_paths: &[PathBuf],
+    output_file: &Path,
+    format: ArchiveFormat,
+    level: Option<i64>,
+    password: Option<&str>,
+) -> Result<u64, BoxedError> {
+    log::debug!(
+        "Creating {:?} archive {:?} from {:?}",
+        format,
+        output_file,
+        input_paths
+    );
+
+    if password.is_some() && format != ArchiveFormat::Zip {
+        return Err(format!("Encryption is not supported for {:?} archive", format).into());
+    }
+
+    let mut sources: Vec<ArchiveSource> = Vec::new();
+    for input_path in input_paths {
+        if !input_path.exists() {
+            return Err(format!("Path not found: {:?}", input_path).into());
+        }
+        let name = match input_path.file_name() {
+            Some(n) => n.to_string_lossy().to_string(),
+            None => return Err(format!("Invalid input path: {:?}", input_path).into()),
+        };
+        collect_archive_sources(input_path, &name, output_file, &mut sources)?;
+    }
+
+    if let Some(p) = output_file.parent() {
+        std::fs::create_dir_all(p)?;
+    }
+
+    let result = match format {
+        ArchiveFormat::Zip => write_zip(&sources, output_file, level, password),
+        ArchiveFormat::Tar | ArchiveFormat::TarGz | ArchiveFormat::TarZst => {
+            write_tar(&sources, output_file, format, level)
+        }
+        ArchiveFormat::SevenZ => Err("Creating 7z archive is not supported".into()),
+    };
+
+    // remove the incomplete archive
+    if result.is_err() && output_file.exists() {
+        let _ = std::fs::remove_file(output_file);
+    }
+
+    result.map(|_| sources.len() as u64)
+}
+
+fn write_zip(
+    sources: &[ArchiveSource],
+    output_file: &Path,
+    level: Option<i64>,
+    password: Option<&str>,
+) -> Result<(), BoxedError> {
+    let file = File::create(output_file)?;
+    let mut writer = ZipWriter::new(file);
+
+    let total = sources.len() as u64;
+    for (i, source) in sources.iter().enumerate() {
+        task::check_cancelled()?;
+        task::report_progress(i as u64, total, "items");
+
+        let metadata = std::fs::metadata(&source.path)?;
+        let mut options = SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .compression_level(level)
+            .large_file(metadata.len() >= u32::MAX as u64);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            options = options.unix_permissions(metadata.permissions().mode());
+        }
+
+        if source.is_dir {
+            writer.add_directory(format!("{}/", source.name), options)?;
+        } else {
+            let options = match password {
+                Some(p) => options.with_aes_encryption(AesMode::Aes256, p),
+                None => options,
+            };
+            writer.start_file(source.name.as_str(), options)?;
+            let mut input = File::open(&source.path)?;
+            std::io::copy(&mut input, &mut writer)?;
+        }
+    }
+
+    writer.finish()?;
+    task::report_progress(total, total, "items");
+
+    Ok(())
+}
+
+fn write_tar(
+    sources: &[ArchiveSource],
+    output_file: &Path,
+    format: ArchiveFormat,
+    level: Option<i64>,
+) -> Result<(), BoxedError> {
+    let file = File::create(output_file)?;
+    match format {
+        ArchiveFormat::Tar => {
+            let mut builder = tar::Builder::new(file);
+            append_tar_entries(&mut builder, sources)?;
+            builder.into_inner()?.flush()?;
+        }
+        ArchiveFormat::TarGz => {
+            let level = level.unwrap_or(6).clamp(0, 9) as u32;
+            let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::new(level));
+            let mut builder = tar::Builder::new(encoder);
+            append_tar_entries(&mut builder, sources)?;
+            builder.into_inner()?.finish()?;
+        }
+        ArchiveFormat::TarZst => {
+            let level = level.unwrap_or(3).clamp(1, 22) as i32;
+            let encoder = zstd::stream::write::Encoder::new(file, level)?;
+            let mut builder = tar::Builder::new(encoder);
+            append_tar_entries(&mut builder, sources)?;
+            builder.into_inner()?.finish()?;
+        }
+        _ => return Err(format!("Not a tar format: {:?}", format).into()),
+    }
+
+    Ok(())
+}
+
+fn append_tar_entries<W: Write>(
+    builder: &mut tar::Builder<W>,
+    sources: &[ArchiveSource],
+) -> Result<(), BoxedError> {
+    let total = sources.len() as u64;
+    for (i, source) in sources.iter().enumerate() {
+        task::check_cancelled()?;
+        task::report_progress(i as u64, total, "items");
+
+        if source.is_dir {
+            builder.append_dir(&source.name, &source.path)?;
+        } else {
+            builder.append_path_with_name(&source.path, &source.name)?;
         }
     }
+    builder.finish()?;
+    task::report_progress(total, total, "items");
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thelib::test_utils::TempDir;
+
+    #[test]
+    fn test_mangle_entry_path() {
+        assert_eq!(mangle_entry_path("a/b.txt"), PathBuf::from("a/b.txt"));
+        assert_eq!(
+            mangle_entry_path("../../etc/passwd"),
+            PathBuf::from("etc/passwd")
+        );
+        assert_eq!(mangle_entry_path("/abs/./c"), PathBuf::from("abs/c"));
+        assert_eq!(
+            mangle_entry_path("..\\win\\d.txt"),
+            PathBuf::from("win/d.txt")
+        );
+    }
+
+    #[test]
+    fn test_create_and_extract_archive() {
+        let tmp = TempDir::new("archive");
+        let dir = tmp.path().to_path_buf();
+        let src_dir = dir.join("src");
+        std::fs::create_dir_all(src_dir.join("sub")).unwrap();
+        std::fs::write(src_dir.join("a.txt"), b"hello").unwrap();
+        std::fs::write(src_dir.join("sub").join("b.txt"), b"world").unwrap();
+
+        for format in [
+            ArchiveFormat::Zip,
+            ArchiveFormat::Tar,
+            ArchiveFormat::TarGz,
+            ArchiveFormat::TarZst,
+        ] {
+            let archive = dir.join(format!("out.{}", format.extension()));
+            let count =
+                create_archive(std::slice::from_ref(&src_dir), &archive, format, None, None)
+                    .unwrap();
+            assert_eq!(count, 4);
+            assert_eq!(ArchiveFormat::from_path(&archive).unwrap(), format);
+
+            let out_dir = dir.join(format!("out-{}", format.extension()));
+            extract_to(&archive, &out_dir).unwrap();
+            assert_eq!(
+                std::fs::read(out_dir.join("src").join("sub").join("b.txt")).unwrap(),
+                b"world"
+            );
+
+            let entries = list_entries(&archive).unwrap();
+            assert_eq!(entries.len(), 4);
+            assert!(entries.iter().any(|e| e.name == "src/a.txt" && e.size == 5));
+
+            let out_dir = dir.join(format!("sel-{}", format.extension()));
+            let filter = EntryFilter::Names(vec!["src/sub".to_string()]);
+            let extracted = extract_entries_to(&archive, &out_dir, Some(&filter), None).unwrap();
+            assert_eq!(extracted.len(), 2);
+            assert!(out_dir.join("src").join("sub").join("b.txt").is_file());
+            assert!(!out_dir.join("src").join("a.txt").exists());
+        }
+    }
+
+    #[test]
+    fn test_encrypted_zip() {
+        let tmp = TempDir::new("zip-aes");
+        let dir = tmp.path().to_path_buf();
+        let src_file = dir.join("secret.txt");
+        std::fs::write(&src_file, b"top secret").unwrap();
+
+        let archive = dir.join("out.zip");
+        let inputs = std::slice::from_ref(&src_file);
+        create_archive(inputs, &archive, ArchiveFormat::Zip, None, Some("pass")).unwrap();
+        assert!(list_entries(&archive).unwrap()[0].encrypted);
+
+        let out_dir = dir.join("out");
+        let err = unzip_entries_to(&archive, &out_dir, None, None).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<PasswordError>(),
+            Some(&PasswordError::Required)
+        );
+        let err = unzip_entries_to(&archive, &out_dir, None, Some("wrong")).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<PasswordError>(),
+            Some(&PasswordError::Wrong)
+        );
+
+        // a plain entry before the encrypted one is removed again on a wrong password
+        let mixed = dir.join("mixed.zip");
+        let mut writer = ZipWriter::new(File::create(&mixed).unwrap());
+        writer
+            .start_file("plain.txt", SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(b"plain").unwrap();
+        let options = SimpleFileOptions::default().with_aes_encryption(AesMode::Aes256, "pass");
+        writer.start_file("secret.txt", options).unwrap();
+        writer.write_all(b"top secret").unwrap();
+        writer.finish().unwrap();
+        let mixed_out = dir.join("mixed");
+        let err = unzip_entries_to(&mixed, &mixed_out, None, Some("wrong")).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<PasswordError>(),
+            Some(&PasswordError::Wrong)
+        );
+        assert!(!mixed_out.join("plain.txt").exists());
+
+        unzip_entries_to(&archive, &out_dir, None, Some("pass")).unwrap();
+        assert_eq!(
+            std::fs::read(out_dir.join("secret.txt")).unwrap(),
+            b"top secret"
+        );
+    }
+}
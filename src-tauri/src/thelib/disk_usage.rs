@@ -0,0 +1,331 @@
+use crate::errors::BoxedError;
+use crate::thelib::task::report_progress;
+use crate::thelib::walk::{walk, WalkOptions};
+use serde_json::{json, Value as JsonValue};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const DAY_SECS: u64 = 24 * 3600;
+
+/// file age buckets by mtime: label and max age in days, the last one has no limit
+const AGE_BUCKETS: [(&str, Option<u64>); 5] = [
+    ("1d", Some(1)),
+    ("7d", Some(7)),
+    ("30d", Some(30)),
+    ("365d", Some(365)),
+    ("older", None),
+];
+
+#[derive(Debug, Clone, Default)]
+struct DirStats {
+    /// allocated size of the files below the dir
+    size: u64,
+    /// sum of the file lengths
+    apparent_size: u64,
+    file_count: u64,
+    dir_count: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct AgeBucket {
+    count: u64,
+    size: u64,
+}
+
+/// Disk usage of a dir: cumulative sizes per dir, the largest files and dirs,
+/// and sizes by file age. Links are not followed, a file with several hardlinks
+/// is counted once
+pub struct DiskUsage {
+    root: PathBuf,
+    /// depth of the tree in `to_json`; sizes always include all levels
+    max_depth: usize,
+    top: usize,
+    dirs: HashMap<String, DirStats>,
+    top_files: BinaryHeap<Reverse<(u64, String)>>,
+    age_buckets: [AgeBucket; AGE_BUCKETS.len()],
+    unknown_age: AgeBucket,
+    hardlinks_skipped: u64,
+}
+
+/// Bytes allocated on disk, the file length where the block count is not available
+#[cfg(unix)]
+fn allocated_size(meta: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    // st_blocks is in 512-byte units regardless of the block size
+    meta.blocks() * 512
+}
+
+#[cfg(not(unix))]
+fn allocated_size(meta: &fs::Metadata) -> u64 {
+    meta.len()
+}
+
+/// (device, inode) of a file with more than one hardlink
+#[cfg(unix)]
+fn hardlink_key(meta: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    match meta.nlink() > 1 {
+        true => Some((meta.dev(), meta.ino())),
+        false => None,
+    }
+}
+
+#[cfg(not(unix))]
+fn hardlink_key(_meta: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+fn parent_of(rel_path: &str) -> &str {
+    rel_path.rsplit_once('/').map(|(p, _)| p).unwrap_or("")
+}
+
+/// the dir of `rel_path` and each dir above it, the root is ""
+fn ancestors(rel_path: &str) -> impl Iterator<Item = &str> {
+    let mut rel = Some(rel_path);
+    std::iter::from_fn(move || {
+        let current = rel?;
+        rel = match current.is_empty() {
+            true => None,
+            false => Some(parent_of(current)),
+        };
+        Some(current)
+    })
+}
+
+fn push_top(heap: &mut BinaryHeap<Reverse<(u64, String)>>, top: usize, size: u64, path: &str) {
+    if top == 0 {
+        return;
+    }
+    if heap.len() < top {
+        heap.push(Reverse((size, path.to_string())));
+    } else if heap.peek().is_some_and(|Reverse((min, _))| size > *min) {
+        heap.pop();
+        heap.push(Reverse((size, path.to_string())));
+    }
+}
+
+/// largest first
+fn sorted_top(heap: BinaryHeap<Reverse<(u64, String)>>) -> Vec<JsonValue> {
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|Reverse((size, path))| json!({ "path": path, "size": size }))
+        .collect()
+}
+
+impl DiskUsage {
+    pub fn scan<P: AsRef<Path>>(root: P, max_depth: usize, top: usize) -> Result<Self, BoxedError> {
+        let root = root.as_ref();
+        if !root.is_dir() {
+            return Err(format!("Not a directory: {}", root.display()).into());
+        }
+        let mut usage = DiskUsage {
+            root: root.to_path_buf(),
+            max_depth,
+            top,
+            dirs: HashMap::new(),
+            top_files: BinaryHeap::new(),
+            age_buckets: [AgeBucket::default(); AGE_BUCKETS.len()],
+            unknown_age: AgeBucket::default(),
+            hardlinks_skipped: 0,
+        };
+        let now = SystemTime::now();
+        let mut seen_links: HashSet<(u64, u64)> = HashSet::new();
+        let mut items: u64 = 0;
+
+        for entry in walk(root, &WalkOptions::default()) {
+            let entry = entry?;
+            items += 1;
+            report_progress(items, 0, "items");
+
+            if entry.is_dir() {
+                usage.dirs.entry(entry.rel_path.clone()).or_default();
+                if !entry.rel_path.is_empty() {
+                    for dir in ancestors(parent_of(&entry.rel_path)) {
+                        usage.dirs.entry(dir.to_string()).or_default().dir_count += 1;
+                    }
+                }
+                continue;
+            }
+            if !entry.is_file() {
+                continue;
+            }
+
+            let meta = match fs::symlink_metadata(&entry.path) {
+                Ok(m) => m,
+                Err(e) => {
+                    log::warn!("Failed to read {:?}: {}", entry.path, e);
+                    continue;
+                }
+            };
+            if let Some(key) = hardlink_key(&meta) {
+                if !seen_links.insert(key) {
+                    usage.hardlinks_skipped += 1;
+                    continue;
+                }
+            }
+            let size = allocated_size(&meta);
+            let apparent_size = meta.len();
+
+            for dir in ancestors(parent_of(&entry.rel_path)) {
+                let stats = usage.dirs.entry(dir.to_string()).or_default();
+                stats.size += size;
+                stats.apparent_size += apparent_size;
+                stats.file_count += 1;
+            }
+            push_top(
+                &mut usage.top_files,
+                top,
+                size,
+                &entry.path.to_string_lossy(),
+            );
+
+            let age = entry.modified.and_then(|m| now.duration_since(m).ok());
+            let bucket = match age {
+                // modified in the future
+                None if entry.modified.is_some() => Some(0),
+                None => None,
+                Some(age) => AGE_BUCKETS.iter().position(|(_, days)| {
+                    days.is_none_or(|d| age <= Duration::from_secs(d * DAY_SECS))
+                }),
+            };
+            let bucket = match bucket {
+                Some(i) => &mut usage.age_buckets[i],
+                None => &mut usage.unknown_age,
+            };
+            bucket.count += 1;
+            bucket.size += size;
+        }
+
+        Ok(usage)
+    }
+
+    fn full_path(&self, rel_path: &str) -> PathBuf {
+        match rel_path.is_empty() {
+            true => self.root.clone(),
+            false => self.root.join(rel_path),
+        }
+    }
+
+    /// the dir node with its sub dirs down to `max_depth`, largest first
+    fn dir_to_json(
+        &self,
+        rel_path: &str,
+        depth: usize,
+        children: &HashMap<&str, Vec<&str>>,
+    ) -> JsonValue {
+        let stats = self.dirs.get(rel_path).cloned().unwrap_or_default();
+        let path = self.full_path(rel_path);
+        let mut sub_dirs: Vec<JsonValue> = Vec::new();
+        if depth < self.max_depth {
+            let mut subs: Vec<&str> = children.get(rel_path).cloned().unwrap_or_default();
+            subs.sort_by_key(|s| Reverse(self.dirs.get(*s).map(|d| d.size).unwrap_or(0)));
+            sub_dirs = subs
+                .into_iter()
+                .map(|s| self.dir_to_json(s, depth + 1, children))
+                .collect();
+        }
+        json!({
+            "name": path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+            "path": path.to_string_lossy(),
+            "size": stats.size,
+            "apparent_size": stats.apparent_size,
+            "file_count": stats.file_count,
+            "dir_count": stats.dir_count,
+            "children": sub_dirs,
+        })
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let mut children: HashMap<&str, Vec<&str>> = HashMap::new();
+        let mut top_dirs: BinaryHeap<Reverse<(u64, String)>> = BinaryHeap::new();
+        for (rel_path, stats) in self.dirs.iter() {
+            if rel_path.is_empty() {
+                continue;
+            }
+            children
+                .entry(parent_of(rel_path))
+                .or_default()
+                .push(rel_path);
+            push_top(
+                &mut top_dirs,
+                self.top,
+                stats.size,
+                &self.full_path(rel_path).to_string_lossy(),
+            );
+        }
+
+        let mut age_buckets: Vec<JsonValue> = AGE_BUCKETS
+            .iter()
+            .zip(self.age_buckets.iter())
+            .map(|((label, days), b)| {
+                json!({ "label": label, "max_days": days, "count": b.count, "size": b.size })
+            })
+            .collect();
+        age_buckets.push(json!({
+            "label": "unknown",
+            "max_days": null,
+            "count": self.unknown_age.count,
+            "size": self.unknown_age.size,
+        }));
+
+        let root = self.dirs.get("").cloned().unwrap_or_default();
+        json!({
+            "tree": self.dir_to_json("", 0, &children),
+            "size": root.size,
+            "apparent_size": root.apparent_size,
+            "file_count": root.file_count,
+            "dir_count": root.dir_count,
+            "top_files": sorted_top(self.top_files.clone()),
+            "top_dirs": sorted_top(top_dirs),
+            "age_buckets": age_buckets,
+            "hardlinks_skipped": self.hardlinks_skipped,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thelib::test_utils::TempDir;
+
+    #[test]
+    fn test_disk_usage() {
+        let tmp = TempDir::new("disk-usage");
+        let dir = tmp.path().to_path_buf();
+        fs::create_dir_all(dir.join("a/b/c")).unwrap();
+        fs::create_dir_all(dir.join("d")).unwrap();
+        fs::write(dir.join("x.bin"), vec![0u8; 100]).unwrap();
+        fs::write(dir.join("a/y.bin"), vec![0u8; 5000]).unwrap();
+        fs::write(dir.join("a/b/c/z.bin"), vec![0u8; 20000]).unwrap();
+        #[cfg(unix)]
+        fs::hard_link(dir.join("a/y.bin"), dir.join("d/y-link.bin")).unwrap();
+
+        let json = DiskUsage::scan(&dir, 1, 2).unwrap().to_json();
+        assert_eq!(json["file_count"], 3);
+        assert_eq!(json["dir_count"], 4);
+        assert_eq!(json["apparent_size"], 25100);
+        #[cfg(unix)]
+        assert_eq!(json["hardlinks_skipped"], 1);
+
+        // depth 1: a and d, sizes include the levels below
+        let tree = &json["tree"];
+        assert_eq!(tree["children"].as_array().unwrap().len(), 2);
+        assert_eq!(tree["children"][0]["name"], "a");
+        assert_eq!(tree["children"][0]["apparent_size"], 25000);
+        assert_eq!(tree["children"][0]["file_count"], 2);
+        assert_eq!(tree["children"][0]["dir_count"], 2);
+        assert!(tree["children"][0]["children"]
+            .as_array()
+            .unwrap()
+            .is_empty());
+
+        let top_files = json["top_files"].as_array().unwrap();
+        assert_eq!(top_files.len(), 2);
+        assert!(top_files[0]["path"].as_str().unwrap().ends_with("z.bin"));
+        assert_eq!(json["top_dirs"].as_array().unwrap().len(), 2);
+        assert_eq!(json["age_buckets"][0]["count"], 3);
+    }
+}
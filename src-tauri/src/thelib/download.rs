@@ -1,6 +1,11 @@
+use crate::thelib::hash::hash_file;
+use crate::thelib::task::current_task;
 use futures::TryStreamExt;
-use reqwest::header::{HeaderName, HeaderValue, RANGE};
-use std::path::PathBuf;
+use reqwest::header::{HeaderName, HeaderValue, ACCEPT_RANGES, CONTENT_RANGE, RANGE};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 use tokio::{
     fs,
     io::{AsyncSeekExt, AsyncWriteExt, BufWriter, SeekFrom},
@@ -8,13 +13,84 @@ use tokio::{
 
 use std::collections::HashMap;
 
+type DownloadError = Box<dyn std::error::Error + Send + Sync>;
+
+// backoff between retries: 1s, 2s, 4s, ... up to 30s
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
+// smaller files are not worth splitting into segments
+const MIN_SEGMENT_SIZE: u64 = 1024 * 1024;
+
+/// Options of `download_file_with_retry`
+#[derive(Debug, Clone)]
+pub struct DownloadOptions {
+    pub headers: Option<HashMap<String, String>>,
+    /// continue from an existing `.part` file left by a previous download
+    pub resume: bool,
+    /// urls to try in order after the main url fails
+    pub mirrors: Vec<String>,
+    /// expected sha256 (hex) of the downloaded file
+    pub sha256: Option<String>,
+    /// expected size in bytes of the downloaded file
+    pub size: Option<u64>,
+    /// retries for each url on transient errors
+    pub max_retries: u32,
+    /// parallel byte-range segments, 1 for a single stream.
+    /// a failed segmented download restarts from scratch, `resume` is ignored
+    pub segments: u32,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            headers: None,
+            resume: false,
+            mirrors: vec![],
+            sha256: None,
+            size: None,
+            max_retries: 3,
+            segments: 1,
+        }
+    }
+}
+
+/// Non-success http status of a download response
+#[derive(Debug)]
+pub struct HttpStatusError {
+    pub status: reqwest::StatusCode,
+    pub url: String,
+}
+
+impl fmt::Display for HttpStatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}. URL: {}", self.status, self.url)
+    }
+}
+
+impl std::error::Error for HttpStatusError {}
+
+/// The server answered a byte-range request with the whole file
+#[derive(Debug)]
+pub struct RangeIgnoredError {
+    pub url: String,
+}
+
+impl fmt::Display for RangeIgnoredError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Range request not satisfied by the server: {}", self.url)
+    }
+}
+
+impl std::error::Error for RangeIgnoredError {}
+
 pub async fn download_file(
     url: &str,
     file_path: &PathBuf,
     headers: &Option<HashMap<String, String>>,
     resume: bool, // whether support resume download
     client: &reqwest::Client,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+) -> Result<(), DownloadError> {
     log::info!("Downloading file from {} to {:?}", url, file_path);
     let mut start = 0;
 
@@ -22,15 +98,6 @@ pub async fn download_file(
         if resume {
             let metadata = fs::metadata(&file_path).await?;
             start = metadata.len();
-
-            if start > metadata.len() {
-                log::error!(
-                    "Local file is larger than the remote file. Local: {}, Remote: {}",
-                    file_path.display(),
-                    url
-                );
-                return Err("File exists but is larger than the remote file".into());
-            }
         } else {
             // If the file exists and we're not resuming, delete it
             fs::remove_file(&file_path).await?;
@@ -49,39 +116,21 @@ pub async fn download_file(
     }
 
     log::debug!("file open");
-    // Create/append to the file
-    let mut file = if resume {
-        fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .append(true)
-            .open(file_path)
-            .await?
-    } else {
-        fs::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(file_path)
-            .await?
-    };
+    // Create or open the file, not in append mode so it can be truncated below
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(file_path)
+        .await?;
 
     log::debug!("file seek to start: {}", start);
     // Seek to the start position
     file.seek(SeekFrom::Start(start)).await?;
 
-    let mut request = client.get(url);
-    // Loop through the headers keys and values
-    // and add them to the request object.
-    if let Some(headers_map) = headers {
-        for (key, value) in headers_map {
-            request = request.header(
-                HeaderName::from_bytes(key.as_bytes())?,
-                HeaderValue::from_str(value)?,
-            );
-        }
-    }
+    let mut request = add_headers(client.get(url), headers)?;
     // Add the range header to the request (for resuming downloads)
-    if resume {
+    if resume && start > 0 {
         // 如果resume为true，才添加Range头部
         request = request.header(RANGE, format!("bytes={}-", start));
     }
@@ -90,31 +139,594 @@ pub async fn download_file(
     let response = request.send().await?;
     log::debug!("url {:?} Response: {:?}", url, response);
     if !response.status().is_success() {
-        // if response status is 416 Range Not Satisfiable, consider the file downloaded
+        // 416 Range Not Satisfiable: the local file is complete (or larger than the remote one)
         if response.status().as_u16() == 416 {
-            log::info!("File already downloaded");
-            return Ok(());
+            // Content-Range: bytes */<remote size>
+            let remote_size: Option<u64> = response
+                .headers()
+                .get(CONTENT_RANGE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.rsplit('/').next())
+                .and_then(|v| v.trim().parse().ok());
+            match remote_size {
+                Some(remote_size) if start > remote_size => {
+                    log::error!(
+                        "Local file is larger than the remote file. Local: {} ({} bytes), Remote: {} ({} bytes)",
+                        file_path.display(),
+                        start,
+                        url,
+                        remote_size
+                    );
+                    return Err("File exists but is larger than the remote file".into());
+                }
+                _ => {
+                    log::info!("File already downloaded");
+                    return Ok(());
+                }
+            }
         } else {
             log::error!(
                 "Error downloading file: {}. URL: {}",
                 response.status(),
                 url
             );
-            return Err(format!("{}. URL: {}", response.status(), url).into());
+            return Err(HttpStatusError {
+                status: response.status(),
+                url: url.to_string(),
+            }
+            .into());
         }
     }
 
+    // the server ignored the range header and sends the whole file, restart from 0
+    if start > 0 && response.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+        log::info!(
+            "Server does not support resuming, restart download: {}",
+            url
+        );
+        file.set_len(0).await?;
+        file.seek(SeekFrom::Start(0)).await?;
+        start = 0;
+    }
+
+    // total size for progress, 0 if unknown
+    let total_size = match response.content_length() {
+        Some(len) => start + len,
+        None => 0,
+    };
+    let task = current_task();
+
     let mut file = BufWriter::new(file);
     let mut stream = response.bytes_stream();
 
     while let Some(chunk) = stream.try_next().await? {
+        if let Some(task) = &task {
+            if task.is_cancelled() {
+                // keep the downloaded part, it can be resumed later
+                file.flush().await?;
+                log::info!("Download cancelled: {}", url);
+                return Err(format!("Task cancelled: {}", task.id).into());
+            }
+        }
+
         file.write_all(&chunk).await?;
         file.flush().await?;
 
         start += chunk.len() as u64;
+        if let Some(task) = &task {
+            task.report_progress(start, total_size, "bytes");
+        }
+    }
+
+    log::debug!("Downloaded file: {:?}", file_path);
+
+    Ok(())
+}
+
+fn add_headers(
+    mut request: reqwest::RequestBuilder,
+    headers: &Option<HashMap<String, String>>,
+) -> Result<reqwest::RequestBuilder, DownloadError> {
+    // Loop through the headers keys and values
+    // and add them to the request object.
+    if let Some(headers_map) = headers {
+        for (key, value) in headers_map {
+            request = request.header(
+                HeaderName::from_bytes(key.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+    }
+    Ok(request)
+}
+
+/// Download a file in parallel byte-range segments, written into place in the same file.
+/// Falls back to `download_file` (single stream, from scratch) if the server does not
+/// advertise `Accept-Ranges: bytes`, the size is unknown, or a range request gets the whole file.
+/// The file is removed if a segment fails, a partially filled file can't be resumed
+pub async fn download_file_in_segments(
+    url: &str,
+    file_path: &PathBuf,
+    headers: &Option<HashMap<String, String>>,
+    segments: u32,
+    client: &reqwest::Client,
+) -> Result<(), DownloadError> {
+    let total_size = match probe_range_support(url, headers, client).await {
+        Some(size) => size,
+        None => {
+            log::info!(
+                "Range requests not supported, download in one stream: {}",
+                url
+            );
+            return download_file(url, file_path, headers, false, client).await;
+        }
+    };
+    let ranges = segment_ranges(total_size, segments);
+    if ranges.len() == 1 {
+        return download_file(url, file_path, headers, false, client).await;
+    }
+
+    log::info!(
+        "Downloading file from {} to {:?} in {} segments, {} bytes",
+        url,
+        file_path,
+        ranges.len(),
+        total_size
+    );
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    // allocate the whole file, each segment writes into its own range
+    let file = fs::File::create(file_path).await?;
+    file.set_len(total_size).await?;
+    drop(file);
+
+    let downloaded = AtomicU64::new(0);
+    let futures = ranges.into_iter().map(|range| {
+        download_segment(
+            url,
+            file_path,
+            headers,
+            range,
+            total_size,
+            &downloaded,
+            client,
+        )
+    });
+
+    if let Err(e) = futures::future::try_join_all(futures).await {
+        let _ = fs::remove_file(file_path).await;
+        if e.downcast_ref::<RangeIgnoredError>().is_some() {
+            log::info!("Range request ignored, download in one stream: {}", url);
+            return download_file(url, file_path, headers, false, client).await;
+        }
+        return Err(e);
     }
 
     log::debug!("Downloaded file: {:?}", file_path);
 
     Ok(())
 }
+
+/// the inclusive byte ranges of at most `segments` parts, each of at least `MIN_SEGMENT_SIZE`
+fn segment_ranges(total_size: u64, segments: u32) -> Vec<(u64, u64)> {
+    let segments = (segments as u64).min(total_size / MIN_SEGMENT_SIZE).max(1);
+    let segment_size = total_size.div_ceil(segments);
+    (0..segments)
+        .map(|i| {
+            let start = i * segment_size;
+            (start, (start + segment_size).min(total_size) - 1)
+        })
+        .collect()
+}
+
+/// size of the remote file if it accepts byte-range requests
+async fn probe_range_support(
+    url: &str,
+    headers: &Option<HashMap<String, String>>,
+    client: &reqwest::Client,
+) -> Option<u64> {
+    let request = add_headers(client.head(url), headers).ok()?;
+    let response = match request.send().await {
+        Ok(r) => r,
+        Err(e) => {
+            log::warn!("Failed to request headers of {}: {}", url, e);
+            return None;
+        }
+    };
+    if !response.status().is_success() {
+        return None;
+    }
+    let accept_ranges = response
+        .headers()
+        .get(ACCEPT_RANGES)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    if !accept_ranges.eq_ignore_ascii_case("bytes") {
+        return None;
+    }
+    match response.content_length() {
+        Some(len) if len > 0 => Some(len),
+        _ => None,
+    }
+}
+
+/// download the bytes `start..=end` and write them at the same offset of the file
+async fn download_segment(
+    url: &str,
+    file_path: &PathBuf,
+    headers: &Option<HashMap<String, String>>,
+    (start, end): (u64, u64),
+    total_size: u64,
+    downloaded: &AtomicU64,
+    client: &reqwest::Client,
+) -> Result<(), DownloadError> {
+    let request = add_headers(client.get(url), headers)?;
+    let response = request
+        .header(RANGE, format!("bytes={}-{}", start, end))
+        .send()
+        .await?;
+    if response.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+        if !response.status().is_success() {
+            return Err(HttpStatusError {
+                status: response.status(),
+                url: url.to_string(),
+            }
+            .into());
+        }
+        return Err(RangeIgnoredError {
+            url: url.to_string(),
+        }
+        .into());
+    }
+
+    let mut file = fs::OpenOptions::new().write(true).open(file_path).await?;
+    file.seek(SeekFrom::Start(start)).await?;
+    let mut file = BufWriter::new(file);
+
+    let task = current_task();
+    let expected_len = end - start + 1;
+    let mut written: u64 = 0;
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.try_next().await? {
+        if let Some(task) = &task {
+            if task.is_cancelled() {
+                log::info!("Download cancelled: {}", url);
+                return Err(format!("Task cancelled: {}", task.id).into());
+            }
+        }
+        if written + chunk.len() as u64 > expected_len {
+            return Err(format!("Segment {}-{} is larger than requested", start, end).into());
+        }
+
+        file.write_all(&chunk).await?;
+        written += chunk.len() as u64;
+
+        let done = downloaded.fetch_add(chunk.len() as u64, Ordering::Relaxed) + chunk.len() as u64;
+        if let Some(task) = &task {
+            task.report_progress(done, total_size, "bytes");
+        }
+    }
+    file.flush().await?;
+
+    if written != expected_len {
+        return Err(format!(
+            "Segment {}-{} incomplete: {} of {} bytes",
+            start, end, written, expected_len
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// path of the temporary file while downloading: `<file_path>.part`
+pub fn get_part_file_path(file_path: &Path) -> PathBuf {
+    let mut part_path = file_path.as_os_str().to_owned();
+    part_path.push(".part");
+    PathBuf::from(part_path)
+}
+
+/// Download to `<file_path>.part`, retrying transient errors with backoff and falling back to
+/// the mirrors in order. The part file is verified (size, sha256) and then renamed to
+/// `file_path`, so `file_path` never holds a half-written file.
+pub async fn download_file_with_retry(
+    url: &str,
+    file_path: &Path,
+    options: &DownloadOptions,
+    client: &reqwest::Client,
+) -> Result<(), DownloadError> {
+    let part_path = get_part_file_path(file_path);
+
+    let mut urls: Vec<&str> = vec![url];
+    urls.extend(options.mirrors.iter().map(|m| m.as_str()));
+
+    let mut last_error: Option<DownloadError> = None;
+    for (url_index, url) in urls.iter().enumerate() {
+        // a new url starts from scratch, unless resuming a previous download is requested
+        let mut resume = options.resume && url_index == 0;
+
+        for attempt in 0..=options.max_retries {
+            if attempt > 0 {
+                let delay = RETRY_BASE_DELAY
+                    .saturating_mul(2u32.saturating_pow(attempt - 1))
+                    .min(RETRY_MAX_DELAY);
+                log::warn!(
+                    "Retry download in {:?} ({}/{}): {}",
+                    delay,
+                    attempt,
+                    options.max_retries,
+                    url
+                );
+                tokio::time::sleep(delay).await;
+            }
+
+            let result = if options.segments > 1 {
+                let segments = options.segments;
+                download_file_in_segments(url, &part_path, &options.headers, segments, client).await
+            } else {
+                download_file(url, &part_path, &options.headers, resume, client).await
+            };
+            match result {
+                Ok(_) => {
+                    last_error = None;
+                    break;
+                }
+                Err(e) => {
+                    if let Some(task) = current_task() {
+                        if task.is_cancelled() {
+                            return Err(e);
+                        }
+                    }
+                    log::warn!("Failed to download {}: {}", url, e);
+                    let retryable = is_retryable_error(&e);
+                    last_error = Some(e);
+                    if !retryable {
+                        break;
+                    }
+                    // keep what has been downloaded
+                    resume = true;
+                }
+            }
+        }
+
+        if last_error.is_none() {
+            match verify_downloaded_file(&part_path, options) {
+                Ok(_) => {
+                    fs::rename(&part_path, file_path).await?;
+                    log::info!("Downloaded file verified: {:?}", file_path);
+                    return Ok(());
+                }
+                Err(e) => {
+                    log::error!("Downloaded file from {} is invalid: {}", url, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        // the partial or invalid file is useless for the next url
+        if part_path.exists() {
+            let _ = fs::remove_file(&part_path).await;
+        }
+    }
+
+    Err(last_error.unwrap_or("Failed to download file".into()))
+}
+
+/// network errors, server errors (5xx) and throttling (408, 429) are worth a retry.
+/// local I/O errors like a denied permission or a full disk are not
+fn is_retryable_error(e: &DownloadError) -> bool {
+    if let Some(e) = e.downcast_ref::<HttpStatusError>() {
+        return e.status.is_server_error()
+            || e.status == reqwest::StatusCode::REQUEST_TIMEOUT
+            || e.status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+    }
+    if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+        return e.is_timeout() || e.is_connect() || e.is_request() || e.is_body();
+    }
+    match e.downcast_ref::<std::io::Error>() {
+        Some(e) => matches!(
+            e.kind(),
+            std::io::ErrorKind::ConnectionReset
+                | std::io::ErrorKind::ConnectionAborted
+                | std::io::ErrorKind::ConnectionRefused
+                | std::io::ErrorKind::NotConnected
+                | std::io::ErrorKind::BrokenPipe
+                | std::io::ErrorKind::TimedOut
+                | std::io::ErrorKind::Interrupted
+                | std::io::ErrorKind::UnexpectedEof
+        ),
+        None => false,
+    }
+}
+
+fn verify_downloaded_file(
+    file_path: &Path,
+    options: &DownloadOptions,
+) -> Result<(), DownloadError> {
+    if let Some(expected_size) = options.size {
+        let size = std::fs::metadata(file_path)?.len();
+        if size != expected_size {
+            return Err(format!(
+                "Size mismatch: expected {} bytes, got {} bytes",
+                expected_size, size
+            )
+            .into());
+        }
+    }
+
+    if let Some(expected_sha256) = &options.sha256 {
+        let digests = hash_file(file_path, &["sha256"]).map_err(|e| e.to_string())?;
+        let sha256 = digests.get("sha256").cloned().unwrap_or_default();
+        if !sha256.eq_ignore_ascii_case(expected_sha256.trim()) {
+            return Err(format!(
+                "Checksum mismatch: expected sha256 {}, got {}",
+                expected_sha256, sha256
+            )
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thelib::test_utils::TempDir;
+    use std::io::{Error as IoError, ErrorKind};
+
+    #[test]
+    fn test_is_retryable_error() {
+        let http_error = |status: reqwest::StatusCode| -> DownloadError {
+            Box::new(HttpStatusError {
+                status,
+                url: "https://example.com/a.zip".to_string(),
+            })
+        };
+        assert!(is_retryable_error(&http_error(
+            reqwest::StatusCode::SERVICE_UNAVAILABLE
+        )));
+        assert!(is_retryable_error(&http_error(
+            reqwest::StatusCode::TOO_MANY_REQUESTS
+        )));
+        assert!(!is_retryable_error(&http_error(
+            reqwest::StatusCode::NOT_FOUND
+        )));
+
+        let io_error = |kind: ErrorKind| -> DownloadError { Box::new(IoError::from(kind)) };
+        assert!(is_retryable_error(&io_error(ErrorKind::ConnectionReset)));
+        assert!(is_retryable_error(&io_error(ErrorKind::TimedOut)));
+        assert!(!is_retryable_error(&io_error(ErrorKind::PermissionDenied)));
+        assert!(!is_retryable_error(&io_error(ErrorKind::NotFound)));
+        assert!(!is_retryable_error(&io_error(ErrorKind::StorageFull)));
+
+        assert!(!is_retryable_error(&"Segment 0-9 incomplete".into()));
+    }
+
+    /// Serve `data` over http until the test ends, `honor_range`: answer ranges with 206,
+    /// otherwise with the whole file. Returns the url of the file
+    async fn serve(data: Vec<u8>, honor_range: bool) -> String {
+        use tokio::io::AsyncReadExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/file.bin", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let data = data.clone();
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0u8; 1024];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        match stream.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => request.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let request = String::from_utf8_lossy(&request).to_lowercase();
+                    let range = request
+                        .lines()
+                        .find_map(|l| l.strip_prefix("range: bytes="))
+                        .and_then(|r| r.trim().split_once('-'))
+                        .map(|(a, b)| (a.parse::<usize>().unwrap(), b.parse::<usize>().unwrap()));
+                    let (status, body, content_range) = match range {
+                        Some((start, end)) if honor_range => (
+                            "206 Partial Content",
+                            &data[start..=end],
+                            format!("content-range: bytes {}-{}/{}\r\n", start, end, data.len()),
+                        ),
+                        _ => ("200 OK", &data[..], String::new()),
+                    };
+                    let head = format!(
+                        "HTTP/1.1 {}\r\naccept-ranges: bytes\r\ncontent-length: {}\r\n{}connection: close\r\n\r\n",
+                        status,
+                        body.len(),
+                        content_range
+                    );
+                    let _ = stream.write_all(head.as_bytes()).await;
+                    if !request.starts_with("head") {
+                        let _ = stream.write_all(body).await;
+                    }
+                    let _ = stream.shutdown().await;
+                });
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn test_segment_ranges() {
+        let mb = MIN_SEGMENT_SIZE;
+        assert_eq!(segment_ranges(100, 4), vec![(0, 99)]);
+        assert_eq!(segment_ranges(2 * mb, 1), vec![(0, 2 * mb - 1)]);
+        // no part smaller than MIN_SEGMENT_SIZE
+        assert_eq!(segment_ranges(3 * mb, 8).len(), 3);
+
+        let total = 10 * mb + 7;
+        let ranges = segment_ranges(total, 4);
+        assert_eq!(ranges.len(), 4);
+        assert_eq!(ranges[0].0, 0);
+        assert_eq!(ranges[3].1, total - 1);
+        for pair in ranges.windows(2) {
+            assert_eq!(pair[0].1 + 1, pair[1].0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_download_file_in_segments() {
+        let tmp = TempDir::new("download-segments");
+        let data: Vec<u8> = (0..3 * MIN_SEGMENT_SIZE + 5)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let client = reqwest::Client::new();
+
+        for honor_range in [true, false] {
+            let url = serve(data.clone(), honor_range).await;
+            let file = tmp.path().join(format!("out-{}.bin", honor_range));
+            download_file_in_segments(&url, &file, &None, 3, &client)
+                .await
+                .unwrap();
+            assert!(std::fs::read(&file).unwrap() == data, "{}", honor_range);
+        }
+    }
+
+    #[test]
+    fn test_get_part_file_path() {
+        assert_eq!(
+            get_part_file_path(Path::new("/downloads/a.tar.gz")),
+            PathBuf::from("/downloads/a.tar.gz.part")
+        );
+        assert_eq!(get_part_file_path(Path::new("a")), PathBuf::from("a.part"));
+    }
+
+    #[test]
+    fn test_verify_downloaded_file() {
+        let tmp = TempDir::new("download");
+        let file = tmp.path().join("a.txt");
+        std::fs::write(&file, "abc").unwrap();
+        let sha256 = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+        let options = DownloadOptions {
+            size: Some(3),
+            sha256: Some(sha256.to_uppercase()),
+            ..Default::default()
+        };
+        assert!(verify_downloaded_file(&file, &options).is_ok());
+        assert!(verify_downloaded_file(&file, &DownloadOptions::default()).is_ok());
+
+        let options = DownloadOptions {
+            size: Some(4),
+            ..Default::default()
+        };
+        let err = verify_downloaded_file(&file, &options).unwrap_err();
+        assert!(err.to_string().starts_with("Size mismatch"));
+
+        let options = DownloadOptions {
+            sha256: Some("0".repeat(64)),
+            ..Default::default()
+        };
+        let err = verify_downloaded_file(&file, &options).unwrap_err();
+        assert!(err.to_string().starts_with("Checksum mismatch"));
+    }
+}
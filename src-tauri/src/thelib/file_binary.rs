@@ -1,5 +1,6 @@
 use crate::errors::BoxedError;
 use crate::thelib::file_path::{make_parent_dirs, split_file_path};
+use crate::thelib::task;
 use std::fs;
 use std::io::{BufReader, Read, Write};
 use std::path::Path;
@@ -23,12 +24,16 @@ pub fn split_in_bytes(
 
     // read file
     let file = fs::File::open(&file_path)?;
+    let total_size = file.metadata()?.len();
     let mut reader = BufReader::new(file);
 
     // split file
     let mut result = vec![];
     let mut index = 1;
+    let mut done_size: u64 = 0;
     loop {
+        task::check_cancelled()?;
+
         let mut buffer = vec![0; chunk_size as usize];
         let n = reader.read(&mut buffer)?;
         if n == 0 {
@@ -44,6 +49,9 @@ pub fn split_in_bytes(
 
         result.push(output_file_string);
         index += 1;
+
+        done_size += n as u64;
+        task::report_progress(done_size, total_size, "bytes");
     }
 
     Ok(result)
@@ -1,9 +1,11 @@
 use crate::errors::BoxedError;
 use crate::thelib::file_path::{get_relative_path_with_home_dir, split_file_path};
+use crate::thelib::task::report_progress;
+use crate::thelib::walk::{walk, WalkOptions};
 use serde_json::json;
 use serde_json::Value as JsonValue;
 use std::collections::HashMap;
-use std::fs;
+use std::path::Path;
 
 struct FilesCounter {
     pub dir_quantity: u64,
@@ -72,32 +74,44 @@ pub fn count_in_paths(paths: Vec<&str>) -> Result<JsonValue, BoxedError> {
     Ok(json!(result))
 }
 
-fn count_size_and_quantity(path: &str, numbers: &mut FilesCounter) -> Result<(), std::io::Error> {
-    // if path is a file,
-    let file_meta = std::fs::metadata(path)?;
-    if file_meta.is_file() {
-        numbers.file_quantity += 1;
-        numbers.file_size_sum += file_meta.len();
-        // get extension of the file by splitting the path by '.', if no '.' found, return empty string
-        let (_, _, file_ext) = split_file_path(path);
-
-        *numbers
-            .file_quantity_of_types
-            .entry(file_ext.to_string())
-            .or_insert(0) += 1;
-        *numbers
-            .file_size_of_types
-            .entry(file_ext.to_string())
-            .or_insert(0) += file_meta.len();
-    } else {
-        // else path is a folder,
-        numbers.dir_quantity += 1;
-
-        // walk the directory
-        for entry in fs::read_dir(path)? {
-            let entry = entry?;
-            let sub_path = entry.path().to_str().unwrap_or("").to_string();
-            count_size_and_quantity(sub_path.as_str(), numbers)?;
+/// Total size of the files in `path`, or of the file itself. Links are not followed
+pub fn path_size<P: AsRef<Path>>(path: P) -> Result<u64, BoxedError> {
+    let mut size = 0;
+    for entry in walk(path, &WalkOptions::default()) {
+        let entry = entry?;
+        if entry.is_file() {
+            size += entry.size;
+        }
+    }
+    Ok(size)
+}
+
+fn count_size_and_quantity(path: &str, numbers: &mut FilesCounter) -> Result<(), BoxedError> {
+    // the path must exist
+    std::fs::metadata(path)?;
+
+    // links are counted as neither files nor dirs, and not followed
+    for entry in walk(path, &WalkOptions::default()) {
+        let entry = entry?;
+        // total is unknown while walking
+        report_progress(numbers.file_quantity + numbers.dir_quantity, 0, "items");
+
+        if entry.is_file() {
+            numbers.file_quantity += 1;
+            numbers.file_size_sum += entry.size;
+            // get extension of the file by splitting the path by '.', if no '.' found, return empty string
+            let (_, _, file_ext) = split_file_path(&entry.path.to_string_lossy());
+
+            *numbers
+                .file_quantity_of_types
+                .entry(file_ext.to_string())
+                .or_insert(0) += 1;
+            *numbers
+                .file_size_of_types
+                .entry(file_ext.to_string())
+                .or_insert(0) += entry.size;
+        } else if entry.is_dir() {
+            numbers.dir_quantity += 1;
         }
     }
 
@@ -0,0 +1,370 @@
+use crate::errors::BoxedError;
+use crate::thelib::file_path::move_path;
+use crate::thelib::hash::{hash_file, hash_reader};
+use crate::thelib::journal::{Journal, JournalEntry, JournalOp};
+use crate::thelib::task::{check_cancelled, report_progress};
+use crate::thelib::walk::{walk, WalkOptions};
+use serde_json::{json, Value as JsonValue};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// bytes read from the head of a file for the partial hash
+const PARTIAL_HASH_SIZE: u64 = 16 * 1024;
+const PARTIAL_HASH_TYPE: &str = "xxh3";
+const FULL_HASH_TYPE: &str = "blake3";
+
+#[derive(Debug, Clone)]
+pub struct FileItem {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+#[derive(Debug, Clone)]
+pub struct DuplicateGroup {
+    pub size: u64,
+    pub hash: String,
+    pub files: Vec<FileItem>,
+}
+
+impl DuplicateGroup {
+    /// bytes that would be freed by keeping only one copy
+    pub fn wasted_bytes(&self) -> u64 {
+        self.size * (self.files.len() as u64 - 1)
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let paths: Vec<String> = self
+            .files
+            .iter()
+            .map(|f| f.path.to_string_lossy().to_string())
+            .collect();
+        json!({
+            "size": self.size,
+            "hash": self.hash,
+            "paths": paths,
+            "wasted_bytes": self.wasted_bytes(),
+        })
+    }
+}
+
+/// Find duplicate files in paths: group by size, then by partial hash, then by full hash.
+/// The paths are walked by `options` (only files are compared), files smaller than
+/// `options.min_size` are ignored. Groups are sorted by wasted bytes (desc)
+pub fn find_duplicates(
+    paths: Vec<&str>,
+    options: &WalkOptions,
+) -> Result<Vec<DuplicateGroup>, BoxedError> {
+    let options = WalkOptions {
+        files: true,
+        dirs: false,
+        ..options.clone()
+    };
+    let mut files: Vec<FileItem> = Vec::new();
+    let mut seen: HashSet<String> = HashSet::new();
+    for path in paths {
+        collect_files(Path::new(path), &options, &mut files, &mut seen)?;
+    }
+
+    // 1. group by size
+    let mut size_groups: HashMap<u64, Vec<FileItem>> = HashMap::new();
+    for file in files {
+        size_groups.entry(file.size).or_default().push(file);
+    }
+
+    // hashes done, reported as the progress of the current task
+    let mut hashed: u64 = 0;
+    let mut groups: Vec<DuplicateGroup> = Vec::new();
+    for (size, same_size_files) in size_groups {
+        if same_size_files.len() < 2 {
+            continue;
+        }
+
+        // 2. group by partial hash (only needed when files are larger than the partial size)
+        let partial_groups = if size > PARTIAL_HASH_SIZE {
+            group_by_hash(same_size_files, partial_hash, &mut hashed)?
+                .into_values()
+                .collect()
+        } else {
+            vec![same_size_files]
+        };
+
+        // 3. group by full hash
+        for candidates in partial_groups {
+            if candidates.len() < 2 {
+                continue;
+            }
+            for (hash, same_hash_files) in group_by_hash(candidates, full_hash, &mut hashed)? {
+                if same_hash_files.len() < 2 {
+                    continue;
+                }
+                groups.push(DuplicateGroup {
+                    size,
+                    hash,
+                    files: same_hash_files,
+                });
+            }
+        }
+    }
+
+    for group in groups.iter_mut() {
+        group.files.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+    groups.sort_by(|a, b| {
+        b.wasted_bytes()
+            .cmp(&a.wasted_bytes())
+            .then(a.files[0].path.cmp(&b.files[0].path))
+    });
+
+    Ok(groups)
+}
+
+/// Split a duplicate group into the file to keep and the copies, by modification time.
+/// `keep`: "oldest" or "newest"
+pub fn split_keep_and_copies(
+    group: &DuplicateGroup,
+    keep: &str,
+) -> Result<(FileItem, Vec<FileItem>), BoxedError> {
+    let mut files = group.files.clone();
+    match keep {
+        "oldest" => files.sort_by_key(|f| f.modified),
+        "newest" => files.sort_by_key(|f| std::cmp::Reverse(f.modified)),
+        _ => {
+            return Err(format!(
+                "Invalid keep option: '{}'. Must be 'oldest' or 'newest'",
+                keep
+            )
+            .into())
+        }
+    }
+    let kept = files.remove(0);
+    Ok((kept, files))
+}
+
+/// Replace `copy` with a hardlink to `original`. The link is created next to the copy first,
+/// then renamed over it, so the copy is never lost if linking fails.
+/// With a journal entry the copy is moved into its backups first, so it can be undone
+pub fn replace_with_hardlink(
+    original: &Path,
+    copy: &Path,
+    undo: Option<(&Journal, &mut JournalEntry)>,
+) -> Result<(), BoxedError> {
+    let file_name = copy
+        .file_name()
+        .ok_or("Invalid file name")?
+        .to_string_lossy()
+        .to_string();
+    let tmp_path = copy.with_file_name(format!(".{}.{}.tmp", file_name, uuid::Uuid::new_v4()));
+
+    if let Err(e) = fs::hard_link(original, &tmp_path) {
+        return Err(format!(
+            "Failed to create hardlink from {:?} to {:?}: {}",
+            original, copy, e
+        )
+        .into());
+    }
+    let backup = match undo {
+        Some((journal, entry)) => match journal.backup(entry, copy, true) {
+            Ok(b) => Some((b, entry)),
+            Err(e) => {
+                let _ = fs::remove_file(&tmp_path);
+                return Err(format!("Failed to back up {:?}: {}", copy, e).into());
+            }
+        },
+        None => None,
+    };
+    if let Err(e) = fs::rename(&tmp_path, copy) {
+        let _ = fs::remove_file(&tmp_path);
+        if let Some((b, _)) = &backup {
+            move_path(b, copy)?;
+        }
+        return Err(format!("Failed to replace {:?} with hardlink: {}", copy, e).into());
+    }
+    if let Some((backup, entry)) = backup {
+        entry.add(JournalOp::Replace {
+            path: copy.to_path_buf(),
+            backup,
+        });
+    }
+
+    Ok(())
+}
+
+fn collect_files(
+    path: &Path,
+    options: &WalkOptions,
+    files: &mut Vec<FileItem>,
+    seen: &mut HashSet<String>,
+) -> Result<(), BoxedError> {
+    // the path must exist
+    fs::symlink_metadata(path)?;
+
+    for entry in walk(path, options) {
+        let entry = entry?;
+        check_cancelled()?;
+        // a listed link is not a copy, a followed one is its target file
+        if !entry.is_file() || options.min_size.is_some_and(|s| entry.size < s) {
+            continue;
+        }
+        let meta = match fs::metadata(&entry.path) {
+            Ok(m) => m,
+            Err(e) => {
+                log::warn!("Failed to read {:?}: {}", entry.path, e);
+                continue;
+            }
+        };
+        // skip the same path given twice, or the same file already hardlinked
+        if !seen.insert(file_identity(&entry.path, &meta)) {
+            continue;
+        }
+        files.push(FileItem {
+            path: entry.path,
+            size: entry.size,
+            modified: entry.modified.unwrap_or(SystemTime::UNIX_EPOCH),
+        });
+        // total is unknown while walking
+        report_progress(files.len() as u64, 0, "items");
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn file_identity(_path: &Path, meta: &fs::Metadata) -> String {
+    use std::os::unix::fs::MetadataExt;
+    format!("{}:{}", meta.dev(), meta.ino())
+}
+
+#[cfg(not(unix))]
+fn file_identity(path: &Path, _meta: &fs::Metadata) -> String {
+    fs::canonicalize(path)
+        .unwrap_or(path.to_path_buf())
+        .to_string_lossy()
+        .to_string()
+}
+
+fn partial_hash(path: &Path) -> Result<String, BoxedError> {
+    let file = fs::File::open(path)?;
+    let mut digests = hash_reader(file.take(PARTIAL_HASH_SIZE), &[PARTIAL_HASH_TYPE])?;
+    Ok(digests.remove(PARTIAL_HASH_TYPE).unwrap_or_default())
+}
+
+fn full_hash(path: &Path) -> Result<String, BoxedError> {
+    let mut digests = hash_file(path, &[FULL_HASH_TYPE])?;
+    Ok(digests.remove(FULL_HASH_TYPE).unwrap_or_default())
+}
+
+/// `hashed` counts the hashes done of all groups
+fn group_by_hash<F>(
+    files: Vec<FileItem>,
+    hasher: F,
+    hashed: &mut u64,
+) -> Result<HashMap<String, Vec<FileItem>>, BoxedError>
+where
+    F: Fn(&Path) -> Result<String, BoxedError>,
+{
+    let mut groups: HashMap<String, Vec<FileItem>> = HashMap::new();
+    for file in files {
+        check_cancelled()?;
+        let hash = hasher(&file.path);
+        *hashed += 1;
+        // the files of later groups are not known yet
+        report_progress(*hashed, 0, "items");
+        let hash = match hash {
+            Ok(h) => h,
+            Err(e) => {
+                // cancelled while hashing, or an unreadable file which can't be compared
+                check_cancelled()?;
+                log::warn!("Failed to hash file {:?}: {}", file.path, e);
+                continue;
+            }
+        };
+        groups.entry(hash).or_default().push(file);
+    }
+    Ok(groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thelib::test_utils::TempDir;
+
+    #[test]
+    fn test_find_duplicates() {
+        let tmp = TempDir::new("dup");
+        let dir = tmp.path().to_path_buf();
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        let big = vec![1u8; PARTIAL_HASH_SIZE as usize + 10];
+        let mut big_other = big.clone();
+        *big_other.last_mut().unwrap() = 2;
+
+        fs::write(dir.join("a.txt"), b"hello").unwrap();
+        fs::write(dir.join("sub").join("b.txt"), b"hello").unwrap();
+        fs::write(dir.join("c.txt"), b"world").unwrap();
+        fs::write(dir.join("big1.bin"), &big).unwrap();
+        fs::write(dir.join("sub").join("big2.bin"), &big).unwrap();
+        fs::write(dir.join("big3.bin"), &big_other).unwrap();
+        fs::write(dir.join("empty1"), b"").unwrap();
+        fs::write(dir.join("empty2"), b"").unwrap();
+
+        let dir_str = dir.to_string_lossy().to_string();
+        let options = WalkOptions {
+            min_size: Some(1),
+            ..Default::default()
+        };
+        let groups = find_duplicates(vec![&dir_str, &dir_str], &options).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].files.len(), 2);
+        assert_eq!(groups[0].wasted_bytes(), big.len() as u64);
+        assert_eq!(groups[1].wasted_bytes(), 5);
+
+        let (kept, copies) = split_keep_and_copies(&groups[1], "oldest").unwrap();
+        replace_with_hardlink(&kept.path, &copies[0].path, None).unwrap();
+        assert_eq!(fs::read(&copies[0].path).unwrap(), b"hello");
+        #[cfg(unix)]
+        {
+            // hardlinked copies are the same file now, no longer duplicates
+            let groups = find_duplicates(vec![&dir_str], &options).unwrap();
+            assert_eq!(groups.len(), 1);
+        }
+
+        // hidden copies are left out by the walk options
+        fs::write(dir.join(".c.txt"), b"world").unwrap();
+        assert_eq!(find_duplicates(vec![&dir_str], &options).unwrap().len(), 2);
+        let visible = WalkOptions {
+            hidden: false,
+            ..options.clone()
+        };
+        assert_eq!(find_duplicates(vec![&dir_str], &visible).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_hardlink_undo() {
+        let tmp = TempDir::new("dup-undo");
+        let dir = tmp.path().to_path_buf();
+        let journal = Journal::new(dir.join("journal"));
+        let kept = dir.join("a.txt");
+        let copy = dir.join("b.txt");
+        fs::write(&kept, b"hello").unwrap();
+        fs::write(&copy, b"hello").unwrap();
+
+        let mut entry = journal.new_entry("file.find_duplicates");
+        replace_with_hardlink(&kept, &copy, Some((&journal, &mut entry))).unwrap();
+        journal.save(&entry);
+        assert_eq!(entry.ops.len(), 1);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(fs::metadata(&copy).unwrap().nlink(), 2);
+        }
+
+        journal.undo(&entry.id).unwrap();
+        assert_eq!(fs::read(&copy).unwrap(), b"hello");
+        // a file of its own again, a write does not reach the kept one
+        fs::write(&copy, b"world").unwrap();
+        assert_eq!(fs::read(&kept).unwrap(), b"hello");
+    }
+}
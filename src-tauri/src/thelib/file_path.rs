@@ -1,5 +1,7 @@
 use crate::errors::BoxedError;
+use crate::thelib::file_type::FileKind;
 use crate::thelib::sys::get_home_dir;
+use crate::thelib::walk::{walk, WalkOptions};
 use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use uuid;
@@ -27,6 +29,36 @@ pub fn make_parent_dirs<P: AsRef<Path>>(file_path: P) -> Result<(), BoxedError>
     Ok(())
 }
 
+pub fn copy_dir_all(from_dir: &Path, to_dir: &Path) -> Result<(), BoxedError> {
+    std::fs::create_dir_all(to_dir)?;
+    for entry in std::fs::read_dir(from_dir)? {
+        let entry = entry?;
+        let to_path = to_dir.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_all(&entry.path(), &to_path)?;
+        } else {
+            std::fs::copy(entry.path(), &to_path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Move a file or dir, falling back to copy and remove if rename fails,
+/// e.g. across volumes
+pub fn move_path(from: &Path, to: &Path) -> Result<(), BoxedError> {
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    if from.is_dir() {
+        copy_dir_all(from, to)?;
+        std::fs::remove_dir_all(from)?;
+    } else {
+        std::fs::copy(from, to)?;
+        std::fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
 pub fn get_relative_path_with_home_dir(path: &str) -> String {
     let home_dir: String = match get_home_dir() {
         Ok(h) => h.to_string_lossy().to_string(),
@@ -81,6 +113,9 @@ pub fn split_file_path(file_path: &str) -> (String, String, String) {
     (file_dir, file_stem, file_ext)
 }
 
+/// max number suffix tried when looking for an unused path
+pub const MAX_UNUSED_PATH_SUFFIX: u32 = 10_000;
+
 /// Generate a unique file path by adding suffix to the file name
 pub fn get_unique_filepath(
     file_path: &str,
@@ -100,8 +135,7 @@ pub fn get_unique_filepath(
     }
 
     // auto generate a new file name
-    let mut suffix = 1;
-    loop {
+    for suffix in 1..=MAX_UNUSED_PATH_SUFFIX {
         new_path = Path::new(&file_dir).join(format!("{}-{}.{}", stem, suffix, ext));
         if !new_path.exists() {
             return Ok(new_path
@@ -109,21 +143,36 @@ pub fn get_unique_filepath(
                 .ok_or("Invalid new file path")?
                 .to_string());
         }
-        suffix += 1;
     }
+    Err(format!("No unused file path for {}", file_path).into())
 }
 
-pub fn get_all_extensions(paths: &HashSet<String>, limit_walk_file: u32) -> HashSet<String> {
+/// What is selected for a task, to match the file functions
+#[derive(Debug, Clone, Default)]
+pub struct FileSelection {
+    /// kinds of the files walked, with the special kinds `/file`, `/dirs` etc.
+    pub kinds: HashSet<FileKind>,
+    /// number of selected paths
+    pub count: usize,
+    /// size range of the files walked, none if no file
+    pub min_file_size: Option<u64>,
+    pub max_file_size: Option<u64>,
+}
+
+pub fn get_file_selection(paths: &HashSet<String>, limit_walk_file: u32) -> FileSelection {
     log::debug!(
-        "get_all_extensions for paths: {:?}. limit_walk_file: {}",
+        "get_file_selection for paths: {:?}. limit_walk_file: {}",
         paths,
         limit_walk_file
     );
 
-    let mut exts: HashSet<String> = HashSet::new();
+    let mut selection = FileSelection {
+        count: paths.len(),
+        ..Default::default()
+    };
     let mut walk_file_count = 0;
     for path in paths {
-        get_all_ext_handle_a_path(path, &mut walk_file_count, &mut exts).ok();
+        get_all_ext_handle_a_path(path, &mut walk_file_count, limit_walk_file, &mut selection).ok();
 
         if walk_file_count >= limit_walk_file {
             break;
@@ -133,58 +182,60 @@ pub fn get_all_extensions(paths: &HashSet<String>, limit_walk_file: u32) -> Hash
     // add special file type tags for internal use
     // - /paths
     if paths.len() > 1 {
-        exts.insert("/paths".to_string());
+        selection.kinds.insert(FileKind::special("/paths"));
     }
     // - /file, /files, /dir, /dirs
     let to_check_paths: Vec<&Path> = paths.iter().map(|p| Path::new(p)).collect();
     let (file_num, dir_num) = count_files_and_dirs_to_check_bulk(to_check_paths);
     if file_num == 1 {
-        exts.insert("/file".to_string());
+        selection.kinds.insert(FileKind::special("/file"));
     } else if file_num > 1 {
-        exts.insert("/files".to_string());
+        selection.kinds.insert(FileKind::special("/files"));
     };
     if dir_num == 1 {
-        exts.insert("/dir".to_string());
+        selection.kinds.insert(FileKind::special("/dir"));
     } else if dir_num > 1 {
-        exts.insert("/dirs".to_string());
+        selection.kinds.insert(FileKind::special("/dirs"));
     };
 
-    exts
+    selection
 }
 
 fn get_all_ext_handle_a_path(
     path: &str,
     walk_file_count: &mut u32,
-    exts: &mut HashSet<String>,
+    limit_walk_file: u32,
+    selection: &mut FileSelection,
 ) -> Result<(), BoxedError> {
-    let the_path = Path::new(path);
-    if !the_path.exists() {
-        return Ok(());
-    }
-
-    // if exts length is greater than 100, return directly without continuing
-    if exts.len() >= 100 {
-        return Ok(());
-    }
-
-    if the_path.is_file() {
-        *walk_file_count += 1;
-        let (_, _, file_ext) = split_file_path(&path);
-
-        if !file_ext.is_empty() {
-            exts.insert(file_ext.to_lowercase());
+    let options = WalkOptions {
+        dirs: false,
+        ..Default::default()
+    };
+    for entry in walk(path, &options) {
+        // if exts length is greater than 100, return directly without continuing
+        if selection.kinds.len() >= 100 || *walk_file_count >= limit_walk_file {
+            break;
         }
-    } else if the_path.is_dir() {
-        for entry in std::fs::read_dir(&path)? {
-            let entry = entry?;
-            let sub_path = match entry.path().to_str() {
-                Some(sub_path) => sub_path.to_string(),
-                None => continue,
-            };
-            get_all_ext_handle_a_path(&sub_path, walk_file_count, exts)?;
+        let entry = entry?;
+        if !entry.is_file() {
+            continue;
+        }
+        *walk_file_count += 1;
+        selection.min_file_size = Some(
+            selection
+                .min_file_size
+                .map_or(entry.size, |s| s.min(entry.size)),
+        );
+        selection.max_file_size = Some(
+            selection
+                .max_file_size
+                .map_or(entry.size, |s| s.max(entry.size)),
+        );
+        let kind = FileKind::of_file(&entry.path);
+        if !kind.ext.is_empty() || !kind.mime.is_empty() {
+            selection.kinds.insert(kind);
         }
     }
-    // else others: ignored
     Ok(())
 }
 
@@ -193,41 +244,23 @@ pub fn count_files_and_dirs_to_check_bulk(paths: Vec<&Path>) -> (usize, usize) {
     let mut file_count = 0;
     let mut dir_count = 0;
 
-    fn count_files_and_dirs(paths: Vec<&Path>, file_count: &mut usize, dir_count: &mut usize) {
-        for path in paths {
-            if *file_count > 1 && *dir_count > 1 {
-                break;
+    for path in paths {
+        for entry in walk(path, &WalkOptions::default()) {
+            if file_count > 1 && dir_count > 1 {
+                return (file_count, dir_count);
             }
-            if path.is_dir() {
-                *dir_count += 1;
-                let entry_iter = match std::fs::read_dir(path) {
-                    Ok(e) => e,
-                    Err(_) => continue,
-                };
-                for entry in entry_iter {
-                    if *file_count > 1 && *dir_count > 1 {
-                        break;
-                    }
-                    let entry = match entry {
-                        Ok(e) => e,
-                        Err(_) => continue,
-                    };
-                    let path = entry.path();
-                    if path.is_file() {
-                        *file_count += 1;
-                    } else if path.is_dir() {
-                        *dir_count += 1;
-                        count_files_and_dirs(vec![path.as_path()], file_count, dir_count);
-                    }
-                }
-            } else if path.is_file() {
-                *file_count += 1;
+            let entry = match entry {
+                Ok(e) => e,
+                Err(_) => break,
+            };
+            if entry.is_file() {
+                file_count += 1;
+            } else if entry.is_dir() {
+                dir_count += 1;
             }
         }
     }
 
-    count_files_and_dirs(paths, &mut file_count, &mut dir_count);
-
     (file_count, dir_count)
 }
 
@@ -0,0 +1,594 @@
+use crate::errors::BoxedError;
+use crate::thelib::hash::hash_file;
+use chrono::{DateTime, Local, NaiveDateTime};
+use regex::Regex;
+use serde_json::{json, Value as JsonValue};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_MTIME_FORMAT: &str = "%Y-%m-%d";
+const DEFAULT_HASH_LENGTH: usize = 8;
+const HASH_TYPE: &str = "sha256";
+const EXIF_DATETIME_FORMAT: &str = "%Y:%m:%d %H:%M:%S";
+const BUILTIN_TOKENS: [&str; 7] = ["stem", "ext", "name", "parent", "n", "mtime", "hash"];
+
+pub const STATUS_OK: &str = "ok";
+pub const STATUS_UNCHANGED: &str = "unchanged";
+pub const STATUS_SKIPPED: &str = "skipped";
+pub const STATUS_CONFLICT: &str = "conflict";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(String),
+    Field { name: String, spec: Option<String> },
+}
+
+#[derive(Debug, Clone)]
+pub struct RenameOptions {
+    /// first value of `{n}`
+    pub start: u64,
+    /// matched against the file name, its capture groups can be used in the template
+    pub pattern: Option<Regex>,
+}
+
+impl Default for RenameOptions {
+    fn default() -> Self {
+        RenameOptions {
+            start: 1,
+            pattern: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RenameItem {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub status: String,
+    pub message: String,
+    /// part of a chain of renames that ends where it started, e.g. a <-> b
+    pub cycle: bool,
+}
+
+impl RenameItem {
+    pub fn new(from: &Path, to: &Path, status: &str, message: &str) -> Self {
+        RenameItem {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+            status: status.to_string(),
+            message: message.to_string(),
+            cycle: false,
+        }
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        json!({
+            "from": self.from.to_string_lossy(),
+            "to": self.to.to_string_lossy(),
+            "status": self.status,
+            "message": self.message,
+            "cycle": self.cycle,
+        })
+    }
+}
+
+/// Parse a template like `{stem}_{n:03}.{ext}`. `{{` and `}}` are literal braces.
+/// A field is `{name}` or `{name:spec}`
+fn parse_template(template: &str) -> Result<Vec<Token>, BoxedError> {
+    let chars: Vec<char> = template.chars().collect();
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut literal = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if (c == '{' || c == '}') && i + 1 < chars.len() && chars[i + 1] == c {
+            literal.push(c);
+            i += 2;
+            continue;
+        }
+        if c == '}' {
+            return Err(format!("Invalid template, unmatched '}}': '{}'", template).into());
+        }
+        if c != '{' {
+            literal.push(c);
+            i += 1;
+            continue;
+        }
+
+        let close = match chars[i + 1..].iter().position(|&c| c == '}') {
+            Some(p) => i + 1 + p,
+            None => return Err(format!("Invalid template, unmatched '{{': '{}'", template).into()),
+        };
+        let field: String = chars[i + 1..close].iter().collect();
+        let (name, spec) = match field.split_once(':') {
+            Some((n, s)) => (n.trim().to_string(), Some(s.to_string())),
+            None => (field.trim().to_string(), None),
+        };
+        if name.is_empty() {
+            return Err(format!("Invalid template, empty field: '{}'", template).into());
+        }
+        if !literal.is_empty() {
+            tokens.push(Token::Literal(std::mem::take(&mut literal)));
+        }
+        tokens.push(Token::Field { name, spec });
+        i = close + 1;
+    }
+    if !literal.is_empty() {
+        tokens.push(Token::Literal(literal));
+    }
+
+    Ok(tokens)
+}
+
+/// Check that every field of the template can be resolved
+fn validate_tokens(tokens: &[Token], pattern: Option<&Regex>) -> Result<(), BoxedError> {
+    for token in tokens {
+        match token {
+            Token::Literal(s) => {
+                if s.contains('/') || s.contains('\\') {
+                    return Err(
+                        format!("Template must not contain path separators: '{}'", s).into(),
+                    );
+                }
+            }
+            Token::Field { name, spec } => {
+                if BUILTIN_TOKENS.contains(&name.as_str()) || name.starts_with("exif.") {
+                    if (name == "n" || name == "hash") && spec.is_some() {
+                        let spec = spec.as_deref().unwrap_or("");
+                        if spec.parse::<usize>().is_err() {
+                            return Err(
+                                format!("Invalid width for `{{{}}}`: '{}'", name, spec).into()
+                            );
+                        }
+                    }
+                    continue;
+                }
+                let pattern = match pattern {
+                    Some(p) => p,
+                    None => return Err(format!("Unknown token `{{{}}}`", name).into()),
+                };
+                let is_group = match name.parse::<usize>() {
+                    Ok(i) => i < pattern.captures_len(),
+                    Err(_) => pattern.capture_names().any(|n| n == Some(name.as_str())),
+                };
+                if !is_group {
+                    return Err(format!("Unknown token or capture group `{{{}}}`", name).into());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replace chars not allowed in a file name, e.g. `:` in exif dates
+fn sanitize_value(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | '<' | '>' | ':' | '"' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// render a chrono `format()` result, which fails on an invalid format instead of panicking
+fn format_to_string(formatted: impl std::fmt::Display, fmt: &str) -> Result<String, BoxedError> {
+    let mut s = String::new();
+    if write!(s, "{}", formatted).is_err() {
+        return Err(format!("Invalid date format: '{}'", fmt).into());
+    }
+    Ok(s)
+}
+
+/// find a tag in exiftool output, which is grouped (`-g`) like `{"EXIF": {"DateTimeOriginal": ..}}`
+fn find_exif_tag(meta: &JsonValue, tag: &str) -> Option<JsonValue> {
+    let obj = meta.as_object()?;
+    if let Some(v) = obj.get(tag) {
+        return Some(v.clone());
+    }
+    obj.values()
+        .filter_map(|g| g.as_object())
+        .find_map(|g| g.get(tag).cloned())
+}
+
+struct RenderContext<'a> {
+    path: &'a Path,
+    n: u64,
+    captures: Option<regex::Captures<'a>>,
+    exif: &'a mut dyn FnMut(&Path) -> Result<JsonValue, BoxedError>,
+    exif_cache: Option<JsonValue>,
+}
+
+fn render_field(
+    name: &str,
+    spec: Option<&str>,
+    ctx: &mut RenderContext,
+) -> Result<String, BoxedError> {
+    let path = ctx.path;
+    let value = match name {
+        "stem" => path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        "ext" => path
+            .extension()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        "name" => path
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        "parent" => path
+            .parent()
+            .and_then(|p| p.file_name())
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        "n" => {
+            let width: usize = spec.and_then(|s| s.parse().ok()).unwrap_or(0);
+            format!("{:0width$}", ctx.n, width = width)
+        }
+        "mtime" => {
+            let modified = fs::metadata(path)?.modified()?;
+            let dt: DateTime<Local> = modified.into();
+            let fmt = spec.unwrap_or(DEFAULT_MTIME_FORMAT);
+            format_to_string(dt.format(fmt), fmt)?
+        }
+        "hash" => {
+            let len: usize = spec
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(DEFAULT_HASH_LENGTH);
+            let hashes = hash_file(path, &[HASH_TYPE])?;
+            let digest = hashes.get(HASH_TYPE).cloned().unwrap_or_default();
+            digest.chars().take(len).collect()
+        }
+        _ if name.starts_with("exif.") => {
+            let tag = &name["exif.".len()..];
+            if ctx.exif_cache.is_none() {
+                ctx.exif_cache = Some((ctx.exif)(path)?);
+            }
+            let meta = ctx.exif_cache.as_ref().unwrap();
+            let value = match find_exif_tag(meta, tag) {
+                Some(JsonValue::String(s)) => s,
+                Some(JsonValue::Null) | None => {
+                    return Err(format!("No {} found in the file", tag).into())
+                }
+                Some(v) => v.to_string(),
+            };
+            match spec {
+                Some(fmt) => {
+                    // exif dates look like `2024:05:01 12:30:00`, maybe with a zone suffix
+                    let head: String = value.chars().take(19).collect();
+                    let dt = NaiveDateTime::parse_from_str(&head, EXIF_DATETIME_FORMAT)
+                        .map_err(|_| format!("{} is not a date: '{}'", tag, value))?;
+                    format_to_string(dt.format(fmt), fmt)?
+                }
+                None => value,
+            }
+        }
+        _ => {
+            let caps = ctx.captures.as_ref().ok_or("No regex pattern")?;
+            let m = match name.parse::<usize>() {
+                Ok(i) => caps.get(i),
+                Err(_) => caps.name(name),
+            };
+            m.map(|m| m.as_str().to_string()).unwrap_or_default()
+        }
+    };
+
+    Ok(sanitize_value(&value))
+}
+
+/// path key used to detect collisions, case-insensitive where the file system usually is
+fn path_key(path: &Path) -> String {
+    let s = path.to_string_lossy().to_string();
+    if cfg!(any(target_os = "windows", target_os = "macos")) {
+        s.to_lowercase()
+    } else {
+        s
+    }
+}
+
+/// Build the rename plan of `paths` with the template, without touching the disk.
+/// Items are returned in input order; `exif` loads the metadata of a file and is only
+/// called when the template uses `{exif.*}` tokens
+pub fn plan_renames(
+    paths: &[PathBuf],
+    template: &str,
+    options: &RenameOptions,
+    exif: &mut dyn FnMut(&Path) -> Result<JsonValue, BoxedError>,
+) -> Result<Vec<RenameItem>, BoxedError> {
+    let tokens = parse_template(template)?;
+    validate_tokens(&tokens, options.pattern.as_ref())?;
+
+    let mut items: Vec<RenameItem> = Vec::new();
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut n = options.start;
+
+    for path in paths {
+        if !seen.insert(path_key(path)) {
+            items.push(RenameItem::new(
+                path,
+                path,
+                STATUS_SKIPPED,
+                "Duplicate input",
+            ));
+            continue;
+        }
+        if fs::symlink_metadata(path).is_err() {
+            items.push(RenameItem::new(
+                path,
+                path,
+                STATUS_SKIPPED,
+                "Path not found",
+            ));
+            continue;
+        }
+        let file_name = path
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let captures = match &options.pattern {
+            Some(re) => match re.captures(&file_name) {
+                Some(c) => Some(c),
+                None => {
+                    items.push(RenameItem::new(path, path, STATUS_SKIPPED, "Not matched"));
+                    continue;
+                }
+            },
+            None => None,
+        };
+
+        let mut ctx = RenderContext {
+            path,
+            n,
+            captures,
+            exif: &mut *exif,
+            exif_cache: None,
+        };
+        let mut new_name = String::new();
+        let mut error: Option<String> = None;
+        for token in &tokens {
+            match token {
+                Token::Literal(s) => new_name.push_str(s),
+                Token::Field { name, spec } => {
+                    match render_field(name, spec.as_deref(), &mut ctx) {
+                        Ok(v) => new_name.push_str(&v),
+                        Err(e) => {
+                            error = Some(e.to_string());
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(e) = error {
+            items.push(RenameItem::new(path, path, STATUS_SKIPPED, &e));
+            continue;
+        }
+        n += 1;
+
+        let new_name = new_name.trim().to_string();
+        if new_name.is_empty() || new_name == "." || new_name == ".." {
+            items.push(RenameItem::new(
+                path,
+                path,
+                STATUS_SKIPPED,
+                "Empty new name",
+            ));
+            continue;
+        }
+        let to = match path.parent() {
+            Some(p) => p.join(&new_name),
+            None => PathBuf::from(&new_name),
+        };
+        if to == *path {
+            items.push(RenameItem::new(path, &to, STATUS_UNCHANGED, ""));
+        } else {
+            items.push(RenameItem::new(path, &to, STATUS_OK, ""));
+        }
+    }
+
+    resolve_conflicts(&mut items);
+    Ok(items)
+}
+
+/// Mark items whose target is taken as conflicts, and items in cycles.
+/// A target is taken if another item renames to it, or a path exists there that is not
+/// renamed away. Repeats until stable, since a conflicting item keeps its source path
+pub fn resolve_conflicts(items: &mut [RenameItem]) {
+    loop {
+        let moving: HashSet<String> = items
+            .iter()
+            .filter(|i| i.status == STATUS_OK)
+            .map(|i| path_key(&i.from))
+            .collect();
+        let mut target_count: HashMap<String, usize> = HashMap::new();
+        for item in items.iter() {
+            if item.status == STATUS_OK || item.status == STATUS_UNCHANGED {
+                *target_count.entry(path_key(&item.to)).or_insert(0) += 1;
+            }
+        }
+        // sources that stay in place also occupy their path
+        for item in items.iter() {
+            if item.status != STATUS_OK && item.status != STATUS_UNCHANGED {
+                *target_count.entry(path_key(&item.from)).or_insert(0) += 1;
+            }
+        }
+
+        let mut changed = false;
+        for item in items.iter_mut() {
+            if item.status != STATUS_OK {
+                continue;
+            }
+            let to_key = path_key(&item.to);
+            let message = if target_count.get(&to_key).copied().unwrap_or(0) > 1 {
+                "Several paths are renamed to the same name"
+            } else if to_key == path_key(&item.from) {
+                // case-only change on a case-insensitive file system
+                continue;
+            } else if fs::symlink_metadata(&item.to).is_ok() && !moving.contains(&to_key) {
+                "Target path exists"
+            } else {
+                continue;
+            };
+            item.status = STATUS_CONFLICT.to_string();
+            item.message = message.to_string();
+            changed = true;
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    // follow target -> item renaming away from it; a cycle leads back to the start
+    let by_source: HashMap<String, usize> = items
+        .iter()
+        .enumerate()
+        .filter(|(_, i)| i.status == STATUS_OK)
+        .map(|(idx, i)| (path_key(&i.from), idx))
+        .collect();
+    let mut in_cycle: Vec<bool> = vec![false; items.len()];
+    for (start, item) in items.iter().enumerate() {
+        if item.status != STATUS_OK || in_cycle[start] {
+            continue;
+        }
+        let mut chain: Vec<usize> = vec![start];
+        let mut cur = start;
+        while let Some(&next) = by_source.get(&path_key(&items[cur].to)) {
+            if next == start {
+                for idx in &chain {
+                    in_cycle[*idx] = true;
+                }
+                break;
+            }
+            if chain.contains(&next) {
+                break;
+            }
+            chain.push(next);
+            cur = next;
+        }
+    }
+    for (idx, item) in items.iter_mut().enumerate() {
+        item.cycle = in_cycle[idx];
+    }
+}
+
+/// Rename all `ok` items in two phases: each source to a temp name in the same dir, then
+/// to its target, so swaps and chains work. On failure the done steps are reverted.
+/// Returns the `(from, to)` pairs that were renamed
+pub fn apply_renames(items: &[RenameItem]) -> Result<Vec<(PathBuf, PathBuf)>, BoxedError> {
+    if let Some(item) = items.iter().find(|i| i.status == STATUS_CONFLICT) {
+        return Err(format!(
+            "Rename conflict: {} -> {}: {}",
+            item.from.display(),
+            item.to.display(),
+            item.message
+        )
+        .into());
+    }
+    let todo: Vec<&RenameItem> = items.iter().filter(|i| i.status == STATUS_OK).collect();
+
+    let batch_id = uuid::Uuid::new_v4().simple().to_string();
+    let mut temps: Vec<PathBuf> = Vec::new();
+    for (idx, item) in todo.iter().enumerate() {
+        let temp = item
+            .from
+            .with_file_name(format!(".ft-rename-{}-{}.tmp", &batch_id[..8], idx));
+        if let Err(e) = fs::rename(&item.from, &temp) {
+            for (i, t) in temps.iter().enumerate().rev() {
+                let _ = fs::rename(t, &todo[i].from);
+            }
+            return Err(format!("Failed to rename {}: {}", item.from.display(), e).into());
+        }
+        temps.push(temp);
+    }
+
+    let mut done: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for (idx, item) in todo.iter().enumerate() {
+        if let Err(e) = fs::rename(&temps[idx], &item.to) {
+            for (i, (_, to)) in done.iter().enumerate().rev() {
+                let _ = fs::rename(to, &temps[i]);
+            }
+            for (i, t) in temps.iter().enumerate().rev() {
+                let _ = fs::rename(t, &todo[i].from);
+            }
+            return Err(format!("Failed to rename {}: {}", item.from.display(), e).into());
+        }
+        done.push((item.from.clone(), item.to.clone()));
+    }
+
+    Ok(done)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thelib::test_utils::TempDir;
+
+    fn fixed_exif(_: &Path) -> Result<JsonValue, BoxedError> {
+        Ok(json!({ "EXIF": { "DateTimeOriginal": "2024:05:01 12:30:00" } }))
+    }
+
+    #[test]
+    fn test_parse_template() {
+        let tokens = parse_template("{stem}_{n:03}.{{x}}").unwrap();
+        assert_eq!(tokens.len(), 4);
+        assert_eq!(tokens[3], Token::Literal(".{x}".to_string()));
+        assert!(parse_template("{stem").is_err());
+        assert!(parse_template("a}").is_err());
+    }
+
+    #[test]
+    fn test_rename_batch() {
+        let tmp = TempDir::new("rename");
+        let dir = tmp.path().to_path_buf();
+        let a = dir.join("a.txt");
+        let b = dir.join("b.txt");
+        fs::write(&a, "a").unwrap();
+        fs::write(&b, "b").unwrap();
+
+        // tokens
+        let options = RenameOptions {
+            start: 7,
+            pattern: Some(Regex::new(r"^(\w)\.txt$").unwrap()),
+        };
+        let items = plan_renames(
+            &[a.clone(), b.clone()],
+            "{1}_{n:03}_{exif.DateTimeOriginal:%Y%m%d}.{ext}",
+            &options,
+            &mut fixed_exif,
+        )
+        .unwrap();
+        assert_eq!(items[0].to, dir.join("a_007_20240501.txt"));
+        assert_eq!(items[1].to, dir.join("b_008_20240501.txt"));
+
+        // collision
+        let items = plan_renames(
+            &[a.clone(), b.clone()],
+            "same.txt",
+            &RenameOptions::default(),
+            &mut fixed_exif,
+        )
+        .unwrap();
+        assert!(items.iter().all(|i| i.status == STATUS_CONFLICT));
+        assert!(apply_renames(&items).is_err());
+
+        // swap a <-> b
+        let mut items = vec![
+            RenameItem::new(&a, &b, STATUS_OK, ""),
+            RenameItem::new(&b, &a, STATUS_OK, ""),
+        ];
+        resolve_conflicts(&mut items);
+        assert!(items.iter().all(|i| i.status == STATUS_OK && i.cycle));
+
+        let done = apply_renames(&items).unwrap();
+        assert_eq!(done.len(), 2);
+        assert_eq!(fs::read_to_string(&a).unwrap(), "b");
+        assert_eq!(fs::read_to_string(&b).unwrap(), "a");
+    }
+}
@@ -0,0 +1,466 @@
+use crate::errors::BoxedError;
+use serde_json::{json, Value as JsonValue};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// bytes read from the start of a file
+const HEADER_LEN: usize = 512;
+
+/// a detection with at least this confidence overrides the extension of the file name
+pub const TRUSTED_CONFIDENCE: f32 = 0.8;
+
+/// Type of a file detected from its content
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectedType {
+    pub mime: &'static str,
+    /// canonical extension, lowercase without the dot
+    pub ext: &'static str,
+    /// 0..1, lower for short or loose signatures
+    pub confidence: f32,
+}
+
+impl DetectedType {
+    pub fn to_json(&self) -> JsonValue {
+        json!({
+            "mime": self.mime,
+            "ext": self.ext,
+            "confidence": self.confidence,
+        })
+    }
+}
+
+/// magic bytes at an offset: offset, bytes, mime, ext, confidence
+const SIGNATURES: &[(usize, &[u8], &str, &str, f32)] = &[
+    // images
+    (0, b"\x89PNG\r\n\x1a\n", "image/png", "png", 1.0),
+    (0, b"\xFF\xD8\xFF", "image/jpeg", "jpg", 0.95),
+    (0, b"GIF87a", "image/gif", "gif", 1.0),
+    (0, b"GIF89a", "image/gif", "gif", 1.0),
+    (0, b"II*\x00", "image/tiff", "tif", 0.9),
+    (0, b"MM\x00*", "image/tiff", "tif", 0.9),
+    (0, b"\x00\x00\x01\x00", "image/x-icon", "ico", 0.6),
+    (0, b"8BPS", "image/vnd.adobe.photoshop", "psd", 0.95),
+    (0, b"BM", "image/bmp", "bmp", 0.5),
+    // documents and archives
+    (0, b"%PDF-", "application/pdf", "pdf", 1.0),
+    (0, b"Rar!\x1a\x07", "application/vnd.rar", "rar", 1.0),
+    (
+        0,
+        b"7z\xBC\xAF\x27\x1C",
+        "application/x-7z-compressed",
+        "7z",
+        1.0,
+    ),
+    (0, b"\x1F\x8B", "application/gzip", "gz", 0.9),
+    (0, b"BZh", "application/x-bzip2", "bz2", 0.7),
+    (0, b"\xFD7zXZ\x00", "application/x-xz", "xz", 1.0),
+    (0, b"\x28\xB5\x2F\xFD", "application/zstd", "zst", 0.95),
+    (257, b"ustar", "application/x-tar", "tar", 0.95),
+    (
+        0,
+        b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1",
+        "application/x-ole-storage",
+        "doc",
+        0.7,
+    ),
+    (0, b"{\\rtf", "application/rtf", "rtf", 0.95),
+    (0, b"wOFF", "font/woff", "woff", 1.0),
+    (0, b"wOF2", "font/woff2", "woff2", 1.0),
+    (0, b"OTTO", "font/otf", "otf", 0.9),
+    (0, b"\x00\x01\x00\x00\x00", "font/ttf", "ttf", 0.7),
+    (
+        0,
+        b"SQLite format 3\x00",
+        "application/vnd.sqlite3",
+        "sqlite",
+        1.0,
+    ),
+    // audio and video
+    (0, b"ID3", "audio/mpeg", "mp3", 0.9),
+    (0, b"fLaC", "audio/flac", "flac", 1.0),
+    (0, b"OggS", "audio/ogg", "ogg", 0.9),
+    (0, b"\x1A\x45\xDF\xA3", "video/x-matroska", "mkv", 0.9),
+    (0, b"FLV\x01", "video/x-flv", "flv", 1.0),
+    (0, b"\x00\x00\x01\xBA", "video/mpeg", "mpg", 0.8),
+    // executables
+    (0, b"\x7FELF", "application/x-elf", "elf", 1.0),
+    (
+        0,
+        b"MZ",
+        "application/vnd.microsoft.portable-executable",
+        "exe",
+        0.6,
+    ),
+    (0, b"\x00asm", "application/wasm", "wasm", 1.0),
+];
+
+/// types whose signature is shared by more specific formats: odt, jar and apk are zips,
+/// camera raws like dng, cr2 and nef are tiffs, ai is a pdf, mp4 is the fallback of `ftyp`
+const CONTAINER_MIMES: &[&str] = &[
+    "application/zip",
+    "image/tiff",
+    "application/pdf",
+    "application/gzip",
+    "video/mp4",
+];
+
+/// brands of the ISO base media `ftyp` box: brand, mime, ext
+const FTYP_BRANDS: &[(&[u8], &str, &str)] = &[
+    (b"heic", "image/heic", "heic"),
+    (b"heix", "image/heic", "heic"),
+    (b"heim", "image/heic", "heic"),
+    (b"heis", "image/heic", "heic"),
+    (b"hevc", "image/heic-sequence", "heic"),
+    (b"mif1", "image/heif", "heif"),
+    (b"msf1", "image/heif-sequence", "heif"),
+    (b"avif", "image/avif", "avif"),
+    (b"avis", "image/avif", "avif"),
+    (b"qt  ", "video/quicktime", "mov"),
+    (b"M4A ", "audio/mp4", "m4a"),
+    (b"M4V ", "video/x-m4v", "m4v"),
+    (b"3gp", "video/3gpp", "3gp"),
+    (b"crx ", "image/x-canon-cr3", "cr3"),
+];
+
+/// the content of a zip: file name in the archive, mime, ext
+const ZIP_CONTENTS: &[(&[u8], &str, &str)] = &[
+    (
+        b"word/",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "docx",
+    ),
+    (
+        b"xl/",
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "xlsx",
+    ),
+    (
+        b"ppt/",
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "pptx",
+    ),
+    (
+        b"mimetypeapplication/epub+zip",
+        "application/epub+zip",
+        "epub",
+    ),
+];
+
+fn detected(mime: &'static str, ext: &'static str, confidence: f32) -> Option<DetectedType> {
+    Some(DetectedType {
+        mime,
+        ext,
+        confidence,
+    })
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+/// Detect the type from the first bytes of a file
+pub fn detect_from_bytes(header: &[u8]) -> Option<DetectedType> {
+    // containers first, their magic bytes are shared by several types
+    if header.len() >= 12 && &header[4..8] == b"ftyp" {
+        let brand = &header[8..12];
+        for (b, mime, ext) in FTYP_BRANDS {
+            if brand.starts_with(b) {
+                return detected(mime, ext, 0.95);
+            }
+        }
+        return detected("video/mp4", "mp4", 0.8);
+    }
+    if header.len() >= 12 && header.starts_with(b"RIFF") {
+        return match &header[8..12] {
+            b"WEBP" => detected("image/webp", "webp", 1.0),
+            b"WAVE" => detected("audio/wav", "wav", 1.0),
+            b"AVI " => detected("video/x-msvideo", "avi", 1.0),
+            _ => detected("application/octet-stream", "riff", 0.5),
+        };
+    }
+    if header.starts_with(b"PK\x03\x04") {
+        for (name, mime, ext) in ZIP_CONTENTS {
+            if contains(header, name) {
+                return detected(mime, ext, 0.9);
+            }
+        }
+        return detected("application/zip", "zip", 0.9);
+    }
+
+    for (offset, magic, mime, ext, confidence) in SIGNATURES {
+        if header.len() >= offset + magic.len() && &header[*offset..offset + magic.len()] == *magic
+        {
+            return detected(mime, ext, *confidence);
+        }
+    }
+
+    // mpeg audio frame sync
+    if header.len() >= 2 && header[0] == 0xFF && header[1] & 0xE0 == 0xE0 {
+        return detected("audio/mpeg", "mp3", 0.6);
+    }
+
+    detect_text(header)
+}
+
+/// text types, by a sniff of the start of a text; confidence is low, any text looks like plain text
+fn detect_text(header: &[u8]) -> Option<DetectedType> {
+    if header.is_empty() || header.contains(&0) {
+        return None;
+    }
+    // a multi-byte char may be cut at the end of the header
+    let text = match std::str::from_utf8(header) {
+        Ok(t) => t,
+        Err(e) if e.error_len().is_none() => {
+            std::str::from_utf8(&header[..e.valid_up_to()]).ok()?
+        }
+        Err(_) => return None,
+    };
+    let text = text.trim_start_matches('\u{FEFF}').trim_start();
+    let lower = text.to_lowercase();
+
+    if lower.starts_with("<svg") || (lower.starts_with("<?xml") && lower.contains("<svg")) {
+        return detected("image/svg+xml", "svg", 0.9);
+    }
+    if lower.starts_with("<!doctype html") || lower.starts_with("<html") {
+        return detected("text/html", "html", 0.9);
+    }
+    if lower.starts_with("<?xml") {
+        return detected("application/xml", "xml", 0.8);
+    }
+    if text.starts_with("#!") {
+        return detected("text/x-shellscript", "sh", 0.6);
+    }
+    if (text.starts_with('{') || text.starts_with('['))
+        && serde_json::from_str::<JsonValue>(text).is_ok()
+    {
+        return detected("application/json", "json", 0.8);
+    }
+    detected("text/plain", "txt", 0.3)
+}
+
+/// Detect the type of a file from its content. None if unknown, e.g. an empty file
+pub fn detect_file_type<P: AsRef<Path>>(path: P) -> Result<Option<DetectedType>, BoxedError> {
+    let mut file = File::open(path.as_ref())
+        .map_err(|e| format!("Failed to open {}: {}", path.as_ref().display(), e))?;
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    file.by_ref()
+        .take(HEADER_LEN as u64)
+        .read_to_end(&mut header)?;
+    Ok(detect_from_bytes(&header))
+}
+
+/// whether `mime` is a known format stored in the `container` format, e.g. docx in zip
+fn is_stored_in(mime: &str, container: &str) -> bool {
+    match container {
+        "application/zip" => ZIP_CONTENTS.iter().any(|(_, m, _)| *m == mime),
+        "video/mp4" => FTYP_BRANDS.iter().any(|(_, m, _)| *m == mime),
+        _ => false,
+    }
+}
+
+/// Whether a trusted detection replaces the type of the file name's extension `name_ext`.
+/// Always for a file without extension. A container type only replaces the extension of
+/// another known type (e.g. a zip named .txt), an unknown extension is kept as a more
+/// specific type of it (e.g. .odt). Other types replace any other extension
+pub fn overrides_ext(name_ext: &str, detected: &DetectedType) -> bool {
+    if name_ext.is_empty() {
+        return true;
+    }
+    let name_mime = mime_from_ext(name_ext);
+    if !CONTAINER_MIMES.contains(&detected.mime) {
+        return name_mime != Some(detected.mime);
+    }
+    match name_mime {
+        Some(m) => m != detected.mime && !is_stored_in(m, detected.mime),
+        None => false,
+    }
+}
+
+/// Extension and mime type of a file, used to match functions.
+/// Special kinds for internal use have an extension starting with `/` and no mime
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FileKind {
+    pub ext: String,
+    pub mime: String,
+}
+
+impl FileKind {
+    pub fn special(tag: &str) -> Self {
+        FileKind {
+            ext: tag.to_string(),
+            mime: String::new(),
+        }
+    }
+
+    /// the detected type if trusted and it overrides the extension (see `overrides_ext`),
+    /// so a misnamed or extensionless file gets its real type; otherwise the extension of
+    /// the name, with its own mime or else the detected one
+    pub fn of_file(path: &Path) -> Self {
+        let name_ext = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let detected = match detect_file_type(path) {
+            Ok(d) => d,
+            Err(e) => {
+                log::warn!("{}", e);
+                None
+            }
+        };
+        match detected {
+            Some(d) if d.confidence >= TRUSTED_CONFIDENCE && overrides_ext(&name_ext, &d) => {
+                FileKind {
+                    ext: d.ext.to_string(),
+                    mime: d.mime.to_string(),
+                }
+            }
+            // also keeps the name's spelling of the same type, e.g. jpeg for jpg
+            d => FileKind {
+                mime: mime_from_ext(&name_ext)
+                    .or(d.map(|d| d.mime))
+                    .unwrap_or("")
+                    .to_string(),
+                ext: name_ext,
+            },
+        }
+    }
+}
+
+/// mime of an extension, for files not detected by content
+pub fn mime_from_ext(ext: &str) -> Option<&'static str> {
+    let ext = ext.to_lowercase();
+    let ext = match ext.as_str() {
+        "jpeg" | "jpe" => "jpg",
+        "tiff" => "tif",
+        "htm" => "html",
+        e => e,
+    };
+    SIGNATURES
+        .iter()
+        .map(|(_, _, mime, e, _)| (*mime, *e))
+        .chain(FTYP_BRANDS.iter().map(|(_, mime, e)| (*mime, *e)))
+        .chain(ZIP_CONTENTS.iter().map(|(_, mime, e)| (*mime, *e)))
+        .chain([
+            ("image/webp", "webp"),
+            ("audio/wav", "wav"),
+            ("video/x-msvideo", "avi"),
+            ("video/mp4", "mp4"),
+            ("application/zip", "zip"),
+            ("image/svg+xml", "svg"),
+            ("text/html", "html"),
+            ("application/xml", "xml"),
+            ("application/json", "json"),
+            ("text/plain", "txt"),
+        ])
+        .find(|(_, e)| *e == ext)
+        .map(|(mime, _)| mime)
+}
+
+/// `pattern` is a mime type, `type/*` or `*/*`; case-insensitive
+pub fn mime_matches(pattern: &str, mime: &str) -> bool {
+    if mime.is_empty() {
+        return false;
+    }
+    let pattern = pattern.to_lowercase();
+    let mime = mime.to_lowercase();
+    match pattern.strip_suffix("/*") {
+        Some("*") => true,
+        Some(main_type) => mime.split('/').next() == Some(main_type),
+        None => pattern == mime,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thelib::test_utils::TempDir;
+
+    #[test]
+    fn test_detect_from_bytes() {
+        let png = detect_from_bytes(b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR").unwrap();
+        assert_eq!((png.mime, png.ext), ("image/png", "png"));
+
+        // a HEIC named .jpg
+        let heic = detect_from_bytes(b"\x00\x00\x00\x18ftypheic\x00\x00\x00\x00mif1heic").unwrap();
+        assert_eq!(heic.ext, "heic");
+        assert!(heic.confidence >= TRUSTED_CONFIDENCE);
+
+        let webp = detect_from_bytes(b"RIFF\x24\x00\x00\x00WEBPVP8 ").unwrap();
+        assert_eq!(webp.mime, "image/webp");
+
+        let docx =
+            detect_from_bytes(b"PK\x03\x04\x14\x00\x06\x00[Content_Types].xmlword/document.xml");
+        assert_eq!(docx.unwrap().ext, "docx");
+
+        let json = detect_from_bytes(b"  {\"a\": [1, 2]}").unwrap();
+        assert_eq!(json.ext, "json");
+        let text = detect_from_bytes("héllo".as_bytes()).unwrap();
+        assert_eq!(text.mime, "text/plain");
+        assert!(text.confidence < TRUSTED_CONFIDENCE);
+
+        assert!(detect_from_bytes(b"\x00\x01\x02\x03\x04").is_none());
+        assert!(detect_from_bytes(b"").is_none());
+    }
+
+    #[test]
+    fn test_mime() {
+        assert!(mime_matches("image/*", "image/heic"));
+        assert!(mime_matches("*/*", "video/mp4"));
+        assert!(mime_matches("Image/PNG", "image/png"));
+        assert!(!mime_matches("image/*", "video/mp4"));
+        assert!(!mime_matches("image/png", "image/pngx"));
+        assert!(!mime_matches("*/*", ""));
+
+        assert_eq!(mime_from_ext("JPEG"), Some("image/jpeg"));
+        assert_eq!(mime_from_ext("heic"), Some("image/heic"));
+        assert_eq!(mime_from_ext("unknown"), None);
+    }
+
+    #[test]
+    fn test_file_kind() {
+        let tmp = TempDir::new("file-kind");
+        let kind_of = |name: &str, content: &[u8]| {
+            let path = tmp.path().join(name);
+            std::fs::write(&path, content).unwrap();
+            let kind = FileKind::of_file(&path);
+            (kind.ext, kind.mime)
+        };
+        let kind = |ext: &str, mime: &str| (ext.to_string(), mime.to_string());
+        let zip = b"PK\x03\x04\x14\x00\x00\x00\x08\x00content.xml";
+        let tiff = b"II*\x00\x08\x00\x00\x00";
+        let pdf = b"%PDF-1.6\n";
+        let png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR";
+
+        // more specific types of a container keep their extension
+        assert_eq!(kind_of("a.odt", zip), kind("odt", "application/zip"));
+        assert_eq!(kind_of("a.jar", zip), kind("jar", "application/zip"));
+        assert_eq!(
+            kind_of("a.docx", zip),
+            kind("docx", mime_from_ext("docx").unwrap())
+        );
+        assert_eq!(kind_of("a.DNG", tiff), kind("dng", "image/tiff"));
+        assert_eq!(kind_of("a.nef", tiff), kind("nef", "image/tiff"));
+        assert_eq!(kind_of("a.ai", pdf), kind("ai", "application/pdf"));
+        assert_eq!(
+            kind_of("a.mov", b"\x00\x00\x00\x14ftypisom\x00\x00\x02\x00"),
+            kind("mov", "video/quicktime")
+        );
+
+        // a container replaces another known type, or no extension
+        assert_eq!(kind_of("a.txt", zip), kind("zip", "application/zip"));
+        assert_eq!(kind_of("a", tiff), kind("tif", "image/tiff"));
+
+        // a specific type replaces any other extension
+        assert_eq!(kind_of("a.jpg", png), kind("png", "image/png"));
+        assert_eq!(kind_of("a.dat", png), kind("png", "image/png"));
+        assert_eq!(
+            kind_of("a.jpeg", b"\xFF\xD8\xFF\xE0"),
+            kind("jpeg", "image/jpeg")
+        );
+
+        // not trusted, the name wins
+        assert_eq!(kind_of("a.csv", b"a,b\n1,2\n"), kind("csv", "text/plain"));
+        assert_eq!(kind_of("a.md", b"# title"), kind("md", "text/plain"));
+    }
+}
@@ -0,0 +1,215 @@
+use crate::thelib::walk::{walk, WalkOptions};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+/// size and mtime of a watched file
+type FileStamp = (u64, Option<SystemTime>);
+
+/// Watches files by name below some dirs, by comparing their size and mtime
+/// between polls. Polling keeps it working the same on every platform and on
+/// network drives, the watched trees are expected to be small
+pub struct PollWatcher {
+    file_names: Vec<String>,
+    max_depth: usize,
+    files: HashMap<PathBuf, FileStamp>,
+}
+
+impl PollWatcher {
+    /// `max_depth` below each watched dir, the dir itself is depth 0
+    pub fn new(file_names: &[&str], max_depth: usize) -> Self {
+        PollWatcher {
+            file_names: file_names.iter().map(|n| n.to_string()).collect(),
+            max_depth,
+            files: HashMap::new(),
+        }
+    }
+
+    fn snapshot(&self, dirs: &[PathBuf]) -> HashMap<PathBuf, FileStamp> {
+        let options = WalkOptions {
+            max_depth: Some(self.max_depth),
+            dirs: false,
+            ..Default::default()
+        };
+        let mut files = HashMap::new();
+        for dir in dirs.iter().filter(|d| d.is_dir()) {
+            // unreadable entries are left out, as if removed
+            for entry in walk(dir, &options).flatten() {
+                let name = entry.path.file_name().map(|n| n.to_string_lossy());
+                if name.is_some_and(|n| self.file_names.iter().any(|f| *f == n)) {
+                    files.insert(entry.path, (entry.size, entry.modified));
+                }
+            }
+        }
+        files
+    }
+
+    /// Read the current state without reporting changes
+    pub fn reset(&mut self, dirs: &[PathBuf]) {
+        self.files = self.snapshot(dirs);
+    }
+
+    /// The files added, changed or removed since the last poll, sorted
+    pub fn poll(&mut self, dirs: &[PathBuf]) -> Vec<PathBuf> {
+        let files = self.snapshot(dirs);
+        let mut changed: Vec<PathBuf> = files
+            .iter()
+            .filter(|(path, stamp)| self.files.get(*path) != Some(*stamp))
+            .map(|(path, _)| path.clone())
+            .collect();
+        changed.extend(
+            self.files
+                .keys()
+                .filter(|path| !files.contains_key(*path))
+                .cloned(),
+        );
+        changed.sort();
+        self.files = files;
+        changed
+    }
+}
+
+/// The thread of a running `PollWatcher`, it polls until `stop` is called
+pub struct WatcherHandle {
+    stopped: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl WatcherHandle {
+    /// Stop polling and wait for the thread to end, e.g. a running `on_change` to return
+    pub fn stop(self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        self.thread.thread().unpark();
+        if self.thread.join().is_err() {
+            log::error!("Watcher thread panicked");
+        }
+    }
+}
+
+/// Run `watcher` in a thread, calling `on_change` with the changed files.
+/// `get_dirs` is called on each poll, so the watched dirs may change.
+/// polls are skipped while `is_paused` returns true, the changes are reported by the next one
+pub fn spawn_poll_watcher<D, P, F>(
+    mut watcher: PollWatcher,
+    interval: Duration,
+    get_dirs: D,
+    is_paused: P,
+    on_change: F,
+) -> WatcherHandle
+where
+    D: Fn() -> Vec<PathBuf> + Send + 'static,
+    P: Fn() -> bool + Send + 'static,
+    F: Fn(Vec<PathBuf>) + Send + 'static,
+{
+    let stopped = Arc::new(AtomicBool::new(false));
+    let thread_stopped = stopped.clone();
+    let thread = std::thread::spawn(move || {
+        watcher.reset(&get_dirs());
+        loop {
+            // parked instead of slept, so `stop` wakes it
+            let deadline = Instant::now() + interval;
+            while !thread_stopped.load(Ordering::Relaxed) {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                std::thread::park_timeout(deadline - now);
+            }
+            if thread_stopped.load(Ordering::Relaxed) {
+                return;
+            }
+            if is_paused() {
+                continue;
+            }
+            let changed = watcher.poll(&get_dirs());
+            if !changed.is_empty() {
+                on_change(changed);
+            }
+        }
+    });
+
+    WatcherHandle { stopped, thread }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thelib::test_utils::TempDir;
+    use std::fs;
+
+    #[test]
+    fn test_poll_watcher() {
+        let tmp = TempDir::new("watcher");
+        let dir = tmp.path().to_path_buf();
+        fs::create_dir_all(dir.join("a")).unwrap();
+        fs::write(dir.join("a/config.json"), "{}").unwrap();
+        fs::write(dir.join("a/readme.md"), "").unwrap();
+        let dirs = vec![dir.clone()];
+
+        let mut watcher = PollWatcher::new(&["config.json", "worker.js"], 2);
+        watcher.reset(&dirs);
+        assert!(watcher.poll(&dirs).is_empty());
+
+        fs::write(dir.join("a/config.json"), "{\"type\": \"file\"}").unwrap();
+        fs::write(dir.join("a/worker.js"), "").unwrap();
+        fs::write(dir.join("a/readme.md"), "changed").unwrap();
+        assert_eq!(
+            watcher.poll(&dirs),
+            vec![dir.join("a/config.json"), dir.join("a/worker.js")]
+        );
+        assert!(watcher.poll(&dirs).is_empty());
+
+        fs::remove_file(dir.join("a/worker.js")).unwrap();
+        // deeper than max_depth
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        fs::write(dir.join("a/b/config.json"), "{}").unwrap();
+        assert_eq!(watcher.poll(&dirs), vec![dir.join("a/worker.js")]);
+    }
+
+    #[test]
+    fn test_stop_watcher() {
+        let tmp = TempDir::new("watcher-stop");
+        let dir = tmp.path().to_path_buf();
+        let paused = Arc::new(AtomicBool::new(true));
+        let changes = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let (thread_dir, thread_paused, thread_changes) =
+            (dir.clone(), paused.clone(), changes.clone());
+        let handle = spawn_poll_watcher(
+            PollWatcher::new(&["config.json"], 1),
+            Duration::from_millis(10),
+            move || vec![thread_dir.clone()],
+            move || thread_paused.load(Ordering::Relaxed),
+            move |changed| thread_changes.lock().unwrap().extend(changed),
+        );
+
+        // not reported while paused
+        std::thread::sleep(Duration::from_millis(50));
+        fs::write(dir.join("config.json"), "{}").unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(changes.lock().unwrap().is_empty());
+
+        paused.store(false, Ordering::Relaxed);
+        let start = Instant::now();
+        while changes.lock().unwrap().is_empty() && start.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(*changes.lock().unwrap(), vec![dir.join("config.json")]);
+
+        // stops without waiting for a long interval
+        let handle_slow = spawn_poll_watcher(
+            PollWatcher::new(&["config.json"], 1),
+            Duration::from_secs(60),
+            Vec::new,
+            || false,
+            |_| {},
+        );
+        let start = Instant::now();
+        handle.stop();
+        handle_slow.stop();
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,130 @@
+use crate::errors::BoxedError;
+use regex::Regex;
+
+/// Convert a glob pattern into an anchored regex. Supported syntax:
+/// - `*` any chars except `/`, `**` any chars including `/` (`**/` also matches no dir)
+/// - `?` one char except `/`
+/// - `[abc]`, `[a-z]`, `[!abc]` char classes
+/// - `{a,b}` alternatives
+///
+/// Matching is case-sensitive, `\` escapes the next char
+pub fn glob_to_regex(pattern: &str) -> Result<Regex, BoxedError> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut re = String::from("^");
+    let mut in_braces = 0;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '*' => {
+                if i + 1 < chars.len() && chars[i + 1] == '*' {
+                    i += 1;
+                    if i + 1 < chars.len() && chars[i + 1] == '/' {
+                        // `**/` matches zero or more directories
+                        i += 1;
+                        re.push_str("(?:.*/)?");
+                    } else {
+                        re.push_str(".*");
+                    }
+                } else {
+                    re.push_str("[^/]*");
+                }
+            }
+            '?' => re.push_str("[^/]"),
+            '[' => {
+                let close = match chars[i + 1..].iter().position(|&c| c == ']') {
+                    Some(p) => i + 1 + p,
+                    None => return Err(format!("Invalid glob pattern: '{}'", pattern).into()),
+                };
+                push_char_class(&mut re, &chars[i + 1..close]);
+                i = close;
+            }
+            '{' => {
+                in_braces += 1;
+                re.push_str("(?:");
+            }
+            '}' if in_braces > 0 => {
+                in_braces -= 1;
+                re.push(')');
+            }
+            ',' if in_braces > 0 => re.push('|'),
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                re.push_str(&regex::escape(&chars[i].to_string()));
+            }
+            _ => re.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+
+    if in_braces > 0 {
+        return Err(format!("Invalid glob pattern: '{}'", pattern).into());
+    }
+    re.push('$');
+
+    match Regex::new(&re) {
+        Ok(r) => Ok(r),
+        Err(e) => Err(format!("Invalid glob pattern: '{}', error: {}", pattern, e).into()),
+    }
+}
+
+/// `[...]` of a glob, the chars are escaped except ranges like `a-z` and a leading `!`/`^`
+fn push_char_class(re: &mut String, class: &[char]) {
+    let class = match class.first() {
+        Some('!') | Some('^') => {
+            re.push_str("[^");
+            &class[1..]
+        }
+        _ => {
+            re.push('[');
+            class
+        }
+    };
+    let mut i = 0;
+    while i < class.len() {
+        re.push_str(&regex::escape(&class[i].to_string()));
+        if i + 2 < class.len() && class[i + 1] == '-' {
+            re.push('-');
+            re.push_str(&regex::escape(&class[i + 2].to_string()));
+            i += 2;
+        }
+        i += 1;
+    }
+    re.push(']');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_to_regex() {
+        let re = glob_to_regex("*.txt").unwrap();
+        assert!(re.is_match("a.txt"));
+        assert!(!re.is_match("dir/a.txt"));
+        assert!(!re.is_match("a.txt.bak"));
+
+        let re = glob_to_regex("docs/**/*.{md,txt}").unwrap();
+        assert!(re.is_match("docs/a.md"));
+        assert!(re.is_match("docs/x/y/b.txt"));
+        assert!(!re.is_match("docs/c.rs"));
+
+        let re = glob_to_regex("img_??[0-9][!a].png").unwrap();
+        assert!(re.is_match("img_ab1b.png"));
+        assert!(!re.is_match("img_ab1a.png"));
+
+        // regex syntax in a class is literal
+        let re = glob_to_regex("[\\d&&x-]").unwrap();
+        assert!(re.is_match("d"));
+        assert!(re.is_match("&"));
+        assert!(re.is_match("-"));
+        assert!(!re.is_match("5"));
+        let re = glob_to_regex("[^a-c]").unwrap();
+        assert!(re.is_match("d"));
+        assert!(!re.is_match("b"));
+
+        assert!(glob_to_regex("[abc").is_err());
+        assert!(glob_to_regex("{a,b").is_err());
+    }
+}
@@ -1,6 +1,19 @@
+use crate::errors::BoxedError;
+use crate::thelib::task::check_cancelled;
 use md5;
 use sha1::Sha1;
 use sha2::{Digest, Sha256, Sha512};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+pub const SUPPORTED_HASH_TYPES: [&str; 8] = [
+    "md5", "sha1", "sha256", "sha512", "blake3", "crc32", "xxh64", "xxh3",
+];
+
+// read buffer size for streaming hashing
+const HASH_BUFFER_SIZE: usize = 64 * 1024;
 
 pub fn calc_md5(data: &[u8]) -> String {
     let digest = md5::compute(&data);
@@ -27,3 +40,279 @@ pub fn calc_sha512(data: &[u8]) -> String {
     let result = format!("{:x}", digest);
     result
 }
+
+/// incremental hasher, feed data by `update` and get the hex digest by `finalize`
+pub enum StreamHasher {
+    Md5(md5::Context),
+    Sha1(Sha1),
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Blake3(Box<blake3::Hasher>),
+    Crc32(crc32fast::Hasher),
+    Xxh64(xxhash_rust::xxh64::Xxh64),
+    Xxh3(Box<xxhash_rust::xxh3::Xxh3>),
+}
+
+impl StreamHasher {
+    pub fn new(hash_type: &str) -> Result<Self, BoxedError> {
+        let hasher = match hash_type {
+            "md5" => StreamHasher::Md5(md5::Context::new()),
+            "sha1" => StreamHasher::Sha1(Sha1::new()),
+            "sha256" => StreamHasher::Sha256(Sha256::new()),
+            "sha512" => StreamHasher::Sha512(Sha512::new()),
+            "blake3" => StreamHasher::Blake3(Box::new(blake3::Hasher::new())),
+            "crc32" => StreamHasher::Crc32(crc32fast::Hasher::new()),
+            "xxh64" => StreamHasher::Xxh64(xxhash_rust::xxh64::Xxh64::new(0)),
+            "xxh3" => StreamHasher::Xxh3(Box::new(xxhash_rust::xxh3::Xxh3::new())),
+            _ => return Err(format!("Invalid hash type: '{}'", hash_type).into()),
+        };
+        Ok(hasher)
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            StreamHasher::Md5(h) => h.consume(data),
+            StreamHasher::Sha1(h) => h.update(data),
+            StreamHasher::Sha256(h) => h.update(data),
+            StreamHasher::Sha512(h) => h.update(data),
+            StreamHasher::Blake3(h) => {
+                h.update(data);
+            }
+            StreamHasher::Crc32(h) => h.update(data),
+            StreamHasher::Xxh64(h) => h.update(data),
+            StreamHasher::Xxh3(h) => h.update(data),
+        }
+    }
+
+    pub fn finalize(self) -> String {
+        match self {
+            StreamHasher::Md5(h) => format!("{:x}", h.compute()),
+            StreamHasher::Sha1(h) => format!("{:x}", h.finalize()),
+            StreamHasher::Sha256(h) => format!("{:x}", h.finalize()),
+            StreamHasher::Sha512(h) => format!("{:x}", h.finalize()),
+            StreamHasher::Blake3(h) => h.finalize().to_hex().to_string(),
+            StreamHasher::Crc32(h) => format!("{:08x}", h.finalize()),
+            StreamHasher::Xxh64(h) => format!("{:016x}", h.digest()),
+            StreamHasher::Xxh3(h) => format!("{:016x}", h.digest()),
+        }
+    }
+}
+
+/// Calculate several digests of the reader in one pass.
+/// Returns a map of hash type to lowercase hex digest
+pub fn hash_reader<R: Read>(
+    reader: R,
+    hash_types: &[&str],
+) -> Result<HashMap<String, String>, BoxedError> {
+    if hash_types.is_empty() {
+        return Err("No hash type specified".into());
+    }
+
+    let mut hashers: Vec<(String, StreamHasher)> = Vec::new();
+    for hash_type in hash_types {
+        let hash_type = hash_type.to_lowercase();
+        if hashers.iter().any(|(t, _)| t == &hash_type) {
+            continue;
+        }
+        let hasher = StreamHasher::new(&hash_type)?;
+        hashers.push((hash_type, hasher));
+    }
+
+    let mut reader = BufReader::with_capacity(HASH_BUFFER_SIZE, reader);
+    let mut buffer = vec![0; HASH_BUFFER_SIZE];
+    loop {
+        // a large file takes a while, stop with the task
+        check_cancelled()?;
+        let n = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        for (_, hasher) in hashers.iter_mut() {
+            hasher.update(&buffer[..n]);
+        }
+    }
+
+    let mut result: HashMap<String, String> = HashMap::new();
+    for (hash_type, hasher) in hashers {
+        result.insert(hash_type, hasher.finalize());
+    }
+
+    Ok(result)
+}
+
+/// Calculate several digests of the file in one pass, without loading the whole file into memory
+pub fn hash_file<P: AsRef<Path>>(
+    file_path: P,
+    hash_types: &[&str],
+) -> Result<HashMap<String, String>, BoxedError> {
+    let file = match File::open(file_path.as_ref()) {
+        Ok(f) => f,
+        Err(e) => {
+            return Err(format!("Failed to open file {:?}: {}", file_path.as_ref(), e).into());
+        }
+    };
+    hash_reader(file, hash_types)
+}
+
+/// Guess the hash type of a checksum file by its name (e.g. `a.zip.sha256`, `SHA256SUMS`, `md5sum.txt`)
+pub fn guess_hash_type_by_file_name(file_name: &str) -> Option<String> {
+    let name = file_name.to_lowercase();
+    let candidates = [
+        "sha512", "sha256", "sha1", "md5", "blake3", "crc32", "xxh64", "xxh3",
+    ];
+    for hash_type in candidates {
+        if name.contains(hash_type) {
+            return Some(hash_type.to_string());
+        }
+    }
+    None
+}
+
+/// Guess the hash type by the length of a hex digest, only for unambiguous lengths.
+/// 64 chars may be sha256 or blake3, 16 chars xxh64 or xxh3, so they are not guessed
+pub fn guess_hash_type_by_digest(digest: &str) -> Option<String> {
+    let hash_type = match digest.len() {
+        32 => "md5",
+        40 => "sha1",
+        128 => "sha512",
+        8 => "crc32",
+        _ => return None,
+    };
+    Some(hash_type.to_string())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChecksumEntry {
+    pub digest: String,
+    /// file name as written in the checksum file, empty for a bare digest sidecar
+    pub file_name: String,
+    /// hash type declared in the line (BSD style only)
+    pub hash_type: Option<String>,
+}
+
+/// Parse checksum file content. Supported line formats:
+/// - GNU style: `<digest>  <file>` or `<digest> *<file>` (binary mode)
+/// - BSD style: `SHA256 (<file>) = <digest>`
+/// - bare digest (a sidecar file only containing the digest)
+pub fn parse_checksum_content(content: &str) -> Vec<ChecksumEntry> {
+    let mut entries = Vec::new();
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        // BSD style
+        if let Some(open) = line.find(" (") {
+            if let Some(close) = line.rfind(") = ") {
+                if close > open {
+                    let hash_type = line[..open].trim().to_lowercase().replace('-', "");
+                    entries.push(ChecksumEntry {
+                        digest: line[close + 4..].trim().to_lowercase(),
+                        file_name: line[open + 2..close].to_string(),
+                        hash_type: Some(hash_type),
+                    });
+                    continue;
+                }
+            }
+        }
+
+        // GNU style or bare digest
+        let (digest, file_name) = match line.split_once(char::is_whitespace) {
+            Some((d, f)) => {
+                let f = f.trim_start();
+                let f = f.strip_prefix('*').unwrap_or(f);
+                (d, f)
+            }
+            None => (line, ""),
+        };
+        if !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+            log::warn!("Ignore invalid checksum line: {}", line);
+            continue;
+        }
+        entries.push(ChecksumEntry {
+            digest: digest.to_lowercase(),
+            file_name: file_name.to_string(),
+            hash_type: None,
+        });
+    }
+
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_reader() {
+        let data: &[u8] = b"abc";
+        let digests = hash_reader(data, &SUPPORTED_HASH_TYPES).unwrap();
+        assert_eq!(digests["md5"], "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(digests["sha1"], "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(
+            digests["sha256"],
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(digests["sha512"], calc_sha512(data));
+        assert_eq!(
+            digests["blake3"],
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        assert_eq!(digests["crc32"], "352441c2");
+        assert_eq!(digests["xxh64"], "44bc2cf5ad770999");
+        assert_eq!(digests["xxh3"], "78af5f94892f3950");
+    }
+
+    #[test]
+    fn test_hash_reader_large_input() {
+        // larger than the buffer, to cover multiple reads
+        let data = vec![7u8; HASH_BUFFER_SIZE * 3 + 123];
+        let digests = hash_reader(data.as_slice(), &["md5", "sha256"]).unwrap();
+        assert_eq!(digests["md5"], calc_md5(&data));
+        assert_eq!(digests["sha256"], calc_sha256(&data));
+    }
+
+    #[test]
+    fn test_parse_checksum_content() {
+        let content = "\
+# comment
+ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  a b.txt
+900150983CD24FB0D6963F7D28E17F72 *c.bin
+SHA256 (d.iso) = BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD
+not-a-digest file
+";
+        let entries = parse_checksum_content(content);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].file_name, "a b.txt");
+        assert_eq!(entries[1].file_name, "c.bin");
+        assert_eq!(entries[1].digest, "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(entries[2].file_name, "d.iso");
+        assert_eq!(entries[2].hash_type, Some("sha256".to_string()));
+
+        let entries = parse_checksum_content("900150983cd24fb0d6963f7d28e17f72\n");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].file_name, "");
+    }
+
+    #[test]
+    fn test_guess_hash_type() {
+        assert_eq!(
+            guess_hash_type_by_file_name("SHA256SUMS"),
+            Some("sha256".to_string())
+        );
+        assert_eq!(
+            guess_hash_type_by_file_name("a.zip.md5"),
+            Some("md5".to_string())
+        );
+        assert_eq!(guess_hash_type_by_file_name("checksums.txt"), None);
+        assert_eq!(
+            guess_hash_type_by_digest("a9993e364706816aba3e25717850c26c9cd0d89d"),
+            Some("sha1".to_string())
+        );
+        assert_eq!(guess_hash_type_by_digest(&"a".repeat(64)), None);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::errors::BoxedError;
 use crate::thelib::file_path::{make_parent_dirs, split_file_path};
+use crate::thelib::safe_write::{safe_write, ConflictPolicy, WriteOutcome};
 use crate::thelib::svg;
 use oxipng;
 
@@ -52,7 +53,8 @@ pub fn file_image_to_svg(
     src_path: &str,
     dest_path: &str,
     args: &JsonValue,
-) -> Result<String, BoxedError> {
+    conflict: ConflictPolicy,
+) -> Result<WriteOutcome, BoxedError> {
     // prepare arg - color_colorful
     let color_colorful: bool = args
         .get("color_colorful")
@@ -82,12 +84,8 @@ pub fn file_image_to_svg(
     // use image_to_svg to convert dynamic image to svg
     let (_, _, svg_text) = svg::image_to_svg(&img, color_colorful)?;
 
-    make_parent_dirs(&dest_path)?;
-
     // write svg text to dest_path
-    std::fs::write(&dest_path, svg_text.as_bytes())?;
-
-    return Ok(dest_path.to_string());
+    safe_write(Path::new(dest_path), svg_text.as_bytes(), conflict)
 }
 
 pub fn png_optimize(
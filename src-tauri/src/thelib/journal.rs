@@ -0,0 +1,593 @@
+use crate::errors::BoxedError;
+use crate::thelib::file_count::path_size;
+use crate::thelib::file_path::{copy_dir_all, move_path};
+use crate::thelib::file_rename::{self, RenameItem};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+// older entries and their backups are removed, the newest entry is always kept
+const MAX_ENTRIES: usize = 100;
+const MAX_BACKUP_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+const BACKUPS_DIR_NAME: &str = "backups";
+
+/// One change on disk, with what is needed to revert it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum JournalOp {
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    /// `path` was written; `backup` holds the previous content, none if the file was created.
+    /// `written` is the stamp of the file right after the write, undo refuses to replace or
+    /// remove it once it changed. none if not recorded, e.g. the path is renamed over
+    Write {
+        path: PathBuf,
+        backup: Option<PathBuf>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        written: Option<FileStamp>,
+    },
+    /// `path` was deleted, the content is kept in `backup`
+    Delete {
+        path: PathBuf,
+        backup: PathBuf,
+    },
+    /// `path` was moved to the system trash
+    Trash {
+        path: PathBuf,
+    },
+    /// `path` was replaced by another file, e.g. a hardlink, the replaced one is kept in `backup`
+    Replace {
+        path: PathBuf,
+        backup: PathBuf,
+    },
+}
+
+/// Size and modified time of a file, to tell if it has changed since
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileStamp {
+    pub size: u64,
+    /// nanoseconds since the unix epoch
+    pub modified: u64,
+}
+
+impl FileStamp {
+    pub fn of<P: AsRef<Path>>(path: P) -> Result<Self, BoxedError> {
+        let meta = fs::metadata(path)?;
+        let modified = meta
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Ok(FileStamp {
+            size: meta.len(),
+            modified,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub id: String,
+    pub command: String,
+    pub created: String,
+    #[serde(default)]
+    pub undone: bool,
+    pub ops: Vec<JournalOp>,
+}
+
+impl JournalEntry {
+    pub fn add(&mut self, op: JournalOp) {
+        self.ops.push(op);
+    }
+}
+
+/// Operation journal of the mutating commands: one json file per command run,
+/// backups of overwritten or deleted paths are kept in `backups/<id>/`
+pub struct Journal {
+    dir: PathBuf,
+}
+
+impl Journal {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Journal {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// a new entry of `command`, saved by `save` once the ops are added.
+    /// ids start with a timestamp, so they sort by time
+    pub fn new_entry(&self, command: &str) -> JournalEntry {
+        let now = Local::now();
+        let id = format!(
+            "{}-{}",
+            now.format("%Y%m%d%H%M%S%3f"),
+            &uuid::Uuid::new_v4().simple().to_string()[..8]
+        );
+        JournalEntry {
+            id,
+            command: command.to_string(),
+            created: now.to_rfc3339(),
+            undone: false,
+            ops: Vec::new(),
+        }
+    }
+
+    fn entry_file(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    fn backup_dir(&self, id: &str) -> PathBuf {
+        self.dir.join(BACKUPS_DIR_NAME).join(id)
+    }
+
+    /// Keep a copy of `path` for the entry, or move it if `take` (the caller deletes it).
+    /// Returns the backup path
+    pub fn backup(
+        &self,
+        entry: &JournalEntry,
+        path: &Path,
+        take: bool,
+    ) -> Result<PathBuf, BoxedError> {
+        // one numbered sub dir per backup, the same name may be backed up several times
+        let root = self.backup_dir(&entry.id);
+        let index = fs::read_dir(&root).map(|rd| rd.count()).unwrap_or(0);
+        let dir = root.join(index.to_string());
+        fs::create_dir_all(&dir)?;
+        let name = path.file_name().ok_or("Invalid path to back up")?;
+        let backup = dir.join(name);
+
+        if take {
+            move_path(path, &backup)?;
+        } else if path.is_dir() {
+            copy_dir_all(path, &backup)?;
+        } else {
+            fs::copy(path, &backup)?;
+        }
+        Ok(backup)
+    }
+
+    /// Save the entry if it has ops. A failure is only logged,
+    /// the operation it records has already happened
+    pub fn save(&self, entry: &JournalEntry) {
+        if entry.ops.is_empty() {
+            let _ = fs::remove_dir_all(self.backup_dir(&entry.id));
+            return;
+        }
+        if let Err(e) = self.write_entry(entry) {
+            log::error!("Failed to save journal entry {}: {}", entry.id, e);
+            return;
+        }
+        if let Err(e) = self.prune() {
+            log::warn!("Failed to prune journal: {}", e);
+        }
+    }
+
+    fn write_entry(&self, entry: &JournalEntry) -> Result<(), BoxedError> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(
+            self.entry_file(&entry.id),
+            serde_json::to_string_pretty(entry)?,
+        )?;
+        Ok(())
+    }
+
+    fn entry_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = match fs::read_dir(&self.dir) {
+            Ok(rd) => rd
+                .filter_map(|e| e.ok())
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .filter_map(|n| n.strip_suffix(".json").map(|s| s.to_string()))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        ids.sort();
+        ids.reverse();
+        ids
+    }
+
+    fn prune(&self) -> Result<(), BoxedError> {
+        self.prune_to(MAX_ENTRIES, MAX_BACKUP_BYTES)
+    }
+
+    /// keep the newest entries while they are at most `max_entries` and their backups
+    /// take at most `max_backup_bytes`
+    fn prune_to(&self, max_entries: usize, max_backup_bytes: u64) -> Result<(), BoxedError> {
+        let mut backup_bytes: u64 = 0;
+        for (i, id) in self.entry_ids().iter().enumerate() {
+            let backup_dir = self.backup_dir(id);
+            if backup_dir.exists() {
+                backup_bytes += path_size(&backup_dir)?;
+            }
+            if i == 0 || (i < max_entries && backup_bytes <= max_backup_bytes) {
+                continue;
+            }
+            fs::remove_file(self.entry_file(id))?;
+            let _ = fs::remove_dir_all(backup_dir);
+        }
+        Ok(())
+    }
+
+    /// entries, newest first
+    pub fn list(&self) -> Vec<JournalEntry> {
+        self.entry_ids()
+            .iter()
+            .filter_map(|id| match self.load(id) {
+                Ok(e) => Some(e),
+                Err(e) => {
+                    log::warn!("Invalid journal entry {}: {}", id, e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// the newest entry of `command` which is not undone yet
+    pub fn latest(&self, command: &str) -> Option<JournalEntry> {
+        self.list()
+            .into_iter()
+            .find(|e| e.command == command && !e.undone)
+    }
+
+    pub fn load(&self, id: &str) -> Result<JournalEntry, BoxedError> {
+        if id.is_empty() || id.contains('/') || id.contains('\\') || id.contains("..") {
+            return Err(format!("Invalid journal id: '{}'", id).into());
+        }
+        let content = fs::read_to_string(self.entry_file(id))
+            .map_err(|_| format!("Journal entry not found: '{}'", id))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Revert the ops of an entry, last op first. Conflicts are checked before any change,
+    /// e.g. a deleted path that exists again. Returns the restored paths
+    pub fn undo(&self, id: &str) -> Result<Vec<PathBuf>, BoxedError> {
+        let mut entry = self.load(id)?;
+        if entry.undone {
+            return Err(format!("Journal entry already undone: '{}'", id).into());
+        }
+
+        let mut renames: Vec<RenameItem> = Vec::new();
+        for op in entry.ops.iter() {
+            // a written file is replaced or removed, it must be the one written.
+            // a created file without a stamp is never removed
+            if let JournalOp::Write {
+                path,
+                backup,
+                written,
+            } = op
+            {
+                if fs::symlink_metadata(path).is_ok() {
+                    let unchanged = match written {
+                        Some(stamp) => FileStamp::of(path).ok().as_ref() == Some(stamp),
+                        None => backup.is_some(),
+                    };
+                    if !unchanged {
+                        return Err(
+                            format!("Cannot undo, file changed since: {}", path.display()).into(),
+                        );
+                    }
+                }
+            }
+            match op {
+                JournalOp::Rename { from, to } => {
+                    if fs::symlink_metadata(to).is_err() {
+                        return Err(format!("Cannot undo, path not found: {}", to.display()).into());
+                    }
+                    renames.push(RenameItem::new(to, from, file_rename::STATUS_OK, ""));
+                }
+                JournalOp::Write {
+                    backup: Some(b), ..
+                }
+                | JournalOp::Delete { backup: b, .. }
+                | JournalOp::Replace { backup: b, .. } => {
+                    if !b.exists() {
+                        return Err(format!("Backup not found: {}", b.display()).into());
+                    }
+                    match op {
+                        JournalOp::Delete { path, .. } if fs::symlink_metadata(path).is_ok() => {
+                            return Err(
+                                format!("Cannot undo, path exists: {}", path.display()).into()
+                            );
+                        }
+                        JournalOp::Replace { path, .. } if path.is_dir() => {
+                            return Err(format!(
+                                "Cannot undo, path is a directory now: {}",
+                                path.display()
+                            )
+                            .into());
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+        file_rename::resolve_conflicts(&mut renames);
+
+        let mut restored: Vec<PathBuf> = Vec::new();
+        if renames.len() == 1 {
+            // may be across dirs or volumes
+            let item = &renames[0];
+            if item.status != file_rename::STATUS_OK {
+                return Err(format!("Cannot undo, {}: {}", item.message, item.to.display()).into());
+            }
+            move_path(&item.from, &item.to)?;
+            restored.push(item.to.clone());
+        } else if !renames.is_empty() {
+            for (_, to) in file_rename::apply_renames(&renames)? {
+                restored.push(to);
+            }
+        }
+
+        for op in entry.ops.iter().rev() {
+            match op {
+                JournalOp::Rename { .. } => {}
+                JournalOp::Write { path, backup, .. } => {
+                    match backup {
+                        Some(b) => {
+                            fs::copy(b, path)?;
+                        }
+                        None => {
+                            if path.is_file() {
+                                fs::remove_file(path)?;
+                            }
+                        }
+                    }
+                    restored.push(path.clone());
+                }
+                JournalOp::Delete { path, backup } => {
+                    if let Some(parent) = path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    move_path(backup, path)?;
+                    restored.push(path.clone());
+                }
+                JournalOp::Trash { path } => {
+                    restore_from_trash(path)?;
+                    restored.push(path.clone());
+                }
+                JournalOp::Replace { path, backup } => {
+                    // remove the replacement itself, not write through it into a linked file
+                    if fs::symlink_metadata(path).is_ok() {
+                        fs::remove_file(path)?;
+                    }
+                    move_path(backup, path)?;
+                    restored.push(path.clone());
+                }
+            }
+        }
+
+        entry.undone = true;
+        self.write_entry(&entry)?;
+        let _ = fs::remove_dir_all(self.backup_dir(&entry.id));
+
+        Ok(restored)
+    }
+}
+
+#[cfg(any(
+    target_os = "windows",
+    all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )
+))]
+fn restore_from_trash(path: &Path) -> Result<(), BoxedError> {
+    // the latest trashed item with the same original path
+    let item = trash::os_limited::list()?
+        .into_iter()
+        .filter(|i| i.original_path() == path)
+        .max_by_key(|i| i.time_deleted)
+        .ok_or(format!("Not found in trash: {}", path.display()))?;
+    trash::os_limited::restore_all([item])?;
+    Ok(())
+}
+
+#[cfg(not(any(
+    target_os = "windows",
+    all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )
+)))]
+fn restore_from_trash(path: &Path) -> Result<(), BoxedError> {
+    Err(format!(
+        "Restoring from trash is not supported on this platform, please put back {} from the trash manually",
+        path.display()
+    )
+    .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thelib::test_utils::TempDir;
+
+    #[test]
+    fn test_journal_undo() {
+        let tmp = TempDir::new("journal");
+        let dir = tmp.path().to_path_buf();
+        let journal = Journal::new(dir.join("journal"));
+        let a = dir.join("a.txt");
+        let b = dir.join("b.txt");
+        fs::write(&a, "a").unwrap();
+        fs::write(&b, "b").unwrap();
+
+        // overwrite a, delete b
+        let mut entry = journal.new_entry("test");
+        let backup = journal.backup(&entry, &a, false).unwrap();
+        fs::write(&a, "new").unwrap();
+        entry.add(JournalOp::Write {
+            path: a.clone(),
+            backup: Some(backup),
+            written: FileStamp::of(&a).ok(),
+        });
+        let backup = journal.backup(&entry, &b, true).unwrap();
+        entry.add(JournalOp::Delete {
+            path: b.clone(),
+            backup,
+        });
+        journal.save(&entry);
+        assert!(!b.exists());
+
+        // swap a <-> b, in a newer entry (ids sort by milliseconds)
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        fs::write(&b, "b2").unwrap();
+        let items = vec![
+            RenameItem::new(&a, &b, file_rename::STATUS_OK, ""),
+            RenameItem::new(&b, &a, file_rename::STATUS_OK, ""),
+        ];
+        let mut swap = journal.new_entry("test");
+        for (from, to) in file_rename::apply_renames(&items).unwrap() {
+            swap.add(JournalOp::Rename { from, to });
+        }
+        journal.save(&swap);
+        assert_eq!(fs::read_to_string(&a).unwrap(), "b2");
+
+        let entries = journal.list();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].id, swap.id);
+
+        journal.undo(&swap.id).unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "new");
+        assert!(journal.undo(&swap.id).is_err());
+
+        // b exists again
+        assert!(journal.undo(&entry.id).is_err());
+        fs::remove_file(&b).unwrap();
+        journal.undo(&entry.id).unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "a");
+        assert_eq!(fs::read_to_string(&b).unwrap(), "b");
+    }
+
+    #[test]
+    fn test_rename_batch_undo() {
+        let tmp = TempDir::new("journal-rename");
+        let dir = tmp.path().to_path_buf();
+        let journal = Journal::new(dir.join("journal"));
+        let names = ["a.txt", "b.txt", "c.txt"];
+        for name in names {
+            fs::write(dir.join(name), name).unwrap();
+        }
+
+        // a -> b -> c -> a, then an unrelated newer entry
+        let mut items: Vec<RenameItem> = (0..3)
+            .map(|i| {
+                let from = dir.join(names[i]);
+                let to = dir.join(names[(i + 1) % 3]);
+                RenameItem::new(&from, &to, file_rename::STATUS_OK, "")
+            })
+            .collect();
+        file_rename::resolve_conflicts(&mut items);
+        let mut entry = journal.new_entry("files.rename_batch");
+        for (from, to) in file_rename::apply_renames(&items).unwrap() {
+            entry.add(JournalOp::Rename { from, to });
+        }
+        journal.save(&entry);
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let mut other = journal.new_entry("file.write_text");
+        other.add(JournalOp::Write {
+            path: dir.join("other.txt"),
+            backup: None,
+            written: None,
+        });
+        journal.save(&other);
+        assert_eq!(fs::read_to_string(dir.join("b.txt")).unwrap(), "a.txt");
+
+        let latest = journal.latest("files.rename_batch").unwrap();
+        assert_eq!(latest.id, entry.id);
+        let restored = journal.undo(&latest.id).unwrap();
+        assert_eq!(restored.len(), 3);
+        for name in names {
+            assert_eq!(fs::read_to_string(dir.join(name)).unwrap(), name);
+        }
+        assert!(journal.latest("files.rename_batch").is_none());
+        assert_eq!(journal.latest("file.write_text").unwrap().id, other.id);
+    }
+
+    #[test]
+    fn test_write_undo_conflict() {
+        let tmp = TempDir::new("journal-write");
+        let dir = tmp.path().to_path_buf();
+        let journal = Journal::new(dir.join("journal"));
+        let a = dir.join("a.txt");
+        let b = dir.join("b.txt");
+
+        // a is changed after it was created, b is not
+        let mut ids = Vec::new();
+        for path in [&a, &b] {
+            fs::write(path, "created").unwrap();
+            let mut entry = journal.new_entry("test");
+            entry.add(JournalOp::Write {
+                path: path.clone(),
+                backup: None,
+                written: FileStamp::of(path).ok(),
+            });
+            journal.save(&entry);
+            ids.push(entry.id);
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        fs::write(&a, "changed later").unwrap();
+
+        assert!(journal.undo(&ids[0]).is_err());
+        assert_eq!(fs::read_to_string(&a).unwrap(), "changed later");
+        journal.undo(&ids[1]).unwrap();
+        assert!(!b.exists());
+
+        // a created file without a stamp is kept
+        fs::write(&b, "created").unwrap();
+        let mut entry = journal.new_entry("test");
+        entry.add(JournalOp::Write {
+            path: b.clone(),
+            backup: None,
+            written: None,
+        });
+        journal.save(&entry);
+        assert!(journal.undo(&entry.id).is_err());
+        assert!(b.exists());
+    }
+
+    #[test]
+    fn test_prune() {
+        let tmp = TempDir::new("journal-prune");
+        let dir = tmp.path().to_path_buf();
+        let journal = Journal::new(dir.join("journal"));
+        // entries deleting files of these sizes, oldest first
+        let add_entries = |sizes: &[usize]| -> Vec<String> {
+            let mut ids = Vec::new();
+            for size in sizes {
+                let file = dir.join(format!("{}.bin", ids.len()));
+                fs::write(&file, vec![0u8; *size]).unwrap();
+                let mut entry = journal.new_entry("test");
+                let backup = journal.backup(&entry, &file, true).unwrap();
+                entry.add(JournalOp::Delete { path: file, backup });
+                journal.write_entry(&entry).unwrap();
+                ids.push(entry.id);
+                std::thread::sleep(std::time::Duration::from_millis(2));
+            }
+            ids
+        };
+        let kept = || -> Vec<String> { journal.list().into_iter().map(|e| e.id).collect() };
+
+        // the oldest one is over the size limit
+        let ids = add_entries(&[10, 1, 1, 1]);
+        journal.prune_to(10, 5).unwrap();
+        assert_eq!(kept(), vec![ids[3].clone(), ids[2].clone(), ids[1].clone()]);
+        assert!(!journal.backup_dir(&ids[0]).exists());
+
+        journal.prune_to(2, 100).unwrap();
+        assert_eq!(kept(), vec![ids[3].clone(), ids[2].clone()]);
+
+        // the newest one is kept even if it is too big alone
+        let ids = add_entries(&[20]);
+        journal.prune_to(10, 5).unwrap();
+        assert_eq!(kept(), ids);
+    }
+}
@@ -1,17 +1,30 @@
 pub mod compress;
+pub mod disk_usage;
 pub mod download;
 pub mod exiftool;
 pub mod file_attribute;
 pub mod file_binary;
 pub mod file_count;
+pub mod file_duplicate;
 pub mod file_find;
 pub mod file_path;
+pub mod file_rename;
+pub mod file_type;
+pub mod file_watcher;
+pub mod glob;
 pub mod hash;
 pub mod image;
 pub mod image_rmbg;
+pub mod journal;
 pub mod json;
+pub mod path_matcher;
+pub mod safe_write;
 pub mod shell;
 pub mod svg;
 pub mod sys;
+pub mod task;
+#[cfg(test)]
+pub mod test_utils;
 pub mod text;
 pub mod version;
+pub mod walk;
@@ -0,0 +1,151 @@
+use crate::errors::BoxedError;
+use crate::thelib::glob::glob_to_regex;
+use regex::Regex;
+
+/// Regexes and globs matched against the file name or the path of an entry
+#[derive(Debug, Clone, Default)]
+pub struct Patterns {
+    pub names: Vec<Regex>,
+    /// regexes, matched against the full path
+    pub paths: Vec<Regex>,
+    /// globs, matched against the path relative to the searched dir or the full path,
+    /// both with `/` separators
+    pub path_globs: Vec<Regex>,
+}
+
+impl Patterns {
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.paths.is_empty() && self.path_globs.is_empty()
+    }
+
+    pub fn add_name_regex(&mut self, pattern: &str) -> Result<(), BoxedError> {
+        self.names.push(new_regex(pattern)?);
+        Ok(())
+    }
+
+    pub fn add_path_regex(&mut self, pattern: &str) -> Result<(), BoxedError> {
+        self.paths.push(new_regex(pattern)?);
+        Ok(())
+    }
+
+    pub fn add_name_glob(&mut self, pattern: &str) -> Result<(), BoxedError> {
+        self.names.push(glob_to_regex(pattern)?);
+        Ok(())
+    }
+
+    pub fn add_path_glob(&mut self, pattern: &str) -> Result<(), BoxedError> {
+        self.path_globs.push(glob_to_regex(pattern)?);
+        Ok(())
+    }
+
+    /// like .gitignore: a pattern with a `/` is a path glob, otherwise a name glob
+    pub fn add_glob(&mut self, pattern: &str) -> Result<(), BoxedError> {
+        match pattern.trim_start_matches('/').contains('/') {
+            true => self.add_path_glob(pattern.trim_start_matches('/')),
+            false => self.add_name_glob(pattern.trim_start_matches('/')),
+        }
+    }
+
+    pub fn is_match(&self, name: &str, rel_path: &str, full_path: &str) -> bool {
+        if self.names.iter().any(|r| r.is_match(name))
+            || self.paths.iter().any(|r| r.is_match(full_path))
+        {
+            return true;
+        }
+        if self.path_globs.is_empty() {
+            return false;
+        }
+        let full_path = full_path.replace('\\', "/");
+        self.path_globs
+            .iter()
+            .any(|r| r.is_match(rel_path) || r.is_match(&full_path))
+    }
+}
+
+fn new_regex(pattern: &str) -> Result<Regex, BoxedError> {
+    match Regex::new(pattern) {
+        Ok(r) => Ok(r),
+        Err(e) => Err(format!("Invalid regex: '{}', error: {}", pattern, e).into()),
+    }
+}
+
+/// File selection by include and exclude patterns. An entry matches if there are
+/// no includes or one matches, and no exclude matches the entry or a dir above it
+#[derive(Debug, Clone, Default)]
+pub struct PathMatcher {
+    pub includes: Patterns,
+    pub excludes: Patterns,
+}
+
+impl PathMatcher {
+    /// `rel_path` is relative to `root`, with `/` separators
+    pub fn is_match(&self, root: &str, rel_path: &str) -> bool {
+        let root = root.trim_end_matches(['/', '\\']);
+        let full_path = |rel: &str| match rel.is_empty() {
+            true => root.to_string(),
+            false => format!("{}{}{}", root, std::path::MAIN_SEPARATOR, rel),
+        };
+        let name = rel_path.rsplit('/').next().unwrap_or("");
+
+        if !self.includes.is_empty()
+            && !self.includes.is_match(name, rel_path, &full_path(rel_path))
+        {
+            return false;
+        }
+        if self.excludes.is_empty() {
+            return true;
+        }
+
+        // the entry and each dir above it, up to the root
+        let mut rel = rel_path;
+        while !rel.is_empty() {
+            let name = rel.rsplit('/').next().unwrap_or("");
+            if self.excludes.is_match(name, rel, &full_path(rel)) {
+                return false;
+            }
+            rel = rel.rsplit_once('/').map(|(parent, _)| parent).unwrap_or("");
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_matcher() {
+        let mut matcher = PathMatcher::default();
+        matcher.includes.add_glob("*.tmp").unwrap();
+        matcher.excludes.add_glob("keep").unwrap();
+        assert!(matcher.is_match("/data", "a.tmp"));
+        assert!(matcher.is_match("/data", "x/y/a.tmp"));
+        assert!(!matcher.is_match("/data", "a.txt"));
+        assert!(!matcher.is_match("/data", "keep/a.tmp"));
+        assert!(!matcher.is_match("/data", "x/keep/y/a.tmp"));
+
+        // a dot in a regex matches any char, not in a glob
+        let mut matcher = PathMatcher::default();
+        matcher.includes.add_name_glob(".DS_Store").unwrap();
+        assert!(matcher.is_match("/data", "x/.DS_Store"));
+        assert!(!matcher.is_match("/data", "x/aDS_Store"));
+
+        let mut matcher = PathMatcher::default();
+        matcher.includes.add_path_glob("cache/**/*.bin").unwrap();
+        matcher
+            .excludes
+            .add_path_regex(r"[/\\]cache[/\\]keep$")
+            .unwrap();
+        assert!(matcher.is_match("/data/", "cache/a/b.bin"));
+        assert!(!matcher.is_match("/data/", "b.bin"));
+        assert!(!matcher.is_match("/data/", "cache/keep/b.bin"));
+
+        // full path glob
+        let mut matcher = PathMatcher::default();
+        matcher.includes.add_path_glob("/data/**/*.log").unwrap();
+        assert!(matcher.is_match("/data", "a/b.log"));
+        assert!(!matcher.is_match("/other", "a/b.log"));
+
+        assert!(Patterns::default().add_name_regex("(").is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use crate::errors::BoxedError;
+use crate::thelib::file_path::{get_unique_filepath, make_parent_dirs};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// reported as the command status, "ok" if the target did not exist
+pub const WRITE_STATUS_OK: &str = "ok";
+pub const WRITE_STATUS_OVERWRITTEN: &str = "overwritten";
+pub const WRITE_STATUS_SKIPPED: &str = "skipped";
+pub const WRITE_STATUS_RENAMED: &str = "renamed";
+
+/// What to do if the target file exists
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictPolicy {
+    Overwrite,
+    Skip,
+    /// write to a new name, see `get_unique_filepath`
+    Rename,
+    Error,
+}
+
+impl ConflictPolicy {
+    pub fn from_name(name: &str) -> Result<Self, BoxedError> {
+        match name.to_lowercase().as_str() {
+            "overwrite" => Ok(ConflictPolicy::Overwrite),
+            "skip" => Ok(ConflictPolicy::Skip),
+            "rename" => Ok(ConflictPolicy::Rename),
+            "error" => Ok(ConflictPolicy::Error),
+            _ => Err(format!(
+                "Invalid conflict policy: '{}'. Must be 'overwrite', 'skip', 'rename' or 'error'",
+                name
+            )
+            .into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WriteOutcome {
+    /// the file written, or the existing file if skipped
+    pub path: PathBuf,
+    pub status: &'static str,
+}
+
+impl WriteOutcome {
+    pub fn is_skipped(&self) -> bool {
+        self.status == WRITE_STATUS_SKIPPED
+    }
+}
+
+/// Apply the policy to the target path, without writing anything
+pub fn resolve_conflict(path: &Path, policy: ConflictPolicy) -> Result<WriteOutcome, BoxedError> {
+    if !path.exists() {
+        return Ok(WriteOutcome {
+            path: path.to_path_buf(),
+            status: WRITE_STATUS_OK,
+        });
+    }
+    if path.is_dir() {
+        return Err(format!("Target is a directory: {}", path.display()).into());
+    }
+
+    match policy {
+        ConflictPolicy::Overwrite => Ok(WriteOutcome {
+            path: path.to_path_buf(),
+            status: WRITE_STATUS_OVERWRITTEN,
+        }),
+        ConflictPolicy::Skip => Ok(WriteOutcome {
+            path: path.to_path_buf(),
+            status: WRITE_STATUS_SKIPPED,
+        }),
+        ConflictPolicy::Rename => {
+            let path_str = path.to_str().ok_or("Invalid file path")?;
+            let new_path = get_unique_filepath(path_str, None, None)?;
+            Ok(WriteOutcome {
+                path: PathBuf::from(new_path),
+                status: WRITE_STATUS_RENAMED,
+            })
+        }
+        ConflictPolicy::Error => Err(format!("File already exists: {}", path.display()).into()),
+    }
+}
+
+/// Write to a sibling temp file, fsync it, then rename it over the target,
+/// so the target is either the old or the complete new content
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<(), BoxedError> {
+    make_parent_dirs(path)?;
+    let name = path
+        .file_name()
+        .ok_or(format!("Invalid file path: {}", path.display()))?;
+    let temp_path = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        name.to_string_lossy(),
+        &uuid::Uuid::new_v4().simple().to_string()[..8]
+    ));
+
+    if let Err(e) = write_and_rename(&temp_path, path, data) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(format!("Failed to write {}: {}", path.display(), e).into());
+    }
+
+    // persist the rename itself
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        if let Ok(dir) = fs::File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+
+    Ok(())
+}
+
+fn write_and_rename(temp_path: &Path, path: &Path, data: &[u8]) -> Result<(), BoxedError> {
+    let mut file = fs::File::create(temp_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    // keep the permissions of the replaced file
+    if let Ok(meta) = fs::metadata(path) {
+        fs::set_permissions(temp_path, meta.permissions())?;
+    }
+    fs::rename(temp_path, path)?;
+    Ok(())
+}
+
+/// Write data to path with the conflict policy, see `resolve_conflict` and `write_atomic`
+pub fn safe_write(
+    path: &Path,
+    data: &[u8],
+    policy: ConflictPolicy,
+) -> Result<WriteOutcome, BoxedError> {
+    let outcome = resolve_conflict(path, policy)?;
+    if !outcome.is_skipped() {
+        write_atomic(&outcome.path, data)?;
+    }
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thelib::test_utils::TempDir;
+
+    #[test]
+    fn test_safe_write() {
+        let tmp = TempDir::new("safe-write");
+        // not created yet, written files create their parent dir
+        let dir = tmp.path().join("out");
+        let file = dir.join("a.txt");
+
+        let outcome = safe_write(&file, b"1", ConflictPolicy::Error).unwrap();
+        assert_eq!(outcome.status, WRITE_STATUS_OK);
+        assert!(safe_write(&file, b"2", ConflictPolicy::Error).is_err());
+
+        let outcome = safe_write(&file, b"2", ConflictPolicy::Skip).unwrap();
+        assert!(outcome.is_skipped());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "1");
+
+        let outcome = safe_write(&file, b"3", ConflictPolicy::Rename).unwrap();
+        assert_eq!(outcome.status, WRITE_STATUS_RENAMED);
+        assert_eq!(outcome.path, dir.join("a-1.txt"));
+        assert_eq!(fs::read_to_string(&outcome.path).unwrap(), "3");
+
+        let outcome = safe_write(&file, b"4", ConflictPolicy::Overwrite).unwrap();
+        assert_eq!(outcome.status, WRITE_STATUS_OVERWRITTEN);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "4");
+        // no temp file left
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+    }
+}
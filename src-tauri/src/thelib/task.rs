@@ -0,0 +1,296 @@
+use crate::errors::BoxedError;
+use lazy_static::lazy_static;
+use once_cell::sync::OnceCell;
+use serde_json::{json, Value as JsonValue};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// min interval between two progress events of a task, to avoid flooding the front-end
+const PROGRESS_EMIT_INTERVAL: Duration = Duration::from_millis(100);
+
+type ProgressEmitter = Box<dyn Fn(&JsonValue) + Send + Sync>;
+
+lazy_static! {
+    // key is task id, value is the cancel flag of the running task
+    static ref RUNNING_TASKS: Arc<Mutex<HashMap<String, Arc<AtomicBool>>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+}
+
+// where progress payloads go, set by the GUI app (e.g. emit a front-end event)
+static PROGRESS_EMITTER: OnceCell<ProgressEmitter> = OnceCell::new();
+
+tokio::task_local! {
+    static CURRENT_TASK: TaskContext;
+}
+
+/// Handle of a running task, available to the command code by `current_task()`
+#[derive(Clone)]
+pub struct TaskContext {
+    pub id: String,
+    pub command: String,
+    cancelled: Arc<AtomicBool>,
+    last_emit: Arc<Mutex<Option<Instant>>>,
+}
+
+impl TaskContext {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// return an error if the task has been cancelled, use it with `?` in loops
+    pub fn check_cancelled(&self) -> Result<(), BoxedError> {
+        if self.is_cancelled() {
+            log::info!("Task cancelled: {} ({})", self.id, self.command);
+            return Err(format!("Task cancelled: {}", self.id).into());
+        }
+        Ok(())
+    }
+
+    /// report progress, `total` is 0 if unknown. unit: "bytes" or "items"
+    pub fn report_progress(&self, done: u64, total: u64, unit: &str) {
+        // throttle, but always emit the final progress
+        if total == 0 || done < total {
+            if let Ok(mut last_emit) = self.last_emit.lock() {
+                if let Some(t) = *last_emit {
+                    if t.elapsed() < PROGRESS_EMIT_INTERVAL {
+                        return;
+                    }
+                }
+                *last_emit = Some(Instant::now());
+            }
+        }
+
+        emit(&json!({
+            "task_id": self.id,
+            "command": self.command,
+            "status": "running",
+            "done": done,
+            "total": total,
+            "unit": unit,
+        }));
+    }
+}
+
+/// Set the receiver of task progress payloads, can only be set once
+pub fn set_progress_emitter<F>(emitter: F)
+where
+    F: Fn(&JsonValue) + Send + Sync + 'static,
+{
+    if PROGRESS_EMITTER.set(Box::new(emitter)).is_err() {
+        log::warn!("Task progress emitter has been set already");
+    }
+}
+
+fn emit(payload: &JsonValue) {
+    if let Some(emitter) = PROGRESS_EMITTER.get() {
+        emitter(payload);
+    }
+}
+
+/// Get the task the current code is running in, `None` if not in a task
+pub fn current_task() -> Option<TaskContext> {
+    CURRENT_TASK.try_with(|t| t.clone()).ok()
+}
+
+/// Report progress of the current task, do nothing if not in a task
+pub fn report_progress(done: u64, total: u64, unit: &str) {
+    if let Some(task) = current_task() {
+        task.report_progress(done, total, unit);
+    }
+}
+
+/// Return an error if the current task has been cancelled
+pub fn check_cancelled() -> Result<(), BoxedError> {
+    match current_task() {
+        Some(task) => task.check_cancelled(),
+        None => Ok(()),
+    }
+}
+
+/// Removes the task from `RUNNING_TASKS` when the run ends, also if the future panics or is dropped
+struct RunningTaskGuard<'a> {
+    task_id: &'a str,
+}
+
+impl Drop for RunningTaskGuard<'_> {
+    fn drop(&mut self) {
+        match RUNNING_TASKS.lock() {
+            Ok(mut tasks) => {
+                tasks.remove(self.task_id);
+            }
+            Err(e) => log::error!("Failed to lock RUNNING_TASKS: {}", e),
+        }
+    }
+}
+
+/// Run the future as a registered task, so it can be cancelled by `cancel_task(task_id)`.
+/// A `started` status is emitted first, with the task id for callers which didn't set it
+pub async fn run_as_task<F, T>(task_id: &str, command: &str, fut: F) -> Result<T, BoxedError>
+where
+    F: Future<Output = Result<T, BoxedError>>,
+{
+    let cancelled = Arc::new(AtomicBool::new(false));
+    match RUNNING_TASKS.lock() {
+        Ok(mut tasks) => {
+            if tasks.contains_key(task_id) {
+                return Err(format!("Task already running: {}", task_id).into());
+            }
+            tasks.insert(task_id.to_string(), cancelled.clone());
+        }
+        Err(e) => {
+            log::error!("Failed to lock RUNNING_TASKS: {}", e);
+            return Err("Failed to lock RUNNING_TASKS".into());
+        }
+    }
+    let guard = RunningTaskGuard { task_id };
+    emit(&json!({
+        "task_id": task_id,
+        "command": command,
+        "status": "started",
+    }));
+
+    let task = TaskContext {
+        id: task_id.to_string(),
+        command: command.to_string(),
+        cancelled: cancelled.clone(),
+        last_emit: Arc::new(Mutex::new(None)),
+    };
+    let result = CURRENT_TASK.scope(task, fut).await;
+    drop(guard);
+
+    let status = if cancelled.load(Ordering::Relaxed) {
+        "cancelled"
+    } else if result.is_ok() {
+        "finished"
+    } else {
+        "failed"
+    };
+    emit(&json!({
+        "task_id": task_id,
+        "command": command,
+        "status": status,
+    }));
+
+    result
+}
+
+/// Run a sync function on the blocking thread pool, inside the current task if any, so it
+/// can still check cancellation and report progress without stalling the async runtime
+pub async fn run_blocking<F, T>(f: F) -> Result<T, BoxedError>
+where
+    F: FnOnce() -> Result<T, BoxedError> + Send + 'static,
+    T: Send + 'static,
+{
+    let task = current_task();
+    // BoxedError is not Send, errors cross the thread as strings
+    let joined = tokio::task::spawn_blocking(move || {
+        let result = match task {
+            Some(task) => CURRENT_TASK.sync_scope(task, f),
+            None => f(),
+        };
+        result.map_err(|e| e.to_string())
+    })
+    .await;
+
+    match joined {
+        Ok(result) => result.map_err(|e| e.into()),
+        Err(e) => Err(format!("Blocking task failed: {}", e).into()),
+    }
+}
+
+/// Request a running task to stop, returns false if the task is not found (e.g. already finished)
+pub fn cancel_task(task_id: &str) -> Result<bool, BoxedError> {
+    let tasks = match RUNNING_TASKS.lock() {
+        Ok(t) => t,
+        Err(_) => return Err("Failed to lock RUNNING_TASKS".into()),
+    };
+    match tasks.get(task_id) {
+        Some(cancelled) => {
+            cancelled.store(true, Ordering::Relaxed);
+            log::info!("Cancel task requested: {}", task_id);
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// ids of running tasks
+pub fn list_tasks() -> Result<Vec<String>, BoxedError> {
+    let tasks = match RUNNING_TASKS.lock() {
+        Ok(t) => t,
+        Err(_) => return Err("Failed to lock RUNNING_TASKS".into()),
+    };
+    let mut ids: Vec<String> = tasks.keys().cloned().collect();
+    ids.sort();
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cancel_task() {
+        let handle = tokio::spawn(async {
+            let result: Result<u64, BoxedError> = run_as_task("test-task", "test", async {
+                let mut n = 0;
+                loop {
+                    check_cancelled()?;
+                    report_progress(n, 0, "items");
+                    n += 1;
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                }
+            })
+            .await;
+            result.is_err()
+        });
+
+        // wait for the task to be registered
+        while !list_tasks().unwrap().contains(&"test-task".to_string()) {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert!(cancel_task("test-task").unwrap());
+        assert!(handle.await.unwrap());
+        assert!(!cancel_task("test-task").unwrap());
+        assert!(current_task().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_run_blocking_in_task() {
+        let result = run_as_task("test-blocking", "test", async {
+            run_blocking(|| {
+                let task = current_task().ok_or("not in a task")?;
+                Ok(task.id)
+            })
+            .await
+        })
+        .await
+        .unwrap();
+        assert_eq!(result, "test-blocking");
+
+        let err = run_blocking(|| -> Result<(), BoxedError> { Err("failed".into()) })
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "failed");
+    }
+
+    #[tokio::test]
+    async fn test_panicked_task_is_removed() {
+        let handle = tokio::spawn(async {
+            let _: Result<(), BoxedError> = run_as_task("test-panic", "test", async {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                panic!("task panicked");
+            })
+            .await;
+        });
+
+        while !list_tasks().unwrap().contains(&"test-panic".to_string()) {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert!(handle.await.is_err());
+        assert!(!list_tasks().unwrap().contains(&"test-panic".to_string()));
+    }
+}
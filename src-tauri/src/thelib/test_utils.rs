@@ -0,0 +1,27 @@
+//! Helpers shared by the tests of `thelib`
+
+use std::path::{Path, PathBuf};
+
+/// A new dir in the system temp dir, removed on drop, also when an assertion fails
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// `ft-<name>-test-<uuid>`
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("ft-{}-test-{}", name, uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
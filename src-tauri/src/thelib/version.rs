@@ -53,6 +53,46 @@ pub fn compare_semver(ver1: &str, operator: &str, ver2: &str) -> Result<bool, Bo
     Ok(result)
 }
 
+/// Whether `new_version` is newer than `current_version`. Versions are semver, or numbers
+/// (date versions like `20240722`); a number is compared with a semver as `<number>.0.0`.
+/// An invalid version is never newer, nor is anything newer than it
+pub fn is_newer_version(new_version: &str, current_version: &str) -> bool {
+    let (new_version, current_version) = (new_version.trim(), current_version.trim());
+    if let (Ok(new), Ok(current)) = (new_version.parse::<u64>(), current_version.parse::<u64>()) {
+        return new > current;
+    }
+    let as_semver = |v: &str| match v.parse::<u64>() {
+        Ok(n) => format!("{}.0.0", n),
+        Err(_) => v.to_string(),
+    };
+    match compare_semver(&as_semver(new_version), ">", &as_semver(current_version)) {
+        Ok(newer) => newer,
+        Err(e) => {
+            log::warn!(
+                "Can't compare versions '{}' and '{}': {}",
+                new_version,
+                current_version,
+                e
+            );
+            false
+        }
+    }
+}
+
+/// The default pattern of a version in the output of a tool, like "ffmpeg version 6.1.1"
+pub const DEFAULT_VERSION_PATTERN: &str = r"\d+\.\d+(?:\.\d+)?";
+
+/// Find the version in `output` by `pattern`: the 1st capture group if any, otherwise the match
+pub fn find_version(output: &str, pattern: &str) -> Result<Option<String>, BoxedError> {
+    let re = Regex::new(pattern)?;
+    let version = re
+        .captures(output)
+        .and_then(|caps| caps.get(1).or_else(|| caps.get(0)))
+        .map(|m| m.as_str().trim().to_string())
+        .filter(|v| !v.is_empty());
+    Ok(version)
+}
+
 // test compare_semver
 #[cfg(test)]
 mod tests {
@@ -83,4 +123,41 @@ mod tests {
         assert_eq!(compare_semver("1.2", "<", "1.2.1").unwrap(), true);
         assert_eq!(compare_semver("1.2", ">", "1.2.0").unwrap(), false);
     }
+
+    #[test]
+    fn test_is_newer_version() {
+        assert!(is_newer_version("1.10.0", "1.9.0"));
+        assert!(!is_newer_version("1.2", "1.2.0"));
+        assert!(is_newer_version("20241012", "20240722"));
+        assert!(is_newer_version("20240722", "0"));
+        assert!(!is_newer_version("0", "0"));
+        assert!(is_newer_version("1.2.3", "0"));
+        assert!(!is_newer_version("0", "1.2.3"));
+        // no string comparison of invalid versions
+        assert!(!is_newer_version("latest", "1.2.3"));
+        assert!(!is_newer_version("1.2.3", "unknown"));
+    }
+
+    #[test]
+    fn test_find_version() {
+        let output = "ffmpeg version 6.1.1 Copyright (c) 2000-2023 the FFmpeg developers";
+        assert_eq!(
+            find_version(output, DEFAULT_VERSION_PATTERN).unwrap(),
+            Some("6.1.1".to_string())
+        );
+        assert_eq!(
+            find_version("12.76\n", DEFAULT_VERSION_PATTERN).unwrap(),
+            Some("12.76".to_string())
+        );
+        let output = "Version: ImageMagick 7.1.1-29 Q16-HDRI aarch64 (2024 Q3)";
+        assert_eq!(
+            find_version(output, r"ImageMagick (\d+\.\d+\.\d+)").unwrap(),
+            Some("7.1.1".to_string())
+        );
+        assert_eq!(
+            find_version("no version", DEFAULT_VERSION_PATTERN).unwrap(),
+            None
+        );
+        assert!(find_version("1.0", "(").is_err());
+    }
 }
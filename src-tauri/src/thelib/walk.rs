@@ -0,0 +1,567 @@
+use crate::errors::BoxedError;
+use crate::thelib::glob::glob_to_regex;
+use crate::thelib::task::check_cancelled;
+use regex::Regex;
+use serde_json::{json, Value as JsonValue};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const KIND_FILE: &str = "file";
+pub const KIND_DIR: &str = "dir";
+pub const KIND_SYMLINK: &str = "symlink";
+pub const KIND_OTHER: &str = "other";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymlinkPolicy {
+    /// leave links out
+    Skip,
+    /// list the link itself, do not follow it
+    List,
+    /// walk into the target; a dir already walked is not walked again, so loops end
+    Follow,
+}
+
+impl SymlinkPolicy {
+    pub fn from_name(name: &str) -> Result<Self, BoxedError> {
+        match name {
+            "skip" => Ok(SymlinkPolicy::Skip),
+            "list" => Ok(SymlinkPolicy::List),
+            "follow" => Ok(SymlinkPolicy::Follow),
+            _ => Err(format!(
+                "Invalid symlink policy: '{}'. Must be 'skip', 'list' or 'follow'",
+                name
+            )
+            .into()),
+        }
+    }
+}
+
+/// Options of `walk`. Filters apply to entries below the root, the root is only
+/// filtered by `files` and `dirs`
+#[derive(Debug, Clone)]
+pub struct WalkOptions {
+    /// entries shallower than this are walked but not returned; the root is depth 0
+    pub min_depth: usize,
+    pub max_depth: Option<usize>,
+    /// matched against the path relative to the root, with `/` separators.
+    /// if not empty, only matching entries are returned (dirs are still walked)
+    pub includes: Vec<Regex>,
+    /// matching entries are left out, and matching dirs are not walked
+    pub excludes: Vec<Regex>,
+    /// names starting with `.`
+    pub hidden: bool,
+    pub gitignore: bool,
+    pub symlinks: SymlinkPolicy,
+    pub files: bool,
+    pub dirs: bool,
+    /// size and mtime filters apply to files only
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub modified_after: Option<SystemTime>,
+    pub modified_before: Option<SystemTime>,
+    /// resume after this relative path: entries up to it in walk order are left out,
+    /// and dirs entirely before it are not walked
+    pub after: Option<String>,
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        WalkOptions {
+            min_depth: 0,
+            max_depth: None,
+            includes: Vec::new(),
+            excludes: Vec::new(),
+            hidden: true,
+            gitignore: false,
+            symlinks: SymlinkPolicy::List,
+            files: true,
+            dirs: true,
+            min_size: None,
+            max_size: None,
+            modified_after: None,
+            modified_before: None,
+            after: None,
+        }
+    }
+}
+
+impl WalkOptions {
+    pub fn add_include_glob(&mut self, pattern: &str) -> Result<(), BoxedError> {
+        self.includes.push(glob_to_regex(pattern)?);
+        Ok(())
+    }
+
+    pub fn add_exclude_glob(&mut self, pattern: &str) -> Result<(), BoxedError> {
+        self.excludes.push(glob_to_regex(pattern)?);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WalkEntry {
+    pub path: PathBuf,
+    /// relative to the root with `/` separators, empty for the root
+    pub rel_path: String,
+    pub depth: usize,
+    pub kind: &'static str,
+    /// 0 for dirs
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl WalkEntry {
+    pub fn is_file(&self) -> bool {
+        self.kind == KIND_FILE
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.kind == KIND_DIR
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let mtime = self
+            .modified
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+        json!({
+            "path": self.path.to_string_lossy(),
+            "rel_path": self.rel_path,
+            "type": self.kind,
+            "size": self.size,
+            "mtime": mtime,
+        })
+    }
+}
+
+/// one line of a .gitignore, relative to the dir of the file
+#[derive(Debug, Clone)]
+struct IgnoreRule {
+    base: String,
+    regex: Regex,
+    /// pattern has a `/` (besides a trailing one): matched against the path below `base`,
+    /// otherwise against the name at any level
+    anchored: bool,
+    negate: bool,
+    dir_only: bool,
+}
+
+fn load_gitignore(dir: &Path, base: &str) -> Vec<IgnoreRule> {
+    let content = match fs::read_to_string(dir.join(".gitignore")) {
+        Ok(c) => c,
+        Err(_) => return Vec::new(),
+    };
+    let mut rules = Vec::new();
+    for line in content.lines() {
+        let mut pattern = line.trim_end();
+        if pattern.is_empty() || pattern.starts_with('#') {
+            continue;
+        }
+        let negate = pattern.starts_with('!');
+        if negate {
+            pattern = &pattern[1..];
+        }
+        let dir_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        let anchored = pattern.contains('/');
+        let pattern = pattern.trim_start_matches('/');
+        match glob_to_regex(pattern) {
+            Ok(regex) => rules.push(IgnoreRule {
+                base: base.to_string(),
+                regex,
+                anchored,
+                negate,
+                dir_only,
+            }),
+            Err(e) => log::warn!("Ignore .gitignore pattern in {:?}: {}", dir, e),
+        }
+    }
+    rules
+}
+
+fn is_ignored(rules: &[IgnoreRule], rel_path: &str, name: &str, is_dir: bool) -> bool {
+    let mut ignored = false;
+    for rule in rules {
+        if rule.dir_only && !is_dir {
+            continue;
+        }
+        let sub_path = if rule.base.is_empty() {
+            rel_path
+        } else {
+            match rel_path.strip_prefix(&rule.base) {
+                Some(p) => p.trim_start_matches('/'),
+                None => continue,
+            }
+        };
+        let target = if rule.anchored { sub_path } else { name };
+        if rule.regex.is_match(target) {
+            ignored = !rule.negate;
+        }
+    }
+    ignored
+}
+
+/// compare relative paths in walk order: name by name, a dir comes before its children
+fn cmp_walk_order(a: &str, b: &str) -> Ordering {
+    let names_a = a.split('/').filter(|n| !n.is_empty());
+    let names_b = b.split('/').filter(|n| !n.is_empty());
+    names_a.cmp(names_b)
+}
+
+/// whether `rel_path` is `other` or one of its parent dirs
+fn is_self_or_parent(rel_path: &str, other: &str) -> bool {
+    rel_path.is_empty()
+        || (other.starts_with(rel_path)
+            && (other.len() == rel_path.len() || other[rel_path.len()..].starts_with('/')))
+}
+
+struct Pending {
+    path: PathBuf,
+    rel_path: String,
+    depth: usize,
+    rules: Arc<Vec<IgnoreRule>>,
+}
+
+/// Depth-first walker, entries of a dir are sorted by name.
+/// Unreadable entries are logged and skipped; an error is returned if the task is cancelled
+pub struct Walker {
+    options: WalkOptions,
+    stack: Vec<Pending>,
+    visited: HashSet<PathBuf>,
+}
+
+pub fn walk<P: AsRef<Path>>(root: P, options: &WalkOptions) -> Walker {
+    Walker {
+        options: options.clone(),
+        stack: vec![Pending {
+            path: root.as_ref().to_path_buf(),
+            rel_path: String::new(),
+            depth: 0,
+            rules: Arc::new(Vec::new()),
+        }],
+        visited: HashSet::new(),
+    }
+}
+
+impl Walker {
+    /// the entry of the pending path, and whether to walk into it; None to leave it out
+    fn visit(&mut self, item: &Pending) -> Option<(WalkEntry, bool)> {
+        let opts = &self.options;
+        let link_meta = match fs::symlink_metadata(&item.path) {
+            Ok(m) => m,
+            Err(e) => {
+                log::warn!("Failed to read {:?}: {}", item.path, e);
+                return None;
+            }
+        };
+        let is_link = link_meta.file_type().is_symlink();
+        let meta = if is_link && (opts.symlinks == SymlinkPolicy::Follow || item.depth == 0) {
+            // a broken link is listed as a link
+            fs::metadata(&item.path).unwrap_or(link_meta)
+        } else {
+            link_meta
+        };
+        let kind = if meta.file_type().is_symlink() {
+            KIND_SYMLINK
+        } else if meta.is_dir() {
+            KIND_DIR
+        } else if meta.is_file() {
+            KIND_FILE
+        } else {
+            KIND_OTHER
+        };
+        let entry = WalkEntry {
+            path: item.path.clone(),
+            rel_path: item.rel_path.clone(),
+            depth: item.depth,
+            kind,
+            size: if kind == KIND_FILE { meta.len() } else { 0 },
+            modified: meta.modified().ok(),
+        };
+
+        let mut descend = kind == KIND_DIR && opts.max_depth.is_none_or(|d| item.depth < d);
+        if descend && opts.symlinks == SymlinkPolicy::Follow {
+            // do not walk a dir twice through links
+            if let Ok(canonical) = fs::canonicalize(&item.path) {
+                descend = self.visited.insert(canonical);
+            }
+        }
+        if item.depth == 0 {
+            return Some((entry, descend));
+        }
+
+        let name = item
+            .path
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if is_link && opts.symlinks == SymlinkPolicy::Skip {
+            return None;
+        }
+        if !opts.hidden && name.starts_with('.') {
+            return None;
+        }
+        if opts.gitignore
+            && (name == ".git" || is_ignored(&item.rules, &item.rel_path, &name, entry.is_dir()))
+        {
+            return None;
+        }
+        if opts.excludes.iter().any(|r| r.is_match(&item.rel_path)) {
+            return None;
+        }
+        Some((entry, descend))
+    }
+
+    /// whether a visited entry is returned
+    fn is_selected(&self, entry: &WalkEntry) -> bool {
+        let opts = &self.options;
+        if entry.depth < opts.min_depth {
+            return false;
+        }
+        match entry.kind {
+            KIND_DIR if !opts.dirs => return false,
+            KIND_FILE | KIND_SYMLINK | KIND_OTHER if !opts.files => return false,
+            _ => {}
+        }
+        if entry.depth == 0 {
+            return true;
+        }
+        if !opts.includes.is_empty() && !opts.includes.iter().any(|r| r.is_match(&entry.rel_path)) {
+            return false;
+        }
+        if entry.is_file() {
+            if opts.min_size.is_some_and(|s| entry.size < s)
+                || opts.max_size.is_some_and(|s| entry.size > s)
+            {
+                return false;
+            }
+            if let Some(modified) = entry.modified {
+                if opts.modified_after.is_some_and(|t| modified < t)
+                    || opts.modified_before.is_some_and(|t| modified > t)
+                {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    fn push_children(&mut self, item: &Pending) -> Result<(), BoxedError> {
+        check_cancelled()?;
+        let read_dir = match fs::read_dir(&item.path) {
+            Ok(rd) => rd,
+            Err(e) => {
+                log::warn!("Failed to read dir {:?}: {}", item.path, e);
+                return Ok(());
+            }
+        };
+        let mut children: Vec<PathBuf> =
+            read_dir.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+        children.sort();
+
+        let rules = if self.options.gitignore {
+            let own = load_gitignore(&item.path, &item.rel_path);
+            if own.is_empty() {
+                item.rules.clone()
+            } else {
+                let mut rules: Vec<IgnoreRule> = item.rules.as_ref().clone();
+                rules.extend(own);
+                Arc::new(rules)
+            }
+        } else {
+            item.rules.clone()
+        };
+
+        for path in children.into_iter().rev() {
+            let name = path
+                .file_name()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let rel_path = if item.rel_path.is_empty() {
+                name
+            } else {
+                format!("{}/{}", item.rel_path, name)
+            };
+            self.stack.push(Pending {
+                path,
+                rel_path,
+                depth: item.depth + 1,
+                rules: rules.clone(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for Walker {
+    type Item = Result<WalkEntry, BoxedError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(item) = self.stack.pop() {
+            // up to the resume path, only its parent dirs are walked into
+            let before_after = match &self.options.after {
+                Some(after) if cmp_walk_order(&item.rel_path, after) != Ordering::Greater => {
+                    if !is_self_or_parent(&item.rel_path, after) {
+                        continue;
+                    }
+                    true
+                }
+                _ => false,
+            };
+            let (entry, descend) = match self.visit(&item) {
+                Some(v) => v,
+                None => continue,
+            };
+            if descend {
+                if let Err(e) = self.push_children(&item) {
+                    self.stack.clear();
+                    return Some(Err(e));
+                }
+            }
+            if !before_after && self.is_selected(&entry) {
+                return Some(Ok(entry));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thelib::test_utils::TempDir;
+
+    fn rel_paths(root: &Path, options: &WalkOptions) -> Vec<String> {
+        walk(root, options)
+            .map(|e| e.unwrap().rel_path)
+            .filter(|p| !p.is_empty())
+            .collect()
+    }
+
+    #[test]
+    fn test_walk() {
+        let tmp = TempDir::new("walk");
+        let root = tmp.path().to_path_buf();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::create_dir_all(root.join("build")).unwrap();
+        fs::write(root.join("a/1.txt"), "1").unwrap();
+        fs::write(root.join("a/b/2.txt"), "22").unwrap();
+        fs::write(root.join("a/b/3.log"), "333").unwrap();
+        fs::write(root.join("build/out.txt"), "").unwrap();
+        fs::write(root.join(".hidden"), "").unwrap();
+        fs::write(root.join(".gitignore"), "build/\n*.log\n").unwrap();
+
+        let all = rel_paths(&root, &WalkOptions::default());
+        assert_eq!(
+            all,
+            vec![
+                ".gitignore",
+                ".hidden",
+                "a",
+                "a/1.txt",
+                "a/b",
+                "a/b/2.txt",
+                "a/b/3.log",
+                "build",
+                "build/out.txt"
+            ]
+        );
+
+        let options = WalkOptions {
+            max_depth: Some(1),
+            hidden: false,
+            ..Default::default()
+        };
+        assert_eq!(rel_paths(&root, &options), vec!["a", "build"]);
+
+        let mut options = WalkOptions {
+            gitignore: true,
+            dirs: false,
+            ..Default::default()
+        };
+        options.add_include_glob("**/*.txt").unwrap();
+        assert_eq!(rel_paths(&root, &options), vec!["a/1.txt", "a/b/2.txt"]);
+
+        let mut options = WalkOptions {
+            dirs: false,
+            hidden: false,
+            min_size: Some(2),
+            ..Default::default()
+        };
+        options.add_exclude_glob("a/b/*.log").unwrap();
+        assert_eq!(rel_paths(&root, &options), vec!["a/b/2.txt"]);
+
+        // the root obeys `files` and `dirs`
+        let root_entries = |path: &Path, options: &WalkOptions| {
+            walk(path, options)
+                .filter(|e| e.as_ref().unwrap().depth == 0)
+                .count()
+        };
+        let dirs_only = WalkOptions {
+            files: false,
+            ..Default::default()
+        };
+        let files_only = WalkOptions {
+            dirs: false,
+            ..Default::default()
+        };
+        assert_eq!(root_entries(&root, &WalkOptions::default()), 1);
+        assert_eq!(root_entries(&root, &files_only), 0);
+        assert_eq!(root_entries(&root, &dirs_only), 1);
+        let file = root.join("a/1.txt");
+        assert_eq!(root_entries(&file, &files_only), 1);
+        assert_eq!(root_entries(&file, &dirs_only), 0);
+
+        // resume after a path, also a missing or a left out one
+        for (after, expected) in [
+            (
+                "a/b",
+                vec!["a/b/2.txt", "a/b/3.log", "build", "build/out.txt"],
+            ),
+            (
+                "a/1.txt",
+                vec!["a/b", "a/b/2.txt", "a/b/3.log", "build", "build/out.txt"],
+            ),
+            (
+                "a/a",
+                vec!["a/b", "a/b/2.txt", "a/b/3.log", "build", "build/out.txt"],
+            ),
+            (
+                ".hidden",
+                vec![
+                    "a",
+                    "a/1.txt",
+                    "a/b",
+                    "a/b/2.txt",
+                    "a/b/3.log",
+                    "build",
+                    "build/out.txt",
+                ],
+            ),
+            ("build/out.txt", vec![]),
+        ] {
+            let options = WalkOptions {
+                after: Some(after.to_string()),
+                ..Default::default()
+            };
+            assert_eq!(rel_paths(&root, &options), expected, "after {}", after);
+        }
+
+        #[cfg(unix)]
+        {
+            // a link loop ends
+            std::os::unix::fs::symlink(&root, root.join("a/loop")).unwrap();
+            let options = WalkOptions {
+                symlinks: SymlinkPolicy::Follow,
+                ..Default::default()
+            };
+            let paths = rel_paths(&root, &options);
+            assert!(paths.contains(&"a/loop".to_string()));
+            assert!(!paths.iter().any(|p| p.starts_with("a/loop/")));
+            assert!(rel_paths(&root, &WalkOptions::default()).contains(&"a/loop".to_string()));
+        }
+    }
+}